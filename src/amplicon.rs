@@ -0,0 +1,369 @@
+//! Binning amplicon reads by the primer pair they start and end with.
+//!
+//! A `PrimerScheme` lists the amplicons of a targeted panel with their primers and optionally the lengths their reads have.
+//! An `AmpliconBinner` assigns each read to the amplicon whose forward primer it starts with and whose reverse primer
+//! it ends with (reverse complemented), or the other way round for reads of the reverse strand.
+//! `AmpliconBinner::bin_to_dir` writes a file per sample and amplicon, and returns the count matrix.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+
+use super::Record as RecordTrait;
+use super::demux::{check_file_name, Output};
+use super::paths::{long_dir, long_path};
+use super::unfancy_parser::{Record, Error, Writer};
+
+/// The default file name template of `AmpliconBinner::bin_to_dir`.
+pub const AMPLICON_TEMPLATE: &str = "{sample}_{amplicon}.fastq.gz";
+
+/// The bases an IUPAC code stands for, as bits: A, C, G and T are bits 0 to 3. 0 for anything else.
+fn bases(code: u8) -> u8 {
+	match code.to_ascii_uppercase() {
+		b'A' => 0b0001, b'C' => 0b0010, b'G' => 0b0100, b'T' | b'U' => 0b1000,
+		b'R' => 0b0101, b'Y' => 0b1010, b'S' => 0b0110, b'W' => 0b1001, b'K' => 0b1100, b'M' => 0b0011,
+		b'B' => 0b1110, b'D' => 0b1101, b'H' => 0b1011, b'V' => 0b0111, b'N' => 0b1111,
+		_ => 0,
+	}
+}
+
+/// The reverse complement of a primer given as `bases`.
+fn reverse_complement(primer: &[u8]) -> Vec<u8> {
+	primer.iter().rev().map(|&b| (b & 0b0001) << 3 | (b & 0b1000) >> 3 | (b & 0b0010) << 1 | (b & 0b0100) >> 1).collect()
+}
+
+/// The number of read bases not matching a primer. Read bases other than A, C, G and T never match.
+fn mismatches(read: &[u8], primer: &[u8]) -> usize {
+	read.iter().zip(primer).filter(|&(&r, &p)| { let r = bases(r); !r.is_power_of_two() || r & p == 0 }).count()
+}
+
+/// An amplicon of a `PrimerScheme`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amplicon {
+	/// The name, used in file names and the count matrix.
+	pub name: String,
+	/// The forward primer, 5′ to 3′, in IUPAC codes.
+	pub forward: Vec<u8>,
+	/// The reverse primer, 5′ to 3′, in IUPAC codes.
+	pub reverse: Vec<u8>,
+	/// The lengths its reads may have, primers included. Reads of other lengths are off-length.
+	pub lengths: Option<RangeInclusive<usize>>,
+}
+
+/// The amplicons of a targeted panel.
+///
+/// The text form has one amplicon per line: name, forward primer, reverse primer and optionally the read lengths
+/// as `min-max`, separated by tabs or commas. Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrimerScheme {
+	amplicons: Vec<Amplicon>,
+}
+
+impl PrimerScheme {
+	/// Create an empty primer scheme.
+	pub fn new() -> PrimerScheme { PrimerScheme::default() }
+	
+	/// Read a primer scheme from its text form.
+	pub fn from_reader<R: BufRead>(reader: R) -> io::Result<PrimerScheme> {
+		let mut scheme = PrimerScheme::new();
+		for (i, line) in reader.lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() || line.starts_with('#') { continue }
+			let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", i + 1, what));
+			let fields: Vec<&str> = line.trim_end().split(['\t', ',']).map(str::trim).collect();
+			let lengths = match fields[..] {
+				[_, _, _] => None,
+				[_, _, _, lengths] => {
+					let range = lengths.split_once('-').and_then(|(min, max)| Some(min.parse().ok()?..=max.parse().ok()?));
+					Some(range.ok_or_else(|| invalid("expected lengths as <min>-<max>"))?)
+				},
+				_ => return Err(invalid("expected <name>\\t<forward>\\t<reverse>[\\t<min>-<max>]")),
+			};
+			if fields[1..3].iter().any(|primer| primer.is_empty() || primer.bytes().any(|b| bases(b) == 0)) {
+				return Err(invalid("primers have to be IUPAC nucleotide codes"));
+			}
+			scheme.insert(Amplicon { name: fields[0].to_owned(), forward: fields[1].into(), reverse: fields[2].into(), lengths });
+		}
+		Ok(scheme)
+	}
+	
+	/// Read a primer scheme from a file.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<PrimerScheme> {
+		PrimerScheme::from_reader(io::BufReader::new(fs::File::open(long_path(path))?))
+	}
+	
+	/// Add an amplicon.
+	pub fn insert(&mut self, amplicon: Amplicon) {
+		self.amplicons.push(amplicon);
+	}
+	
+	/// The amplicons, in the order they were added.
+	pub fn amplicons(&self) -> &[Amplicon] { &self.amplicons }
+	
+	/// Number of amplicons in the scheme.
+	pub fn len(&self) -> usize { self.amplicons.len() }
+	
+	/// Check if the scheme has no amplicons.
+	pub fn is_empty(&self) -> bool { self.amplicons.is_empty() }
+}
+
+/// The amplicon a read was assigned to, see `AmpliconBinner::assign`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bin<'a> {
+	/// The read matched this amplicon’s primers and has one of its lengths.
+	Amplicon(&'a str),
+	/// The read matched this amplicon’s primers, but is longer or shorter than its reads should be.
+	OffLength(&'a str),
+	/// The read matched no amplicon’s primer pair, or several equally well.
+	Unassigned,
+}
+
+/// An amplicon’s primers as `bases`, and the reverse complements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Primers {
+	forward: Vec<u8>,
+	reverse: Vec<u8>,
+	forward_rc: Vec<u8>,
+	reverse_rc: Vec<u8>,
+}
+
+/// Assigns reads to amplicons by primer pair, tolerating mismatches and some bases before the primers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmpliconBinner {
+	scheme: PrimerScheme,
+	primers: Vec<Primers>,
+	max_mismatches: usize,
+	max_offset: usize,
+	trim_primers: bool,
+}
+
+impl AmpliconBinner {
+	/// Bin by exact primer matches at the very ends of reads.
+	pub fn new(scheme: PrimerScheme) -> AmpliconBinner {
+		let primers = scheme.amplicons.iter().map(|a| {
+			let (forward, reverse): (Vec<u8>, Vec<u8>) = (a.forward.iter().map(|&b| bases(b)).collect(), a.reverse.iter().map(|&b| bases(b)).collect());
+			Primers { forward_rc: reverse_complement(&forward), reverse_rc: reverse_complement(&reverse), forward, reverse }
+		}).collect();
+		AmpliconBinner { scheme, primers, max_mismatches: 0, max_offset: 0, trim_primers: false }
+	}
+	
+	/// Accept primers with up to `n` mismatches each.
+	pub fn max_mismatches(mut self, n: usize) -> Self {
+		self.max_mismatches = n;
+		self
+	}
+	
+	/// Look for primers up to `n` bases from the read ends, e.g. after barcodes or adapter remnants.
+	pub fn max_offset(mut self, n: usize) -> Self {
+		self.max_offset = n;
+		self
+	}
+	
+	/// Cut off the primers and whatever is outside of them from assigned reads.
+	pub fn trim_primers(mut self, yes: bool) -> Self {
+		self.trim_primers = yes;
+		self
+	}
+	
+	/// The fewest mismatches of `primer` starting within `max_offset` of the read start, and where it ends.
+	fn at_start(&self, seq: &[u8], primer: &[u8]) -> Option<(usize, usize)> {
+		(0..=self.max_offset).take_while(|offset| offset + primer.len() <= seq.len())
+			.map(|offset| (mismatches(&seq[offset..], primer), offset + primer.len()))
+			.filter(|&(m, _)| m <= self.max_mismatches)
+			.min_by_key(|&(m, _)| m)
+	}
+	
+	/// The fewest mismatches of `primer` ending within `max_offset` of the read end, and where it starts.
+	fn at_end(&self, seq: &[u8], primer: &[u8]) -> Option<(usize, usize)> {
+		(0..=self.max_offset).take_while(|offset| offset + primer.len() <= seq.len())
+			.map(|offset| seq.len() - offset - primer.len())
+			.map(|start| (mismatches(&seq[start..], primer), start))
+			.filter(|&(m, _)| m <= self.max_mismatches)
+			.min_by_key(|&(m, _)| m)
+	}
+	
+	/// The amplicon whose primers match a sequence with the fewest mismatches,
+	/// if just one does, and the part of the sequence between the primers.
+	fn best(&self, seq: &[u8]) -> Option<(&Amplicon, Range<usize>)> {
+		let mut best: Option<(usize, usize, Range<usize>)> = None;
+		let mut ambiguous = false;
+		for (i, primers) in self.primers.iter().enumerate() {
+			// forward strand reads start with the forward primer, reverse strand reads with the reverse one
+			for (first, last) in [(&primers.forward, &primers.reverse_rc), (&primers.reverse, &primers.forward_rc)] {
+				let Some((m1, end)) = self.at_start(seq, first) else { continue };
+				let Some((m2, start)) = self.at_end(seq, last) else { continue };
+				if start < end { continue }
+				let mismatches = m1 + m2;
+				match best {
+					Some((m, j, _)) if m == mismatches => ambiguous |= i != j,
+					Some((m, _, _)) if m < mismatches => {},
+					_ => { best = Some((mismatches, i, end..start)); ambiguous = false },
+				}
+			}
+		}
+		if ambiguous { return None }
+		best.map(|(_, i, insert)| (&self.scheme.amplicons[i], insert))
+	}
+	
+	/// The amplicon a record belongs to, cutting off its primers if `trim_primers` is set.
+	pub fn assign(&self, record: &mut Record) -> Bin<'_> {
+		let Some((amplicon, insert)) = self.best(record.seq()) else { return Bin::Unassigned };
+		if amplicon.lengths.as_ref().is_some_and(|lengths| !lengths.contains(&record.seq_len())) {
+			return Bin::OffLength(&amplicon.name)
+		}
+		if self.trim_primers { record.keep_range(insert) }
+		Bin::Amplicon(&amplicon.name)
+	}
+	
+	/// Write each sample’s reads to a file per amplicon in `dir`, which is created if needed,
+	/// and off-length and unassigned reads to a file per sample named as if `unassigned` was an amplicon.
+	///
+	/// File names are made from `template` by replacing `{sample}` and `{amplicon}`, see `AMPLICON_TEMPLATE`.
+	/// Files are compressed as in `demux::Demultiplexer::demux_pairs_to_dir`, and only created for amplicons with reads.
+	/// Fails with `InvalidInput` before writing anything if a sample or amplicon name is no valid file name,
+	/// e.g. `a/b` or `unassigned`, or a sample name is given twice.
+	pub fn bin_to_dir<I, R, P>(&self, samples: I, dir: P, template: &str) -> Result<AmpliconCounts, Error>
+		where I: IntoIterator<Item=(String, R)>, R: IntoIterator<Item=Result<Record, Error>>, P: AsRef<Path>
+	{
+		let invalid = |msg: String| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg));
+		if !template.contains("{sample}") || !template.contains("{amplicon}") {
+			return Err(invalid(format!("File name template {:?} needs both {{sample}} and {{amplicon}}", template)));
+		}
+		let samples: Vec<_> = samples.into_iter().collect();
+		for (i, (sample, _)) in samples.iter().enumerate() {
+			check_file_name("Sample", sample, "")?;
+			if samples[..i].iter().any(|(s, _)| s == sample) { return Err(invalid(format!("Sample name {:?} is given twice", sample))) }
+		}
+		for amplicon in &self.scheme.amplicons { check_file_name("Amplicon", &amplicon.name, "unassigned")? }
+		let dir = long_dir(dir);
+		fs::create_dir_all(&dir)?;
+	
+		let mut counts = AmpliconCounts::new(&self.scheme);
+		for (s, (sample, records)) in samples.into_iter().enumerate() {
+			let open = |amplicon: &str| -> io::Result<Writer<Output>> {
+				Output::create(&dir.join(template.replace("{sample}", &sample).replace("{amplicon}", amplicon)), &[]).map(Writer::new)
+			};
+			let mut writers: Vec<Option<Writer<Output>>> = self.scheme.amplicons.iter().map(|_| None).collect();
+			let mut unassigned = open("unassigned")?;
+			counts.add_sample(&sample);
+			for record in records {
+				let mut record = record?;
+				let writer = match self.assign(&mut record) {
+					Bin::Amplicon(name) => {
+						let a = self.scheme.amplicons.iter().position(|a| a.name == name).unwrap();
+						counts.amplicons[a].1[s] += 1;
+						if writers[a].is_none() { writers[a] = Some(open(name)?) }
+						writers[a].as_mut().unwrap()
+					},
+					Bin::OffLength(_) => { counts.off_length[s] += 1; &mut unassigned },
+					Bin::Unassigned => { counts.unassigned[s] += 1; &mut unassigned },
+				};
+				writer.write_record(&record)?;
+			}
+			for writer in writers.into_iter().flatten().chain([unassigned]) { writer.into_inner()?.finish()? }
+		}
+		Ok(counts)
+	}
+}
+
+/// The count matrix of `AmpliconBinner::bin_to_dir`: reads per amplicon and sample.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AmpliconCounts {
+	/// The samples, i.e. the columns of the matrix.
+	pub samples: Vec<String>,
+	/// Each amplicon with its reads per sample, in the order of the primer scheme.
+	pub amplicons: Vec<(String, Vec<u64>)>,
+	/// Reads per sample that matched an amplicon’s primers, but not its lengths.
+	pub off_length: Vec<u64>,
+	/// Reads per sample that matched no amplicon’s primer pair, or several equally well.
+	pub unassigned: Vec<u64>,
+}
+
+impl AmpliconCounts {
+	fn new(scheme: &PrimerScheme) -> AmpliconCounts {
+		let amplicons = scheme.amplicons.iter().map(|a| (a.name.clone(), Vec::new())).collect();
+		AmpliconCounts { amplicons, ..AmpliconCounts::default() }
+	}
+	
+	fn add_sample(&mut self, sample: &str) {
+		self.samples.push(sample.to_owned());
+		for (_, counts) in &mut self.amplicons { counts.push(0) }
+		self.off_length.push(0);
+		self.unassigned.push(0);
+	}
+	
+	/// Write the matrix as TSV: a header line with `amplicon` and the samples,
+	/// then a line per amplicon, with `off_length` and `unassigned` last.
+	pub fn write_tsv<W: Write>(&self, out: W) -> io::Result<()> {
+		let mut out = io::BufWriter::new(out);
+		writeln!(out, "amplicon\t{}", self.samples.join("\t"))?;
+		let rows = self.amplicons.iter().map(|(name, counts)| (name.as_str(), counts))
+			.chain([("off_length", &self.off_length), ("unassigned", &self.unassigned)]);
+		for (name, counts) in rows {
+			let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+			writeln!(out, "{}\t{}", name, counts.join("\t"))?;
+		}
+		out.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::Reader;
+	
+	const SCHEME: &str = "# name\tforward\treverse\tlengths\namp1\tACGTTGCA\tGGATCCAA\t20-30\namp2,TTGGCCAA,CAGRTACT\n";
+	
+	fn record(seq: &str) -> Record {
+		let text = format!("@r\n{}\n+\n{}\n", seq, "I".repeat(seq.len()));
+		Reader::new(text.as_bytes()).records().next().unwrap().unwrap()
+	}
+	
+	#[test]
+	fn assigns_by_primer_pair_on_both_strands() {
+		let scheme = PrimerScheme::from_reader(SCHEME.as_bytes()).unwrap();
+		assert_eq!(scheme.amplicons()[0].lengths, Some(20..=30));
+		let binner = AmpliconBinner::new(scheme.clone());
+		// amp1: forward primer, insert, reverse complement of the reverse primer
+		assert_eq!(binner.assign(&mut record("ACGTTGCAcccccTTGGATCC")), Bin::Amplicon("amp1"));
+		// the reverse strand of amp1, and too short
+		assert_eq!(binner.assign(&mut record("GGATCCAAgggTGCAACGT")), Bin::OffLength("amp1"));
+		// amp2 with R in the reverse primer matching A; its reverse complement is AGTAYCTG
+		assert_eq!(binner.assign(&mut record("TTGGCCAAggggggAGTATCTG")), Bin::Amplicon("amp2"));
+		assert_eq!(binner.assign(&mut record("TTGGCCAAggggggAGTANCTG")), Bin::Unassigned);
+		assert_eq!(binner.assign(&mut record("ACGTTGCAccccc")), Bin::Unassigned);
+		let tolerant = AmpliconBinner::new(scheme).max_mismatches(1).max_offset(2).trim_primers(true);
+		let mut read = record("NNACGTTGCAcccccccTTGGATCCNN");
+		assert_eq!(tolerant.assign(&mut read), Bin::Amplicon("amp1"));
+		assert_eq!(read.seq(), b"ccccccc");
+		assert_eq!(tolerant.assign(&mut record("TTGGCCAAggggggAGTANCTG")), Bin::Amplicon("amp2"));
+		assert!(PrimerScheme::from_reader("a\tACGT\n".as_bytes()).is_err());
+		assert!(PrimerScheme::from_reader("a\tACGT\tAC-T\n".as_bytes()).is_err());
+		assert!(PrimerScheme::from_reader("a\tACGT\tACGT\t30\n".as_bytes()).is_err());
+	}
+	
+	#[test]
+	fn writes_files_per_sample_and_amplicon_and_a_count_matrix() {
+		let dir = std::env::temp_dir().join(format!("fastq-amplicon-test-{}", std::process::id()));
+		let binner = AmpliconBinner::new(PrimerScheme::from_reader(SCHEME.as_bytes()).unwrap());
+		let reads = |seqs: &[&str]| -> String { seqs.iter().map(|s| format!("@r\n{}\n+\n{}\n", s, "I".repeat(s.len()))).collect() };
+		let (a, b) = (reads(&["ACGTTGCAcccccTTGGATCC", "TTGGCCAAggggggAGTATCTG", "ACGT"]), reads(&["ACGTTGCAccccccTTGGATCC", "GGATCCAAgggTGCAACGT"]));
+		let samples = vec![("a".to_owned(), Reader::new(a.as_bytes()).records()), ("b".to_owned(), Reader::new(b.as_bytes()).records())];
+		let counts = binner.bin_to_dir(samples, &dir, "{sample}_{amplicon}.fq").unwrap();
+		let mut tsv = Vec::new();
+		counts.write_tsv(&mut tsv).unwrap();
+		assert_eq!(String::from_utf8(tsv).unwrap(), "amplicon\ta\tb\namp1\t1\t1\namp2\t1\t0\noff_length\t0\t1\nunassigned\t1\t0\n");
+		assert_eq!(fs::read_to_string(dir.join("b_amp1.fq")).unwrap(), reads(&["ACGTTGCAccccccTTGGATCC"]));
+		assert_eq!(fs::read_to_string(dir.join("b_unassigned.fq")).unwrap(), reads(&["GGATCCAAgggTGCAACGT"]));
+		assert!(!dir.join("b_amp2.fq").exists());
+		for (samples, template) in [(vec!["a/b"], "{sample}_{amplicon}.fq"), (vec!["a", "a"], "{sample}_{amplicon}.fq"), (vec!["a"], "{amplicon}.fq")] {
+			let samples = samples.into_iter().map(|s| (s.to_owned(), Vec::<Result<Record, Error>>::new()));
+			let result = binner.bin_to_dir(samples, dir.join("invalid"), template);
+			assert!(matches!(result, Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::InvalidInput), "{:?}", template);
+		}
+		assert!(!dir.join("invalid").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,153 @@
+//! Asynchronous versions of the fancy and unfancy parsers, for use with tokio.
+//!
+//! Both read from any `AsyncBufRead` and can be turned into a `Stream` of records.
+//! Multiline records and whitespace stripping are not supported here.
+
+use futures_util::stream::{self, Stream};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use super::Position;
+use super::fancy_parser::{self, ParseError};
+use super::unfancy_parser::{self, Error};
+
+/// An asynchronous reader producing `unfancy_parser::Record`s, see `unfancy_parser::Reader`.
+pub struct AsyncReader<R> {
+	reader: R,
+	sep_line: String,
+	pos: Position,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncReader<R> {
+	/// Read from a given `AsyncBufRead`.
+	pub fn new(reader: R) -> Self {
+		AsyncReader { reader, sep_line: String::new(), pos: Position::start() }
+	}
+	
+	/// The position of the next line to be read.
+	pub fn position(&self) -> Position { self.pos }
+	
+	/// Read the next record, or `None` at the end of input.
+	pub async fn read(&mut self) -> Result<Option<unfancy_parser::Record>, Error> {
+		let next = self.read_record().await;
+		if !matches!(next, Ok(None)) { self.pos.record += 1 }
+		next
+	}
+	
+	async fn read_record(&mut self) -> Result<Option<unfancy_parser::Record>, Error> {
+		let (mut header, mut seq, mut qual) = (String::new(), String::new(), String::new());
+		self.sep_line.clear();
+		let start = self.pos;
+		read_line_at(&mut self.reader, &mut self.pos, &mut header).await?;
+		if header.is_empty() { return Ok(None) }
+		if !header.starts_with('@') { return Err(Error::MissingAt(start)) }
+		read_line_at(&mut self.reader, &mut self.pos, &mut seq).await?;
+		let sep_pos = self.pos;
+		read_line_at(&mut self.reader, &mut self.pos, &mut self.sep_line).await?;
+		read_line_at(&mut self.reader, &mut self.pos, &mut qual).await?;
+		if qual.is_empty() { return Err(Error::IncompleteRecord(self.pos)) }
+		if !self.sep_line.starts_with('+') { return Err(Error::MissingPlus(sep_pos)) }
+		let mut record = unfancy_parser::Record::from_lines(header, seq, qual);
+		record.set_offset(Some(start.offset));
+		Ok(Some(record))
+	}
+	
+	/// Return a stream over the records.
+	pub fn into_stream(self) -> impl Stream<Item=Result<unfancy_parser::Record, Error>> {
+		stream::unfold(self, |mut reader| async move {
+			reader.read().await.transpose().map(|record| (record, reader))
+		})
+	}
+	
+	/// Return the underlying reader.
+	pub fn into_inner(self) -> R { self.reader }
+}
+
+/// An asynchronous reader producing `fancy_parser::Record`s, see `fancy_parser::FastqReader`.
+pub struct AsyncFastqReader<R> {
+	reader: R,
+	pos: Position,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncFastqReader<R> {
+	/// Read from a given `AsyncBufRead`.
+	pub fn new(reader: R) -> Self {
+		AsyncFastqReader { reader, pos: Position::start() }
+	}
+	
+	/// The position of the next line to be read.
+	pub fn position(&self) -> Position { self.pos }
+	
+	/// Return the underlying reader.
+	pub fn into_inner(self) -> R { self.reader }
+	
+	/// Read a line including its line break, advancing the position.
+	async fn read_line(&mut self, line: &mut String) -> Result<usize, ParseError> {
+		let pos = self.pos;
+		read_line_at(&mut self.reader, &mut self.pos, line).await.map_err(|e| ParseError::Io(pos, e))
+	}
+	
+	/// Read the next line without line break, failing with `Incomplete(prev())` if there is none.
+	async fn line_without_nl<F: Fn() -> String>(&mut self, prev: F) -> Result<String, ParseError> {
+		let pos = self.pos;
+		let mut line = String::new();
+		self.read_line(&mut line).await?;
+		if line.ends_with('\n') { line.pop(); }
+		if line.is_empty() { return Err(ParseError::Incomplete(pos, prev())) }
+		Ok(line)
+	}
+	
+	/// Read the next record, or `None` at the end of input.
+	pub async fn next_record(&mut self) -> Option<Result<fancy_parser::Record, ParseError>> {
+		let next = self.read_record().await?;
+		self.pos.record += 1;
+		Some(next)
+	}
+	
+	async fn read_record(&mut self) -> Option<Result<fancy_parser::Record, ParseError>> {
+		let start = self.pos;
+		let mut header = String::new();
+		match self.read_line(&mut header).await {
+			Ok(0) => return None,
+			Ok(_) => {},
+			Err(e) => return Some(Err(e)),
+		}
+		if !header.starts_with('@') { return Some(Err(ParseError::NoAt(start, header.as_bytes()[0]))) }
+		let mut header = header[1..].trim_end_matches('\n').to_owned();
+		let desc = fancy_parser::split_desc(&mut header);
+		let seq = match self.line_without_nl(|| format!("@{}\n<nothing>\n+\n<nothing>", header)).await {
+			Ok(seq) => seq,
+			Err(e) => return Some(Err(e)),
+		};
+		let sep_pos = self.pos;
+		let mut qual_head = String::new();
+		if let Err(e) = self.read_line(&mut qual_head).await { return Some(Err(e)) }
+		if !qual_head.starts_with('+') {
+			let byte = qual_head.bytes().next().unwrap_or(b'\n');
+			return Some(Err(ParseError::NoPlus(sep_pos, format!("@{}\n{}", header, seq), byte)));
+		}
+		let qual_pos = self.pos;
+		let qual = match self.line_without_nl(|| format!("@{}\n{}\n+\n<nothing>", header, seq)).await {
+			Ok(qual) => qual,
+			Err(e) => return Some(Err(e)),
+		};
+		Some(if seq.len() == qual.len() {
+			Ok(fancy_parser::Record::from_strings(header, desc, seq, qual).at_offset(start.offset))
+		} else {
+			Err(ParseError::LengthMismatch(qual_pos, seq, qual))
+		})
+	}
+	
+	/// Return a stream over the records.
+	pub fn into_stream(self) -> impl Stream<Item=Result<fancy_parser::Record, ParseError>> {
+		stream::unfold(self, |mut reader| async move {
+			reader.next_record().await.map(|record| (record, reader))
+		})
+	}
+}
+
+/// Read a line including its line break, advancing `pos` past it.
+async fn read_line_at<R: AsyncBufRead + Unpin>(reader: &mut R, pos: &mut Position, line: &mut String) -> std::io::Result<usize> {
+	let n = reader.read_line(line).await?;
+	pos.advance_line(n);
+	Ok(n)
+}
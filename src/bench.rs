@@ -0,0 +1,784 @@
+//! Measuring the throughput of the parsers on the same input.
+//!
+//! Allocation counts are only available if the program installs `CountingAllocator`:
+//!
+//! ```no_run
+//! #[global_allocator]
+//! static ALLOC: fastq_comparison::bench::CountingAllocator = fastq_comparison::bench::CountingAllocator;
+//! ```
+//!
+//! `Benchmark::text_policies` runs each parser under several `TextPolicy`s, to weigh UTF-8 validation against cheaper checks.
+//!
+//! `Benchmark::external` adds external tools like `seqtk` as baselines, timed as subprocesses on the same input.
+//!
+//! `Benchmark::writers` times writing the parsed records back to a file in each `WriteConfig`:
+//! FastQ formatting options, FASTA with or without wrapping, compression settings, and with or without buffering.
+//!
+//! With the `fetch` feature, `fetch_dataset` downloads public datasets listed in `DATASETS` once
+//! and caches them, so runs on different machines use the same input.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::{env, fmt, fs};
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::process::{self, Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::{ReaderOptions, Record as RecordTrait, TextPolicy};
+use super::borrowed_parser;
+#[cfg(feature = "pipeline")]
+use super::fasta;
+use super::fancy_parser::FastqReader;
+use super::generate::{LengthDistribution, RecordGenerator};
+use super::input::Input;
+use super::paths::long_path;
+#[cfg(feature = "fetch")]
+use super::paths::long_dir;
+use super::unfancy_parser::{self, HeaderStyle, LineEnding, WriterOptions};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static COUNTING: AtomicBool = AtomicBool::new(false);
+
+/// A global allocator counting allocations, to be installed with `#[global_allocator]`.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		COUNTING.store(true, Ordering::Relaxed);
+		System.alloc(layout)
+	}
+	
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+	
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		System.realloc(ptr, layout, new_size)
+	}
+}
+
+/// The number of allocations so far, if `CountingAllocator` is installed.
+fn allocations() -> Option<u64> {
+	if COUNTING.load(Ordering::Relaxed) { Some(ALLOCATIONS.load(Ordering::Relaxed)) } else { None }
+}
+
+/// One of the parsers of this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Parser {
+	/// `fancy_parser::FastqReader`
+	Fancy,
+	/// `unfancy_parser::Reader`
+	Unfancy,
+	/// `borrowed_parser::Reader`
+	Borrowed,
+}
+
+impl Parser {
+	pub const ALL: [Parser; 3] = [Parser::Fancy, Parser::Unfancy, Parser::Borrowed];
+}
+
+impl fmt::Display for Parser {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.pad(match *self { Parser::Fancy => "fancy", Parser::Unfancy => "unfancy", Parser::Borrowed => "borrowed" })
+	}
+}
+
+/// The input to run the parsers on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+	/// A file, read (and decompressed) anew for every run.
+	File(PathBuf),
+	/// A buffer in memory, excluding I/O from the measurement.
+	Memory(Vec<u8>),
+	/// Random reads of the given length, generated into memory before measuring.
+	Synthetic { records: usize, length: usize, seed: u64 },
+}
+
+/// Throughput of one parser.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchResult {
+	pub parser: Parser,
+	/// How the parser treated bytes outside ASCII.
+	pub text: TextPolicy,
+	/// Records parsed per run.
+	pub records: u64,
+	/// Input bytes per run, after decompression.
+	pub bytes: u64,
+	/// Fastest of all runs.
+	pub elapsed: Duration,
+	/// Allocations during the fastest run, if counted.
+	pub allocations: Option<u64>,
+}
+
+impl BenchResult {
+	pub fn records_per_sec(&self) -> f64 { self.records as f64 / self.elapsed.as_secs_f64() }
+	
+	pub fn mb_per_sec(&self) -> f64 { self.bytes as f64 / 1e6 / self.elapsed.as_secs_f64() }
+}
+
+/// The results of a `Benchmark`, one per parser.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchReport {
+	pub results: Vec<BenchResult>,
+	/// The results of external tools, in the order they were added.
+	pub external: Vec<ExternalResult>,
+	/// The results of write configurations, in the order they were added.
+	pub writers: Vec<WriteResult>,
+}
+
+impl BenchReport {
+	/// The result of a given parser, if it was run.
+	pub fn get(&self, parser: Parser) -> Option<&BenchResult> {
+		self.results.iter().find(|r| r.parser == parser)
+	}
+}
+
+impl fmt::Display for BenchReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "{:<10} {:<6} {:>12} {:>14} {:>10} {:>12}", "parser", "text", "records", "records/s", "MB/s", "allocations")?;
+		for r in &self.results {
+			let allocations = r.allocations.map_or("-".to_owned(), |a| a.to_string());
+			writeln!(f, "{:<10} {:<6} {:>12} {:>14.0} {:>10.1} {:>12}", r.parser, r.text, r.records, r.records_per_sec(), r.mb_per_sec(), allocations)?;
+		}
+		for r in &self.external {
+			let records = r.records.map_or("-".to_owned(), |n| n.to_string());
+			match (r.elapsed, &r.error) {
+				(Some(elapsed), _) => {
+					let secs = elapsed.as_secs_f64();
+					let per_sec = r.records.map_or("-".to_owned(), |n| format!("{:.0}", n as f64 / secs));
+					writeln!(f, "{:<10} {:<6} {:>12} {:>14} {:>10.1} {:>12}", r.name, "-", records, per_sec, r.bytes as f64 / 1e6 / secs, "-")?;
+				},
+				(None, error) => writeln!(f, "{:<10} {:<6} {:>12} failed: {}", r.name, "-", records, error.as_deref().unwrap_or("unknown error"))?,
+			}
+		}
+		if !self.writers.is_empty() {
+			writeln!(f)?;
+			writeln!(f, "{:<24} {:>12} {:>14} {:>10} {:>8}", "writer", "records", "records/s", "MB/s", "ratio")?;
+		}
+		for r in &self.writers {
+			writeln!(f, "{:<24} {:>12} {:>14.0} {:>10.1} {:>8.3}", r.name, r.records, r.records_per_sec(), r.mb_per_sec(), r.ratio())?;
+		}
+		Ok(())
+	}
+}
+
+/// An external program run on the benchmark input as a baseline, see `Benchmark::external`.
+///
+/// Its output is discarded, so only the time it takes is measured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalTool {
+	/// The name in the report.
+	pub name: String,
+	pub program: String,
+	/// The arguments, where an argument `{}` is replaced by the path of the input file.
+	pub args: Vec<String>,
+}
+
+impl ExternalTool {
+	/// Run `program` with `args`, see `ExternalTool::args`.
+	pub fn new(name: &str, program: &str, args: &[&str]) -> ExternalTool {
+		ExternalTool { name: name.to_owned(), program: program.to_owned(), args: args.iter().map(|&a| a.to_owned()).collect() }
+	}
+	
+	/// `seqtk seq`, parsing and writing every record.
+	pub fn seqtk() -> ExternalTool { ExternalTool::new("seqtk", "seqtk", &["seq", "{}"]) }
+	
+	/// `fastp` with trimming, filtering and reports disabled, so it only parses the records.
+	pub fn fastp() -> ExternalTool {
+		ExternalTool::new("fastp", "fastp", &[
+			"-i", "{}", "-w", "1", "-j", "/dev/null", "-h", "/dev/null",
+			"--disable_adapter_trimming", "--disable_quality_filtering", "--disable_length_filtering", "--disable_trim_poly_g",
+		])
+	}
+	
+	/// `cat | wc -l`, the time to merely read the file, compressed or not.
+	pub fn cat_wc() -> ExternalTool { ExternalTool::new("cat|wc", "sh", &["-c", "cat \"$1\" | wc -l", "sh", "{}"]) }
+	
+	/// `seqtk`, `fastp` and `cat | wc -l`.
+	pub fn common() -> Vec<ExternalTool> { vec![ExternalTool::seqtk(), ExternalTool::fastp(), ExternalTool::cat_wc()] }
+	
+	/// Run the tool on a file once, returning how long it took.
+	fn time(&self, path: &Path) -> io::Result<Duration> {
+		let args = self.args.iter().map(|a| if a == "{}" { path.as_os_str().to_owned() } else { OsString::from(a) });
+		let start = Instant::now();
+		let status = Command::new(&self.program).args(args)
+			.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null())
+			.status()
+			.map_err(|e| match e.kind() {
+				io::ErrorKind::NotFound => io::Error::new(e.kind(), format!("{} not found", self.program)),
+				_ => e,
+			})?;
+		let elapsed = start.elapsed();
+		if !status.success() { return Err(io::Error::other(format!("{} exited with {}", self.program, status))) }
+		Ok(elapsed)
+	}
+}
+
+/// Time taken by an external tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExternalResult {
+	pub name: String,
+	/// Records in the input, if a parser of this crate counted them.
+	pub records: Option<u64>,
+	/// Input bytes after decompression if a parser of this crate counted them, else the file size.
+	pub bytes: u64,
+	/// Fastest of all runs, `None` if the tool failed.
+	pub elapsed: Option<Duration>,
+	/// Why the tool failed, e.g. because it is not installed.
+	pub error: Option<String>,
+}
+
+/// How a write benchmark formats records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+	/// FastQ, written by `unfancy_parser::Writer` with the given options.
+	Fastq(WriterOptions),
+	/// FASTA, written by `fasta::Writer` and wrapped after the given number of bases, if any.
+	#[cfg(feature = "pipeline")]
+	Fasta(Option<usize>),
+}
+
+/// How a write benchmark compresses its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputCompression {
+	None,
+	/// gzip at a level from 0 to 9.
+	#[cfg(feature = "gzip")]
+	Gzip(u32),
+	/// zstd at a level from 1 to 22.
+	#[cfg(feature = "zstd")]
+	Zstd(i32),
+}
+
+/// A way to write records, see `Benchmark::writers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteConfig {
+	pub format: Format,
+	pub compression: OutputCompression,
+	/// Whether the writer buffers, or passes every field straight on to the compressor or file.
+	pub buffered: bool,
+}
+
+impl WriteConfig {
+	/// Buffered, uncompressed FastQ with default formatting.
+	pub fn fastq() -> WriteConfig {
+		WriteConfig { format: Format::Fastq(WriterOptions::default()), compression: OutputCompression::None, buffered: true }
+	}
+	
+	/// Buffered, uncompressed FASTA, wrapped after `line_width` bases if given.
+	#[cfg(feature = "pipeline")]
+	pub fn fasta(line_width: Option<usize>) -> WriteConfig {
+		WriteConfig { format: Format::Fasta(line_width), ..WriteConfig::fastq() }
+	}
+	
+	/// FastQ with the given formatting options.
+	pub fn options(options: WriterOptions) -> WriteConfig {
+		WriteConfig { format: Format::Fastq(options), ..WriteConfig::fastq() }
+	}
+	
+	/// Compress the output.
+	pub fn compression(mut self, compression: OutputCompression) -> WriteConfig {
+		self.compression = compression;
+		self
+	}
+	
+	/// Write without buffering.
+	pub fn unbuffered(mut self) -> WriteConfig {
+		self.buffered = false;
+		self
+	}
+	
+	/// Default FastQ buffered and not, the other formatting options, FASTA with and without wrapping,
+	/// and fast and default levels of the compressions of the enabled features.
+	pub fn common() -> Vec<WriteConfig> {
+		#[cfg_attr(not(any(feature = "pipeline", feature = "gzip")), allow(unused_mut))]
+		let mut configs = vec![
+			WriteConfig::fastq(),
+			WriteConfig::fastq().unbuffered(),
+			WriteConfig::options(WriterOptions { line_ending: LineEnding::CrLf, ..WriterOptions::default() }),
+			WriteConfig::options(WriterOptions { repeat_header: true, ..WriterOptions::default() }),
+			WriteConfig::options(WriterOptions { header_style: HeaderStyle::Normalized, ..WriterOptions::default() }),
+		];
+		#[cfg(feature = "pipeline")]
+		configs.extend([WriteConfig::fasta(None), WriteConfig::fasta(Some(60))]);
+		#[cfg(feature = "gzip")]
+		configs.extend([1, 6].map(|level| WriteConfig::fastq().compression(OutputCompression::Gzip(level))));
+		#[cfg(feature = "zstd")]
+		configs.extend([1, 3].map(|level| WriteConfig::fastq().compression(OutputCompression::Zstd(level))));
+		configs
+	}
+}
+
+impl fmt::Display for WriteConfig {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut name = match self.format {
+			Format::Fastq(ref options) => {
+				let mut name = "fastq".to_owned();
+				if options.line_ending == LineEnding::CrLf { name.push_str(" crlf") }
+				if options.repeat_header { name.push_str(" +header") }
+				match options.header_style {
+					HeaderStyle::Original => {},
+					HeaderStyle::Normalized => name.push_str(" normalized"),
+					HeaderStyle::Casava => name.push_str(" casava"),
+					HeaderStyle::IdOnly => name.push_str(" id-only"),
+				}
+				name
+			},
+			#[cfg(feature = "pipeline")]
+			Format::Fasta(None) => "fasta".to_owned(),
+			#[cfg(feature = "pipeline")]
+			Format::Fasta(Some(width)) => format!("fasta/{}", width),
+		};
+		match self.compression {
+			OutputCompression::None => {},
+			#[cfg(feature = "gzip")]
+			OutputCompression::Gzip(level) => name.push_str(&format!(" gzip-{}", level)),
+			#[cfg(feature = "zstd")]
+			OutputCompression::Zstd(level) => name.push_str(&format!(" zstd-{}", level)),
+		}
+		if !self.buffered { name.push_str(" unbuffered") }
+		f.pad(&name)
+	}
+}
+
+/// Throughput of one write configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WriteResult {
+	/// The `WriteConfig` as displayed.
+	pub name: String,
+	/// Records written per run.
+	pub records: u64,
+	/// Bytes formatted per run, before compression.
+	pub bytes: u64,
+	/// Bytes in the output file.
+	pub output_bytes: u64,
+	/// Fastest of all runs, including flushing and finishing compression.
+	pub elapsed: Duration,
+}
+
+impl WriteResult {
+	pub fn records_per_sec(&self) -> f64 { self.records as f64 / self.elapsed.as_secs_f64() }
+	
+	/// Formatted megabytes per second, before compression.
+	pub fn mb_per_sec(&self) -> f64 { self.bytes as f64 / 1e6 / self.elapsed.as_secs_f64() }
+	
+	/// Output size relative to the formatted size, 1 if uncompressed.
+	pub fn ratio(&self) -> f64 { self.output_bytes as f64 / self.bytes.max(1) as f64 }
+}
+
+/// A temporary file for external tools or the output of writers, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+	fn new(extension: &str) -> TempFile {
+		TempFile(env::temp_dir().join(format!("fastq-comparison-bench-{}.{}", process::id(), extension)))
+	}
+	
+	fn with_data(data: &[u8]) -> io::Result<TempFile> {
+		let file = TempFile::new("fastq");
+		fs::write(&file.0, data)?;
+		Ok(file)
+	}
+}
+
+impl Drop for TempFile {
+	fn drop(&mut self) { let _ = fs::remove_file(&self.0); }
+}
+
+/// Runs parsers over the same input and measures them.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+	source: Source,
+	parsers: Vec<Parser>,
+	text: Vec<TextPolicy>,
+	external: Vec<ExternalTool>,
+	writers: Vec<WriteConfig>,
+	runs: usize,
+}
+
+impl Benchmark {
+	/// Benchmark all parsers on a source, taking the fastest of 3 runs.
+	pub fn new(source: Source) -> Benchmark {
+		Benchmark { source, parsers: Parser::ALL.to_vec(), text: vec![TextPolicy::Utf8], external: Vec::new(), writers: Vec::new(), runs: 3 }
+	}
+	
+	/// Only benchmark the given parsers.
+	pub fn parsers(mut self, parsers: &[Parser]) -> Benchmark {
+		self.parsers = parsers.to_vec();
+		self
+	}
+	
+	/// Run each parser under each of the given text policies, instead of validating UTF-8 only.
+	pub fn text_policies(mut self, policies: &[TextPolicy]) -> Benchmark {
+		self.text = policies.to_vec();
+		self
+	}
+	
+	/// Also time external tools, e.g. `ExternalTool::common()`. Tools that fail or are not installed
+	/// are reported as failed. In-memory input is written to a temporary file for them.
+	pub fn external(mut self, tools: &[ExternalTool]) -> Benchmark {
+		self.external = tools.to_vec();
+		self
+	}
+	
+	/// Also time writing the records of the input to a temporary file in each configuration,
+	/// e.g. `WriteConfig::common()`. The records are parsed into memory first, so only writing is timed.
+	pub fn writers(mut self, configs: &[WriteConfig]) -> Benchmark {
+		self.writers = configs.to_vec();
+		self
+	}
+	
+	/// Run each parser, external tool and writer `runs` times, keeping the fastest.
+	pub fn runs(mut self, runs: usize) -> Benchmark {
+		self.runs = runs.max(1);
+		self
+	}
+	
+	/// Run the benchmark. Fails if the input cannot be read or parsed.
+	pub fn run(&self) -> io::Result<BenchReport> {
+		let synthetic;
+		let data = match self.source {
+			Source::File(_) => None,
+			Source::Memory(ref data) => Some(&data[..]),
+			Source::Synthetic { records, length, seed } => {
+				synthetic = RecordGenerator::new(seed).lengths(LengthDistribution::Fixed(length)).to_bytes(records);
+				Some(&synthetic[..])
+			},
+		};
+		let mut report = BenchReport::default();
+		for &parser in &self.parsers {
+			for &text in &self.text {
+				let mut best: Option<BenchResult> = None;
+				for _ in 0..self.runs {
+					let result = match (data, &self.source) {
+						(Some(data), _) => measure(parser, text, data)?,
+						(None, Source::File(path)) => measure(parser, text, Input::open(long_path(path))?)?,
+						(None, _) => unreachable!(),
+					};
+					if best.as_ref().is_none_or(|b| result.elapsed < b.elapsed) { best = Some(result) }
+				}
+				report.results.extend(best);
+			}
+		}
+		if !self.external.is_empty() { self.run_external(data, &mut report)? }
+		if !self.writers.is_empty() { self.run_writers(data, &mut report)? }
+		Ok(report)
+	}
+	
+	fn run_writers(&self, data: Option<&[u8]>, report: &mut BenchReport) -> io::Result<()> {
+		let records = match (data, &self.source) {
+			(Some(data), _) => unfancy_parser::Reader::new(data).records().collect::<Result<Vec<_>, _>>(),
+			(None, Source::File(path)) => unfancy_parser::Reader::new(Input::open(long_path(path))?).records().collect(),
+			(None, _) => unreachable!(),
+		}.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+		let output = TempFile::new("out");
+		for config in &self.writers {
+			let mut best: Option<WriteResult> = None;
+			for _ in 0..self.runs {
+				let result = measure_write(config, &records, &output.0)?;
+				if best.as_ref().is_none_or(|b| result.elapsed < b.elapsed) { best = Some(result) }
+			}
+			report.writers.extend(best);
+		}
+		Ok(())
+	}
+	
+	fn run_external(&self, data: Option<&[u8]>, report: &mut BenchReport) -> io::Result<()> {
+		let temp;
+		let path = match (data, &self.source) {
+			(Some(data), _) => {
+				temp = TempFile::with_data(data)?;
+				temp.0.clone()
+			},
+			(None, Source::File(path)) => long_path(path),
+			(None, _) => unreachable!(),
+		};
+		let counted = report.results.first();
+		let bytes = match counted {
+			Some(r) => r.bytes,
+			None => fs::metadata(&path)?.len(),
+		};
+		for tool in &self.external {
+			let mut result = ExternalResult { name: tool.name.clone(), records: counted.map(|r| r.records), bytes, elapsed: None, error: None };
+			for _ in 0..self.runs {
+				match tool.time(&path) {
+					Ok(elapsed) => if result.elapsed.is_none_or(|e| elapsed < e) { result.elapsed = Some(elapsed) },
+					Err(e) => {
+						result.elapsed = None;
+						result.error = Some(e.to_string());
+						break;
+					},
+				}
+			}
+			report.external.push(result);
+		}
+		Ok(())
+	}
+}
+
+/// Parse all of `input` with `parser` under a text policy, failing on the first invalid record.
+fn measure<R: Read>(parser: Parser, text: TextPolicy, input: R) -> io::Result<BenchResult> {
+	let invalid = |e: &dyn fmt::Display| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+	let mut input = Counting { inner: input, bytes: 0 };
+	let allocations_before = allocations();
+	let start = Instant::now();
+	let mut records = 0;
+	match parser {
+		Parser::Fancy => for record in FastqReader::new(io::BufReader::new(&mut input)).records_with_options(ReaderOptions::new().text(text)) {
+			record.map_err(|e| invalid(&e))?;
+			records += 1;
+		},
+		Parser::Unfancy => {
+			let mut reader = unfancy_parser::Reader::with_options(&mut input, ReaderOptions::new().text(text));
+			let mut record = unfancy_parser::Record::new();
+			loop {
+				reader.read(&mut record).map_err(|e| invalid(&e))?;
+				if record.is_empty() { break }
+				records += 1;
+			}
+		},
+		Parser::Borrowed => {
+			let mut reader = borrowed_parser::Reader::new(&mut input).text(text);
+			while let Some(record) = reader.next() {
+				record.map_err(|e| invalid(&e))?;
+				records += 1;
+			}
+		},
+	}
+	let elapsed = start.elapsed();
+	let allocations = allocations().and_then(|after| Some(after - allocations_before?));
+	Ok(BenchResult { parser, text, records, bytes: input.bytes, elapsed, allocations })
+}
+
+/// Write all `records` to a new file at `path` as `config` says.
+fn measure_write(config: &WriteConfig, records: &[unfancy_parser::Record], path: &Path) -> io::Result<WriteResult> {
+	let start = Instant::now();
+	let mut output = CountingWriter { inner: CompressedFile::create(path, config.compression)?, bytes: 0 };
+	match config.format {
+		Format::Fastq(ref options) => {
+			let mut writer = match config.buffered {
+				true => unfancy_parser::Writer::with_options(&mut output, options.clone()),
+				false => unfancy_parser::Writer::with_capacity(&mut output, options.clone(), 0),
+			};
+			for record in records { writer.write_record(record)? }
+			writer.flush()?;
+		},
+		#[cfg(feature = "pipeline")]
+		Format::Fasta(line_width) => {
+			let mut writer = match config.buffered {
+				true => fasta::Writer::new(&mut output),
+				false => fasta::Writer::with_capacity(&mut output, 0),
+			};
+			if let Some(width) = line_width { writer = writer.with_line_width(width) }
+			for record in records { writer.write_record(record)? }
+			writer.flush()?;
+		},
+	}
+	let bytes = output.bytes;
+	output.inner.finish()?;
+	let elapsed = start.elapsed();
+	Ok(WriteResult { name: config.to_string(), records: records.len() as u64, bytes, output_bytes: fs::metadata(path)?.len(), elapsed })
+}
+
+/// An output file of a write benchmark, compressed as configured.
+enum CompressedFile {
+	Plain(fs::File),
+	#[cfg(feature = "gzip")]
+	Gzip(flate2::write::GzEncoder<fs::File>),
+	#[cfg(feature = "zstd")]
+	Zstd(zstd::Encoder<'static, fs::File>),
+}
+
+impl CompressedFile {
+	fn create(path: &Path, compression: OutputCompression) -> io::Result<CompressedFile> {
+		let file = fs::File::create(path)?;
+		Ok(match compression {
+			OutputCompression::None => CompressedFile::Plain(file),
+			#[cfg(feature = "gzip")]
+			OutputCompression::Gzip(level) => CompressedFile::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::new(level))),
+			#[cfg(feature = "zstd")]
+			OutputCompression::Zstd(level) => CompressedFile::Zstd(zstd::Encoder::new(file, level)?),
+		})
+	}
+	
+	/// Write the gzip trailer or end the zstd frame if compressed.
+	fn finish(self) -> io::Result<()> {
+		match self {
+			CompressedFile::Plain(_) => Ok(()),
+			#[cfg(feature = "gzip")]
+			CompressedFile::Gzip(gz) => gz.finish().map(drop),
+			#[cfg(feature = "zstd")]
+			CompressedFile::Zstd(zstd) => zstd.finish().map(drop),
+		}
+	}
+}
+
+impl Write for CompressedFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			CompressedFile::Plain(file) => file.write(buf),
+			#[cfg(feature = "gzip")]
+			CompressedFile::Gzip(gz) => gz.write(buf),
+			#[cfg(feature = "zstd")]
+			CompressedFile::Zstd(zstd) => zstd.write(buf),
+		}
+	}
+	
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			CompressedFile::Plain(file) => file.flush(),
+			#[cfg(feature = "gzip")]
+			CompressedFile::Gzip(gz) => gz.flush(),
+			#[cfg(feature = "zstd")]
+			CompressedFile::Zstd(zstd) => zstd.flush(),
+		}
+	}
+}
+
+/// A writer counting the bytes written through it.
+struct CountingWriter<W> {
+	inner: W,
+	bytes: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = self.inner.write(buf)?;
+		self.bytes += n as u64;
+		Ok(n)
+	}
+	
+	fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+/// A reader counting the bytes read through it.
+struct Counting<R> {
+	inner: R,
+	bytes: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.bytes += n as u64;
+		Ok(n)
+	}
+}
+
+/// Read a whole file into memory, e.g. to benchmark a `Source::Memory` without I/O.
+pub fn load<P: Into<PathBuf>>(path: P) -> io::Result<Source> {
+	let mut data = Vec::new();
+	io::BufReader::new(Input::open(long_path(path.into()))?).read_to_end(&mut data)?;
+	Ok(Source::Memory(data))
+}
+
+/// A small public FastQ dataset, see `fetch_dataset`.
+#[cfg(feature = "fetch")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dataset {
+	/// The name to fetch it by.
+	pub name: &'static str,
+	/// The run accession in the European Nucleotide Archive. Only the first mates are fetched.
+	pub accession: &'static str,
+	/// What sets it apart.
+	pub description: &'static str,
+}
+
+#[cfg(feature = "fetch")]
+impl Dataset {
+	/// Where ENA serves the gzipped FastQ file of the first mates.
+	pub fn url(&self) -> String {
+		let acc = self.accession;
+		let digits = acc.len().saturating_sub(3);
+		let dir = match digits {
+			0..=6 => format!("{}/{}", &acc[..acc.len().min(6)], acc),
+			_ => format!("{}/{:0>3}/{}", &acc[..6], &acc[9..], acc),
+		};
+		format!("https://ftp.sra.ebi.ac.uk/vol1/fastq/{}/{}_1.fastq.gz", dir, acc)
+	}
+}
+
+/// The datasets `fetch_dataset` knows.
+#[cfg(feature = "fetch")]
+pub const DATASETS: &[Dataset] = &[
+	Dataset { name: "sra-example", accession: "SRR390728", description: "Short paired-end Illumina reads, the SRA Toolkit’s example run" },
+	Dataset { name: "ecoli-hiseq", accession: "SRR2584863", description: "150 bp paired-end Illumina HiSeq reads of E. coli" },
+];
+
+/// Where `fetch_dataset` caches downloads: `$FASTQ_COMPARISON_CACHE`,
+/// or a `fastq-comparison` directory in the user’s cache directory.
+#[cfg(feature = "fetch")]
+pub fn cache_dir() -> PathBuf {
+	if let Some(dir) = env::var_os("FASTQ_COMPARISON_CACHE") { return dir.into() }
+	let base = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+		.or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+		.or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+		.unwrap_or_else(env::temp_dir);
+	base.join("fastq-comparison")
+}
+
+/// Download a dataset from `DATASETS` by name into `cache_dir()`, unless it is there already,
+/// and return the path of the gzipped FastQ file, e.g. for a `Source::File`.
+#[cfg(feature = "fetch")]
+pub fn fetch_dataset(name: &str) -> io::Result<PathBuf> {
+	fetch_dataset_to(name, cache_dir())
+}
+
+/// Like `fetch_dataset`, caching in `dir`.
+#[cfg(feature = "fetch")]
+pub fn fetch_dataset_to<P: AsRef<Path>>(name: &str, dir: P) -> io::Result<PathBuf> {
+	let dataset = DATASETS.iter().find(|d| d.name == name)
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Unknown dataset {:?}", name)))?;
+	let dir = long_dir(dir);
+	let path = dir.join(format!("{}_1.fastq.gz", dataset.accession));
+	if path.is_file() { return Ok(path) }
+	fs::create_dir_all(&dir)?;
+	let response = ureq::get(&dataset.url()).call().map_err(io::Error::other)?;
+	let mut tmp = path.clone().into_os_string();
+	tmp.push(".tmp");
+	io::copy(&mut response.into_body().into_reader(), &mut fs::File::create(&tmp)?)?;
+	fs::rename(tmp, &path)?;
+	Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn write_paths() {
+		let source = Source::Synthetic { records: 2000, length: 150, seed: 1 };
+		let report = Benchmark::new(source).parsers(&[Parser::Unfancy]).writers(&WriteConfig::common()).runs(1).run().unwrap();
+		assert_eq!(report.writers.len(), WriteConfig::common().len());
+		assert!(report.writers.iter().all(|r| r.records == 2000));
+		let get = |name: &str| report.writers.iter().find(|r| r.name == name).unwrap_or_else(|| panic!("{}", name));
+		let fastq = get("fastq");
+		assert_eq!((fastq.bytes, fastq.output_bytes), (report.results[0].bytes, report.results[0].bytes));
+		assert_eq!(get("fastq unbuffered").output_bytes, fastq.output_bytes);
+		assert_eq!(get("fastq crlf").bytes, fastq.bytes + 4 * 2000);
+		#[cfg(feature = "pipeline")]
+		assert_eq!(get("fasta/60").bytes, get("fasta").bytes + 2 * 2000);
+		#[cfg(feature = "gzip")]
+		assert!(get("fastq gzip-6").ratio() < 0.6 && get("fastq gzip-6").bytes == fastq.bytes);
+		assert!(report.to_string().contains("\nwriter "));
+		assert!(!TempFile::new("out").0.exists());
+	}
+	
+	#[test]
+	fn parsers_under_text_policies() {
+		let source = Source::Synthetic { records: 100, length: 50, seed: 1 };
+		let report = Benchmark::new(source).text_policies(&TextPolicy::ALL).runs(1).run().unwrap();
+		assert_eq!(report.results.len(), Parser::ALL.len() * TextPolicy::ALL.len());
+		assert!(report.results.iter().all(|r| r.records == 100 && r.bytes == report.results[0].bytes));
+		assert_eq!(report.results.iter().filter(|r| r.text == TextPolicy::Ascii).count(), Parser::ALL.len());
+	}
+}
@@ -0,0 +1,1280 @@
+//! Command line interface to validate, summarize, compare and convert FastQ files.
+
+#[macro_use] extern crate quick_error;
+extern crate fastq_comparison;
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "gzip")]
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+
+use fastq_comparison::Record as RecordTrait;
+#[cfg(feature = "async")]
+use fastq_comparison::async_reader::AsyncReader;
+use fastq_comparison::amplicon::{AmpliconBinner, PrimerScheme, AMPLICON_TEMPLATE};
+use fastq_comparison::borrowed_parser;
+use fastq_comparison::checksum::ChecksumIndex;
+#[cfg(feature = "zstd")]
+use fastq_comparison::codec::{self, SeqCodec, ZstdFramed};
+use fastq_comparison::compare::{compare, compare_pairs, CompareOptions};
+use fastq_comparison::demux::{BarcodeSource, DemuxCounts, Demultiplexer, SampleSheet, PAIR_TEMPLATE};
+use fastq_comparison::example;
+use fastq_comparison::fancy_parser::FastqReader;
+use fastq_comparison::fasta;
+use fastq_comparison::filter::min_length as min_length_filter;
+use fastq_comparison::fidelity::{verify_conversion, FidelityOptions};
+use fastq_comparison::index::{Index, IndexedReader};
+#[cfg(feature = "notify")]
+use fastq_comparison::ingest::{Ingest, IngestError, Pipeline, Status};
+use fastq_comparison::input::Input;
+use fastq_comparison::json::json_string;
+use fastq_comparison::limit::limit;
+use fastq_comparison::paired::{deinterleave_to, interleave, PairError, PairedReader};
+use fastq_comparison::parallel::{par_for_each, par_records, ParallelOptions};
+use fastq_comparison::quality::Encoding;
+#[cfg(feature = "bam")]
+use fastq_comparison::sam::BamWriter;
+use fastq_comparison::sample::{sample_fraction, sample_fragments};
+use fastq_comparison::sketch::{compare_many, SketchOptions};
+use fastq_comparison::stats::{fastq_files, guess_platform, stats_many, PairStatsCollector, Platform, PerReadWriter, StatsCollector};
+use fastq_comparison::trim::{trim_preset, TrimPreset};
+use fastq_comparison::unfancy_parser::{self, HeaderStyle, LineEnding, Reader, WriterOptions};
+use fastq_comparison::validate::{Strictness, Validator};
+
+const USAGE: &str = "\
+Usage: fastq [--error-json <path>] <command> [options] <files>
+
+Commands:
+  validate [--strictness strict|standard|lenient] [--max-issues N] <file>...
+      Report format issues. Exits with 1 if there are any.
+  stats [--quality] [--lengths] [--dinucleotides] [--per-read] <file>...
+  stats --paired [--quality] <r1> <r2>
+  stats --per-file [--threads N] <file or directory>...
+      Print read count, length, GC and quality summary of all files as TSV.
+      With --per-read, print length, GC, mean quality, entropy, DUST score and N count of every read instead.
+      With --paired, print mate length correlation and quality difference along with each mate's summary.
+      With --per-file, print a column per file and the total, reading files on N threads (default: all cores).
+      Directories stand for the .fastq and .fq files in them, optionally gzip compressed.
+  compare [--ignore-quality] [--ignore-desc] [--unordered] [--no-reorder] [--max-reported N]
+          [--encoding-a E] [--encoding-b E] [--json] <a> <b>
+      Compare two files record by record, printing a summary as text or JSON. Exits with 1 if they differ.
+      Records are matched by ID. With --unordered, they may be in any order, otherwise moved records count as a difference.
+      With --no-reorder, moved records are not looked for, which saves memory on huge files.
+      With --paired, compare the mate files <a-r1> <a-r2> <b-r1> <b-r2> by fragment and list fragments that lost a mate.
+      Qualities are compared as Phred+33, given each file's encoding: phred33, phred64 or solexa.
+  verify [--trimmed] [--allow-reordering] [--allow-quality-change] [--allow-desc-change] [--allow-encoding-change]
+         [--encoding-original E] [--encoding-converted E] [--max-reported N] <original> <converted>
+      Check that a conversion kept records, quality encoding and summary statistics. Exits with 1 if not.
+      With --trimmed, reads may be shortened or dropped. Encodings not given are detected.
+  similarity [--k N] [--sketch-size N] [--max-reads N] [--csv] <file>...
+      Print the pairwise k-mer similarity (0 to 1) of files as a table or CSV, e.g. to spot swapped samples.
+      Only the first N reads (default 1000000) of each file are used.
+  checksum [--chunk-records N] [--verify] <file>...
+      Save CRC-32 checksums of every N records (default 100000) of plain files to <file>.crc.
+      With --verify, check files against their checksums and list the byte ranges of corrupt chunks.
+      Exits with 1 if any chunk is corrupt.
+  convert [--to fastq|fasta] [--fill-quality C] [--line-width N] [--crlf]
+          [--header-style original|normalized|casava|id-only] <in> [<out>]
+      Convert between FastQ and FASTA. The output format defaults to the output file extension.
+  demux --samplesheet <sheet> --r1 <r1> --r2 <r2> --out <dir> [--barcode header|start:N]
+        [--max-mismatches N] [--template T] [--dictionary-pairs N]
+      Split read pairs into a file pair per sample by barcode, read from the headers or the first N bases of R1.
+      The sheet has a barcode and a sample per line, separated by a tab or comma.
+      Files are named by the template (default {sample}_{mate}.fastq.gz) and listed in <dir>/manifest.tsv.
+      With a .zst template, files share a dictionary trained on the first N pairs (default 10000, 0 for none),
+      written to <dir>/dictionary.zstd and needed to decompress them, e.g. with zstd -D.
+      Pairs per sample are shown while running if standard error is a terminal, and printed at the end.
+  amplicons --primers <scheme> --out <dir> [--max-mismatches N] [--max-offset N] [--trim-primers] [--template T] <file>...
+      Sort each file's reads into a file per amplicon by the primer pair they start and end with, on either strand.
+      The scheme has an amplicon per line: name, forward and reverse primer (IUPAC codes) and optionally the
+      read lengths as min-max, separated by tabs or commas. Primers may have N mismatches each and start
+      up to N bases from the read ends. Files are named by the template (default {sample}_{amplicon}.fastq.gz),
+      with the sample named after the file. Reads of other lengths or no amplicon go to {sample}_unassigned.
+      Prints the reads per amplicon and sample, which are also written to <dir>/counts.tsv.
+  trim [--preset illumina-dna|nextera|small-rna] [--encoding E] [--min-length N] [-i <in>] [-o <out>]
+      Remove the preset's adapter, then 3' ends below Q20, and drop reads shorter than N
+      (default 20, 18 for small-rna). Prints reads and bases removed to standard error.
+      Without --preset, illumina-dna is used if the read lengths and headers look like Illumina.
+  ingest --out <dir> [--preset illumina-dna|nextera|small-rna] [--encoding E] [--min-length N] [--settle SECS] [--once] <dir>
+      Trim and filter each FastQ file appearing in a directory once it stopped growing for SECS (default 10),
+      writing the reads and their statistics to <dir> and a line per file to <dir>/ledger.tsv, which is also printed.
+      Files in the ledger are skipped unless they changed. Without a preset, reads are only filtered by length.
+      With --once, process the files already there and exit with 1 if any failed. Needs the notify feature.
+  sample -f F [-s SEED] [--paired] [-o <out>] [<in>]
+      Keep each record with probability F, the same ones for the same seed (default 0).
+      With --paired, records are kept by fragment ID: mates in an interleaved file stay together,
+      and R1 and R2 files sampled separately with the same seed keep the same pairs.
+  head [-n N] [-o <out>] [<in>]
+      Copy the first N records (default 10), without reading further.
+  interleave [-o <out>] <r1> <r2>
+  deinterleave -1 <r1> -2 <r2> [<in>]
+      Merge mate files into one with alternating R1 and R2 records, or split such a file.
+      Fails with a parse error if mate IDs do not match or a mate is missing.
+  completions bash|zsh|fish
+      Print a shell completion script, e.g. for ~/.local/share/bash-completion/completions/fastq.
+  selftest
+      Check the compiled features (parsers, gzip, mmap, async, threads, index, zstd, bam) on built-in data.
+      serde and fetch are listed as skipped. Exits with 1 if any check fails.
+
+Use --help-json to list the commands and their options as JSON.
+
+Files can be gzip compressed. Use - for standard input or output.
+Output files ending in .gz are gzip compressed, standard output if the input is.
+
+Exit codes: 0 success, 1 validation failure or difference, 2 parse error, 3 I/O error, 64 usage error.
+With --error-json <path>, errors (exit codes 2, 3 and 64) are also written to the file as JSON:
+{\"exit_code\":3,\"kind\":\"io\",\"io_kind\":\"NotFound\",\"message\":\"...\"}, with io_kind null for other kinds.";
+
+/// The exit code of a successful run.
+const EXIT_OK: i32 = 0;
+/// The exit code if the input is invalid or the compared files differ.
+const EXIT_FAILED: i32 = 1;
+
+quick_error! {
+	#[derive(Debug)]
+	enum CliError {
+		Usage(msg: String) {
+			display("{}", msg)
+		}
+		Parse(msg: String) {
+			display("{}", msg)
+		}
+		Io(err: io::Error) {
+			from()
+			display("{}", err)
+		}
+	}
+}
+
+impl CliError {
+	fn exit_code(&self) -> i32 {
+		match *self {
+			CliError::Parse(_) => 2,
+			CliError::Io(_) => 3,
+			CliError::Usage(_) => 64,
+		}
+	}
+	
+	/// The report written to the `--error-json` file.
+	fn to_json(&self) -> String {
+		let (kind, io_kind) = match *self {
+			CliError::Usage(_) => ("usage", None),
+			CliError::Parse(_) => ("parse", None),
+			CliError::Io(ref e) => ("io", Some(format!("{:?}", e.kind()))),
+		};
+		let io_kind = io_kind.map_or("null".to_owned(), |k| json_string(&k));
+		format!("{{\"exit_code\":{},\"kind\":\"{}\",\"io_kind\":{},\"message\":{}}}", self.exit_code(), kind, io_kind, json_string(&self.to_string()))
+	}
+}
+
+impl From<unfancy_parser::Error> for CliError {
+	fn from(e: unfancy_parser::Error) -> CliError {
+		match e {
+			unfancy_parser::Error::Io(e) => CliError::Io(e),
+			e => CliError::Parse(e.to_string()),
+		}
+	}
+}
+
+impl From<PairError> for CliError {
+	fn from(e: PairError) -> CliError {
+		match e {
+			PairError::Parse(_, _, unfancy_parser::Error::Io(e)) | PairError::Io(e) => CliError::Io(e),
+			e => CliError::Parse(e.to_string()),
+		}
+	}
+}
+
+#[cfg(feature = "notify")]
+impl From<IngestError> for CliError {
+	fn from(e: IngestError) -> CliError {
+		match e {
+			IngestError::Io(e) => CliError::Io(e),
+			e => CliError::Io(io::Error::other(e.to_string())),
+		}
+	}
+}
+
+impl From<fasta::Error> for CliError {
+	fn from(e: fasta::Error) -> CliError {
+		match e {
+			fasta::Error::Io(e) => CliError::Io(e),
+			e => CliError::Parse(e.to_string()),
+		}
+	}
+}
+
+/// Command line arguments split into flags, options with values, and positional arguments.
+struct Args {
+	flags: Vec<String>,
+	options: HashMap<String, String>,
+	positional: Vec<OsString>,
+}
+
+impl Args {
+	/// Parse arguments, treating the names in `with_value` as options taking a value.
+	fn parse<I: Iterator<Item=OsString>>(mut args: I, with_value: &[&str]) -> Result<Args, CliError> {
+		let mut parsed = Args { flags: Vec::new(), options: HashMap::new(), positional: Vec::new() };
+		while let Some(arg) = args.next() {
+			let name = match arg.to_str() {
+				Some(a) if a.starts_with("--") => a[2..].to_owned(),
+				// short options such as -o take a value
+				Some(a) if a.len() == 2 && a.starts_with('-') && with_value.contains(&&a[1..]) => a[1..].to_owned(),
+				_ => { parsed.positional.push(arg); continue },
+			};
+			if name.is_empty() {
+				parsed.positional.extend(args.by_ref());
+			} else if with_value.contains(&name.as_str()) {
+				let value = args.next().and_then(|v| v.into_string().ok())
+					.ok_or_else(|| CliError::Usage(format!("--{} needs a value", name)))?;
+				parsed.options.insert(name, value);
+			} else {
+				parsed.flags.push(name);
+			}
+		}
+		Ok(parsed)
+	}
+	
+	fn flag(&self, name: &str) -> bool {
+		self.flags.iter().any(|f| f == name)
+	}
+	
+	fn number(&self, name: &str) -> Result<Option<usize>, CliError> {
+		self.options.get(name).map(|v| v.parse().map_err(|_| CliError::Usage(format!("--{} needs a number, not {:?}", name, v)))).transpose()
+	}
+	
+	/// Fail on flags the command does not know.
+	fn check_flags(&self, known: &[&str]) -> Result<(), CliError> {
+		match self.flags.iter().find(|f| !known.contains(&f.as_str())) {
+			Some(f) => Err(CliError::Usage(format!("Unknown option --{}", f))),
+			None => Ok(()),
+		}
+	}
+}
+
+fn open(path: &OsStr) -> io::Result<Box<dyn BufRead>> {
+	open_input(path).map(|(reader, _)| reader)
+}
+
+/// Open a file or standard input, and tell if it is gzip compressed.
+fn open_input(path: &OsStr) -> io::Result<(Box<dyn BufRead>, bool)> {
+	if path != "-" {
+		let input = Input::open(path)?;
+		let compressed = input.is_compressed();
+		return Ok((Box::new(io::BufReader::new(input)), compressed));
+	}
+	let mut stdin = io::BufReader::new(io::stdin());
+	let compressed = stdin.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+	#[cfg(feature = "gzip")]
+	if compressed { return Ok((Box::new(io::BufReader::new(MultiGzDecoder::new(stdin))), true)) }
+	Ok((Box::new(stdin), compressed))
+}
+
+fn create(path: Option<&OsStr>) -> io::Result<Box<dyn Write>> {
+	match path {
+		None => Ok(Box::new(io::stdout())),
+		Some(p) if p == "-" => Ok(Box::new(io::stdout())),
+		Some(p) => Ok(Box::new(fs::File::create(p)?)),
+	}
+}
+
+/// A file or standard output records are written to, gzip compressed if asked to.
+enum Output {
+	Plain(Box<dyn Write>),
+	#[cfg(feature = "gzip")]
+	Gzip(GzEncoder<Box<dyn Write>>),
+}
+
+impl Output {
+	/// Create a file, or use standard output for `None` or `-`.
+	/// Files are compressed if their name ends in `.gz`, standard output if `compress_stdout` is set.
+	fn create(path: Option<&OsStr>, compress_stdout: bool) -> io::Result<Output> {
+		let compress = match path {
+			Some(p) if p != "-" => Path::new(p).extension().is_some_and(|e| e == "gz"),
+			_ => compress_stdout,
+		};
+		let out = create(path)?;
+		if !compress { return Ok(Output::Plain(out)) }
+		#[cfg(feature = "gzip")]
+		return Ok(Output::Gzip(GzEncoder::new(out, Compression::default())));
+		#[cfg(not(feature = "gzip"))]
+		Err(io::Error::new(io::ErrorKind::Unsupported, "gzip support is not compiled in (feature \"gzip\")."))
+	}
+	
+	/// Flush the output, completing the compressed stream.
+	fn finish(self) -> io::Result<()> {
+		match self {
+			Output::Plain(mut out) => out.flush(),
+			#[cfg(feature = "gzip")]
+			Output::Gzip(encoder) => encoder.finish()?.flush(),
+		}
+	}
+}
+
+impl Write for Output {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			Output::Plain(out) => out.write(buf),
+			#[cfg(feature = "gzip")]
+			Output::Gzip(encoder) => encoder.write(buf),
+		}
+	}
+	
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			Output::Plain(out) => out.flush(),
+			#[cfg(feature = "gzip")]
+			Output::Gzip(encoder) => encoder.flush(),
+		}
+	}
+}
+
+fn validate(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	let strictness = match args.options.get("strictness").map(String::as_str) {
+		None | Some("standard") => Strictness::Standard,
+		Some("strict") => Strictness::Strict,
+		Some("lenient") => Strictness::Lenient,
+		Some(s) => return Err(CliError::Usage(format!("Unknown strictness {:?}", s))),
+	};
+	let max_issues = args.number("max-issues")?.unwrap_or(usize::MAX);
+	if args.positional.is_empty() { return Err(CliError::Usage("validate needs at least one file".to_owned())) }
+	let validator = Validator::with_strictness(strictness);
+	let mut out = io::stdout().lock();
+	let mut total = 0;
+	for path in &args.positional {
+		let mut issues = validator.issues(open(path)?);
+		let mut count = 0;
+		for issue in &mut issues {
+			let issue = issue?;
+			if count < max_issues { writeln!(out, "{}: {}", path.to_string_lossy(), issue)?; }
+			count += 1;
+		}
+		writeln!(out, "{}: {} records, {} issues", path.to_string_lossy(), issues.records(), count)?;
+		total += count;
+	}
+	Ok(if total == 0 { EXIT_OK } else { EXIT_FAILED })
+}
+
+fn stats(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["quality", "lengths", "dinucleotides", "per-read", "paired", "per-file"])?;
+	if args.positional.is_empty() { return Err(CliError::Usage("stats needs at least one file".to_owned())) }
+	if args.flag("paired") { return pair_stats(args) }
+	if args.flag("per-file") { return file_stats(args) }
+	if args.flag("per-read") {
+		let mut table = PerReadWriter::new(io::stdout().lock());
+		for path in &args.positional {
+			for record in Reader::new(open(path)?).records() {
+				table.write_record(&record?)?;
+			}
+		}
+		table.flush()?;
+		return Ok(EXIT_OK);
+	}
+	let mut collector = StatsCollector::new();
+	for path in &args.positional {
+		for record in Reader::new(open(path)?).records() {
+			collector.add(&record?);
+		}
+	}
+	let report = collector.report();
+	let mut out = io::stdout().lock();
+	report.write_tsv(&mut out)?;
+	if args.flag("quality") {
+		writeln!(out)?;
+		report.write_quality_tsv(&mut out)?;
+	}
+	if args.flag("lengths") {
+		writeln!(out)?;
+		report.write_length_tsv(&mut out)?;
+	}
+	if args.flag("dinucleotides") {
+		writeln!(out)?;
+		report.write_dinucleotide_tsv(&mut out)?;
+	}
+	Ok(EXIT_OK)
+}
+
+fn file_stats(args: Args) -> Result<i32, CliError> {
+	if args.flag("quality") || args.flag("lengths") || args.flag("dinucleotides") || args.flag("per-read") {
+		return Err(CliError::Usage("stats --per-file does not support other options".to_owned()));
+	}
+	let mut paths = Vec::new();
+	for path in &args.positional {
+		if Path::new(path).is_dir() { paths.extend(fastq_files(path)?) } else { paths.push(path.into()) }
+	}
+	let threads = args.number("threads")?.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+	stats_many(&paths, threads)?.write_tsv(io::stdout().lock())?;
+	Ok(EXIT_OK)
+}
+
+fn pair_stats(args: Args) -> Result<i32, CliError> {
+	if args.flag("lengths") || args.flag("dinucleotides") || args.flag("per-read") {
+		return Err(CliError::Usage("stats --paired only supports --quality".to_owned()));
+	}
+	let [r1, r2] = &args.positional[..] else {
+		return Err(CliError::Usage("stats --paired needs exactly two files".to_owned()));
+	};
+	let mut collector = PairStatsCollector::new();
+	for pair in PairedReader::new(Reader::new(open(r1)?).records(), Reader::new(open(r2)?).records()) {
+		let (a, b) = pair?;
+		collector.add(&a, &b);
+	}
+	let report = collector.report();
+	let mut out = io::stdout().lock();
+	report.write_tsv(&mut out)?;
+	if args.flag("quality") {
+		writeln!(out)?;
+		report.write_quality_delta_tsv(&mut out)?;
+	}
+	Ok(EXIT_OK)
+}
+
+fn encoding(args: &Args, name: &str) -> Result<Encoding, CliError> {
+	match args.options.get(name).map(String::as_str) {
+		None | Some("phred33") => Ok(Encoding::Sanger),
+		Some("phred64") => Ok(Encoding::Illumina13),
+		Some("solexa") => Ok(Encoding::Solexa),
+		Some(e) => Err(CliError::Usage(format!("Unknown quality encoding {:?}", e))),
+	}
+}
+
+fn compare_files(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["ignore-quality", "ignore-qual", "ignore-desc", "no-reorder", "unordered", "json", "paired"])?;
+	let ignore_quality = args.flag("ignore-quality") || args.flag("ignore-qual");
+	let mut options = CompareOptions { ignore_quality, ignore_desc: args.flag("ignore-desc"), ..CompareOptions::default() };
+	options.detect_reordering = !args.flag("no-reorder");
+	if let Some(n) = args.number("max-reported")? { options.max_reported = n }
+	options.encoding_a = encoding(&args, "encoding-a")?;
+	options.encoding_b = encoding(&args, "encoding-b")?;
+	if args.flag("paired") { return pair_compare(args, &options) }
+	let (a, b) = match args.positional[..] {
+		[ref a, ref b] => (a, b),
+		_ => return Err(CliError::Usage("compare needs exactly two files".to_owned())),
+	};
+	// compare stops at the first parse error of either file, which is reported instead
+	let (mut error_a, mut error_b) = (None, None);
+	let report = compare(records_until_error(a, &mut error_a)?, records_until_error(b, &mut error_b)?, &options);
+	if let Some(e) = error_a.or(error_b) { return Err(e.into()) }
+	if args.flag("json") { println!("{}", report.to_json()) } else { println!("{}", report) }
+	let same = if args.flag("unordered") { report.is_equivalent() } else { report.is_identical() };
+	Ok(if same { EXIT_OK } else { EXIT_FAILED })
+}
+
+/// The records of a file up to its first parse error, which is kept in `error`.
+fn records_until_error<'e>(path: &OsStr, error: &'e mut Option<unfancy_parser::Error>) -> io::Result<impl Iterator<Item=unfancy_parser::Record> + 'e> {
+	Ok(Reader::new(open(path)?).records().map_while(move |r| r.map_err(|e| *error = Some(e)).ok()))
+}
+
+fn pair_compare(args: Args, options: &CompareOptions) -> Result<i32, CliError> {
+	let [a1, a2, b1, b2] = &args.positional[..] else {
+		return Err(CliError::Usage("compare --paired needs exactly four files: A R1, A R2, B R1 and B R2".to_owned()));
+	};
+	// the comparison stops at the first parse error, which is reported instead
+	let mut errors = [None, None, None, None];
+	let [e1, e2, e3, e4] = &mut errors;
+	let report = compare_pairs(records_until_error(a1, e1)?, records_until_error(a2, e2)?, records_until_error(b1, e3)?, records_until_error(b2, e4)?, options);
+	if let Some(e) = errors.into_iter().flatten().next() { return Err(e.into()) }
+	if args.flag("json") { println!("{}", report.to_json()) } else { print!("{}", report) }
+	let same = if args.flag("unordered") { report.is_equivalent() } else { report.is_identical() };
+	Ok(if same { EXIT_OK } else { EXIT_FAILED })
+}
+
+fn verify(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["trimmed", "allow-reordering", "allow-quality-change", "allow-desc-change", "allow-encoding-change"])?;
+	let (original, converted) = match args.positional[..] {
+		[ref original, ref converted] => (original, converted),
+		_ => return Err(CliError::Usage("verify needs exactly two files".to_owned())),
+	};
+	let mut options = if args.flag("trimmed") { FidelityOptions::trimming() } else { FidelityOptions::recompression() };
+	options.allow_reordering = args.flag("allow-reordering");
+	options.allow_quality_change = args.flag("allow-quality-change");
+	options.allow_desc_change = args.flag("allow-desc-change");
+	options.allow_encoding_change = args.flag("allow-encoding-change");
+	if let Some(n) = args.number("max-reported")? { options.max_reported = n }
+	if args.options.contains_key("encoding-original") { options.encoding_original = Some(encoding(&args, "encoding-original")?) }
+	if args.options.contains_key("encoding-converted") { options.encoding_converted = Some(encoding(&args, "encoding-converted")?) }
+	let report = verify_conversion(original, converted, &options)?;
+	print!("{}", report);
+	Ok(if report.is_faithful() { EXIT_OK } else { EXIT_FAILED })
+}
+
+fn similarity(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["csv"])?;
+	if args.positional.len() < 2 { return Err(CliError::Usage("similarity needs at least two files".to_owned())) }
+	let mut options = SketchOptions::default();
+	if let Some(k) = args.number("k")? { options.k = k }
+	if let Some(n) = args.number("sketch-size")? { options.sketch_size = n }
+	if let Some(n) = args.number("max-reads")? { options.max_reads = Some(n as u64) }
+	let matrix = compare_many(&args.positional, &options)?;
+	if args.flag("csv") {
+		matrix.write_csv(io::stdout().lock())?;
+	} else {
+		print!("{}", matrix);
+	}
+	Ok(EXIT_OK)
+}
+
+fn checksum(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["verify"])?;
+	if args.positional.is_empty() { return Err(CliError::Usage("checksum needs at least one file".to_owned())) }
+	let chunk_records = args.number("chunk-records")?.unwrap_or(100_000) as u64;
+	let mut out = io::stdout().lock();
+	let mut failed = false;
+	for path in &args.positional {
+		let index_path = ChecksumIndex::path_for(path);
+		if !args.flag("verify") {
+			ChecksumIndex::from_file(path, chunk_records)?.save(&index_path)?;
+			continue;
+		}
+		let index = ChecksumIndex::load(&index_path)?;
+		let result = index.verify_file(path)?;
+		let name = Path::new(path).display();
+		for &n in &result.corrupt {
+			let chunk = index.chunks()[n];
+			writeln!(out, "{}: chunk {} corrupt (bytes {}-{}, records {}-{})", name, n,
+				chunk.offset, chunk.offset + chunk.bytes, chunk.first_record, chunk.first_record + chunk.records)?;
+		}
+		if result.trailing_bytes > 0 { writeln!(out, "{}: {} bytes after the last chunk", name, result.trailing_bytes)? }
+		if result.is_ok() { writeln!(out, "{}: ok", name)? }
+		failed |= !result.is_ok();
+	}
+	Ok(if failed { EXIT_FAILED } else { EXIT_OK })
+}
+
+fn convert(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["crlf"])?;
+	let (input, output) = match args.positional[..] {
+		[ref i] => (i, None),
+		[ref i, ref o] => (i, Some(o.as_os_str())),
+		_ => return Err(CliError::Usage("convert needs an input and optionally an output file".to_owned())),
+	};
+	let to_fasta = match args.options.get("to").map(String::as_str) {
+		Some("fasta") => true,
+		Some("fastq") => false,
+		Some(f) => return Err(CliError::Usage(format!("Unknown format {:?}", f))),
+		None => output.and_then(|o| Path::new(o).extension()).and_then(OsStr::to_str)
+			.is_some_and(|e| matches!(e, "fa" | "fasta" | "fna" | "fas")),
+	};
+	let header_style = match args.options.get("header-style").map(String::as_str) {
+		None | Some("original") => HeaderStyle::Original,
+		Some("normalized") => HeaderStyle::Normalized,
+		Some("casava") => HeaderStyle::Casava,
+		Some("id-only") => HeaderStyle::IdOnly,
+		Some(s) => return Err(CliError::Usage(format!("Unknown header style {:?}", s))),
+	};
+	let fill = match args.options.get("fill-quality").map(String::as_bytes) {
+		None => b'I',
+		Some(&[q]) if q.is_ascii_graphic() => q,
+		Some(_) => return Err(CliError::Usage("--fill-quality needs a single quality character".to_owned())),
+	};
+	let line_width = args.number("line-width")?;
+	
+	let mut reader = open(input)?;
+	let is_fasta = reader.fill_buf()?.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'>');
+	let out = create(output)?;
+	if to_fasta {
+		let mut writer = fasta::Writer::new(out);
+		if let Some(w) = line_width { writer = writer.with_line_width(w) }
+		if is_fasta {
+			for record in fasta::Reader::new(reader).records() { writer.write_record(&record?)? }
+		} else {
+			for record in Reader::new(reader).records() { writer.write_record(&record?)? }
+		}
+		writer.flush()?;
+	} else {
+		let line_ending = if args.flag("crlf") { LineEnding::CrLf } else { LineEnding::Lf };
+		let mut writer = unfancy_parser::Writer::with_options(out, WriterOptions { line_ending, header_style, ..WriterOptions::default() });
+		if is_fasta {
+			for record in fasta::Reader::new(reader).records() { writer.write_record(&record?.to_fastq(fill))? }
+		} else {
+			for record in Reader::new(reader).records() { writer.write_record(&record?)? }
+		}
+		writer.flush()?;
+	}
+	Ok(EXIT_OK)
+}
+
+fn demux(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	if !args.positional.is_empty() { return Err(CliError::Usage("demux takes its files as options".to_owned())) }
+	let required = |name: &str| args.options.get(name).ok_or_else(|| CliError::Usage(format!("demux needs --{}", name)));
+	let (sheet, r1, r2, out) = (required("samplesheet")?, required("r1")?, required("r2")?, required("out")?);
+	let source = match args.options.get("barcode").map(String::as_str) {
+		None | Some("header") => BarcodeSource::Header,
+		Some(s) => match s.strip_prefix("start:").and_then(|n| n.parse().ok()) {
+			Some(n) if n > 0 => BarcodeSource::ReadStart(n),
+			_ => return Err(CliError::Usage(format!("Unknown barcode source {:?}", s))),
+		},
+	};
+	let template = args.options.get("template").map_or(PAIR_TEMPLATE, String::as_str);
+	let sheet = SampleSheet::from_file(sheet)?;
+	if sheet.is_empty() { return Err(CliError::Usage("The sample sheet lists no barcodes".to_owned())) }
+	let demultiplexer = Demultiplexer::new(sheet, source).max_mismatches(args.number("max-mismatches")?.unwrap_or(0));
+	#[cfg(feature = "zstd")]
+	let demultiplexer = demultiplexer.zstd_dictionary(args.number("dictionary-pairs")?.unwrap_or(10_000));
+	#[cfg(not(feature = "zstd"))]
+	if args.options.contains_key("dictionary-pairs") { return Err(CliError::Usage("--dictionary-pairs needs the zstd feature".to_owned())) }
+	let pairs = PairedReader::new(Reader::new(open(r1.as_ref())?).records(), Reader::new(open(r2.as_ref())?).records());
+	let live = io::stderr().is_terminal();
+	let show = |counts: &DemuxCounts| if live {
+		let samples: Vec<_> = counts.samples.iter().map(|(sample, n)| format!("{} {}", sample, n)).collect();
+		eprint!("\r{} pairs: {}, undetermined {}", counts.total(), samples.join(", "), counts.undetermined);
+	};
+	let counts = demultiplexer.demux_pairs_to_dir_with_progress(pairs, out, template, 100_000, show);
+	if live { eprintln!() }
+	// a template or sample names that cannot be used are a usage error
+	let counts = counts.map_err(|e| match e {
+		PairError::Io(e) if e.kind() == io::ErrorKind::InvalidInput => CliError::Usage(e.to_string()),
+		e => e.into(),
+	})?;
+	counts.write_manifest(fs::File::create(Path::new(out).join("manifest.tsv"))?, template)?;
+	let mut stdout = io::stdout().lock();
+	writeln!(stdout, "sample\tpairs")?;
+	for (sample, n) in &counts.samples { writeln!(stdout, "{}\t{}", sample, n)? }
+	writeln!(stdout, "undetermined\t{}", counts.undetermined)?;
+	Ok(EXIT_OK)
+}
+
+fn amplicons(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["trim-primers"])?;
+	let required = |name: &str| args.options.get(name).ok_or_else(|| CliError::Usage(format!("amplicons needs --{}", name)));
+	let (primers, out) = (required("primers")?, required("out")?);
+	if args.positional.is_empty() { return Err(CliError::Usage("amplicons needs at least one file".to_owned())) }
+	let scheme = PrimerScheme::from_file(primers)?;
+	if scheme.is_empty() { return Err(CliError::Usage("The primer scheme lists no amplicons".to_owned())) }
+	let binner = AmpliconBinner::new(scheme)
+		.max_mismatches(args.number("max-mismatches")?.unwrap_or(0))
+		.max_offset(args.number("max-offset")?.unwrap_or(0))
+		.trim_primers(args.flag("trim-primers"));
+	let template = args.options.get("template").map_or(AMPLICON_TEMPLATE, String::as_str);
+	let samples = args.positional.iter()
+		.map(|path| Ok((sample_name(path), Reader::new(open(path)?).records())))
+		.collect::<io::Result<Vec<_>>>()?;
+	// a template, sample or amplicon names that cannot be used are a usage error
+	let counts = binner.bin_to_dir(samples, out, template).map_err(|e| match e {
+		unfancy_parser::Error::Io(e) if e.kind() == io::ErrorKind::InvalidInput => CliError::Usage(e.to_string()),
+		e => e.into(),
+	})?;
+	counts.write_tsv(fs::File::create(Path::new(out).join("counts.tsv"))?)?;
+	counts.write_tsv(io::stdout().lock())?;
+	Ok(EXIT_OK)
+}
+
+/// The sample a file is named after: its name without `.gz` and `.fastq` or `.fq`.
+fn sample_name(path: &OsStr) -> String {
+	let name = Path::new(path).file_name().map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+	let name = name.strip_suffix(".gz").unwrap_or(&name);
+	name.strip_suffix(".fastq").or_else(|| name.strip_suffix(".fq")).unwrap_or(name).to_owned()
+}
+
+fn trim(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	if !args.positional.is_empty() { return Err(CliError::Usage("trim takes its files as -i and -o".to_owned())) }
+	let (reader, compressed) = open_input(args.options.get("i").map_or(OsStr::new("-"), OsStr::new))?;
+	let mut records = Reader::new(reader).records();
+	// without a preset, the first reads tell the platform and are then trimmed like the rest
+	let mut head = Vec::new();
+	let preset = match args.options.get("preset") {
+		Some(name) => TrimPreset::from_name(name).ok_or_else(|| CliError::Usage(format!("Unknown preset {:?}", name)))?,
+		None => {
+			head.extend(records.by_ref().take(PLATFORM_SAMPLE));
+			let preset = guess_preset(&head)?;
+			eprintln!("preset:                  {} (guessed from the reads)", preset.name());
+			preset
+		}
+	};
+	let encoding = encoding(&args, "encoding")?;
+	let min_length = args.number("min-length")?.unwrap_or(preset.min_length());
+	let mut trimmed = trim_preset(head.into_iter().chain(records), preset, encoding);
+	let mut filtered = min_length_filter(&mut trimmed, min_length);
+	write_records(&args, &mut filtered, compressed)?;
+	let filter_stats = filtered.stats();
+	let trim_stats = trimmed.stats();
+	eprintln!("reads:                   {}", trim_stats.reads);
+	eprintln!("reads trimmed:           {}", trim_stats.trimmed);
+	eprintln!("bases trimmed:           {}", trim_stats.bases_removed);
+	eprintln!("reads too short:         {}", filter_stats.discarded);
+	eprintln!("reads written:           {}", filter_stats.reads - filter_stats.discarded);
+	Ok(EXIT_OK)
+}
+
+#[cfg(feature = "notify")]
+fn ingest(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["once"])?;
+	let input = match args.positional[..] {
+		[ref dir] => dir,
+		_ => return Err(CliError::Usage("ingest watches a single directory".to_owned())),
+	};
+	let out = args.options.get("out").ok_or_else(|| CliError::Usage("ingest needs --out".to_owned()))?;
+	let mut pipeline = match args.options.get("preset") {
+		Some(name) => Pipeline::preset(TrimPreset::from_name(name).ok_or_else(|| CliError::Usage(format!("Unknown preset {:?}", name)))?),
+		None => Pipeline::default(),
+	};
+	pipeline.encoding = encoding(&args, "encoding")?;
+	if let Some(n) = args.number("min-length")? { pipeline.min_length = n }
+	let once = args.flag("once");
+	let settle = if once { Duration::ZERO } else { Duration::from_secs(args.number("settle")?.unwrap_or(10) as u64) };
+	let mut ingest = Ingest::new(input, out, pipeline).map_err(|e| match e.kind() {
+		io::ErrorKind::InvalidInput => CliError::Usage(e.to_string()),
+		_ => e.into(),
+	})?.settle(settle);
+	let mut stdout = io::stdout().lock();
+	if once {
+		let entries = ingest.process_pending()?;
+		for entry in &entries { writeln!(stdout, "{}", entry)? }
+		return Ok(if entries.iter().all(|e| e.status == Status::Done) { EXIT_OK } else { EXIT_FAILED })
+	}
+	// runs until printing fails, e.g. because the pipe was closed
+	let mut printed = Ok(());
+	ingest.watch(|entry| {
+		printed = writeln!(stdout, "{}", entry).and_then(|()| stdout.flush());
+		printed.is_ok()
+	})?;
+	printed?;
+	Ok(EXIT_OK)
+}
+
+#[cfg(not(feature = "notify"))]
+fn ingest(_args: Args) -> Result<i32, CliError> {
+	Err(CliError::Usage("ingest needs the notify feature".to_owned()))
+}
+
+/// The number of reads the platform is guessed from if a command’s preset is not given.
+const PLATFORM_SAMPLE: usize = 10_000;
+
+/// The trim preset for the platform the reads look like they are from, see `guess_platform`.
+fn guess_preset(reads: &[Result<unfancy_parser::Record, unfancy_parser::Error>]) -> Result<TrimPreset, CliError> {
+	let mut stats = StatsCollector::new();
+	for record in reads.iter().flatten() { stats.add(record) }
+	let header = reads.iter().flatten().next().map(|r| match r.desc() {
+		Some(desc) => format!("{} {}", r.id().unwrap_or(""), desc),
+		None => r.id().unwrap_or("").to_owned(),
+	});
+	match guess_platform(&stats.report(), header.as_deref()) {
+		Some(Platform::Illumina) => Ok(TrimPreset::IlluminaDna),
+		Some(platform) => Err(CliError::Usage(format!("The reads look like {}, which no preset is for, give --preset", platform))),
+		None => Err(CliError::Usage("Cannot tell the platform from the reads, give --preset".to_owned())),
+	}
+}
+
+/// The input of commands reading one file: a positional argument or standard input.
+fn single_input<'a>(args: &'a Args, command: &str) -> Result<&'a OsStr, CliError> {
+	match args.positional[..] {
+		[] => Ok(OsStr::new("-")),
+		[ref path] => Ok(path),
+		_ => Err(CliError::Usage(format!("{} reads a single file", command))),
+	}
+}
+
+/// Write records to `-o` or standard output, which is compressed if the input is.
+fn write_records<I>(args: &Args, records: I, compressed: bool) -> Result<(), CliError> where I: IntoIterator<Item=Result<unfancy_parser::Record, unfancy_parser::Error>> {
+	let mut writer = unfancy_parser::Writer::new(Output::create(args.options.get("o").map(OsStr::new), compressed)?);
+	for record in records { writer.write_record(&record?)? }
+	writer.into_inner()?.finish()?;
+	Ok(())
+}
+
+fn sample(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&["paired"])?;
+	let fraction = match args.options.get("f").map(|f| f.parse::<f64>()) {
+		None => return Err(CliError::Usage("sample needs -f".to_owned())),
+		Some(Ok(f)) if (0. ..=1.).contains(&f) => f,
+		Some(_) => return Err(CliError::Usage("-f needs a fraction from 0 to 1".to_owned())),
+	};
+	let seed = args.number("s")?.unwrap_or(0) as u64;
+	let (reader, compressed) = open_input(single_input(&args, "sample")?)?;
+	let records = Reader::new(reader).records();
+	if args.flag("paired") {
+		write_records(&args, sample_fragments(records, fraction, seed), compressed)?;
+	} else {
+		write_records(&args, sample_fraction(records, fraction, seed), compressed)?;
+	}
+	Ok(EXIT_OK)
+}
+
+fn head(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	let n = args.number("n")?.unwrap_or(10);
+	let (reader, compressed) = open_input(single_input(&args, "head")?)?;
+	write_records(&args, limit(Reader::new(reader).records()).max_records(n), compressed)?;
+	Ok(EXIT_OK)
+}
+
+fn interleave_files(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	let [r1, r2] = &args.positional[..] else {
+		return Err(CliError::Usage("interleave needs exactly two files".to_owned()));
+	};
+	let ((r1, compressed1), (r2, compressed2)) = (open_input(r1)?, open_input(r2)?);
+	let mut writer = unfancy_parser::Writer::new(Output::create(args.options.get("o").map(OsStr::new), compressed1 || compressed2)?);
+	for record in interleave(Reader::new(r1).records(), Reader::new(r2).records()) { writer.write_record(&record?)? }
+	writer.into_inner()?.finish()?;
+	Ok(EXIT_OK)
+}
+
+fn deinterleave_file(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	let required = |name: &str| args.options.get(name).map(OsStr::new).ok_or_else(|| CliError::Usage(format!("deinterleave needs -{}", name)));
+	let (path1, path2) = (required("1")?, required("2")?);
+	let (reader, _) = open_input(single_input(&args, "deinterleave")?)?;
+	let mut r1 = unfancy_parser::Writer::new(Output::create(Some(path1), false)?);
+	let mut r2 = unfancy_parser::Writer::new(Output::create(Some(path2), false)?);
+	deinterleave_to(Reader::new(reader).records(), &mut r1, &mut r2)?;
+	r1.into_inner()?.finish()?;
+	r2.into_inner()?.finish()?;
+	Ok(EXIT_OK)
+}
+
+/// Embedded inputs of `selftest`: eight records, and the same as two concatenated gzip members.
+const FIXTURE: &str = example::FASTQ;
+#[cfg(feature = "gzip")]
+const FIXTURE_GZ: &[u8] = example::FASTQ_GZ;
+
+/// What `selftest` compares records by.
+type Fields = (String, Vec<u8>, Vec<u8>);
+
+fn fields<R: RecordTrait>(record: &R) -> Fields {
+	(record.header().into_owned(), record.seq().to_vec(), record.qual().to_vec())
+}
+
+/// Check that `records` are the fixture records, describing them if so.
+fn same_records(records: &[Fields], expected: &[Fields]) -> Result<String, String> {
+	if records.len() != expected.len() {
+		return Err(format!("read {} records instead of {}", records.len(), expected.len()));
+	}
+	match records.iter().zip(expected).position(|(r, e)| r != e) {
+		Some(i) => Err(format!("record {} differs", i + 1)),
+		None => Ok(format!("{} records", records.len())),
+	}
+}
+
+fn check_parsers(expected: &[Fields]) -> Result<String, String> {
+	let fancy = FastqReader::new(FIXTURE.as_bytes()).map(|r| r.map(|r| fields(&r)))
+		.collect::<Result<Vec<_>, _>>().map_err(|e| format!("fancy: {}", e))?;
+	same_records(&fancy, expected).map_err(|e| format!("fancy: {}", e))?;
+	let mut reader = borrowed_parser::Reader::new(FIXTURE.as_bytes());
+	let mut borrowed = Vec::new();
+	while let Some(record) = reader.next() {
+		borrowed.push(fields(&record.map_err(|e| format!("borrowed: {}", e))?.to_owned_record()));
+	}
+	same_records(&borrowed, expected).map_err(|e| format!("borrowed: {}", e))?;
+	Ok("fancy, unfancy and borrowed agree".to_owned())
+}
+
+#[cfg(feature = "gzip")]
+fn check_gzip(dir: &Path, expected: &[Fields]) -> Result<String, String> {
+	let path = dir.join("selftest.fastq.gz");
+	fs::write(&path, FIXTURE_GZ).map_err(|e| e.to_string())?;
+	let input = Input::open(&path).map_err(|e| e.to_string())?;
+	if !input.is_compressed() { return Err("not detected as compressed".to_owned()) }
+	let records = Reader::new(io::BufReader::new(input)).records().map(|r| r.map(|r| fields(&r)))
+		.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?;
+	same_records(&records, expected).map(|n| format!("{} from multi-member gzip", n))
+}
+
+#[cfg(feature = "mmap")]
+fn check_mmap(path: &Path, expected: &[Fields]) -> Result<String, String> {
+	let mut reader = borrowed_parser::Reader::from_mmap(path).map_err(|e| e.to_string())?;
+	let mut records = Vec::new();
+	while let Some(record) = reader.next() {
+		records.push(fields(&record.map_err(|e| e.to_string())?.to_owned_record()));
+	}
+	same_records(&records, expected)
+}
+
+#[cfg(feature = "async")]
+fn check_async(expected: &[Fields]) -> Result<String, String> {
+	use std::future::Future;
+	use std::task::{Context, Poll, Waker};
+	
+	let mut reader = AsyncReader::new(FIXTURE.as_bytes());
+	let mut context = Context::from_waker(Waker::noop());
+	let mut records = Vec::new();
+	loop {
+		// reading from a slice never waits, so every read is ready on the first poll
+		let next = match std::pin::pin!(reader.read()).poll(&mut context) {
+			Poll::Ready(next) => next.map_err(|e| e.to_string())?,
+			Poll::Pending => return Err("reading from memory did not complete".to_owned()),
+		};
+		match next {
+			Some(record) => records.push(fields(&record)),
+			None => break,
+		}
+	}
+	same_records(&records, expected)
+}
+
+#[cfg(feature = "zstd")]
+fn check_zstd(expected: &[Fields]) -> Result<String, String> {
+	let records = Reader::new(FIXTURE.as_bytes()).records().collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?;
+	for (name, codec) in [("fastq", &ZstdFramed::new(codec::Fastq) as &dyn SeqCodec), ("2-bit", &ZstdFramed::new(codec::TwoBit))] {
+		let encoded = codec.encode_to_vec(&records).map_err(|e| format!("{}: {}", name, e))?;
+		let decoded = codec.decode(&encoded).map_err(|e| format!("{}: {}", name, e))?;
+		same_records(&decoded.iter().map(fields).collect::<Vec<_>>(), expected).map_err(|e| format!("{}: {}", name, e))?;
+	}
+	Ok("fastq and 2-bit batches compressed and restored".to_owned())
+}
+
+/// Write the fixture as BAM, and check that it decompresses to a BAM file with the fixture’s read names.
+#[cfg(feature = "bam")]
+fn check_bam(expected: &[Fields]) -> Result<String, String> {
+	use std::io::Read;
+	
+	let mut writer = BamWriter::new(Vec::new());
+	for record in Reader::new(FIXTURE.as_bytes()).records() {
+		writer.write_record(&record.map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+	}
+	let compressed = writer.finish().map_err(|e| e.to_string())?;
+	let mut bam = Vec::new();
+	flate2::read::MultiGzDecoder::new(&compressed[..]).read_to_end(&mut bam).map_err(|e| format!("BGZF: {}", e))?;
+	let u32_at = |at: usize| bam.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+	if !bam.starts_with(b"BAM\x01") { return Err("no BAM magic".to_owned()) }
+	let truncated = || "truncated".to_owned();
+	// skip the header text and the reference sequences
+	let mut at = 8 + u32_at(4).ok_or_else(truncated)?;
+	for _ in 0..u32_at(at).ok_or_else(truncated)? {
+		at += 4;
+		at += 4 + u32_at(at).ok_or_else(truncated)?;
+	}
+	at += 4;
+	let mut names = Vec::new();
+	while at < bam.len() {
+		let size = u32_at(at).ok_or_else(truncated)?;
+		let name_len = *bam.get(at + 12).ok_or_else(truncated)? as usize;
+		let name = bam.get(at + 36..at + 35 + name_len).ok_or_else(truncated)?;
+		names.push(String::from_utf8_lossy(name).into_owned());
+		at += 4 + size;
+	}
+	let ids: Vec<_> = expected.iter().map(|(header, ..)| header.split(' ').next().unwrap_or("")).collect();
+	if names != ids { return Err(format!("read {} records with names {:?}", names.len(), names)) }
+	Ok(format!("{} records written as BGZF", names.len()))
+}
+
+fn check_threads(path: &Path, expected: &[Fields]) -> Result<String, String> {
+	let options = ParallelOptions { threads: 2, chunk_size: 256, stall_timeout: Some(Duration::from_secs(10)) };
+	let records = par_records(io::Cursor::new(FIXTURE), &options).map(|r| r.map(|r| fields(&r)))
+		.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?;
+	same_records(&records, expected).map_err(|e| format!("par_records: {}", e))?;
+	let seen = AtomicUsize::new(0);
+	let n = par_for_each(path, 2, |_| { seen.fetch_add(1, Ordering::Relaxed); }).map_err(|e| e.to_string())?;
+	if n != expected.len() || seen.into_inner() != n {
+		return Err(format!("par_for_each: read {} records instead of {}", n, expected.len()));
+	}
+	Ok(format!("{} records on 2 threads, in order", n))
+}
+
+fn check_index(dir: &Path, path: &Path, expected: &[Fields]) -> Result<String, String> {
+	let index = Index::from_file(path, 3).map_err(|e| e.to_string())?;
+	if index.len() != expected.len() { return Err(format!("indexed {} records instead of {}", index.len(), expected.len())) }
+	let index_path = dir.join("selftest.fqi");
+	index.save(&index_path).map_err(|e| format!("save: {}", e))?;
+	let loaded = Index::load(&index_path).map_err(|e| format!("load: {}", e))?;
+	if loaded != index { return Err("the loaded index differs from the saved one".to_owned()) }
+	let mut reader = IndexedReader::new(fs::File::open(path).map_err(|e| e.to_string())?, loaded);
+	for (n, want) in expected.iter().enumerate().rev() {
+		let record = reader.fetch(n).map_err(|e| e.to_string())?;
+		if record.as_ref().map(fields).as_ref() != Some(want) { return Err(format!("fetching record {} failed", n + 1)) }
+	}
+	let (last_id, ..) = &expected[expected.len() - 1];
+	let last_id = last_id.split(' ').next().unwrap_or("");
+	let record = reader.fetch_by_id(last_id).map_err(|e| e.to_string())?;
+	if record.as_ref().and_then(RecordTrait::id) != Some(last_id) { return Err(format!("fetching {} by ID failed", last_id)) }
+	Ok("written, read back and fetched from".to_owned())
+}
+
+/// The outcome of a `selftest` check.
+enum Check {
+	Passed(String),
+	Failed(String),
+	/// Compiled in, but not checked by `selftest` for this reason.
+	Skipped(&'static str),
+	/// Not compiled in.
+	Missing,
+}
+
+impl From<Result<String, String>> for Check {
+	fn from(result: Result<String, String>) -> Check {
+		result.map_or_else(Check::Failed, Check::Passed)
+	}
+}
+
+fn selftest(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	if !args.positional.is_empty() { return Err(CliError::Usage("selftest takes no files".to_owned())) }
+	let expected = Reader::new(FIXTURE.as_bytes()).records().map(|r| r.map(|r| fields(&r))).collect::<Result<Vec<_>, _>>()?;
+	let dir = std::env::temp_dir().join(format!("fastq-selftest-{}", process::id()));
+	fs::create_dir_all(&dir)?;
+	let path = dir.join("selftest.fastq");
+	fs::write(&path, FIXTURE)?;
+	
+	let checks: Vec<(&str, Check)> = vec![
+		("parsers", check_parsers(&expected).into()),
+		#[cfg(feature = "gzip")]
+		("gzip", check_gzip(&dir, &expected).into()),
+		#[cfg(not(feature = "gzip"))]
+		("gzip", Check::Missing),
+		#[cfg(feature = "mmap")]
+		("mmap", check_mmap(&path, &expected).into()),
+		#[cfg(not(feature = "mmap"))]
+		("mmap", Check::Missing),
+		#[cfg(feature = "async")]
+		("async", check_async(&expected).into()),
+		#[cfg(not(feature = "async"))]
+		("async", Check::Missing),
+		("threads", check_threads(&path, &expected).into()),
+		("index", check_index(&dir, &path, &expected).into()),
+		#[cfg(feature = "zstd")]
+		("zstd", check_zstd(&expected).into()),
+		#[cfg(not(feature = "zstd"))]
+		("zstd", Check::Missing),
+		#[cfg(feature = "bam")]
+		("bam", check_bam(&expected).into()),
+		#[cfg(not(feature = "bam"))]
+		("bam", Check::Missing),
+		("serde", if cfg!(feature = "serde") { Check::Skipped("needs a serializer such as serde_json") } else { Check::Missing }),
+		("fetch", if cfg!(feature = "fetch") { Check::Skipped("needs network access") } else { Check::Missing }),
+	];
+	let _ = fs::remove_dir_all(&dir);
+	
+	let mut out = io::stdout().lock();
+	let mut failed = 0;
+	for (name, check) in &checks {
+		match check {
+			Check::Passed(detail) => writeln!(out, "{:<8} ok      {}", name, detail)?,
+			Check::Failed(e) => { writeln!(out, "{:<8} FAILED  {}", name, e)?; failed += 1 },
+			Check::Skipped(reason) => writeln!(out, "{:<8} skipped {}", name, reason)?,
+			Check::Missing => writeln!(out, "{:<8} -       not compiled in", name)?,
+		}
+	}
+	Ok(if failed == 0 { EXIT_OK } else { EXIT_FAILED })
+}
+
+/// Remove `--error-json <path>` from the arguments before `--`, and return the path.
+fn take_error_json(args: &mut Vec<OsString>) -> Result<Option<OsString>, CliError> {
+	let end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+	let Some(at) = args[..end].iter().position(|a| a == "--error-json") else { return Ok(None) };
+	if at + 1 >= end { return Err(CliError::Usage("--error-json needs a value".to_owned())) }
+	let path = args.remove(at + 1);
+	args.remove(at);
+	Ok(Some(path))
+}
+
+/// An option of a command, as listed by `--help-json` and the shell completions.
+struct OptionSpec {
+	/// The name without dashes. Single characters are short options.
+	name: &'static str,
+	/// What the value is, `None` for flags.
+	value: Option<&'static str>,
+	/// The values it can take, if there are few.
+	choices: &'static [&'static str],
+	/// The value is a file or directory.
+	path: bool,
+}
+
+impl OptionSpec {
+	fn dashed(&self) -> String {
+		if self.name.len() == 1 { format!("-{}", self.name) } else { format!("--{}", self.name) }
+	}
+}
+
+const fn flag(name: &'static str) -> OptionSpec { OptionSpec { name, value: None, choices: &[], path: false } }
+const fn value(name: &'static str, value: &'static str) -> OptionSpec { OptionSpec { name, value: Some(value), choices: &[], path: false } }
+const fn path(name: &'static str, value: &'static str) -> OptionSpec { OptionSpec { name, value: Some(value), choices: &[], path: true } }
+const fn choice(name: &'static str, value: &'static str, choices: &'static [&'static str]) -> OptionSpec {
+	OptionSpec { name, value: Some(value), choices, path: false }
+}
+
+const ENCODINGS: &[&str] = &["phred33", "phred64", "solexa"];
+
+/// A command: how to parse its arguments, what to run, and how to describe it.
+struct CommandSpec {
+	name: &'static str,
+	summary: &'static str,
+	options: &'static [OptionSpec],
+	/// The positional arguments, as in the usage.
+	arguments: &'static str,
+	run: fn(Args) -> Result<i32, CliError>,
+}
+
+const COMMANDS: &[CommandSpec] = &[
+	CommandSpec { name: "validate", summary: "Report format issues", arguments: "<file>...", run: validate,
+		options: &[choice("strictness", "strictness", &["strict", "standard", "lenient"]), value("max-issues", "N")] },
+	CommandSpec { name: "stats", summary: "Summarize files as TSV", arguments: "<file>...", run: stats,
+		options: &[flag("quality"), flag("lengths"), flag("dinucleotides"), flag("per-read"), flag("paired"), flag("per-file"), value("threads", "N")] },
+	CommandSpec { name: "compare", summary: "Compare two files record by record", arguments: "<a> <b>", run: compare_files,
+		options: &[flag("ignore-quality"), flag("ignore-qual"), flag("ignore-desc"), flag("unordered"), flag("no-reorder"), flag("json"), flag("paired"),
+			value("max-reported", "N"), choice("encoding-a", "E", ENCODINGS), choice("encoding-b", "E", ENCODINGS)] },
+	CommandSpec { name: "verify", summary: "Check that a conversion kept what it had to", arguments: "<original> <converted>", run: verify,
+		options: &[flag("trimmed"), flag("allow-reordering"), flag("allow-quality-change"), flag("allow-desc-change"), flag("allow-encoding-change"),
+			choice("encoding-original", "E", ENCODINGS), choice("encoding-converted", "E", ENCODINGS), value("max-reported", "N")] },
+	CommandSpec { name: "similarity", summary: "Print the pairwise k-mer similarity of files", arguments: "<file>...", run: similarity,
+		options: &[value("k", "N"), value("sketch-size", "N"), value("max-reads", "N"), flag("csv")] },
+	CommandSpec { name: "checksum", summary: "Save or verify checksums of record chunks", arguments: "<file>...", run: checksum,
+		options: &[value("chunk-records", "N"), flag("verify")] },
+	CommandSpec { name: "convert", summary: "Convert between FastQ and FASTA", arguments: "<in> [<out>]", run: convert,
+		options: &[choice("to", "format", &["fastq", "fasta"]), value("fill-quality", "C"), value("line-width", "N"), flag("crlf"),
+			choice("header-style", "style", &["original", "normalized", "casava", "id-only"])] },
+	CommandSpec { name: "demux", summary: "Split read pairs into files per sample by barcode", arguments: "", run: demux,
+		options: &[path("samplesheet", "sheet"), path("r1", "r1"), path("r2", "r2"), path("out", "dir"), value("barcode", "header|start:N"),
+			value("max-mismatches", "N"), value("template", "T"), value("dictionary-pairs", "N")] },
+	CommandSpec { name: "amplicons", summary: "Sort reads into files per amplicon by primer pair", arguments: "<file>...", run: amplicons,
+		options: &[path("primers", "scheme"), path("out", "dir"), value("max-mismatches", "N"), value("max-offset", "N"), flag("trim-primers"),
+			value("template", "T")] },
+	CommandSpec { name: "trim", summary: "Remove adapters and low-quality ends", arguments: "", run: trim,
+		options: &[choice("preset", "preset", &["illumina-dna", "nextera", "small-rna"]), choice("encoding", "E", ENCODINGS),
+			value("min-length", "N"), path("i", "in"), path("o", "out")] },
+	CommandSpec { name: "ingest", summary: "Process FastQ files as they appear in a directory", arguments: "<dir>", run: ingest,
+		options: &[path("out", "dir"), choice("preset", "preset", &["illumina-dna", "nextera", "small-rna"]), choice("encoding", "E", ENCODINGS),
+			value("min-length", "N"), value("settle", "SECS"), flag("once")] },
+	CommandSpec { name: "sample", summary: "Keep a random fraction of records", arguments: "[<in>]", run: sample,
+		options: &[value("f", "F"), value("s", "SEED"), flag("paired"), path("o", "out")] },
+	CommandSpec { name: "head", summary: "Copy the first records", arguments: "[<in>]", run: head,
+		options: &[value("n", "N"), path("o", "out")] },
+	CommandSpec { name: "interleave", summary: "Merge mate files into one", arguments: "<r1> <r2>", run: interleave_files,
+		options: &[path("o", "out")] },
+	CommandSpec { name: "deinterleave", summary: "Split an interleaved file into mate files", arguments: "[<in>]", run: deinterleave_file,
+		options: &[path("1", "r1"), path("2", "r2")] },
+	CommandSpec { name: "selftest", summary: "Check the compiled features on built-in data", arguments: "", run: selftest, options: &[] },
+	CommandSpec { name: "completions", summary: "Print a shell completion script", arguments: "bash|zsh|fish", run: completions, options: &[] },
+	CommandSpec { name: "help", summary: "Print the usage", arguments: "", run: help, options: &[] },
+];
+
+fn help(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	println!("{}", USAGE);
+	Ok(EXIT_OK)
+}
+
+/// The commands and their options as JSON, for tools wrapping this one.
+fn help_json() -> String {
+	let strings = |values: &[&str]| values.iter().map(|v| json_string(v)).collect::<Vec<_>>().join(",");
+	let commands: Vec<String> = COMMANDS.iter().map(|command| {
+		let options: Vec<String> = command.options.iter().map(|o| format!("{{\"name\":{},\"value\":{},\"choices\":[{}],\"path\":{}}}",
+			json_string(&o.dashed()), o.value.map_or("null".to_owned(), json_string), strings(o.choices), o.path)).collect();
+		format!("{{\"name\":{},\"summary\":{},\"arguments\":{},\"options\":[{}]}}",
+			json_string(command.name), json_string(command.summary), json_string(command.arguments), options.join(","))
+	}).collect();
+	format!("{{\"name\":\"fastq\",\"global_options\":[{{\"name\":\"--error-json\",\"value\":\"path\",\"choices\":[],\"path\":true}}],\
+		\"exit_codes\":{{\"0\":\"success\",\"1\":\"validation failure or difference\",\"2\":\"parse error\",\"3\":\"I/O error\",\"64\":\"usage error\"}},\
+		\"commands\":[{}]}}", commands.join(","))
+}
+
+fn bash_completions() -> String {
+	let names: Vec<_> = COMMANDS.iter().map(|c| c.name).collect();
+	let mut script = format!("_fastq() {{\n\
+		\tlocal cur=${{COMP_WORDS[COMP_CWORD]}} prev=${{COMP_WORDS[COMP_CWORD-1]}} opts\n\
+		\tif [ \"$COMP_CWORD\" -eq 1 ]; then COMPREPLY=($(compgen -W \"--error-json {}\" -- \"$cur\")); return; fi\n\
+		\tcase \"${{COMP_WORDS[1]}}\" in\n", names.join(" "));
+	for command in COMMANDS {
+		let options: Vec<_> = command.options.iter().map(OptionSpec::dashed).collect();
+		script += &format!("\t\t{})\n", command.name);
+		for option in command.options.iter().filter(|o| !o.choices.is_empty()) {
+			script += &format!("\t\t\t[ \"$prev\" = {} ] && {{ COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return; }}\n", option.dashed(), option.choices.join(" "));
+		}
+		if command.name == "completions" { script += "\t\t\tCOMPREPLY=($(compgen -W \"bash zsh fish\" -- \"$cur\")); return;;\n" }
+		else { script += &format!("\t\t\topts=\"{}\";;\n", options.join(" ")) }
+	}
+	script += "\tesac\n\
+		\tif [[ $cur == -* ]]; then COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\")); else COMPREPLY=($(compgen -f -- \"$cur\")); fi\n\
+		}\n\
+		complete -o filenames -F _fastq fastq\n";
+	script
+}
+
+fn zsh_completions() -> String {
+	let quote = |s: &str| s.replace('\'', "'\\''").replace(':', "\\:");
+	let mut script = "#compdef fastq\n\n_fastq() {\n\tlocal -a commands\n\tcommands=(\n".to_owned();
+	for command in COMMANDS { script += &format!("\t\t'{}:{}'\n", command.name, quote(command.summary)) }
+	script += "\t)\n\tif (( CURRENT == 2 )); then _describe command commands; return; fi\n\
+		\twords=(${words[2,-1]})\n\t(( CURRENT-- ))\n\tcase $words[1] in\n";
+	for command in COMMANDS {
+		let mut specs: Vec<String> = command.options.iter().map(|o| match o.value {
+			None => format!("'{}'", o.dashed()),
+			Some(value) => format!("'{}:{}:{}'", o.dashed(), quote(value), match o.choices {
+				[] if o.path => "_files".to_owned(),
+				[] => " ".to_owned(),
+				choices => format!("({})", choices.join(" ")),
+			}),
+		}).collect();
+		specs.push(if command.name == "completions" { "'1:shell:(bash zsh fish)'".to_owned() } else { "'*:file:_files'".to_owned() });
+		script += &format!("\t\t{}) _arguments {} ;;\n", command.name, specs.join(" "));
+	}
+	script += "\tesac\n}\n\n_fastq \"$@\"\n";
+	script
+}
+
+fn fish_completions() -> String {
+	let mut script = "complete -c fastq -f\ncomplete -c fastq -n __fish_use_subcommand -l error-json -r -F\n".to_owned();
+	for command in COMMANDS {
+		script += &format!("complete -c fastq -n __fish_use_subcommand -a {} -d '{}'\n", command.name, command.summary.replace('\'', "\\'"));
+		let when = format!("complete -c fastq -n '__fish_seen_subcommand_from {}'", command.name);
+		for option in command.options {
+			let name = if option.name.len() == 1 { format!("-s {}", option.name) } else { format!("-l {}", option.name) };
+			script += &match (option.value, option.choices) {
+				(None, _) => format!("{} {}\n", when, name),
+				(Some(_), []) if option.path => format!("{} {} -r -F\n", when, name),
+				(Some(_), []) => format!("{} {} -x\n", when, name),
+				(Some(_), choices) => format!("{} {} -x -a '{}'\n", when, name, choices.join(" ")),
+			};
+		}
+		script += &if command.name == "completions" { format!("{} -a 'bash zsh fish'\n", when) } else { format!("{} -F\n", when) };
+	}
+	script
+}
+
+fn completions(args: Args) -> Result<i32, CliError> {
+	args.check_flags(&[])?;
+	let script = match args.positional.first().and_then(|s| s.to_str()) {
+		Some("bash") if args.positional.len() == 1 => bash_completions(),
+		Some("zsh") if args.positional.len() == 1 => zsh_completions(),
+		Some("fish") if args.positional.len() == 1 => fish_completions(),
+		_ => return Err(CliError::Usage("completions needs a shell: bash, zsh or fish".to_owned())),
+	};
+	print!("{}", script);
+	Ok(EXIT_OK)
+}
+
+fn run(args: Vec<OsString>) -> Result<i32, CliError> {
+	let mut args = args.into_iter();
+	let command = args.next().and_then(|c| c.into_string().ok()).unwrap_or_default();
+	let name = match command.as_str() {
+		"--help" | "-h" => "help",
+		"--help-json" => { println!("{}", help_json()); return Ok(EXIT_OK) },
+		"" => return Err(CliError::Usage("No command given".to_owned())),
+		c => c,
+	};
+	let command = COMMANDS.iter().find(|c| c.name == name).ok_or_else(|| CliError::Usage(format!("Unknown command {:?}", name)))?;
+	let with_value: Vec<&str> = command.options.iter().filter(|o| o.value.is_some()).map(|o| o.name).collect();
+	(command.run)(Args::parse(args, &with_value)?)
+}
+
+fn main() {
+	let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+	let (error_json, result) = match take_error_json(&mut args) {
+		Ok(path) => (path, run(args)),
+		Err(e) => (None, Err(e)),
+	};
+	let code = match result {
+		Ok(code) => code,
+		Err(e) => {
+			eprintln!("fastq: {}", e);
+			if let CliError::Usage(_) = e { eprintln!("\n{}", USAGE) }
+			if let Some(path) = error_json {
+				if let Err(write_error) = fs::write(&path, e.to_json() + "\n") {
+					eprintln!("fastq: could not write {}: {}", Path::new(&path).display(), write_error);
+				}
+			}
+			e.exit_code()
+		},
+	};
+	process::exit(code);
+}
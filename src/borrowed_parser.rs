@@ -0,0 +1,395 @@
+//! A zero-copy parser whose records borrow from the reader’s buffer.
+//!
+//! `Reader::next` hands out `RefRecord`s pointing into an internal buffer,
+//! so iterating does not allocate per record.
+//! A record is only valid until the next call; use `RefRecord::to_owned_record` to keep it.
+//!
+//! With the `mmap` feature, `Reader::from_mmap` parses a memory-mapped file in place,
+//! without copying it into a buffer at all.
+
+use std::borrow::Cow;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use super::{Position, TextPolicy};
+use super::buffer::{self, BufferSize, BufferSizer};
+use super::input::Input;
+use super::unfancy_parser::{self, Error};
+#[cfg(feature = "mmap")]
+use super::paths::long_path;
+
+const INITIAL_CAPACITY: usize = 64 * 1024;
+
+/// The bytes a reader parses.
+enum Buffer {
+	/// A buffer filled from the reader.
+	Owned(Vec<u8>),
+	/// A whole file mapped into memory.
+	#[cfg(feature = "mmap")]
+	Mapped(Mmap),
+}
+
+impl Buffer {
+	fn bytes(&self) -> &[u8] {
+		match *self {
+			Buffer::Owned(ref buf) => buf,
+			#[cfg(feature = "mmap")]
+			Buffer::Mapped(ref map) => map,
+		}
+	}
+	
+	/// The buffer to read into, unless the data is mapped.
+	fn owned_mut(&mut self) -> Option<&mut Vec<u8>> {
+		match *self {
+			Buffer::Owned(ref mut buf) => Some(buf),
+			#[cfg(feature = "mmap")]
+			Buffer::Mapped(_) => None,
+		}
+	}
+}
+
+/// A FastQ reader lending out borrowed records.
+pub struct Reader<R: Read> {
+	reader: R,
+	buf: Buffer,
+	start: usize,
+	end: usize,
+	eof: bool,
+	consumed: u64,
+	records: u64,
+	sizer: BufferSizer,
+	text: TextPolicy,
+}
+
+impl Reader<Input> {
+	/// Read from a given file, decompressing it if it is gzip or bgzip compressed.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Input::open(path).map(Reader::new)
+	}
+	
+	/// Read from a given gzip or bgzip compressed file.
+	pub fn from_gz<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Input::open_gz(path).map(Reader::new)
+	}
+}
+
+#[cfg(feature = "example-data")]
+impl Reader<&'static [u8]> {
+	/// Read the eight records of `example::FASTQ`, to try things out without a file.
+	pub fn example() -> Self {
+		Reader::new(super::example::FASTQ.as_bytes())
+	}
+}
+
+#[cfg(feature = "mmap")]
+impl Reader<io::Empty> {
+	/// Memory-map a plain FastQ file and parse it in place.
+	///
+	/// The file must not be changed while the reader exists:
+	/// records would change under your hands, and truncating it can crash the program.
+	pub fn from_mmap<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		let file = std::fs::File::open(long_path(path))?;
+		// SAFETY: the caller promises not to modify the file while it is mapped, see above.
+		let map = unsafe { Mmap::map(&file)? };
+		#[cfg(unix)]
+		map.advise(memmap2::Advice::Sequential)?;
+		let end = map.len();
+		let sizer = BufferSizer::new(BufferSize::Fixed(end));
+		Ok(Reader { reader: io::empty(), buf: Buffer::Mapped(map), start: 0, end, eof: true, consumed: 0, records: 0, sizer, text: TextPolicy::Bytes })
+	}
+}
+
+impl<R: Read> Reader<R> {
+	/// Read from a given `io::Read`, with a buffer adapting to the read lengths.
+	pub fn new(reader: R) -> Self {
+		Reader::with_buffer(BufferSize::Adaptive { min: INITIAL_CAPACITY, max: buffer::MAX_ADAPTIVE }, reader)
+	}
+	
+	/// Read from a given `io::Read`, starting with a buffer of `capacity` bytes.
+	/// The buffer grows if a record does not fit.
+	pub fn with_capacity(capacity: usize, reader: R) -> Self {
+		Reader::with_buffer(BufferSize::Fixed(capacity), reader)
+	}
+	
+	/// Read from a given `io::Read` with a buffer of the given size.
+	/// The buffer grows beyond it if a record does not fit.
+	pub fn with_buffer(size: BufferSize, reader: R) -> Self {
+		let sizer = BufferSizer::new(size);
+		Reader { reader, buf: Buffer::Owned(vec![0; sizer.initial()]), start: 0, end: 0, eof: false, consumed: 0, records: 0, sizer, text: TextPolicy::Bytes }
+	}
+	
+	/// Check each record as `policy` says. By default, records are handed out as bytes without any check.
+	pub fn text(mut self, policy: TextPolicy) -> Self {
+		self.text = policy;
+		self
+	}
+	
+	/// The current size of the buffer in bytes, or of the file if it is memory-mapped.
+	pub fn buffer_size(&self) -> usize { self.buf.bytes().len() }
+	
+	/// The position of line `i` of the next record, at byte `offset`.
+	fn position(&self, i: u64, offset: u64) -> Position {
+		Position { line: self.records * 4 + i + 1, record: self.records, offset }
+	}
+	
+	/// Return the next record, borrowing from the reader.
+	#[allow(clippy::should_implement_trait)]
+	pub fn next(&mut self) -> Option<Result<RefRecord<'_>, Error>> {
+		let lines = loop {
+			match find_lines(&self.buf.bytes()[self.start..self.end], self.eof) {
+				Some(lines) => break lines,
+				None if self.eof => {
+					if self.buf.bytes()[self.start..self.end].iter().all(|b| b.is_ascii_whitespace()) { return None }
+					let pos = self.position(0, self.consumed + self.start as u64);
+					self.start = self.end;
+					self.records += 1;
+					return Some(Err(Error::IncompleteRecord(pos)));
+				},
+				None => if let Err(e) = self.fill() { return Some(Err(e.into())) },
+			}
+		};
+		let base = self.start;
+		let offset = self.consumed + base as u64;
+		self.start += lines[4];
+		self.sizer.observe(lines[4] as u64);
+		let (at, plus) = (self.position(0, offset), self.position(2, offset + lines[2] as u64));
+		self.records += 1;
+		let data = &self.buf.bytes()[base..base + lines[4]];
+		if let Err(e) = self.text.check(data) { return Some(Err(e.into())) }
+		let line = |i: usize| trim_eol(&data[lines[i]..lines[i + 1]]);
+		let (head, seq, sep, qual) = (line(0), line(1), line(2), line(3));
+		if head.first() != Some(&b'@') { return Some(Err(Error::MissingAt(at))) }
+		if sep.first() != Some(&b'+') { return Some(Err(Error::MissingPlus(plus))) }
+		Some(Ok(RefRecord { head: &head[1..], seq, qual, offset: Some(offset) }))
+	}
+	
+	/// Move unconsumed data to the front of the buffer, resize it to fit the records seen
+	/// or grow it if full, and read more.
+	fn fill(&mut self) -> io::Result<()> {
+		let Some(buf) = self.buf.owned_mut() else { self.eof = true; return Ok(()) };
+		if self.start > 0 {
+			buf.copy_within(self.start..self.end, 0);
+			self.consumed += self.start as u64;
+			self.end -= self.start;
+			self.start = 0;
+		}
+		let target = self.sizer.target();
+		if buffer::should_resize(buf.len(), target) && self.end < target {
+			buf.resize(target, 0);
+			buf.shrink_to_fit();
+		}
+		if self.end == buf.len() {
+			let len = buf.len();
+			buf.resize(len * 2, 0);
+		}
+		let n = self.reader.read(&mut buf[self.end..])?;
+		if n == 0 { self.eof = true }
+		self.end += n;
+		Ok(())
+	}
+}
+
+/// Offsets of the starts of four lines and the end of the fourth.
+/// At EOF, the last line need not end in a newline.
+fn find_lines(data: &[u8], eof: bool) -> Option<[usize; 5]> {
+	let mut lines = [0; 5];
+	let mut pos = 0;
+	for line in lines.iter_mut().skip(1) {
+		match data[pos..].iter().position(|&b| b == b'\n') {
+			Some(nl) => pos += nl + 1,
+			None if eof && pos < data.len() => pos = data.len(),
+			None => return None,
+		}
+		*line = pos;
+	}
+	Some(lines)
+}
+
+fn trim_eol(line: &[u8]) -> &[u8] {
+	let mut end = line.len();
+	while end > 0 && (line[end - 1] == b'\n' || line[end - 1] == b'\r') { end -= 1 }
+	&line[..end]
+}
+
+/// A FastQ record borrowing its fields from a reader’s buffer.
+/// Equality compares header, sequence and qualities only.
+#[derive(Debug, Clone, Copy)]
+pub struct RefRecord<'a> {
+	head: &'a [u8],
+	seq: &'a [u8],
+	qual: &'a [u8],
+	offset: Option<u64>,
+}
+
+impl<'a> PartialEq for RefRecord<'a> {
+	fn eq(&self, other: &RefRecord<'a>) -> bool {
+		self.head == other.head && self.seq == other.seq && self.qual == other.qual
+	}
+}
+
+impl<'a> Eq for RefRecord<'a> {}
+
+impl<'a> RefRecord<'a> {
+	/// The raw header line, without `@` and line break.
+	pub fn head(&self) -> &'a [u8] { self.head }
+	
+	/// Copy the record into an owned `unfancy_parser::Record`.
+	pub fn to_owned_record(&self) -> unfancy_parser::Record {
+		let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+		let mut record = unfancy_parser::Record::from_lines(format!("@{}\n", text(self.head)), text(self.seq) + "\n", text(self.qual) + "\n");
+		record.set_offset(self.offset);
+		record
+	}
+}
+
+impl<'a> super::Record for RefRecord<'a> {
+	fn new() -> Self {
+		RefRecord { head: &[], seq: &[], qual: &[], offset: None }
+	}
+	
+	fn is_empty(&self) -> bool {
+		self.head.is_empty() && self.seq.is_empty() && self.qual.is_empty()
+	}
+	
+	fn check(&self) -> Result<(), &str> {
+		if self.id().is_none_or(str::is_empty) {
+			return Err("Expecting id for FastQ record.");
+		}
+		if !self.seq.is_ascii() {
+			return Err("Non-ascii character found in sequence.");
+		}
+		if !self.qual.is_ascii() {
+			return Err("Non-ascii character found in qualities.");
+		}
+		if self.seq.len() != self.qual.len() {
+			return Err("Unequal length of sequence an qualities.");
+		}
+		Ok(())
+	}
+	
+	fn id(&self) -> Option<&str> {
+		str::from_utf8(self.head).ok()?.split(' ').next()
+	}
+	
+	fn desc(&self) -> Option<&str> {
+		str::from_utf8(self.head).ok()?.split_once(' ').map(|(_, desc)| desc)
+	}
+	
+	fn seq(&self) -> &[u8] { self.seq }
+	fn qual(&self) -> &[u8] { self.qual }
+	
+	fn header(&self) -> Cow<'_, str> { String::from_utf8_lossy(self.head) }
+	fn offset(&self) -> Option<u64> { self.offset }
+	
+	fn clear(&mut self) {
+		*self = RefRecord::new();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Record;
+	
+	/// A reader handing out at most `chunk` bytes per read.
+	struct Trickle<'a> {
+		data: &'a [u8],
+		chunk: usize,
+	}
+	
+	impl Read for Trickle<'_> {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			let n = self.chunk.min(buf.len()).min(self.data.len());
+			buf[..n].copy_from_slice(&self.data[..n]);
+			self.data = &self.data[n..];
+			Ok(n)
+		}
+	}
+	
+	fn read_all<R: Read>(mut reader: Reader<R>) -> Vec<Result<(String, u64), String>> {
+		let mut out = Vec::new();
+		while let Some(result) = reader.next() {
+			out.push(result.map(|r| (format!("{}:{}:{}", r.id().unwrap(), str::from_utf8(r.seq()).unwrap(), str::from_utf8(r.qual()).unwrap()), r.offset().unwrap())).map_err(|e| e.to_string()));
+		}
+		out
+	}
+	
+	const DATA: &str = "@a\nACGT\n+\nIIII\n@b x\nAC\n+b\n!!\n@c\nACGTACGTACGTACGTACGTACGT\n+\nIIIIIIIIIIIIIIIIIIIIIIII\n";
+	
+	#[test]
+	fn finds_lines() {
+		assert_eq!(find_lines(b"@a\nAC\n+\nII\n@b", false), Some([0, 3, 6, 8, 11]));
+		assert_eq!(find_lines(b"@a\nAC\n+\nII", false), None);
+		assert_eq!(find_lines(b"@a\nAC\n+\nII", true), Some([0, 3, 6, 8, 10]));
+		assert_eq!(find_lines(b"@a\nAC\n+\n", true), None);
+	}
+	
+	#[test]
+	fn reads_across_buffer_boundaries() {
+		let expected = read_all(Reader::new(DATA.as_bytes()));
+		assert_eq!(expected, vec![
+			Ok(("a:ACGT:IIII".to_owned(), 0)),
+			Ok(("b:AC:!!".to_owned(), 15)),
+			Ok(("c:ACGTACGTACGTACGTACGTACGT:IIIIIIIIIIIIIIIIIIIIIIII".to_owned(), 29)),
+		]);
+		for capacity in [1, 4, 15, 16] {
+			for chunk in 1..=7 {
+				let reader = Reader::with_capacity(capacity, Trickle { data: DATA.as_bytes(), chunk });
+				assert_eq!(read_all(reader), expected, "capacity {}, chunks of {}", capacity, chunk);
+			}
+		}
+	}
+	
+	#[test]
+	fn grows_buffer_for_long_records() {
+		let mut reader = Reader::with_capacity(4, Trickle { data: DATA.as_bytes(), chunk: 3 });
+		for _ in 0..3 {
+			let len = {
+				let record = reader.next().unwrap().unwrap();
+				record.head().len() + record.seq().len() * 2 + 6
+			};
+			assert!(reader.buffer_size() >= len, "buffer of {} bytes for a record of {}", reader.buffer_size(), len);
+		}
+		assert!(reader.next().is_none());
+	}
+	
+	#[test]
+	fn reads_final_line_without_newline() {
+		let records = read_all(Reader::with_capacity(2, Trickle { data: b"@a\nAC\n+\nII\n@b\nGT\n+\n#I", chunk: 1 }));
+		assert_eq!(records, vec![Ok(("a:AC:II".to_owned(), 0)), Ok(("b:GT:#I".to_owned(), 11))]);
+	}
+	
+	#[test]
+	fn reports_incomplete_records_at_their_start() {
+		let mut reader = Reader::with_capacity(4, b"@a\nAC\n+\nII\n@b\nAC\n".as_slice());
+		assert!(reader.next().unwrap().is_ok());
+		match reader.next() {
+			Some(Err(Error::IncompleteRecord(pos))) => assert_eq!(pos, Position { line: 5, record: 1, offset: 11 }),
+			other => panic!("Expected incomplete record, got {:?}", other.map(|r| r.map(|r| r.to_owned_record()))),
+		}
+		assert!(reader.next().is_none());
+		
+		let mut reader = Reader::new(b"@a\nAC\n+\nII\n\n\r\n".as_slice());
+		assert!(reader.next().unwrap().is_ok());
+		assert!(reader.next().is_none());
+	}
+	
+	#[test]
+	fn reports_missing_at_and_plus() {
+		let mut reader = Reader::new(b"@a\nAC\n+\nII\nb\nAC\n+\nII\n@c\nAC\n-\nII\n".as_slice());
+		assert!(reader.next().unwrap().is_ok());
+		match reader.next() {
+			Some(Err(Error::MissingAt(pos))) => assert_eq!(pos, Position { line: 5, record: 1, offset: 11 }),
+			_ => panic!("Expected missing @"),
+		}
+		match reader.next() {
+			Some(Err(Error::MissingPlus(pos))) => assert_eq!(pos, Position { line: 11, record: 2, offset: 27 }),
+			_ => panic!("Expected missing +"),
+		}
+		assert!(reader.next().is_none());
+	}
+}
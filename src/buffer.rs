@@ -0,0 +1,158 @@
+//! Read buffers that adapt their size to the records passing through them.
+//!
+//! A buffer holding a few dozen records avoids frequent refills for multi-megabase nanopore reads
+//! without wasting memory on 150 bp Illumina reads.
+
+use std::io::{self, BufRead, Read};
+
+use super::{Position, Warning};
+
+/// Number of typical records an adaptive buffer should hold.
+const RECORDS_PER_BUFFER: f64 = 64.;
+/// Default upper bound of an adaptive buffer.
+pub(crate) const MAX_ADAPTIVE: usize = 16 * 1024 * 1024;
+/// Weight of the newest record in the running mean record size.
+const SMOOTHING: f64 = 1. / 32.;
+
+/// How large a reader’s buffer is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferSize {
+	/// Always use a buffer of this many bytes.
+	Fixed(usize),
+	/// Size the buffer to fit a few dozen records of the observed size, within these bounds.
+	Adaptive { min: usize, max: usize },
+}
+
+impl Default for BufferSize {
+	fn default() -> BufferSize {
+		BufferSize::Adaptive { min: 8 * 1024, max: MAX_ADAPTIVE }
+	}
+}
+
+/// Tracks the mean size of records to pick a buffer size.
+#[derive(Debug, Clone)]
+pub(crate) struct BufferSizer {
+	size: BufferSize,
+	mean: Option<f64>,
+}
+
+impl BufferSizer {
+	pub(crate) fn new(size: BufferSize) -> BufferSizer {
+		BufferSizer { size, mean: None }
+	}
+	
+	/// The buffer size to start with.
+	pub(crate) fn initial(&self) -> usize {
+		match self.size {
+			BufferSize::Fixed(n) => n.max(1),
+			BufferSize::Adaptive { min, .. } => min.max(1),
+		}
+	}
+	
+	/// Record that a record of `bytes` bytes was read.
+	pub(crate) fn observe(&mut self, bytes: u64) {
+		let bytes = bytes as f64;
+		self.mean = Some(self.mean.map_or(bytes, |m| m + (bytes - m) * SMOOTHING));
+	}
+	
+	/// The buffer size fitting the records seen so far.
+	pub(crate) fn target(&self) -> usize {
+		match (self.size, self.mean) {
+			(BufferSize::Adaptive { min, max }, Some(mean)) => {
+				let wanted = (mean * RECORDS_PER_BUFFER) as usize;
+				wanted.next_power_of_two().clamp(min.max(1), max.max(min).max(1))
+			},
+			_ => self.initial(),
+		}
+	}
+}
+
+/// Whether a buffer of `capacity` bytes should be resized to `target`:
+/// if it is too small, or more than four times too large.
+pub(crate) fn should_resize(capacity: usize, target: usize) -> bool {
+	target > capacity || target.saturating_mul(4) <= capacity
+}
+
+/// A buffered reader like `io::BufReader` whose buffer can be resized while reading.
+pub(crate) struct AdaptiveReader<R> {
+	inner: R,
+	buf: Vec<u8>,
+	start: usize,
+	end: usize,
+}
+
+impl<R: Read> AdaptiveReader<R> {
+	pub(crate) fn with_capacity(capacity: usize, inner: R) -> AdaptiveReader<R> {
+		AdaptiveReader { inner, buf: vec![0; capacity.max(1)], start: 0, end: 0 }
+	}
+	
+	/// The wrapped reader.
+	pub(crate) fn get_ref(&self) -> &R { &self.inner }
+	
+	/// The current buffer size.
+	pub(crate) fn capacity(&self) -> usize { self.buf.len() }
+	
+	/// Resize the buffer if `should_resize` says so and the buffered data fits.
+	pub(crate) fn adapt(&mut self, target: usize) {
+		let target = target.max(1);
+		if !should_resize(self.buf.len(), target) || self.end - self.start > target { return }
+		self.buf.copy_within(self.start..self.end, 0);
+		self.end -= self.start;
+		self.start = 0;
+		self.buf.resize(target, 0);
+		self.buf.shrink_to_fit();
+	}
+}
+
+impl<R: Read> Read for AdaptiveReader<R> {
+	fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+		if self.start == self.end && out.len() >= self.buf.len() {
+			return self.inner.read(out);
+		}
+		let n = {
+			let available = self.fill_buf()?;
+			let n = available.len().min(out.len());
+			out[..n].copy_from_slice(&available[..n]);
+			n
+		};
+		self.consume(n);
+		Ok(n)
+	}
+}
+
+impl<R: Read> BufRead for AdaptiveReader<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> {
+		if self.start >= self.end {
+			self.start = 0;
+			self.end = self.inner.read(&mut self.buf)?;
+		}
+		Ok(&self.buf[self.start..self.end])
+	}
+	
+	fn consume(&mut self, amount: usize) {
+		self.start = (self.start + amount).min(self.end);
+	}
+}
+
+/// A UTF-8 byte order mark.
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Skip a UTF-8 byte order mark and blank lines at the start of input, advancing `pos` past them.
+/// Returns a warning if anything was skipped.
+pub(crate) fn skip_preamble<B: BufRead>(reader: &mut B, pos: &mut Position) -> io::Result<Option<Warning>> {
+	let bom = reader.fill_buf()?.starts_with(BOM);
+	if bom {
+		reader.consume(BOM.len());
+		pos.offset += BOM.len() as u64;
+	}
+	let mut blank_lines = 0;
+	loop {
+		let buf = reader.fill_buf()?;
+		let Some(end) = buf.iter().position(|&b| b == b'\n') else { break };
+		if !buf[..end].iter().all(u8::is_ascii_whitespace) { break }
+		reader.consume(end + 1);
+		pos.advance_line(end + 1);
+		blank_lines += 1;
+	}
+	Ok((bom || blank_lines > 0).then_some(Warning::SkippedPreamble { bom, blank_lines }))
+}
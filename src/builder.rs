@@ -0,0 +1,111 @@
+//! Creating records from scratch and changing them, e.g. to rename reads before writing them out.
+//!
+//! Setters and `RecordBuilder` check their input, so that changed records still write as valid FastQ.
+
+use super::RecordMut;
+
+quick_error! {
+	/// A value that would not make a valid FastQ record.
+	#[derive(Debug, Clone, PartialEq, Eq)]
+	pub enum RecordError {
+		/// IDs must be non-empty and contain no whitespace.
+		InvalidId(id: String) {
+			description("Invalid record ID")
+			display("Invalid record ID {:?}: IDs must be non-empty and contain no whitespace", id)
+		}
+		/// Descriptions must not contain line breaks.
+		InvalidDesc(desc: String) {
+			description("Invalid record description")
+			display("Invalid record description {:?}: descriptions must not contain line breaks", desc)
+		}
+		/// Sequence or qualities (`what`) contained bytes other than printable ASCII.
+		NotPrintable(what: &'static str) {
+			description("Non-printable sequence or qualities")
+			display("The {} may only contain printable ASCII characters", what)
+		}
+		/// Sequence and qualities differ in length.
+		LengthMismatch(seq: usize, qual: usize) {
+			description("Sequence and qualities differ in length")
+			display("Sequence has {} bases but {} qualities", seq, qual)
+		}
+	}
+}
+
+pub(crate) fn check_id(id: &str) -> Result<(), RecordError> {
+	if id.is_empty() || id.contains(char::is_whitespace) {
+		return Err(RecordError::InvalidId(id.to_owned()));
+	}
+	Ok(())
+}
+
+pub(crate) fn check_desc(desc: &str) -> Result<(), RecordError> {
+	if desc.contains(['\r', '\n']) {
+		return Err(RecordError::InvalidDesc(desc.to_owned()));
+	}
+	Ok(())
+}
+
+pub(crate) fn check_seq_qual(seq: &[u8], qual: &[u8]) -> Result<(), RecordError> {
+	if !seq.iter().all(u8::is_ascii_graphic) {
+		return Err(RecordError::NotPrintable("sequence"));
+	}
+	if !qual.iter().all(u8::is_ascii_graphic) {
+		return Err(RecordError::NotPrintable("qualities"));
+	}
+	if seq.len() != qual.len() {
+		return Err(RecordError::LengthMismatch(seq.len(), qual.len()));
+	}
+	Ok(())
+}
+
+/// Assembles a record of either owned type.
+///
+/// ```
+/// use fastq_comparison::{Record, RecordBuilder};
+/// use fastq_comparison::unfancy_parser;
+///
+/// let record: unfancy_parser::Record = RecordBuilder::new("read1").desc("sample=A").seq("ACGT").qual("IIII").build()?;
+/// assert_eq!(record.header(), "read1 sample=A");
+/// # Ok::<(), fastq_comparison::RecordError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordBuilder {
+	id: String,
+	desc: Option<String>,
+	seq: Vec<u8>,
+	qual: Vec<u8>,
+}
+
+impl RecordBuilder {
+	/// Start a record with the given ID, no description and an empty sequence.
+	pub fn new<S: Into<String>>(id: S) -> Self {
+		RecordBuilder { id: id.into(), ..RecordBuilder::default() }
+	}
+	
+	/// Set the description.
+	pub fn desc<S: Into<String>>(mut self, desc: S) -> Self {
+		self.desc = Some(desc.into());
+		self
+	}
+	
+	/// Set the sequence.
+	pub fn seq<B: AsRef<[u8]>>(mut self, seq: B) -> Self {
+		self.seq = seq.as_ref().to_vec();
+		self
+	}
+	
+	/// Set the Phred+33 qualities.
+	pub fn qual<B: AsRef<[u8]>>(mut self, qual: B) -> Self {
+		self.qual = qual.as_ref().to_vec();
+		self
+	}
+	
+	/// Create the record, failing if a field is invalid.
+	pub fn build<R: RecordMut>(self) -> Result<R, RecordError> {
+		let mut record = R::new();
+		record.set_id(&self.id)?;
+		record.set_desc(self.desc.as_deref())?;
+		record.set_seq_qual(&self.seq, &self.qual)?;
+		Ok(record)
+	}
+}
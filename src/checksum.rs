@@ -0,0 +1,316 @@
+//! Per-chunk CRC-32 checksums to find which part of a large FastQ file is corrupt.
+//!
+//! A checksum index lists byte range and CRC-32 of every chunk of `n` records.
+//! It is saved as a small TSV file, by convention next to the FastQ file with `.crc` appended.
+//! Verification only reads the listed byte ranges, so after a damaged transfer
+//! just the failing chunks need to be fetched again, e.g. with HTTP range requests.
+//!
+//! Records are taken to be four lines each, as written by the writers of this crate.
+
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::paths::long_path;
+
+const fn crc_table() -> [u32; 256] {
+	let mut table = [0; 256];
+	let mut i = 0;
+	while i < 256 {
+		let mut c = i as u32;
+		let mut k = 0;
+		while k < 8 {
+			c = if c & 1 == 1 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+			k += 1;
+		}
+		table[i] = c;
+		i += 1;
+	}
+	table
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// An incremental CRC-32 (IEEE, as in gzip and zip).
+#[derive(Debug, Clone, Copy)]
+struct Crc32(u32);
+
+impl Crc32 {
+	fn new() -> Crc32 { Crc32(!0) }
+	
+	fn update(&mut self, data: &[u8]) {
+		for &b in data {
+			self.0 = CRC_TABLE[((self.0 ^ b as u32) & 0xff) as usize] ^ (self.0 >> 8);
+		}
+	}
+	
+	fn sum(self) -> u32 { !self.0 }
+}
+
+/// A chunk of consecutive records as listed in the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chunk {
+	/// The number of the first record in the chunk, counting from 0.
+	pub first_record: u64,
+	/// The number of records in the chunk.
+	pub records: u64,
+	/// The byte offset of the chunk in the file.
+	pub offset: u64,
+	/// The length of the chunk in bytes.
+	pub bytes: u64,
+	/// The CRC-32 of the chunk’s bytes.
+	pub crc: u32,
+}
+
+/// The outcome of checking a file against a `ChecksumIndex`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Verification {
+	/// The numbers of the chunks whose bytes differ or are missing, counting from 0.
+	pub corrupt: Vec<usize>,
+	/// Bytes after the last chunk, which the index does not cover.
+	pub trailing_bytes: u64,
+}
+
+impl Verification {
+	/// Check if every chunk is intact and the file has nothing more.
+	pub fn is_ok(&self) -> bool { self.corrupt.is_empty() && self.trailing_bytes == 0 }
+}
+
+/// Byte ranges and checksums of every chunk of `chunk_records` records of a FastQ file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChecksumIndex {
+	chunk_records: u64,
+	chunks: Vec<Chunk>,
+}
+
+impl ChecksumIndex {
+	/// Checksum every chunk of `chunk_records` records (at least 1) of a plain FastQ stream.
+	pub fn build<R: Read>(reader: R, chunk_records: u64) -> io::Result<ChecksumIndex> {
+		let mut writer = ChecksumWriter::new(io::sink(), chunk_records);
+		io::copy(&mut io::BufReader::new(reader), &mut writer)?;
+		Ok(writer.finish().1)
+	}
+	
+	/// Checksum a plain FastQ file.
+	pub fn from_file<P: AsRef<Path>>(path: P, chunk_records: u64) -> io::Result<ChecksumIndex> {
+		ChecksumIndex::build(fs::File::open(long_path(path))?, chunk_records)
+	}
+	
+	/// The number of records per chunk; the last chunk may have fewer.
+	pub fn chunk_records(&self) -> u64 { self.chunk_records }
+	
+	/// The chunks in file order.
+	pub fn chunks(&self) -> &[Chunk] { &self.chunks }
+	
+	/// The number of records in the indexed file.
+	pub fn records(&self) -> u64 { self.chunks.iter().map(|c| c.records).sum() }
+	
+	/// The size of the indexed file in bytes.
+	pub fn bytes(&self) -> u64 { self.chunks.last().map_or(0, |c| c.offset + c.bytes) }
+	
+	/// Check a whole stream against the index, reading it once from the start.
+	/// A truncated stream fails the chunks it does not fully contain.
+	pub fn verify<R: Read>(&self, reader: R) -> io::Result<Verification> {
+		let mut reader = io::BufReader::new(reader);
+		let mut result = Verification::default();
+		for (n, chunk) in self.chunks.iter().enumerate() {
+			if checksum(&mut reader, chunk.bytes)? != (chunk.bytes, chunk.crc) { result.corrupt.push(n) }
+		}
+		result.trailing_bytes = io::copy(&mut reader, &mut io::sink())?;
+		Ok(result)
+	}
+	
+	/// Check a file against the index.
+	pub fn verify_file<P: AsRef<Path>>(&self, path: P) -> io::Result<Verification> {
+		self.verify(fs::File::open(long_path(path))?)
+	}
+	
+	/// Check a single chunk, e.g. after fetching it again, reading only its byte range.
+	pub fn verify_chunk<R: Read + Seek>(&self, mut reader: R, n: usize) -> io::Result<bool> {
+		let chunk = self.chunks.get(n).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("No chunk {}", n)))?;
+		reader.seek(SeekFrom::Start(chunk.offset))?;
+		Ok(checksum(&mut reader, chunk.bytes)? == (chunk.bytes, chunk.crc))
+	}
+	
+	/// Write the index as TSV: a `#chunk_records` line, then one line per chunk with
+	/// first record, record count, offset, length and CRC-32 as hex.
+	pub fn write_to<W: Write>(&self, out: W) -> io::Result<()> {
+		let mut out = io::BufWriter::new(out);
+		writeln!(out, "#chunk_records\t{}", self.chunk_records)?;
+		for c in &self.chunks {
+			writeln!(out, "{}\t{}\t{}\t{}\t{:08x}", c.first_record, c.records, c.offset, c.bytes, c.crc)?;
+		}
+		out.flush()
+	}
+	
+	/// Read an index written by `write_to`.
+	pub fn read_from<R: Read>(reader: R) -> io::Result<ChecksumIndex> {
+		let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid checksum index: {}", msg));
+		let mut lines = io::BufReader::new(reader).lines();
+		let head = lines.next().ok_or_else(|| invalid("empty".to_owned()))??;
+		let chunk_records = match head.trim_end().split_once('\t') {
+			Some(("#chunk_records", n)) => n.parse().map_err(|_| invalid("bad chunk size".to_owned()))?,
+			_ => return Err(invalid("missing header".to_owned())),
+		};
+		let mut chunks = Vec::new();
+		for (i, line) in lines.enumerate() {
+			let line = line?;
+			if line.trim().is_empty() { continue }
+			let bad_line = || invalid(format!("line {}: expected 5 tab-separated fields", i + 2));
+			let fields: Vec<&str> = line.trim_end().split('\t').collect();
+			if fields.len() != 5 { return Err(bad_line()) }
+			chunks.push(Chunk {
+				first_record: fields[0].parse().map_err(|_| bad_line())?,
+				records: fields[1].parse().map_err(|_| bad_line())?,
+				offset: fields[2].parse().map_err(|_| bad_line())?,
+				bytes: fields[3].parse().map_err(|_| bad_line())?,
+				crc: u32::from_str_radix(fields[4], 16).map_err(|_| bad_line())?,
+			});
+		}
+		Ok(ChecksumIndex { chunk_records, chunks })
+	}
+	
+	/// Save the index to a file.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write_to(fs::File::create(long_path(path))?)
+	}
+	
+	/// Load an index from a file.
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ChecksumIndex> {
+		ChecksumIndex::read_from(fs::File::open(long_path(path))?)
+	}
+	
+	/// The conventional index path for a FastQ file: its path with `.crc` appended.
+	pub fn path_for<P: AsRef<Path>>(fastq: P) -> PathBuf {
+		let mut path = fastq.as_ref().as_os_str().to_owned();
+		path.push(".crc");
+		path.into()
+	}
+}
+
+/// Length and CRC-32 of the next `len` bytes of a stream, or of what is left if it ends earlier.
+fn checksum<R: Read>(reader: R, len: u64) -> io::Result<(u64, u32)> {
+	let mut reader = reader.take(len);
+	let mut crc = Crc32::new();
+	let mut buf = vec![0; 64 * 1024];
+	let mut read = 0;
+	loop {
+		match reader.read(&mut buf) {
+			Ok(0) => return Ok((read, crc.sum())),
+			Ok(n) => { crc.update(&buf[..n]); read += n as u64 },
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+/// A writer building a `ChecksumIndex` of the FastQ data written through it,
+/// so the index of an output comes for free, e.g. `Writer::new(ChecksumWriter::new(file, 100_000))`.
+pub struct ChecksumWriter<W> {
+	inner: W,
+	chunk_records: u64,
+	chunks: Vec<Chunk>,
+	crc: Crc32,
+	offset: u64,
+	bytes: u64,
+	records: u64,
+	lines: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+	/// Checksum every chunk of `chunk_records` records (at least 1) written to `inner`.
+	pub fn new(inner: W, chunk_records: u64) -> ChecksumWriter<W> {
+		ChecksumWriter { inner, chunk_records: chunk_records.max(1), chunks: Vec::new(), crc: Crc32::new(), offset: 0, bytes: 0, records: 0, lines: 0 }
+	}
+	
+	/// Return the underlying writer and the index, including a last, possibly incomplete chunk.
+	pub fn finish(mut self) -> (W, ChecksumIndex) {
+		if self.bytes > 0 { self.end_chunk() }
+		(self.inner, ChecksumIndex { chunk_records: self.chunk_records, chunks: self.chunks })
+	}
+	
+	fn end_chunk(&mut self) {
+		let records = self.records + u64::from(self.lines > 0);
+		let first_record = self.chunks.last().map_or(0, |c| c.first_record + c.records);
+		self.chunks.push(Chunk { first_record, records, offset: self.offset, bytes: self.bytes, crc: self.crc.sum() });
+		self.offset += self.bytes;
+		self.bytes = 0;
+		self.records = 0;
+		self.crc = Crc32::new();
+	}
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = self.inner.write(buf)?;
+		let mut rest = &buf[..n];
+		while let Some(i) = rest.iter().position(|&b| b == b'\n') {
+			let (line, tail) = rest.split_at(i + 1);
+			self.crc.update(line);
+			self.bytes += line.len() as u64;
+			rest = tail;
+			self.lines += 1;
+			if self.lines == 4 {
+				self.lines = 0;
+				self.records += 1;
+				if self.records == self.chunk_records { self.end_chunk() }
+			}
+		}
+		self.crc.update(rest);
+		self.bytes += rest.len() as u64;
+		Ok(n)
+	}
+	
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn crc32(data: &[u8]) -> u32 {
+		let mut crc = Crc32::new();
+		crc.update(data);
+		crc.sum()
+	}
+	
+	#[test]
+	fn crc32_known_answers() {
+		assert_eq!(crc32(b""), 0);
+		assert_eq!(crc32(b"a"), 0xe8b7be43);
+		assert_eq!(crc32(b"123456789"), 0xcbf43926);
+		assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414fa339);
+		// incremental updates give the same sum
+		let mut crc = Crc32::new();
+		for part in [&b"1234"[..], b"", b"56789"] { crc.update(part) }
+		assert_eq!(crc.sum(), 0xcbf43926);
+	}
+	
+	#[test]
+	fn locates_corrupt_chunks() {
+		let data: Vec<u8> = (0..5).flat_map(|i| format!("@r{}\nACGT\n+\nIIII\n", i).into_bytes()).collect();
+		let index = ChecksumIndex::build(&data[..], 2).unwrap();
+		let chunks: Vec<_> = index.chunks().iter().map(|c| (c.first_record, c.records, c.offset, c.bytes)).collect();
+		assert_eq!(chunks, [(0, 2, 0, 32), (2, 2, 32, 32), (4, 1, 64, 16)]);
+		assert_eq!(index.chunks()[0].crc, crc32(&data[..32]));
+		assert!(index.verify(&data[..]).unwrap().is_ok());
+		
+		let mut corrupt = data.clone();
+		corrupt[40] = b'N';
+		corrupt.extend_from_slice(b"\n");
+		assert_eq!(index.verify(&corrupt[..]).unwrap(), Verification { corrupt: vec![1], trailing_bytes: 1 });
+		assert!(!index.verify_chunk(io::Cursor::new(&corrupt), 1).unwrap());
+		assert!(index.verify_chunk(io::Cursor::new(&corrupt), 2).unwrap());
+		assert_eq!(index.verify(&data[..50]).unwrap().corrupt, [1, 2]);
+		
+		let mut saved = Vec::new();
+		index.write_to(&mut saved).unwrap();
+		assert!(saved.starts_with(b"#chunk_records\t2\n0\t2\t0\t32\t"));
+		assert_eq!(ChecksumIndex::read_from(&saved[..]).unwrap(), index);
+	}
+}
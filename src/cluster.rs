@@ -0,0 +1,216 @@
+//! Greedy clustering of similar sequences or barcodes, e.g. to collapse amplicon variants.
+//!
+//! Distinct sequences are visited from most to least abundant (ties in input order).
+//! Each joins the first centroid within the distance threshold, or becomes a new centroid.
+
+use std::collections::HashMap;
+
+use super::Record as RecordTrait;
+use super::seqcmp;
+use super::unfancy_parser::Record;
+
+/// How the distance between two sequences is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Distance {
+	/// Number of mismatching positions. Sequences of different length never match.
+	Hamming,
+	/// Number of substitutions, insertions and deletions (Levenshtein distance).
+	Edit,
+}
+
+impl Distance {
+	/// The distance between `a` and `b` if it is at most `max`.
+	pub fn within(self, a: &[u8], b: &[u8], max: usize) -> Option<usize> {
+		match self {
+			Distance::Hamming => hamming(a, b).filter(|&d| d <= max),
+			Distance::Edit => seqcmp::edit_distance(a, b, max),
+		}
+	}
+}
+
+pub use super::seqcmp::hamming;
+
+/// The Levenshtein distance between two sequences.
+pub fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+	edit_distance_within(a, b, a.len().max(b.len())).unwrap_or(usize::MAX)
+}
+
+/// The Levenshtein distance if it is at most `max`, giving up early otherwise, see `seqcmp::edit_distance`.
+pub fn edit_distance_within(a: &[u8], b: &[u8], max: usize) -> Option<usize> {
+	seqcmp::edit_distance(a, b, max)
+}
+
+/// A group of similar sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+	/// Index of the sequence all members are within the threshold of.
+	pub centroid: usize,
+	/// Indices of all sequences in the cluster, including the centroid, in input order.
+	pub members: Vec<usize>,
+}
+
+impl Cluster {
+	/// The number of sequences in the cluster.
+	pub fn size(&self) -> usize { self.members.len() }
+}
+
+/// The result of clustering: clusters ordered by when their centroid was picked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Clustering {
+	/// The cluster of each input sequence, as an index into `clusters`.
+	pub assignments: Vec<usize>,
+	pub clusters: Vec<Cluster>,
+}
+
+impl Clustering {
+	/// One consensus record per cluster, see `consensus`.
+	pub fn consensus_records<R: RecordTrait>(&self, records: &[R]) -> Vec<Record> {
+		self.clusters.iter().map(|c| consensus(records, c)).collect()
+	}
+}
+
+/// Greedy centroid-based clustering with a distance threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clusterer {
+	distance: Distance,
+	max_distance: usize,
+}
+
+impl Clusterer {
+	/// Cluster sequences that are at most `max_distance` away from a centroid.
+	pub fn new(distance: Distance, max_distance: usize) -> Clusterer {
+		Clusterer { distance, max_distance }
+	}
+	
+	/// Cluster arbitrary sequences, e.g. barcodes or UMIs.
+	pub fn cluster_keys<K: AsRef<[u8]>>(&self, keys: &[K]) -> Clustering {
+		// identical sequences are handled once, most abundant first
+		let mut distinct: Vec<(&[u8], Vec<usize>)> = Vec::new();
+		let mut seen: HashMap<&[u8], usize> = HashMap::new();
+		for (i, key) in keys.iter().enumerate() {
+			let key = key.as_ref();
+			let d = *seen.entry(key).or_insert_with(|| { distinct.push((key, Vec::new())); distinct.len() - 1 });
+			distinct[d].1.push(i);
+		}
+		distinct.sort_by_key(|(_, indices)| std::cmp::Reverse(indices.len()));
+		
+		let mut clustering = Clustering { assignments: vec![0; keys.len()], clusters: Vec::new() };
+		let mut centroids: Vec<&[u8]> = Vec::new();
+		for (key, indices) in distinct {
+			let c = match centroids.iter().position(|c| self.distance.within(c, key, self.max_distance).is_some()) {
+				Some(c) => c,
+				None => {
+					centroids.push(key);
+					clustering.clusters.push(Cluster { centroid: indices[0], members: Vec::new() });
+					centroids.len() - 1
+				},
+			};
+			for &i in &indices { clustering.assignments[i] = c }
+			clustering.clusters[c].members.extend(indices);
+		}
+		for cluster in &mut clustering.clusters { cluster.members.sort_unstable() }
+		clustering
+	}
+	
+	/// Cluster records by their sequence.
+	pub fn cluster<R: RecordTrait>(&self, records: &[R]) -> Clustering {
+		self.cluster_by(records, RecordTrait::seq)
+	}
+	
+	/// Cluster records by a part of them, e.g. a barcode taken from the header.
+	pub fn cluster_by<R, F>(&self, records: &[R], key: F) -> Clustering where F: Fn(&R) -> &[u8] {
+		let keys: Vec<&[u8]> = records.iter().map(key).collect();
+		self.cluster_keys(&keys)
+	}
+}
+
+/// Build the consensus record of a cluster.
+///
+/// Each position of the centroid gets the most frequent base among the members covering it
+/// (the centroid’s on ties), with the highest quality any member has for that base there.
+/// The header is the centroid’s id with a `cluster_size=N` description.
+pub fn consensus<R: RecordTrait>(records: &[R], cluster: &Cluster) -> Record {
+	let centroid = &records[cluster.centroid];
+	let mut seq = Vec::with_capacity(centroid.seq_len());
+	let mut qual = Vec::with_capacity(centroid.seq_len());
+	for (pos, &base) in centroid.seq().iter().enumerate() {
+		let mut votes: Vec<(u8, usize, u8)> = vec![(base, 0, 0)];
+		for &m in &cluster.members {
+			let (s, q) = (records[m].seq(), records[m].qual());
+			let Some(&b) = s.get(pos) else { continue };
+			let q = q.get(pos).copied().unwrap_or(b'!');
+			match votes.iter_mut().find(|v| v.0 == b) {
+				Some(v) => { v.1 += 1; v.2 = v.2.max(q) },
+				None => votes.push((b, 1, q)),
+			}
+		}
+		// max_by_key returns the last maximum, so iterate in reverse to prefer the centroid base
+		let &(b, _, q) = votes.iter().rev().max_by_key(|v| v.1).unwrap();
+		seq.push(b);
+		qual.push(q);
+	}
+	let header = format!("@{} cluster_size={}\n", centroid.id().unwrap_or(""), cluster.size());
+	let seq = String::from_utf8_lossy(&seq).into_owned() + "\n";
+	let qual = String::from_utf8_lossy(&qual).into_owned() + "\n";
+	Record::from_lines(header, seq, qual)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn sizes(clustering: &Clustering) -> Vec<usize> {
+		clustering.clusters.iter().map(Cluster::size).collect()
+	}
+	
+	#[test]
+	fn measures_hamming_and_edit_distance() {
+		assert_eq!(Distance::Hamming.within(b"ACGT", b"ACGA", 0), None);
+		assert_eq!(Distance::Hamming.within(b"ACGT", b"ACGA", 1), Some(1));
+		assert_eq!(Distance::Hamming.within(b"ACGTACGT", b"ACGTACG", 8), None);
+		assert_eq!(Distance::Edit.within(b"ACGTACGT", b"ACGTACG", 1), Some(1));
+		assert_eq!(Distance::Hamming.within(b"ACGTACGT", b"CGTACGTA", 7), None);
+		assert_eq!(Distance::Edit.within(b"ACGTACGT", b"CGTACGTA", 1), None);
+		assert_eq!(Distance::Edit.within(b"ACGTACGT", b"CGTACGTA", 2), Some(2));
+		assert_eq!(edit_distance(b"kitten", b"sitting"), 3);
+		assert_eq!(edit_distance_within(b"kitten", b"sitting", 2), None);
+	}
+	
+	#[test]
+	fn clusters_within_threshold_by_distance() {
+		let keys = ["ACGTACGT", "ACGTACGT", "ACGTACG", "CGTACGTA"];
+		assert_eq!(sizes(&Clusterer::new(Distance::Hamming, 1).cluster_keys(&keys)), [2, 1, 1]);
+		assert_eq!(sizes(&Clusterer::new(Distance::Edit, 1).cluster_keys(&keys)), [3, 1]);
+		assert_eq!(sizes(&Clusterer::new(Distance::Edit, 2).cluster_keys(&keys)), [4]);
+		assert_eq!(sizes(&Clusterer::new(Distance::Edit, 0).cluster_keys(&keys)), [2, 1, 1]);
+	}
+	
+	#[test]
+	fn picks_centroids_by_abundance_then_input_order() {
+		// AATT is within 1 of the member AAAT, but not of the centroid AAAA
+		let clustering = Clusterer::new(Distance::Hamming, 1).cluster_keys(&["AAAT", "AAAA", "AAAA", "TTTT", "AATT"]);
+		assert_eq!(clustering.clusters, [
+			Cluster { centroid: 1, members: vec![0, 1, 2] },
+			Cluster { centroid: 3, members: vec![3] },
+			Cluster { centroid: 4, members: vec![4] },
+		]);
+		assert_eq!(clustering.assignments, [0, 0, 0, 1, 2]);
+		
+		let clustering = Clusterer::new(Distance::Hamming, 1).cluster_keys(&["TTTT", "TTTA", "TTAA"]);
+		assert_eq!(clustering.clusters.iter().map(|c| c.centroid).collect::<Vec<_>>(), [0, 2]);
+		let clustering = Clusterer::new(Distance::Hamming, 1).cluster_keys(&["TTAA", "TTTA", "TTTT"]);
+		assert_eq!(clustering.clusters.iter().map(|c| c.centroid).collect::<Vec<_>>(), [0, 2]);
+		assert_eq!(clustering.assignments, [0, 0, 1]);
+	}
+	
+	#[test]
+	fn builds_consensus_records() {
+		let read = |id: &str, seq: &str, qual: &str| Record::from_lines(format!("@{}\n", id), format!("{}\n", seq), format!("{}\n", qual));
+		let records = [read("a", "ACGT", "IIII"), read("b", "ACGA", "####"), read("c", "ACGA", "5555"), read("d", "TTTT", "IIII")];
+		let clustering = Clusterer::new(Distance::Hamming, 1).cluster(&records);
+		let consensus = clustering.consensus_records(&records);
+		assert_eq!(consensus.len(), 2);
+		assert_eq!((consensus[0].id(), consensus[0].desc()), (Some("b"), Some("cluster_size=3")));
+		assert_eq!((consensus[0].seq(), consensus[0].qual()), (&b"ACGA"[..], &b"III5"[..]));
+	}
+}
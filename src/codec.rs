@@ -0,0 +1,291 @@
+//! Serializing batches of records to bytes, to keep them in storage systems like key-value or object stores.
+//!
+//! A `SeqCodec` turns a batch of records into one byte string and back. `Fastq` stores the text as is,
+//! `TwoBit` packs bases into two bits each, and `ZstdFramed` (with the `zstd` feature) compresses what another
+//! codec produces into a self-contained zstd frame. All of them give back the records exactly as they were.
+
+use std::io;
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Reader, Record, Error};
+
+quick_error! {
+	/// Why a batch could not be encoded or decoded.
+	#[derive(Debug)]
+	pub enum CodecError {
+		/// A record cannot be encoded, e.g. because sequence and qualities differ in length.
+		Invalid(msg: String) {
+			display("Cannot encode record: {}", msg)
+		}
+		/// The bytes are not a batch encoded by the codec.
+		Corrupt(msg: String) {
+			display("Corrupt record batch: {}", msg)
+		}
+		/// A `Fastq` batch could not be parsed.
+		Parse(err: Error) {
+			from()
+			cause(err)
+			display("{}", err)
+		}
+		/// Compressing or decompressing failed.
+		Io(err: io::Error) {
+			from()
+			cause(err)
+			display("{}", err)
+		}
+	}
+}
+
+/// Encodes batches of records to bytes and back.
+pub trait SeqCodec {
+	/// Append the encoding of a batch of records to `out`.
+	fn encode(&self, records: &[Record], out: &mut Vec<u8>) -> Result<(), CodecError>;
+	
+	/// Decode a batch encoded by `encode`.
+	fn decode(&self, data: &[u8]) -> Result<Vec<Record>, CodecError>;
+	
+	/// Encode a batch of records into a new buffer.
+	fn encode_to_vec(&self, records: &[Record]) -> Result<Vec<u8>, CodecError> {
+		let mut out = Vec::new();
+		self.encode(records, &mut out)?;
+		Ok(out)
+	}
+}
+
+/// FastQ text, four lines per record.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fastq;
+
+impl SeqCodec for Fastq {
+	fn encode(&self, records: &[Record], out: &mut Vec<u8>) -> Result<(), CodecError> {
+		for record in records { record.write_raw(out)?; }
+		Ok(())
+	}
+	
+	fn decode(&self, data: &[u8]) -> Result<Vec<Record>, CodecError> {
+		Ok(Reader::new(data).records().collect::<Result<_, _>>()?)
+	}
+}
+
+/// Bases packed four to a byte, with qualities and headers as they are.
+///
+/// A batch is the number of records, then for each record: header length and header without `@`,
+/// sequence length, the bases as two bits each (`A`, `C`, `G`, `T`; the first base in the highest bits),
+/// the number of other bases like `N` or lowercase ones and, for each, its distance from the previous one
+/// and the base itself, and finally the qualities. Numbers are LEB128 varints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TwoBit;
+
+impl SeqCodec for TwoBit {
+	fn encode(&self, records: &[Record], out: &mut Vec<u8>) -> Result<(), CodecError> {
+		write_varint(out, records.len() as u64);
+		for record in records {
+			let (header, seq, qual) = (record.header(), record.seq(), record.qual());
+			if seq.len() != qual.len() {
+				return Err(CodecError::Invalid(format!("{} has {} bases but {} qualities", header, seq.len(), qual.len())));
+			}
+			write_varint(out, header.len() as u64);
+			out.extend_from_slice(header.as_bytes());
+			write_varint(out, seq.len() as u64);
+			for chunk in seq.chunks(4) {
+				let packed = chunk.iter().enumerate().fold(0, |packed, (i, &b)| packed | base_code(b).unwrap_or(0) << (6 - 2 * i));
+				out.push(packed);
+			}
+			let others: Vec<usize> = (0..seq.len()).filter(|&i| base_code(seq[i]).is_none()).collect();
+			write_varint(out, others.len() as u64);
+			let mut previous = 0;
+			for i in others {
+				write_varint(out, (i - previous) as u64);
+				out.push(seq[i]);
+				previous = i;
+			}
+			out.extend_from_slice(qual);
+		}
+		Ok(())
+	}
+	
+	fn decode(&self, data: &[u8]) -> Result<Vec<Record>, CodecError> {
+		let mut input = Decoder { data, pos: 0 };
+		let count = input.varint()?;
+		let mut records = Vec::with_capacity(count.min(data.len() as u64) as usize);
+		for _ in 0..count {
+			let header_len = input.length()?;
+			let header = text(input.take(header_len)?, "header")?;
+			let len = input.length()?;
+			let packed = input.take(len.div_ceil(4))?;
+			let mut seq: Vec<u8> = (0..len).map(|i| b"ACGT"[(packed[i / 4] >> (6 - 2 * (i % 4)) & 3) as usize]).collect();
+			let mut i = 0usize;
+			for n in 0..input.varint()? {
+				let distance = usize::try_from(input.varint()?).ok().filter(|&d| n == 0 || d > 0);
+				i = match distance.and_then(|d| i.checked_add(d)).filter(|&i| i < len) {
+					Some(i) => i,
+					None => return Err(CodecError::Corrupt(format!("base position after {} repeated or out of range", i))),
+				};
+				seq[i] = input.take(1)?[0];
+			}
+			let qual = text(input.take(len)?, "qualities")?;
+			records.push(Record::from_lines(format!("@{}\n", header), text(&seq, "sequence")? + "\n", qual + "\n"));
+		}
+		if input.pos != data.len() { return Err(CodecError::Corrupt(format!("trailing data after the last record, {} bytes", data.len() - input.pos))) }
+		Ok(records)
+	}
+}
+
+/// The encoding of another codec compressed into a single zstd frame.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZstdFramed<C> {
+	inner: C,
+	level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl<C: SeqCodec> ZstdFramed<C> {
+	/// Compress what `inner` encodes at zstd’s default level.
+	pub fn new(inner: C) -> Self {
+		ZstdFramed { inner, level: zstd::DEFAULT_COMPRESSION_LEVEL }
+	}
+	
+	/// Compress at another level, from 1 (fastest) to 22 (smallest).
+	pub fn level(mut self, level: i32) -> Self {
+		self.level = level;
+		self
+	}
+}
+
+#[cfg(feature = "zstd")]
+impl<C: SeqCodec> SeqCodec for ZstdFramed<C> {
+	fn encode(&self, records: &[Record], out: &mut Vec<u8>) -> Result<(), CodecError> {
+		let encoded = self.inner.encode_to_vec(records)?;
+		out.extend_from_slice(&zstd::encode_all(&encoded[..], self.level)?);
+		Ok(())
+	}
+	
+	fn decode(&self, data: &[u8]) -> Result<Vec<Record>, CodecError> {
+		self.inner.decode(&zstd::decode_all(data)?)
+	}
+}
+
+/// The two bits of a base, `None` for anything but `ACGT`.
+fn base_code(b: u8) -> Option<u8> {
+	match b {
+		b'A' => Some(0),
+		b'C' => Some(1),
+		b'G' => Some(2),
+		b'T' => Some(3),
+		_ => None,
+	}
+}
+
+/// Append `n` as a LEB128 varint.
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+	while n >= 0x80 {
+		out.push(n as u8 | 0x80);
+		n >>= 7;
+	}
+	out.push(n as u8);
+}
+
+/// The bytes of a batch being decoded.
+struct Decoder<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+	fn take(&mut self, n: usize) -> Result<&'a [u8], CodecError> {
+		let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len())
+			.ok_or_else(|| CodecError::Corrupt(format!("truncated at byte {}", self.pos)))?;
+		let bytes = &self.data[self.pos..end];
+		self.pos = end;
+		Ok(bytes)
+	}
+	
+	/// A length, which cannot be longer than the data.
+	fn length(&mut self) -> Result<usize, CodecError> {
+		let n = self.varint()?;
+		usize::try_from(n).ok().filter(|&n| n <= self.data.len())
+			.ok_or_else(|| CodecError::Corrupt(format!("length {} longer than the data", n)))
+	}
+	
+	fn varint(&mut self) -> Result<u64, CodecError> {
+		let mut n = 0u64;
+		for shift in (0..64).step_by(7) {
+			let b = self.take(1)?[0];
+			n |= u64::from(b & 0x7f) << shift;
+			if b & 0x80 == 0 { return Ok(n) }
+		}
+		Err(CodecError::Corrupt(format!("varint too long at byte {}", self.pos)))
+	}
+}
+
+/// A line of a record, which must be UTF-8 without line breaks.
+fn text(bytes: &[u8], what: &str) -> Result<String, CodecError> {
+	if bytes.iter().any(|&b| b == b'\n' || b == b'\r') { return Err(CodecError::Corrupt(format!("{} contains a line break", what))) }
+	String::from_utf8(bytes.to_vec()).map_err(|_| CodecError::Corrupt(format!("{} is not UTF-8", what)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const DATA: &[u8] = b"@r1 desc\nACGTNacgtRYA\n+\nIIIII#####II\n@empty\n\n+\n\n@r3\nNNNN\n+\n!!!!\n@r4\nGATTACA\n+\nABCDEFG\n";
+	
+	fn records() -> Vec<Record> {
+		Reader::new(DATA).records().collect::<Result<_, _>>().unwrap()
+	}
+	
+	fn round_trip<C: SeqCodec>(codec: C) {
+		let records = records();
+		let encoded = codec.encode_to_vec(&records).unwrap();
+		assert_eq!(codec.decode(&encoded).unwrap(), records);
+		assert!(codec.decode(&codec.encode_to_vec(&[]).unwrap()).unwrap().is_empty());
+	}
+	
+	#[test]
+	fn fastq_round_trip() { round_trip(Fastq) }
+	
+	#[test]
+	fn two_bit_round_trip() { round_trip(TwoBit) }
+	
+	#[cfg(feature = "zstd")]
+	#[test]
+	fn zstd_round_trip() {
+		round_trip(ZstdFramed::new(Fastq));
+		round_trip(ZstdFramed::new(TwoBit).level(19));
+	}
+	
+	#[test]
+	fn two_bit_packs_bases() {
+		let records = Reader::new(&b"@r\nACGTA\n+\nIIIII\n"[..]).records().collect::<Result<Vec<_>, _>>().unwrap();
+		let encoded = TwoBit.encode_to_vec(&records).unwrap();
+		// count, header, length, packed bases, no other bases, qualities
+		assert_eq!(encoded, b"\x01\x01r\x05\x1b\x00\x00IIIII");
+	}
+	
+	#[test]
+	fn two_bit_rejects_truncated_input() {
+		let encoded = TwoBit.encode_to_vec(&records()).unwrap();
+		for len in 0..encoded.len() {
+			assert!(matches!(TwoBit.decode(&encoded[..len]), Err(CodecError::Corrupt(_))), "accepted {} of {} bytes", len, encoded.len());
+		}
+		let mut trailing = encoded.clone();
+		trailing.push(0);
+		assert!(matches!(TwoBit.decode(&trailing), Err(CodecError::Corrupt(_))));
+	}
+	
+	#[test]
+	fn two_bit_rejects_overflowing_positions() {
+		// one record with 2 bases, 2 other bases at distance 1 and 2^64 - 1
+		let mut data = b"\x01\x01r\x02\x00\x02\x01N".to_vec();
+		data.extend_from_slice(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01N");
+		data.extend_from_slice(b"II");
+		assert!(matches!(TwoBit.decode(&data), Err(CodecError::Corrupt(_))));
+	}
+	
+	#[test]
+	fn two_bit_rejects_unequal_lengths() {
+		let record = Record::from_lines("@r\n".to_owned(), "ACGT\n".to_owned(), "II\n".to_owned());
+		assert!(matches!(TwoBit.encode_to_vec(&[record]), Err(CodecError::Invalid(_))));
+	}
+}
@@ -0,0 +1,522 @@
+//! Record-level comparison of two FastQ streams.
+//!
+//! Records are matched by ID, or by a key derived from it. Streams are walked in lockstep
+//! and records that do not line up are kept aside until their counterpart shows up,
+//! so memory use grows with how far apart matching records are, not with file size.
+//!
+//! `compare_pairs` compares paired-end data the same way, and also tells which fragments lost a mate:
+//! a pipeline dropping one mate of a pair shows up in a single-file comparison as a missing record only.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use super::Record as RecordTrait;
+use super::quality::{Encoding, qual_to_phred33};
+use super::json::json_string;
+#[cfg(feature = "pipeline")]
+use super::paired::{mate_base_id, Mate};
+use super::seqcmp;
+
+/// The largest edit distance between differing sequences that is worked out for the report.
+pub const MAX_EDIT_DISTANCE: usize = 16;
+
+/// Options controlling which differences are reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompareOptions {
+	/// Do not compare qualities.
+	pub ignore_quality: bool,
+	/// Do not compare descriptions.
+	pub ignore_desc: bool,
+	/// Count a record whose sequence in the second stream is a stretch of the one in the first,
+	/// with the qualities of that stretch, as trimmed instead of as a mismatch.
+	pub allow_trimming: bool,
+	/// Detect records that moved relative to the others.
+	/// Needs 16 bytes per record and can be disabled for huge inputs.
+	pub detect_reordering: bool,
+	/// Maximum number of individual differences to keep in the report.
+	pub max_reported: usize,
+	/// Quality encoding of the first stream. Qualities are compared as Phred+33,
+	/// so a file can be compared against its conversion to another encoding.
+	pub encoding_a: Encoding,
+	/// Quality encoding of the second stream.
+	pub encoding_b: Encoding,
+}
+
+impl Default for CompareOptions {
+	fn default() -> CompareOptions {
+		CompareOptions { ignore_quality: false, ignore_desc: false, allow_trimming: false, detect_reordering: true, max_reported: 100,
+			encoding_a: Encoding::Sanger, encoding_b: Encoding::Sanger }
+	}
+}
+
+/// A single difference between the two streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Difference {
+	/// The record is only in the second stream.
+	MissingInA { id: String, index_b: usize },
+	/// The record is only in the first stream.
+	MissingInB { id: String, index_a: usize },
+	/// The sequences differ, by `distance` edits if that is at most `MAX_EDIT_DISTANCE`.
+	Sequence { id: String, index_a: usize, index_b: usize, distance: Option<usize> },
+	/// The qualities differ at `positions` positions.
+	Quality { id: String, index_a: usize, index_b: usize, positions: usize },
+	/// The descriptions differ.
+	Description { id: String, index_a: usize, index_b: usize },
+}
+
+impl Difference {
+	/// Render as a JSON object, in the form `serde` gives it: `{"Quality":{"id":"r1",…}}`.
+	pub fn to_json(&self) -> String {
+		let pair = |id: &str, index_a: usize, index_b: usize| format!("\"id\":{},\"index_a\":{},\"index_b\":{}", json_string(id), index_a, index_b);
+		match *self {
+			Difference::MissingInA { ref id, index_b } => format!("{{\"MissingInA\":{{\"id\":{},\"index_b\":{}}}}}", json_string(id), index_b),
+			Difference::MissingInB { ref id, index_a } => format!("{{\"MissingInB\":{{\"id\":{},\"index_a\":{}}}}}", json_string(id), index_a),
+			Difference::Sequence { ref id, index_a, index_b, distance } => format!("{{\"Sequence\":{{{},\"distance\":{}}}}}",
+				pair(id, index_a, index_b), distance.map_or("null".to_owned(), |d| d.to_string())),
+			Difference::Quality { ref id, index_a, index_b, positions } => format!("{{\"Quality\":{{{},\"positions\":{}}}}}", pair(id, index_a, index_b), positions),
+			Difference::Description { ref id, index_a, index_b } => format!("{{\"Description\":{{{}}}}}", pair(id, index_a, index_b)),
+		}
+	}
+}
+
+impl fmt::Display for Difference {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Difference::MissingInA { ref id, index_b } => write!(f, "{}: only in B (record {})", id, index_b),
+			Difference::MissingInB { ref id, index_a } => write!(f, "{}: only in A (record {})", id, index_a),
+			Difference::Sequence { ref id, distance: Some(d), .. } => write!(f, "{}: sequences differ by {} edits", id, d),
+			Difference::Sequence { ref id, distance: None, .. } => write!(f, "{}: sequences differ by more than {} edits", id, MAX_EDIT_DISTANCE),
+			Difference::Quality { ref id, positions, .. } => write!(f, "{}: qualities differ at {} positions", id, positions),
+			Difference::Description { ref id, .. } => write!(f, "{}: descriptions differ", id),
+		}
+	}
+}
+
+/// Summary and details of a comparison.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompareReport {
+	pub records_a: usize,
+	pub records_b: usize,
+	/// Records present in both streams without differences.
+	pub identical: usize,
+	/// Records trimmed in the second stream, if `allow_trimming` is set.
+	pub trimmed: usize,
+	pub missing_in_a: usize,
+	pub missing_in_b: usize,
+	pub sequence_mismatches: usize,
+	pub quality_mismatches: usize,
+	pub description_mismatches: usize,
+	/// Matched records that would have to move to restore the other stream’s order.
+	pub reordered: usize,
+	/// The first `max_reported` differences.
+	pub differences: Vec<Difference>,
+}
+
+impl CompareReport {
+	/// Check if both streams hold the same records, in any order, up to trimming if it is allowed.
+	pub fn is_equivalent(&self) -> bool {
+		self.missing_in_a == 0 && self.missing_in_b == 0
+			&& self.sequence_mismatches == 0 && self.quality_mismatches == 0 && self.description_mismatches == 0
+	}
+	
+	/// Check if both streams hold the same records in the same order.
+	pub fn is_identical(&self) -> bool {
+		self.is_equivalent() && self.reordered == 0
+	}
+	
+	/// Render as a JSON object, in the form `serde` gives it.
+	pub fn to_json(&self) -> String {
+		let counts = [
+			("records_a", self.records_a), ("records_b", self.records_b), ("identical", self.identical), ("trimmed", self.trimmed),
+			("missing_in_a", self.missing_in_a), ("missing_in_b", self.missing_in_b), ("sequence_mismatches", self.sequence_mismatches),
+			("quality_mismatches", self.quality_mismatches), ("description_mismatches", self.description_mismatches), ("reordered", self.reordered),
+		];
+		let counts: Vec<String> = counts.iter().map(|(name, n)| format!("\"{}\":{}", name, n)).collect();
+		let differences: Vec<String> = self.differences.iter().map(Difference::to_json).collect();
+		format!("{{{},\"differences\":[{}]}}", counts.join(","), differences.join(","))
+	}
+}
+
+impl fmt::Display for CompareReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "records in A:            {}", self.records_a)?;
+		writeln!(f, "records in B:            {}", self.records_b)?;
+		writeln!(f, "identical:               {}", self.identical)?;
+		writeln!(f, "trimmed:                 {}", self.trimmed)?;
+		writeln!(f, "only in A:               {}", self.missing_in_b)?;
+		writeln!(f, "only in B:               {}", self.missing_in_a)?;
+		writeln!(f, "sequence mismatches:     {}", self.sequence_mismatches)?;
+		writeln!(f, "quality mismatches:      {}", self.quality_mismatches)?;
+		writeln!(f, "description mismatches:  {}", self.description_mismatches)?;
+		writeln!(f, "reordered:               {}", self.reordered)?;
+		for d in &self.differences { writeln!(f, "  {}", d)? }
+		Ok(())
+	}
+}
+
+/// What is kept of a record until its counterpart is found.
+struct Pending {
+	index: usize,
+	desc: Option<String>,
+	seq: Vec<u8>,
+	qual: Vec<u8>,
+}
+
+impl Pending {
+	fn of<R: RecordTrait>(index: usize, record: &R, encoding: Encoding) -> Pending {
+		let mut qual = record.qual().to_vec();
+		// qualities with unprintable bytes are compared as they are, which shows them as mismatches
+		let _ = qual_to_phred33(&mut qual, encoding);
+		Pending { index, desc: record.desc().map(str::to_owned), seq: record.seq().to_vec(), qual }
+	}
+}
+
+struct Comparison<'o> {
+	options: &'o CompareOptions,
+	report: CompareReport,
+	matches: Vec<(usize, usize)>,
+	pending_a: HashMap<String, VecDeque<Pending>>,
+	pending_b: HashMap<String, VecDeque<Pending>>,
+}
+
+impl<'o> Comparison<'o> {
+	fn new(options: &'o CompareOptions) -> Comparison<'o> {
+		Comparison { options, report: CompareReport::default(), matches: Vec::new(), pending_a: HashMap::new(), pending_b: HashMap::new() }
+	}
+	
+	/// Compare the next records of both streams by their keys, or keep them aside until their counterparts show up.
+	fn step<RA: RecordTrait, RB: RecordTrait>(&mut self, a: Option<(String, &RA)>, b: Option<(String, &RB)>) {
+		let pa = a.map(|(key, r)| {
+			self.report.records_a += 1;
+			(key, Pending::of(self.report.records_a - 1, r, self.options.encoding_a))
+		});
+		let pb = b.map(|(key, r)| {
+			self.report.records_b += 1;
+			(key, Pending::of(self.report.records_b - 1, r, self.options.encoding_b))
+		});
+		match (pa, pb) {
+			(Some((ida, pa)), Some((idb, pb))) if ida == idb => self.compare(&ida, &pa, &pb),
+			(pa, pb) => {
+				if let Some((id, pa)) = pa {
+					match take(&mut self.pending_b, &id) {
+						Some(pb) => self.compare(&id, &pa, &pb),
+						None => self.pending_a.entry(id).or_default().push_back(pa),
+					}
+				}
+				if let Some((id, pb)) = pb {
+					match take(&mut self.pending_a, &id) {
+						Some(pa) => self.compare(&id, &pa, &pb),
+						None => self.pending_b.entry(id).or_default().push_back(pb),
+					}
+				}
+			},
+		}
+	}
+	
+	/// Count the records left without a counterpart, and the reordered ones.
+	fn finish(mut self) -> CompareReport {
+		let mut only_a: Vec<(String, usize)> = self.pending_a.drain().flat_map(|(id, q)| q.into_iter().map(move |p| (id.clone(), p.index))).collect();
+		let mut only_b: Vec<(String, usize)> = self.pending_b.drain().flat_map(|(id, q)| q.into_iter().map(move |p| (id.clone(), p.index))).collect();
+		only_a.sort_by_key(|&(_, i)| i);
+		only_b.sort_by_key(|&(_, i)| i);
+		self.report.missing_in_b = only_a.len();
+		self.report.missing_in_a = only_b.len();
+		for (id, index_a) in only_a { self.report(Difference::MissingInB { id, index_a }) }
+		for (id, index_b) in only_b { self.report(Difference::MissingInA { id, index_b }) }
+		
+		if self.options.detect_reordering {
+			self.matches.sort_unstable();
+			let in_order = longest_increasing(self.matches.iter().map(|&(_, b)| b));
+			self.report.reordered = self.matches.len() - in_order;
+		}
+		self.report
+	}
+	
+	fn report(&mut self, difference: Difference) {
+		if self.report.differences.len() < self.options.max_reported { self.report.differences.push(difference) }
+	}
+	
+	fn compare(&mut self, id: &str, a: &Pending, b: &Pending) {
+		if self.options.detect_reordering { self.matches.push((a.index, b.index)) }
+		let (index_a, index_b) = (a.index, b.index);
+		let mut identical = true;
+		if a.seq != b.seq && self.options.allow_trimming && self.is_trimmed(a, b) {
+			identical = false;
+			self.report.trimmed += 1;
+		} else if a.seq != b.seq {
+			identical = false;
+			self.report.sequence_mismatches += 1;
+			if self.report.differences.len() < self.options.max_reported {
+				let distance = seqcmp::edit_distance(&a.seq, &b.seq, MAX_EDIT_DISTANCE);
+				self.report(Difference::Sequence { id: id.to_owned(), index_a, index_b, distance });
+			}
+		} else if !self.options.ignore_quality && a.qual != b.qual {
+			identical = false;
+			self.report.quality_mismatches += 1;
+			let positions = a.qual.iter().zip(&b.qual).filter(|&(x, y)| x != y).count() + a.qual.len().abs_diff(b.qual.len());
+			self.report(Difference::Quality { id: id.to_owned(), index_a, index_b, positions });
+		}
+		if !self.options.ignore_desc && a.desc != b.desc {
+			identical = false;
+			self.report.description_mismatches += 1;
+			self.report(Difference::Description { id: id.to_owned(), index_a, index_b });
+		}
+		if identical { self.report.identical += 1 }
+	}
+	
+	/// Check if `b` is a stretch of `a`, with the same qualities unless they are ignored.
+	fn is_trimmed(&self, a: &Pending, b: &Pending) -> bool {
+		let len = b.seq.len();
+		len <= a.seq.len() && (0..=a.seq.len() - len).any(|start| {
+			a.seq[start..start + len] == b.seq[..]
+				&& (self.options.ignore_quality || a.qual.get(start..start + len) == Some(&b.qual[..]))
+		})
+	}
+}
+
+fn take(pending: &mut HashMap<String, VecDeque<Pending>>, id: &str) -> Option<Pending> {
+	let queue = pending.get_mut(id)?;
+	let p = queue.pop_front();
+	if queue.is_empty() { pending.remove(id); }
+	p
+}
+
+/// Compare two record streams, matching records by ID.
+pub fn compare<A, B, RA, RB>(a: A, b: B, options: &CompareOptions) -> CompareReport
+	where A: IntoIterator<Item=RA>, B: IntoIterator<Item=RB>, RA: RecordTrait, RB: RecordTrait
+{
+	compare_by_key(a, b, options, str::to_owned)
+}
+
+/// Compare two record streams, matching records whose IDs have the same key,
+/// e.g. `|id| paired::mate_base_id(id).to_owned()` to ignore mate suffixes.
+/// Differences name records by their key.
+pub fn compare_by_key<A, B, RA, RB, K>(a: A, b: B, options: &CompareOptions, mut key: K) -> CompareReport
+	where A: IntoIterator<Item=RA>, B: IntoIterator<Item=RB>, RA: RecordTrait, RB: RecordTrait, K: FnMut(&str) -> String
+{
+	let mut cmp = Comparison::new(options);
+	let (mut a, mut b) = (a.into_iter(), b.into_iter());
+	loop {
+		let (ra, rb) = (a.next(), b.next());
+		if ra.is_none() && rb.is_none() { break }
+		let ka = ra.as_ref().map(|r| key(r.id().unwrap_or("")));
+		let kb = rb.as_ref().map(|r| key(r.id().unwrap_or("")));
+		cmp.step(ka.zip(ra.as_ref()), kb.zip(rb.as_ref()));
+	}
+	cmp.finish()
+}
+
+/// One of the two compared inputs.
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Side {
+	A,
+	B,
+}
+
+/// A fragment with both mates in one input, but only one in the other.
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DroppedMate {
+	/// The fragment ID, without mate suffix.
+	pub id: String,
+	/// The mate that is missing.
+	pub mate: Mate,
+	/// The input it is missing from.
+	pub dropped_in: Side,
+}
+
+#[cfg(feature = "pipeline")]
+impl fmt::Display for DroppedMate {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}: {} dropped in {:?}", self.id, self.mate, self.dropped_in)
+	}
+}
+
+/// Summary and details of a comparison of two pairs of mate files.
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PairedCompareReport {
+	/// The comparison of the R1 files, with records matched by fragment ID.
+	pub r1: CompareReport,
+	/// The comparison of the R2 files, with records matched by fragment ID.
+	pub r2: CompareReport,
+	/// Fragments with both mates in both inputs.
+	pub complete: usize,
+	/// Fragments in A only.
+	pub only_in_a: usize,
+	/// Fragments in B only.
+	pub only_in_b: usize,
+	/// Fragments complete in B that lost a mate in A.
+	pub mate_dropped_in_a: usize,
+	/// Fragments complete in A that lost a mate in B.
+	pub mate_dropped_in_b: usize,
+	/// Fragments with at most one mate in either input.
+	pub incomplete_in_both: usize,
+	/// The first `max_reported` fragments that lost a mate, by ID.
+	pub dropped_mates: Vec<DroppedMate>,
+}
+
+#[cfg(feature = "pipeline")]
+impl PairedCompareReport {
+	/// Check if both inputs hold the same fragments, in any order, up to trimming if it is allowed.
+	pub fn is_equivalent(&self) -> bool {
+		self.r1.is_equivalent() && self.r2.is_equivalent()
+	}
+	
+	/// Check if both inputs hold the same fragments in the same order.
+	pub fn is_identical(&self) -> bool {
+		self.r1.is_identical() && self.r2.is_identical()
+	}
+	
+	/// Render as a JSON object, in the form `serde` gives it.
+	pub fn to_json(&self) -> String {
+		let dropped: Vec<String> = self.dropped_mates.iter()
+			.map(|d| format!("{{\"id\":{},\"mate\":\"{}\",\"dropped_in\":\"{:?}\"}}", json_string(&d.id), d.mate, d.dropped_in))
+			.collect();
+		format!("{{\"r1\":{},\"r2\":{},\"complete\":{},\"only_in_a\":{},\"only_in_b\":{},\"mate_dropped_in_a\":{},\"mate_dropped_in_b\":{},\
+			\"incomplete_in_both\":{},\"dropped_mates\":[{}]}}",
+			self.r1.to_json(), self.r2.to_json(), self.complete, self.only_in_a, self.only_in_b,
+			self.mate_dropped_in_a, self.mate_dropped_in_b, self.incomplete_in_both, dropped.join(","))
+	}
+}
+
+#[cfg(feature = "pipeline")]
+impl fmt::Display for PairedCompareReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "complete fragments:      {}", self.complete)?;
+		writeln!(f, "only in A:               {}", self.only_in_a)?;
+		writeln!(f, "only in B:               {}", self.only_in_b)?;
+		writeln!(f, "mate dropped in A:       {}", self.mate_dropped_in_a)?;
+		writeln!(f, "mate dropped in B:       {}", self.mate_dropped_in_b)?;
+		writeln!(f, "incomplete in both:      {}", self.incomplete_in_both)?;
+		for d in &self.dropped_mates { writeln!(f, "  {}", d)? }
+		for (mate, report) in [("R1", &self.r1), ("R2", &self.r2)] {
+			writeln!(f, "{}:", mate)?;
+			for line in report.to_string().lines() { writeln!(f, "  {}", line)? }
+		}
+		Ok(())
+	}
+}
+
+/// Which of the four streams a fragment was seen in, as bits: A R1, A R2, B R1, B R2.
+#[cfg(feature = "pipeline")]
+const ALL_MATES: u8 = 0b1111;
+
+/// Compare two pairs of mate streams, matching records by fragment ID (the read ID without `/1` or `/2`).
+///
+/// Besides the record differences of each mate, this counts the fragments that lost a mate in one of the inputs.
+/// The four streams are walked in lockstep, so memory use grows with how far apart matching records are.
+#[cfg(feature = "pipeline")]
+pub fn compare_pairs<A1, A2, B1, B2, RA, RB>(a_r1: A1, a_r2: A2, b_r1: B1, b_r2: B2, options: &CompareOptions) -> PairedCompareReport
+	where A1: IntoIterator<Item=RA>, A2: IntoIterator<Item=RA>, B1: IntoIterator<Item=RB>, B2: IntoIterator<Item=RB>, RA: RecordTrait, RB: RecordTrait
+{
+	fn key<R: RecordTrait>(record: &Option<R>) -> Option<String> {
+		record.as_ref().map(|r| mate_base_id(r.id().unwrap_or("")).to_owned())
+	}
+	
+	let (mut cmp1, mut cmp2) = (Comparison::new(options), Comparison::new(options));
+	let (mut a1, mut a2, mut b1, mut b2) = (a_r1.into_iter(), a_r2.into_iter(), b_r1.into_iter(), b_r2.into_iter());
+	// fragments not yet seen in all four streams
+	let mut seen: HashMap<String, u8> = HashMap::new();
+	let mut complete = 0;
+	loop {
+		let (ra1, ra2, rb1, rb2) = (a1.next(), a2.next(), b1.next(), b2.next());
+		if ra1.is_none() && ra2.is_none() && rb1.is_none() && rb2.is_none() { break }
+		let (ka1, ka2, kb1, kb2) = (key(&ra1), key(&ra2), key(&rb1), key(&rb2));
+		for (k, bit) in [(&ka1, 0b0001), (&ka2, 0b0010), (&kb1, 0b0100), (&kb2, 0b1000)] {
+			let Some(k) = k else { continue };
+			let bits = seen.entry(k.clone()).or_insert(0);
+			*bits |= bit;
+			if *bits == ALL_MATES {
+				seen.remove(k);
+				complete += 1;
+			}
+		}
+		cmp1.step(ka1.zip(ra1.as_ref()), kb1.zip(rb1.as_ref()));
+		cmp2.step(ka2.zip(ra2.as_ref()), kb2.zip(rb2.as_ref()));
+	}
+	
+	let mut report = PairedCompareReport { r1: cmp1.finish(), r2: cmp2.finish(), complete, ..PairedCompareReport::default() };
+	let mut incomplete: Vec<(String, u8)> = seen.into_iter().collect();
+	incomplete.sort();
+	for (id, bits) in incomplete {
+		let (a, b) = (bits & 0b11, bits >> 2);
+		let (mate, dropped_in) = match (a, b) {
+			(0b11, 0) => { report.only_in_a += 1; continue },
+			(0, 0b11) => { report.only_in_b += 1; continue },
+			(0b11, 0b01) => (Mate::R2, Side::B),
+			(0b11, 0b10) => (Mate::R1, Side::B),
+			(0b01, 0b11) => (Mate::R2, Side::A),
+			(0b10, 0b11) => (Mate::R1, Side::A),
+			_ => { report.incomplete_in_both += 1; continue },
+		};
+		match dropped_in {
+			Side::A => report.mate_dropped_in_a += 1,
+			Side::B => report.mate_dropped_in_b += 1,
+		}
+		if report.dropped_mates.len() < options.max_reported { report.dropped_mates.push(DroppedMate { id, mate, dropped_in }) }
+	}
+	report
+}
+
+/// Length of the longest strictly increasing subsequence (patience sorting).
+fn longest_increasing<I: Iterator<Item=usize>>(values: I) -> usize {
+	let mut tails: Vec<usize> = Vec::new();
+	for v in values {
+		match tails.binary_search(&v) {
+			Ok(_) => {},
+			Err(i) if i == tails.len() => tails.push(v),
+			Err(i) => tails[i] = v,
+		}
+	}
+	tails.len()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::Reader;
+	
+	#[test]
+	fn report_as_json() {
+		let a = Reader::new(&b"@r1\nACGT\n+\nIIII\n@r\"2\nGG\n+\nII\n"[..]).records().map(Result::unwrap);
+		let b = Reader::new(&b"@r\"2\nGA\n+\nII\n@r3\nA\n+\nI\n"[..]).records().map(Result::unwrap);
+		let report = compare(a, b, &CompareOptions::default());
+		assert_eq!(report.to_json(), "{\"records_a\":2,\"records_b\":2,\"identical\":0,\"trimmed\":0,\
+			\"missing_in_a\":1,\"missing_in_b\":1,\"sequence_mismatches\":1,\"quality_mismatches\":0,\"description_mismatches\":0,\"reordered\":0,\
+			\"differences\":[{\"Sequence\":{\"id\":\"r\\\"2\",\"index_a\":1,\"index_b\":0,\"distance\":1}},\
+			{\"MissingInB\":{\"id\":\"r1\",\"index_a\":0}},{\"MissingInA\":{\"id\":\"r3\",\"index_b\":1}}]}");
+	}
+	
+	#[cfg(feature = "pipeline")]
+	#[test]
+	fn pairs_with_dropped_mates() {
+		use crate::unfancy_parser::Record;
+		
+		let reads = |ids: &[&str], mate: usize| -> Vec<Record> {
+			let text: String = ids.iter().map(|id| format!("@{}/{}\nACGT\n+\nIIII\n", id, mate)).collect();
+			Reader::new(text.as_bytes()).records().map(Result::unwrap).collect()
+		};
+		// f3 loses R2 in B, f4 loses R1 in A, f5 is only in A, f6 has a single mate everywhere
+		let report = compare_pairs(
+			reads(&["f1", "f2", "f3", "f5", "f6"], 1), reads(&["f1", "f2", "f3", "f4", "f5"], 2),
+			reads(&["f1", "f2", "f3", "f4"], 1), reads(&["f1", "f2", "f4", "f6"], 2),
+			&CompareOptions::default(),
+		);
+		assert_eq!((report.complete, report.only_in_a, report.only_in_b), (2, 1, 0));
+		assert_eq!((report.mate_dropped_in_a, report.mate_dropped_in_b, report.incomplete_in_both), (1, 1, 1));
+		assert_eq!(report.dropped_mates, [
+			DroppedMate { id: "f3".to_owned(), mate: Mate::R2, dropped_in: Side::B },
+			DroppedMate { id: "f4".to_owned(), mate: Mate::R1, dropped_in: Side::A },
+		]);
+		assert_eq!((report.r1.missing_in_a, report.r1.missing_in_b, report.r2.missing_in_a, report.r2.missing_in_b), (1, 2, 1, 2));
+		assert!(!report.is_equivalent());
+		assert!(report.to_string().contains("  f3: R2 dropped in B\n"), "{}", report);
+	}
+}
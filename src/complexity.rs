@@ -0,0 +1,35 @@
+//! Sequence complexity measures, shared by read filters and per-read statistics.
+
+use std::collections::HashMap;
+
+/// Counts of the trinucleotides in a sequence.
+fn trinucleotides(seq: &[u8]) -> HashMap<[u8; 3], usize> {
+	let mut counts = HashMap::new();
+	for w in seq.windows(3) {
+		let triplet = [w[0].to_ascii_uppercase(), w[1].to_ascii_uppercase(), w[2].to_ascii_uppercase()];
+		if triplet.contains(&b'N') { continue }
+		*counts.entry(triplet).or_insert(0) += 1;
+	}
+	counts
+}
+
+/// The DUST score of a sequence, see `filter::Complexity::Dust`.
+pub fn dust_score(seq: &[u8]) -> f64 {
+	let counts = trinucleotides(seq);
+	let l: usize = counts.values().sum();
+	if l < 2 { return 0. }
+	let pairs: usize = counts.values().map(|&c| c * (c - 1) / 2).sum();
+	pairs as f64 / (l - 1) as f64
+}
+
+/// The trinucleotide entropy of a sequence from 0 to 1, see `filter::Complexity::Entropy`.
+pub fn entropy(seq: &[u8]) -> f64 {
+	let counts = trinucleotides(seq);
+	let l: usize = counts.values().sum();
+	if l < 2 { return 1. }
+	let h: f64 = counts.values().map(|&c| {
+		let p = c as f64 / l as f64;
+		-p * p.log2()
+	}).sum();
+	h / (l.min(64) as f64).log2()
+}
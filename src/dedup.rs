@@ -0,0 +1,166 @@
+//! Removing duplicate reads, e.g. PCR duplicates, while streaming.
+//!
+//! Reads are keyed by sequence, a sequence prefix, or ID. Seen keys are kept in a hash set,
+//! or in a Bloom filter of bounded size that may drop a few unique reads as false positives.
+
+use std::collections::HashSet;
+
+use super::Record as RecordTrait;
+use super::rng::hash64;
+use super::unfancy_parser::{Record, Error};
+
+/// What makes two reads duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupKey {
+	/// The same sequence.
+	#[default]
+	Sequence,
+	/// The same first `k` bases. Shorter reads are keyed by their whole sequence.
+	Prefix(usize),
+	/// The same read ID.
+	Id,
+}
+
+impl DedupKey {
+	fn of<'r>(&self, record: &'r Record) -> &'r [u8] {
+		match *self {
+			DedupKey::Sequence => record.seq(),
+			DedupKey::Prefix(k) => &record.seq()[..k.min(record.seq_len())],
+			DedupKey::Id => record.id().unwrap_or("").as_bytes(),
+		}
+	}
+}
+
+/// A Bloom filter sized for an expected number of keys and false positive rate.
+#[derive(Debug, Clone)]
+struct BloomFilter {
+	bits: Vec<u64>,
+	hashes: u32,
+}
+
+impl BloomFilter {
+	fn new(expected: usize, false_positive_rate: f64) -> BloomFilter {
+		let n = expected.max(1) as f64;
+		let p = false_positive_rate.clamp(1e-12, 0.5);
+		let m = (-n * p.ln() / std::f64::consts::LN_2.powi(2)).ceil().max(64.);
+		let hashes = ((m / n) * std::f64::consts::LN_2).round().clamp(1., 32.) as u32;
+		BloomFilter { bits: vec![0; (m as usize).div_ceil(64)], hashes }
+	}
+	
+	/// Add a key, returning whether it was (probably) present already.
+	fn insert(&mut self, key: &[u8]) -> bool {
+		let len = self.bits.len() as u64 * 64;
+		let (h1, h2) = (hash64(key, 0), hash64(key, 0x9E37_79B9_7F4A_7C15) | 1);
+		let mut present = true;
+		for i in 0..self.hashes as u64 {
+			let bit = h1.wrapping_add(i.wrapping_mul(h2)) % len;
+			let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+			present &= self.bits[word] & mask != 0;
+			self.bits[word] |= mask;
+		}
+		present
+	}
+}
+
+/// The keys seen so far.
+enum Seen {
+	Exact(HashSet<Vec<u8>>),
+	Bloom(BloomFilter),
+}
+
+/// An iterator adapter dropping duplicate reads, see `dedup`.
+pub struct Dedup<I> {
+	records: I,
+	key: DedupKey,
+	seen: Seen,
+	duplicates: u64,
+}
+
+impl<I> Dedup<I> {
+	/// Set what makes reads duplicates.
+	pub fn key(mut self, key: DedupKey) -> Self {
+		self.key = key;
+		self
+	}
+	
+	/// Remember keys in a Bloom filter sized for `expected` unique reads, using bounded memory.
+	/// About `false_positive_rate` of unique reads are dropped as supposed duplicates.
+	pub fn bloom(mut self, expected: usize, false_positive_rate: f64) -> Self {
+		self.seen = Seen::Bloom(BloomFilter::new(expected, false_positive_rate));
+		self
+	}
+	
+	/// Number of reads dropped so far.
+	pub fn duplicates(&self) -> u64 { self.duplicates }
+}
+
+impl<I> Iterator for Dedup<I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		loop {
+			let record = match self.records.next()? {
+				Ok(record) => record,
+				Err(e) => return Some(Err(e)),
+			};
+			let key = self.key.of(&record);
+			let duplicate = match self.seen {
+				Seen::Exact(ref mut set) => !set.insert(key.to_vec()),
+				Seen::Bloom(ref mut filter) => filter.insert(key),
+			};
+			if !duplicate { return Some(Ok(record)) }
+			self.duplicates += 1;
+		}
+	}
+}
+
+/// Drop reads with a sequence seen before, keeping the first occurrence.
+pub fn dedup<I>(records: I) -> Dedup<I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	Dedup { records: records.into_iter(), key: DedupKey::default(), seen: Seen::Exact(HashSet::new()), duplicates: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::Reader;
+	
+	fn records(reads: &[(&str, &str)]) -> Vec<Result<Record, Error>> {
+		let text: String = reads.iter().map(|(id, seq)| format!("@{}\n{}\n+\n{}\n", id, seq, "I".repeat(seq.len()))).collect();
+		Reader::new(std::io::Cursor::new(text)).records().collect()
+	}
+	
+	fn ids<I: Iterator<Item=Result<Record, Error>>>(records: I) -> Vec<String> {
+		records.map(|r| r.unwrap().id().unwrap().to_owned()).collect()
+	}
+	
+	const READS: &[(&str, &str)] = &[("a", "ACGTAC"), ("b", "ACGTAC"), ("c", "ACGTTT"), ("a", "GG"), ("d", "AC"), ("e", "ACGTAC")];
+	
+	#[test]
+	fn keys() {
+		let mut by_seq = dedup(records(READS));
+		assert_eq!(ids(by_seq.by_ref()), ["a", "c", "a", "d"]);
+		assert_eq!(by_seq.duplicates(), 2);
+		assert_eq!(ids(dedup(records(READS)).key(DedupKey::Prefix(4))), ["a", "a", "d"]);
+		// reads shorter than the prefix are keyed by their whole sequence
+		assert_eq!(ids(dedup(records(READS)).key(DedupKey::Prefix(3))), ["a", "a", "d"]);
+		assert_eq!(ids(dedup(records(READS)).key(DedupKey::Id)), ["a", "b", "c", "d", "e"]);
+	}
+	
+	#[test]
+	fn bloom_filter_finds_all_duplicates() {
+		assert_eq!(ids(dedup(records(READS)).bloom(100, 0.01)), ["a", "c", "a", "d"]);
+		let mut filter = BloomFilter::new(1000, 0.01);
+		let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+		let false_positives = keys.iter().filter(|k| filter.insert(k.as_bytes())).count();
+		assert!(false_positives < 30, "{} false positives", false_positives);
+		assert!(keys.iter().all(|k| filter.insert(k.as_bytes())));
+	}
+	
+	#[test]
+	fn passes_errors_on() {
+		let mut input = records(&[("a", "A"), ("b", "A")]);
+		input.insert(1, Err(Error::MissingAt(crate::Position::start())));
+		let result: Vec<_> = dedup(input).collect();
+		assert!(matches!(result[..], [Ok(_), Err(Error::MissingAt(_))]), "{:?}", result);
+	}
+}
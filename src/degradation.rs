@@ -0,0 +1,325 @@
+//! Watching quality along a file to catch instrument problems mid-run.
+//!
+//! Records are summarized in windows of consecutive records. Each window is compared to the ones just before it,
+//! catching sudden shifts like a bubble or a lost tile, and to the first windows of the file,
+//! catching slow degradation like reagents running out. Reads in a FastQ file are mostly in the order
+//! they were sequenced, so where an event is found in the file tells roughly when it happened in the run.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::Record as RecordTrait;
+use super::quality::{Encoding, Quality};
+use super::unfancy_parser::{Record, Error};
+
+/// The number of preceding windows whose steps a window’s step is compared to for shifts.
+const HISTORY_WINDOWS: usize = 10;
+/// The number of windows at the start of the file that degradation is measured against.
+const BASELINE_WINDOWS: usize = 5;
+
+/// The summary of a window of consecutive records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowSummary {
+	/// The index of the window’s first record.
+	pub first_record: u64,
+	pub records: u64,
+	/// The mean Phred score, `NaN` if the window had no bases.
+	pub mean_quality: f64,
+	/// The fraction of bases below Q20.
+	pub low_quality_fraction: f64,
+	/// The fraction of `N` bases.
+	pub n_fraction: f64,
+}
+
+impl WindowSummary {
+	/// The value of a metric.
+	pub fn get(&self, metric: Metric) -> f64 {
+		match metric {
+			Metric::MeanQuality => self.mean_quality,
+			Metric::LowQualityFraction => self.low_quality_fraction,
+			Metric::NFraction => self.n_fraction,
+		}
+	}
+}
+
+/// What a `DegradationDetector` tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Metric {
+	MeanQuality,
+	LowQualityFraction,
+	NFraction,
+}
+
+impl Metric {
+	const ALL: [Metric; 3] = [Metric::MeanQuality, Metric::LowQualityFraction, Metric::NFraction];
+	
+	/// The smallest change worth reporting, so that very steady files do not report noise.
+	fn min_change(self) -> f64 {
+		match self {
+			Metric::MeanQuality => 2.,
+			Metric::LowQualityFraction => 0.02,
+			Metric::NFraction => 0.005,
+		}
+	}
+	
+	/// How far the metric may get worse than at the start of the file before it counts as degraded.
+	fn tolerance(self) -> f64 {
+		match self {
+			Metric::MeanQuality => 3.,
+			Metric::LowQualityFraction => 0.05,
+			Metric::NFraction => 0.01,
+		}
+	}
+	
+	/// How much worse `value` is than `reference`.
+	fn worsening(self, reference: f64, value: f64) -> f64 {
+		match self {
+			Metric::MeanQuality => reference - value,
+			Metric::LowQualityFraction | Metric::NFraction => value - reference,
+		}
+	}
+}
+
+impl fmt::Display for Metric {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			Metric::MeanQuality => "mean quality",
+			Metric::LowQualityFraction => "fraction of bases below Q20",
+			Metric::NFraction => "fraction of N bases",
+		};
+		f.write_str(name)
+	}
+}
+
+/// How a metric changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventKind {
+	/// A sudden change from the previous window, for better or worse.
+	Shift,
+	/// Worse than at the start of the file by more than a tolerance.
+	/// Reported when the metric first gets that bad, and again only after it recovered in between.
+	Degradation,
+}
+
+/// A notable change found by a `DegradationDetector`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DegradationEvent {
+	pub kind: EventKind,
+	pub metric: Metric,
+	/// The window where the change was seen, counting from 0.
+	pub window: usize,
+	/// The index of the window’s first record.
+	pub first_record: u64,
+	/// The value the window was compared to.
+	pub expected: f64,
+	/// The value in the window.
+	pub observed: f64,
+}
+
+impl fmt::Display for DegradationEvent {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let how = match self.kind {
+			EventKind::Shift => "shifted",
+			EventKind::Degradation => "degraded",
+		};
+		write!(f, "record {}: {} {} from {:.3} to {:.3}", self.first_record, self.metric, how, self.expected, self.observed)
+	}
+}
+
+/// Summarizes windows of records and reports shifts and degradation as they occur.
+#[derive(Debug, Clone)]
+pub struct DegradationDetector {
+	window: u64,
+	encoding: Encoding,
+	sensitivity: f64,
+	records: u64,
+	bases: u64,
+	qual_sum: u64,
+	qual_bases: u64,
+	low_quality: u64,
+	n: u64,
+	windows: Vec<WindowSummary>,
+	history: VecDeque<WindowSummary>,
+	events: Vec<DegradationEvent>,
+	degraded: Vec<Metric>,
+}
+
+impl Default for DegradationDetector {
+	fn default() -> DegradationDetector { DegradationDetector::new() }
+}
+
+impl DegradationDetector {
+	/// A detector for Phred+33 qualities with windows of 10 000 records.
+	pub fn new() -> DegradationDetector {
+		DegradationDetector {
+			window: 10_000, encoding: Encoding::Sanger, sensitivity: 4.,
+			records: 0, bases: 0, qual_sum: 0, qual_bases: 0, low_quality: 0, n: 0,
+			windows: Vec::new(), history: VecDeque::new(), events: Vec::new(), degraded: Vec::new(),
+		}
+	}
+	
+	/// Summarize windows of `records` records (at least 1). Smaller windows react sooner but are noisier.
+	pub fn window(mut self, records: u64) -> Self {
+		self.window = records.max(1);
+		self
+	}
+	
+	/// Read qualities in another encoding.
+	pub fn encoding(mut self, encoding: Encoding) -> Self {
+		self.encoding = encoding;
+		self
+	}
+	
+	/// Report steps between windows of more than about `sigmas` standard deviations of the steps before (default 4).
+	pub fn sensitivity(mut self, sigmas: f64) -> Self {
+		self.sensitivity = sigmas;
+		self
+	}
+	
+	/// Account for the next record.
+	pub fn add<R: RecordTrait>(&mut self, record: &R) {
+		for &b in record.seq() {
+			if b.eq_ignore_ascii_case(&b'N') { self.n += 1 }
+		}
+		self.bases += record.seq().len() as u64;
+		for &c in record.qual() {
+			if let Some(q) = Quality::from_ascii(c, self.encoding) {
+				self.qual_sum += u64::from(q.score());
+				self.qual_bases += 1;
+				if q.score() < 20 { self.low_quality += 1 }
+			}
+		}
+		self.records += 1;
+		if self.records == self.window { self.end_window() }
+	}
+	
+	/// Summarize the records after the last full window. Only a window of at least half the size is checked,
+	/// as smaller ones are too noisy.
+	pub fn finish(&mut self) {
+		if self.records == 0 { return }
+		let check = self.records * 2 >= self.window;
+		let summary = self.summarize();
+		if check { self.check(&summary) }
+		self.windows.push(summary);
+	}
+	
+	/// The windows summarized so far.
+	pub fn windows(&self) -> &[WindowSummary] { &self.windows }
+	
+	/// The events found so far, in file order.
+	pub fn events(&self) -> &[DegradationEvent] { &self.events }
+	
+	fn end_window(&mut self) {
+		let summary = self.summarize();
+		self.check(&summary);
+		self.windows.push(summary);
+		self.history.push_back(summary);
+		if self.history.len() > HISTORY_WINDOWS { self.history.pop_front(); }
+	}
+	
+	fn summarize(&mut self) -> WindowSummary {
+		let fraction = |n: u64, of: u64| if of == 0 { f64::NAN } else { n as f64 / of as f64 };
+		let first_record = self.windows.last().map_or(0, |w| w.first_record + w.records);
+		let summary = WindowSummary {
+			first_record,
+			records: self.records,
+			mean_quality: fraction(self.qual_sum, self.qual_bases),
+			low_quality_fraction: fraction(self.low_quality, self.qual_bases),
+			n_fraction: fraction(self.n, self.bases),
+		};
+		self.records = 0;
+		self.bases = 0;
+		self.qual_sum = 0;
+		self.qual_bases = 0;
+		self.low_quality = 0;
+		self.n = 0;
+		summary
+	}
+	
+	fn check(&mut self, summary: &WindowSummary) {
+		let window = self.windows.len();
+		for metric in Metric::ALL {
+			let value = summary.get(metric);
+			if value.is_nan() { continue }
+			let event = |kind, expected| DegradationEvent { kind, metric, window, first_record: summary.first_record, expected, observed: value };
+			// a shift is a step from the previous window much larger than the steps between the windows before it
+			let values: Vec<f64> = self.history.iter().map(|w| w.get(metric)).collect();
+			if let (true, Some(&previous)) = (values.len() >= 3, values.last()) {
+				let (_, spread) = median_spread(values.windows(2).map(|pair| pair[1] - pair[0]));
+				if (value - previous).abs() > (self.sensitivity * spread).max(metric.min_change()) {
+					self.events.push(event(EventKind::Shift, previous));
+				}
+			}
+			if window < BASELINE_WINDOWS { continue }
+			let (baseline, _) = median_spread(self.windows[..BASELINE_WINDOWS].iter().map(|w| w.get(metric)));
+			let degraded = metric.worsening(baseline, value) > metric.tolerance();
+			match self.degraded.iter().position(|&m| m == metric) {
+				None if degraded => {
+					self.degraded.push(metric);
+					self.events.push(event(EventKind::Degradation, baseline));
+				},
+				Some(i) if !degraded => { self.degraded.swap_remove(i); },
+				_ => {},
+			}
+		}
+	}
+}
+
+/// Median and standard deviation estimated from the median absolute deviation, ignoring `NaN`s.
+/// Unlike mean and standard deviation, they are not thrown off by a single outlier.
+fn median_spread<I: Iterator<Item=f64>>(values: I) -> (f64, f64) {
+	let mut values: Vec<f64> = values.filter(|v| !v.is_nan()).collect();
+	let center = median(&mut values);
+	let mut deviations: Vec<f64> = values.iter().map(|v| (v - center).abs()).collect();
+	(center, 1.4826 * median(&mut deviations))
+}
+
+fn median(values: &mut [f64]) -> f64 {
+	if values.is_empty() { return f64::NAN }
+	values.sort_by(f64::total_cmp);
+	let mid = values.len() / 2;
+	if values.len().is_multiple_of(2) { (values[mid - 1] + values[mid]) / 2. } else { values[mid] }
+}
+
+/// An iterator adapter passing records through a `DegradationDetector`, see `watch_degradation`.
+pub struct DegradationWatch<I> {
+	records: I,
+	detector: DegradationDetector,
+}
+
+impl<I> DegradationWatch<I> {
+	/// The detector, with the events found so far.
+	pub fn detector(&self) -> &DegradationDetector { &self.detector }
+	
+	/// Return the detector.
+	pub fn into_detector(self) -> DegradationDetector { self.detector }
+}
+
+impl<I> Iterator for DegradationWatch<I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		match self.records.next() {
+			Some(Ok(record)) => {
+				self.detector.add(&record);
+				Some(Ok(record))
+			},
+			Some(Err(e)) => Some(Err(e)),
+			None => {
+				self.detector.finish();
+				None
+			},
+		}
+	}
+}
+
+/// Watch the records passing through for quality degradation,
+/// e.g. `watch_degradation(records, DegradationDetector::new().window(50_000))`.
+/// The last, partial window is checked when the records run out.
+pub fn watch_degradation<I>(records: I, detector: DegradationDetector) -> DegradationWatch<I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	DegradationWatch { records: records.into_iter(), detector }
+}
@@ -0,0 +1,508 @@
+//! Demultiplexing reads into per-sample outputs by barcode.
+//!
+//! Paired reads are assigned by their first mate’s barcode and written to per-sample file pairs,
+//! named by a template such as `{sample}_{mate}.fastq.gz`.
+//! With the `zstd` feature, `.zst` outputs can share a dictionary trained on the first pairs,
+//! which shrinks the thousands of tiny files of a large sample sheet considerably.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+#[cfg(feature = "gzip")]
+use flate2::{Compression, write::GzEncoder};
+
+use super::Record as RecordTrait;
+use super::paired::{Mate, PairError};
+use super::paths::{long_dir, long_path};
+use super::unfancy_parser::{Record, Error, Writer};
+
+/// The default file name template of `Demultiplexer::demux_pairs_to_dir`.
+pub const PAIR_TEMPLATE: &str = "{sample}_{mate}.fastq.gz";
+
+/// The name of the shared dictionary `.zst` outputs need to be decompressed with, see `Demultiplexer::zstd_dictionary`.
+#[cfg(feature = "zstd")]
+pub const DICTIONARY_FILE: &str = "dictionary.zstd";
+
+/// The maximum size of a trained dictionary, as for `zstd --train`.
+#[cfg(feature = "zstd")]
+const DICTIONARY_SIZE: usize = 110 * 1024;
+
+/// Barcodes and the samples they belong to.
+///
+/// The text form has one barcode per line: barcode and sample name, separated by a tab or comma.
+/// Dual indices are written as one barcode joined by `+`, as in Casava headers.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampleSheet {
+	barcodes: Vec<(Vec<u8>, String)>,
+}
+
+impl SampleSheet {
+	/// Create an empty sample sheet.
+	pub fn new() -> SampleSheet { SampleSheet::default() }
+	
+	/// Read a sample sheet from its text form.
+	pub fn from_reader<R: BufRead>(reader: R) -> io::Result<SampleSheet> {
+		let mut sheet = SampleSheet::new();
+		for (i, line) in reader.lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() || line.starts_with('#') { continue }
+			let (barcode, sample) = line.trim_end().split_once(['\t', ','])
+				.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: expected <barcode>\\t<sample>", i + 1)))?;
+			sheet.insert(barcode.trim(), sample.trim());
+		}
+		Ok(sheet)
+	}
+	
+	/// Read a sample sheet from a file.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<SampleSheet> {
+		SampleSheet::from_reader(io::BufReader::new(fs::File::open(long_path(path))?))
+	}
+	
+	/// Assign a barcode to a sample. A sample can have several barcodes.
+	pub fn insert(&mut self, barcode: &str, sample: &str) {
+		self.barcodes.push((barcode.as_bytes().to_ascii_uppercase(), sample.to_owned()));
+	}
+	
+	/// The names of all samples, without duplicates.
+	pub fn samples(&self) -> Vec<&str> {
+		let mut samples: Vec<&str> = Vec::new();
+		for (_, sample) in &self.barcodes {
+			if !samples.contains(&sample.as_str()) { samples.push(sample) }
+		}
+		samples
+	}
+	
+	/// Number of barcodes in the sheet.
+	pub fn len(&self) -> usize { self.barcodes.len() }
+	
+	/// Check if the sheet has no barcodes.
+	pub fn is_empty(&self) -> bool { self.barcodes.is_empty() }
+	
+	/// Check that every sample name can be used in a file name of its own.
+	/// Names that are empty, contain path separators, or are `.`, `..` or `undetermined`
+	/// (in any case) would write outside the output directory or into another sample’s file.
+	fn check_file_names(&self) -> io::Result<()> {
+		self.samples().into_iter().try_for_each(|sample| check_file_name("Sample", sample, "undetermined"))
+	}
+}
+
+/// Where the barcode of a read is found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarcodeSource {
+	/// The index in the header: the last `:`-separated field of a Casava 1.8 description
+	/// (`1:N:0:ACGT+TTGA`), or the part after `#` in older IDs (`…#ACGT/1`).
+	Header,
+	/// The first bases of the read, which are cut off.
+	ReadStart(usize),
+}
+
+/// Number of reads (or pairs) written per sample.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DemuxCounts {
+	pub samples: BTreeMap<String, u64>,
+	/// Reads without barcode, with an unknown one, or matching several samples equally well.
+	/// Pairs whose mates’ header barcodes point to different samples are undetermined as well.
+	pub undetermined: u64,
+}
+
+impl DemuxCounts {
+	/// The number of reads (or pairs) assigned to a sample or undetermined.
+	pub fn total(&self) -> u64 { self.samples.values().sum::<u64>() + self.undetermined }
+	
+	/// Write a TSV manifest of the file pairs `demux_pairs_to_dir` wrote with `template`:
+	/// a header line, then sample, pair count and the names of both files, with `undetermined` last.
+	pub fn write_manifest<W: Write>(&self, out: W, template: &str) -> io::Result<()> {
+		let mut out = io::BufWriter::new(out);
+		writeln!(out, "sample\tpairs\tr1\tr2")?;
+		let rows = self.samples.iter().map(|(sample, &n)| (sample.as_str(), n)).chain([("undetermined", self.undetermined)]);
+		for (sample, n) in rows {
+			let name = |mate: Mate| template.replace("{sample}", sample).replace("{mate}", &mate.to_string());
+			writeln!(out, "{}\t{}\t{}\t{}", sample, n, name(Mate::R1), name(Mate::R2))?;
+		}
+		out.flush()
+	}
+}
+
+/// The writers of each sample’s mates.
+type PairWriters<W> = HashMap<String, (Writer<W>, Writer<W>)>;
+
+/// Assigns reads to samples by barcode, tolerating mismatches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Demultiplexer {
+	sheet: SampleSheet,
+	source: BarcodeSource,
+	max_mismatches: usize,
+	#[cfg(feature = "zstd")]
+	dictionary_pairs: usize,
+}
+
+impl Demultiplexer {
+	/// Demultiplex by exact barcode matches.
+	pub fn new(sheet: SampleSheet, source: BarcodeSource) -> Demultiplexer {
+		Demultiplexer { sheet, source, max_mismatches: 0, #[cfg(feature = "zstd")] dictionary_pairs: 0 }
+	}
+	
+	/// Accept barcodes with up to `n` mismatches if they are closest to a single sample.
+	pub fn max_mismatches(mut self, n: usize) -> Self {
+		self.max_mismatches = n;
+		self
+	}
+	
+	/// Train a zstd dictionary on the first `pairs` pairs and compress all `.zst` outputs of `demux_pairs_to_dir` with it.
+	/// The dictionary is written to `DICTIONARY_FILE` in the output directory, e.g. for `zstd -D dictionary.zstd -d`.
+	/// If there are too few pairs to train on, the outputs are compressed without dictionary and none is written.
+	#[cfg(feature = "zstd")]
+	pub fn zstd_dictionary(mut self, pairs: usize) -> Self {
+		self.dictionary_pairs = pairs;
+		self
+	}
+	
+	/// The barcode of a record, cutting it off if it is part of the read.
+	fn barcode(&self, record: &mut Record) -> Option<Vec<u8>> {
+		match self.source {
+			BarcodeSource::Header => {
+				let index = match record.desc().and_then(|d| d.rsplit_once(':')) {
+					Some((_, index)) => index,
+					None => record.id()?.split_once('#')?.1.split('/').next()?,
+				};
+				Some(index.as_bytes().to_ascii_uppercase())
+			},
+			BarcodeSource::ReadStart(len) => {
+				if record.seq_len() < len { return None }
+				let barcode = record.seq()[..len].to_ascii_uppercase();
+				record.keep_range(len..record.seq_len());
+				Some(barcode)
+			},
+		}
+	}
+	
+	/// The sample a barcode belongs to, if exactly one is closest within the tolerance.
+	/// `N` in the barcode counts as mismatch.
+	pub fn sample_for(&self, barcode: &[u8]) -> Option<&str> {
+		let mut best: Option<(usize, &str)> = None;
+		let mut ambiguous = false;
+		for (known, sample) in &self.sheet.barcodes {
+			if known.len() != barcode.len() { continue }
+			let mismatches = known.iter().zip(barcode).filter(|&(k, b)| k != b || *b == b'N').count();
+			if mismatches > self.max_mismatches { continue }
+			match best {
+				Some((m, s)) if m == mismatches => ambiguous |= s != sample,
+				Some((m, _)) if m < mismatches => {},
+				_ => { best = Some((mismatches, sample)); ambiguous = false },
+			}
+		}
+		if ambiguous { None } else { best.map(|(_, s)| s) }
+	}
+	
+	/// The sample a record belongs to, cutting off a barcode at the read start.
+	pub fn assign(&self, record: &mut Record) -> Option<&str> {
+		self.sample_for(&self.barcode(record)?)
+	}
+	
+	/// The sample a read pair belongs to, by the barcode of the first mate, cutting off a barcode at its start.
+	/// With barcodes in the headers, the pair is undetermined if the second mate’s barcode points elsewhere.
+	pub fn assign_pair(&self, r1: &mut Record, r2: &mut Record) -> Option<&str> {
+		let sample = self.assign(r1)?;
+		if self.source == BarcodeSource::Header {
+			if let Some(barcode) = self.barcode(r2) {
+				if self.sample_for(&barcode) != Some(sample) { return None }
+			}
+		}
+		Some(sample)
+	}
+	
+	/// Write each record to its sample’s writer, or to `undetermined`.
+	/// Writers are opened with `open(sample)` when a sample’s first read comes along.
+	pub fn demux<I, W, F>(&self, records: I, mut open: F, undetermined: &mut Writer<W>) -> Result<DemuxCounts, Error>
+		where I: IntoIterator<Item=Result<Record, Error>>, W: Write, F: FnMut(&str) -> io::Result<Writer<W>>
+	{
+		let mut writers: HashMap<String, Writer<W>> = HashMap::new();
+		let mut counts = DemuxCounts::default();
+		for record in records {
+			let mut record = record?;
+			match self.assign(&mut record) {
+				Some(sample) => {
+					if !writers.contains_key(sample) { writers.insert(sample.to_owned(), open(sample)?); }
+					writers.get_mut(sample).unwrap().write_record(&record)?;
+					*counts.samples.entry(sample.to_owned()).or_insert(0) += 1;
+				},
+				None => {
+					undetermined.write_record(&record)?;
+					counts.undetermined += 1;
+				},
+			}
+		}
+		for writer in writers.values_mut() { writer.flush()? }
+		undetermined.flush()?;
+		Ok(counts)
+	}
+	
+	/// Write both mates of each pair to their sample’s writers, or to the `undetermined` pair of writers.
+	/// Writers are opened with `open(sample, mate)` when a sample’s first pair comes along.
+	pub fn demux_pairs<I, W, F>(&self, pairs: I, open: F, undetermined: &mut (Writer<W>, Writer<W>)) -> Result<DemuxCounts, PairError>
+		where I: IntoIterator<Item=Result<(Record, Record), PairError>>, W: Write, F: FnMut(&str, Mate) -> io::Result<Writer<W>>
+	{
+		let (counts, mut writers) = self.demux_pairs_into(pairs, open, undetermined, 0, |_| {})?;
+		for (w1, w2) in writers.values_mut() {
+			w1.flush()?;
+			w2.flush()?;
+		}
+		undetermined.0.flush()?;
+		undetermined.1.flush()?;
+		Ok(counts)
+	}
+	
+	/// Like `demux_pairs`, returning the sample writers instead of flushing them.
+	/// Calls `progress` every `interval` pairs unless it is 0.
+	fn demux_pairs_into<I, W, F, C>(&self, pairs: I, mut open: F, undetermined: &mut (Writer<W>, Writer<W>), interval: u64, mut progress: C) -> Result<(DemuxCounts, PairWriters<W>), PairError>
+		where I: IntoIterator<Item=Result<(Record, Record), PairError>>, W: Write, F: FnMut(&str, Mate) -> io::Result<Writer<W>>, C: FnMut(&DemuxCounts)
+	{
+		let mut writers: PairWriters<W> = HashMap::new();
+		let mut counts = DemuxCounts::default();
+		for pair in pairs {
+			let (mut r1, mut r2) = pair?;
+			let (w1, w2) = match self.assign_pair(&mut r1, &mut r2) {
+				Some(sample) => {
+					if !writers.contains_key(sample) {
+						let pair = (open(sample, Mate::R1)?, open(sample, Mate::R2)?);
+						writers.insert(sample.to_owned(), pair);
+					}
+					*counts.samples.entry(sample.to_owned()).or_insert(0) += 1;
+					writers.get_mut(sample).unwrap()
+				},
+				None => {
+					counts.undetermined += 1;
+					&mut *undetermined
+				},
+			};
+			w1.write_record(&r1)?;
+			w2.write_record(&r2)?;
+			if interval > 0 && counts.total().is_multiple_of(interval) { progress(&counts) }
+		}
+		Ok((counts, writers))
+	}
+	
+	/// Write a file pair per sample and an `undetermined` pair to `dir`, which is created if needed.
+	///
+	/// File names are made from `template` by replacing `{sample}` and `{mate}` (`R1` or `R2`), see `PAIR_TEMPLATE`.
+	/// Files whose names end with `.gz` are gzip compressed, which needs the `gzip` feature,
+	/// and ones ending with `.zst` are zstd compressed, which needs the `zstd` feature.
+	/// Fails with `InvalidInput` before writing anything if a sample name is no valid file name, e.g. `a/b` or `undetermined`.
+	pub fn demux_pairs_to_dir<I, P>(&self, pairs: I, dir: P, template: &str) -> Result<DemuxCounts, PairError>
+		where I: IntoIterator<Item=Result<(Record, Record), PairError>>, P: AsRef<Path>
+	{
+		self.demux_pairs_to_dir_with_progress(pairs, dir, template, 0, |_| {})
+	}
+	
+	/// Like `demux_pairs_to_dir`, calling `progress` with the counts so far every `interval` pairs.
+	pub fn demux_pairs_to_dir_with_progress<I, P, F>(&self, pairs: I, dir: P, template: &str, interval: u64, progress: F) -> Result<DemuxCounts, PairError>
+		where I: IntoIterator<Item=Result<(Record, Record), PairError>>, P: AsRef<Path>, F: FnMut(&DemuxCounts)
+	{
+		if !template.contains("{sample}") || !template.contains("{mate}") {
+			let msg = format!("File name template {:?} needs both {{sample}} and {{mate}}", template);
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, msg).into());
+		}
+		self.sheet.check_file_names()?;
+		let dir = long_dir(dir);
+		fs::create_dir_all(&dir)?;
+		// the pairs a dictionary is trained on are demultiplexed like the rest afterwards
+		let mut pairs = pairs.into_iter();
+		let (head, dictionary) = self.dictionary(&mut pairs, template, &dir)?;
+		let open = |sample: &str, mate: Mate| -> io::Result<Writer<Output>> {
+			let name = template.replace("{sample}", sample).replace("{mate}", &mate.to_string());
+			Output::create(&dir.join(name), &dictionary).map(Writer::new)
+		};
+		let mut undetermined = (open("undetermined", Mate::R1)?, open("undetermined", Mate::R2)?);
+		let (counts, writers) = self.demux_pairs_into(head.into_iter().chain(pairs), open, &mut undetermined, interval, progress)?;
+		for (w1, w2) in writers.into_values().chain([undetermined]) {
+			w1.into_inner()?.finish()?;
+			w2.into_inner()?.finish()?;
+		}
+		Ok(counts)
+	}
+	
+	/// The first pairs and the dictionary trained on them and written to `dir`, if `.zst` files are to be written with one.
+	#[cfg(feature = "zstd")]
+	fn dictionary<I>(&self, pairs: &mut I, template: &str, dir: &Path) -> io::Result<(Vec<I::Item>, Vec<u8>)>
+		where I: Iterator<Item=Result<(Record, Record), PairError>>
+	{
+		if self.dictionary_pairs == 0 || !template.ends_with(".zst") { return Ok((Vec::new(), Vec::new())) }
+		let head: Vec<_> = pairs.take(self.dictionary_pairs).collect();
+		let dictionary = train_dictionary(head.iter().flatten())?.unwrap_or_default();
+		if !dictionary.is_empty() { fs::write(dir.join(DICTIONARY_FILE), &dictionary)? }
+		Ok((head, dictionary))
+	}
+	
+	/// Without the `zstd` feature, there are no dictionaries.
+	#[cfg(not(feature = "zstd"))]
+	fn dictionary<I>(&self, _: &mut I, _: &str, _: &Path) -> io::Result<(Vec<I::Item>, Vec<u8>)> where I: Iterator {
+		Ok((Vec::new(), Vec::new()))
+	}
+	
+	/// Write `<sample>.fastq` files and `undetermined.fastq` to `dir`, which is created if needed.
+	/// Fails with `InvalidInput` before writing anything if a sample name is no valid file name.
+	pub fn demux_to_dir<I, P>(&self, records: I, dir: P) -> Result<DemuxCounts, Error>
+		where I: IntoIterator<Item=Result<Record, Error>>, P: AsRef<Path>
+	{
+		self.sheet.check_file_names()?;
+		let dir = long_dir(dir);
+		fs::create_dir_all(&dir)?;
+		let mut undetermined = Writer::new(fs::File::create(dir.join("undetermined.fastq"))?);
+		self.demux(records, |sample| Ok(Writer::new(fs::File::create(dir.join(format!("{}.fastq", sample)))?)), &mut undetermined)
+	}
+}
+
+/// Train a zstd dictionary on records as they are written, `None` if there are too few to train on.
+#[cfg(feature = "zstd")]
+fn train_dictionary<'a, I>(pairs: I) -> io::Result<Option<Vec<u8>>> where I: IntoIterator<Item=&'a (Record, Record)> {
+	let (mut samples, mut sizes) = (Vec::new(), Vec::new());
+	for (r1, r2) in pairs {
+		for record in [r1, r2] {
+			let start = samples.len();
+			let mut writer = Writer::new(&mut samples);
+			writer.write_record(record)?;
+			writer.flush()?;
+			drop(writer);
+			sizes.push(samples.len() - start);
+		}
+	}
+	Ok(zstd::dict::from_continuous(&samples, &sizes, DICTIONARY_SIZE).ok())
+}
+
+/// Check that `name` can be used in a file name of its own, i.e. is not empty, `.`, `..` or `reserved`
+/// (in any case), and contains no path separators. Fails with `InvalidInput` naming the `kind` of name otherwise.
+pub(crate) fn check_file_name(kind: &str, name: &str, reserved: &str) -> io::Result<()> {
+	if matches!(name, "" | "." | "..") || name.eq_ignore_ascii_case(reserved) || name.contains(['/', '\\', '\0']) {
+		let msg = format!("{} name {:?} cannot be used as a file name", kind, name);
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+	}
+	Ok(())
+}
+
+/// An output file of `demux_pairs_to_dir` or `amplicon::AmpliconBinner::bin_to_dir`, compressed if its name ends with `.gz` or `.zst`.
+pub(crate) enum Output {
+	Plain(fs::File),
+	#[cfg(feature = "gzip")]
+	Gzip(GzEncoder<fs::File>),
+	#[cfg(feature = "zstd")]
+	Zstd(zstd::Encoder<'static, fs::File>),
+}
+
+impl Output {
+	/// Create a file, compressing `.zst` files with `dictionary` unless it is empty.
+	#[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+	pub(crate) fn create(path: &Path, dictionary: &[u8]) -> io::Result<Output> {
+		let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+		let file = fs::File::create(path)?;
+		match extension {
+			#[cfg(feature = "gzip")]
+			"gz" => Ok(Output::Gzip(GzEncoder::new(file, Compression::default()))),
+			#[cfg(not(feature = "gzip"))]
+			"gz" => Err(io::Error::new(io::ErrorKind::Unsupported, "Writing .gz files needs the gzip feature")),
+			#[cfg(feature = "zstd")]
+			"zst" => zstd::Encoder::with_dictionary(file, zstd::DEFAULT_COMPRESSION_LEVEL, dictionary).map(Output::Zstd),
+			#[cfg(not(feature = "zstd"))]
+			"zst" => Err(io::Error::new(io::ErrorKind::Unsupported, "Writing .zst files needs the zstd feature")),
+			_ => Ok(Output::Plain(file)),
+		}
+	}
+	
+	/// Write the gzip trailer or end the zstd frame if compressed.
+	pub(crate) fn finish(self) -> io::Result<()> {
+		match self {
+			Output::Plain(_) => Ok(()),
+			#[cfg(feature = "gzip")]
+			Output::Gzip(gz) => gz.finish().map(drop),
+			#[cfg(feature = "zstd")]
+			Output::Zstd(zstd) => zstd.finish().map(drop),
+		}
+	}
+}
+
+impl Write for Output {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			Output::Plain(file) => file.write(buf),
+			#[cfg(feature = "gzip")]
+			Output::Gzip(gz) => gz.write(buf),
+			#[cfg(feature = "zstd")]
+			Output::Zstd(zstd) => zstd.write(buf),
+		}
+	}
+	
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			Output::Plain(file) => file.flush(),
+			#[cfg(feature = "gzip")]
+			Output::Gzip(gz) => gz.flush(),
+			#[cfg(feature = "zstd")]
+			Output::Zstd(zstd) => zstd.flush(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn rejects_sample_names_that_are_no_file_names() {
+		let dir = std::env::temp_dir().join(format!("fastq-demux-test-{}", std::process::id()));
+		for name in ["../x", "a/b", "a\\b", "..", "undetermined", "Undetermined", ""] {
+			let mut sheet = SampleSheet::new();
+			sheet.insert("ACGT", "fine");
+			sheet.insert("TTTT", name);
+			let demux = Demultiplexer::new(sheet, BarcodeSource::Header);
+			let single = demux.demux_to_dir(Vec::new(), &dir);
+			assert!(matches!(single, Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::InvalidInput), "{:?}", name);
+			let paired = demux.demux_pairs_to_dir(Vec::new(), &dir, "{sample}_{mate}.fastq");
+			assert!(matches!(paired, Err(PairError::Io(ref e)) if e.kind() == io::ErrorKind::InvalidInput), "{:?}", name);
+		}
+		assert!(!dir.exists());
+	}
+	
+	#[cfg(feature = "zstd")]
+	#[test]
+	fn zstd_outputs_share_a_dictionary() {
+		use super::super::paired::PairedReader;
+		use super::super::unfancy_parser::Reader;
+		let dir = std::env::temp_dir().join(format!("fastq-demux-zstd-test-{}", std::process::id()));
+		let barcodes: Vec<String> = (0..16).map(|i| (0..4).map(|j| "ACGT".as_bytes()[(i >> (2 * j)) & 3] as char).collect()).collect();
+		let mut sheet = SampleSheet::new();
+		for (i, barcode) in barcodes.iter().enumerate() { sheet.insert(barcode, &format!("s{}", i)) }
+		let mut state = 1u64;
+		let mut seq = || -> String { (0..100).map(|_| { state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407); "ACGT".as_bytes()[(state >> 62) as usize] as char }).collect() };
+		let mates = |mate: usize, seqs: &[String]| -> String {
+			seqs.iter().enumerate().map(|(i, s)| format!("@M00123:12:000000000-A1B2C:1:1101:{}:1332 {}:N:0:{}\n{}\n+\n{}\n", i, mate, barcodes[i % 16], s, "F".repeat(100))).collect()
+		};
+		let seqs: Vec<String> = (0..2000).map(|_| seq()).collect();
+		let (r1, r2) = (mates(1, &seqs), mates(2, &seqs));
+		let pairs = || PairedReader::new(Reader::new(r1.as_bytes()).records(), Reader::new(r2.as_bytes()).records());
+		let demux = Demultiplexer::new(sheet, BarcodeSource::Header);
+		let size = |dir: &Path| fs::read_dir(dir).unwrap().map(|e| e.unwrap().metadata().unwrap().len()).sum::<u64>();
+		
+		let counts = demux.demux_pairs_to_dir(pairs(), dir.join("plain"), "{sample}_{mate}.fq.zst").unwrap();
+		assert_eq!(counts.samples["s3"], 125);
+		assert!(!dir.join("plain").join(DICTIONARY_FILE).exists());
+		let demux = demux.zstd_dictionary(1000);
+		assert_eq!(demux.demux_pairs_to_dir(pairs(), dir.join("dict"), "{sample}_{mate}.fq.zst").unwrap(), counts);
+		let dictionary = fs::read(dir.join("dict").join(DICTIONARY_FILE)).unwrap();
+		let compressed = fs::read(dir.join("dict").join("s3_R2.fq.zst")).unwrap();
+		let decompressed = zstd::stream::Decoder::with_dictionary(&compressed[..], &dictionary).map(io::read_to_string).unwrap().unwrap();
+		let expected: String = mates(2, &seqs).split_inclusive('\n').collect::<Vec<_>>().chunks(4).skip(3).step_by(16).map(|c| c.concat()).collect();
+		assert_eq!(decompressed, expected);
+		assert!(zstd::decode_all(&compressed[..]).is_err());
+		// the dictionary is written once, while each of the 34 files gets smaller
+		assert!(size(&dir.join("dict")) - (dictionary.len() as u64) < size(&dir.join("plain")), "{} {}", size(&dir.join("dict")), size(&dir.join("plain")));
+		// too few pairs to train on
+		let (r1, r2) = (mates(1, &seqs[..1]), mates(2, &seqs[..1]));
+		let few = PairedReader::new(Reader::new(r1.as_bytes()).records(), Reader::new(r2.as_bytes()).records());
+		demux.demux_pairs_to_dir(few, dir.join("few"), "{sample}_{mate}.fq.zst").unwrap();
+		assert!(!dir.join("few").join(DICTIONARY_FILE).exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,147 @@
+//! An error type shared by all parsers, for code that reads with several of them.
+//!
+//! Each parser keeps its own error type, which converts into `Error`,
+//! so `?` works across parsers and failures can be matched by kind.
+
+use std::io;
+
+use super::{Position, Warning};
+use super::fancy_parser::ParseError;
+use super::unfancy_parser;
+
+quick_error! {
+	/// A failure to read FastQ, independent of the parser that encountered it.
+	#[derive(Debug)]
+	pub enum Error {
+		/// A record did not start with `@`. `found` is the byte instead, if known.
+		MissingAt(pos: Position, found: Option<u8>) {
+			description("No @ at record start")
+			display("{}: Expected @ at record start{}", pos, found_desc(*found))
+		}
+		/// The separator line did not start with `+`. `found` is the byte instead, if known.
+		MissingPlus(pos: Position, found: Option<u8>) {
+			description("No + after sequence")
+			display("{}: Expected + after sequence{}", pos, found_desc(*found))
+		}
+		/// Input ended in the middle of a record.
+		Incomplete(pos: Position) {
+			description("Incomplete record")
+			display("{}: Incomplete record", pos)
+		}
+		/// Sequence and qualities differ in length.
+		LengthMismatch(pos: Position, seq: usize, qual: usize) {
+			description("Sequence and qualities differ in length")
+			display("{}: Sequence has {} bases but {} qualities", pos, seq, qual)
+		}
+		/// A numeric quality was no integer from 0 to 93, see `ReaderOptions::numeric_quality`,
+		/// or a quality converted to Phred+33 was no printable character, see `quality::normalize_phred33`.
+		InvalidQuality(pos: Position, value: String) {
+			description("Invalid quality")
+			display("{}: Invalid quality {:?}", pos, value)
+		}
+		/// A record failed `Record::check` in a validating reader.
+		InvalidRecord(pos: Position, reason: String) {
+			description("Invalid record")
+			display("{}: Invalid record: {}", pos, reason)
+		}
+		/// A warning occurred with `ReaderOptions::warnings_fatal`.
+		Warning(pos: Position, warning: Warning) {
+			description("Warning treated as error")
+			display("{}: {}", pos, warning)
+		}
+		/// Reading from the underlying source failed.
+		Io(err: io::Error) {
+			from()
+			cause(err)
+			display("{}", err)
+		}
+	}
+}
+
+fn found_desc(found: Option<u8>) -> String {
+	found.map_or(String::new(), |b| format!(", found {:?}", b as char))
+}
+
+impl Error {
+	/// Where in the input the error was found, unless it is an I/O error.
+	pub fn position(&self) -> Option<Position> {
+		match *self {
+			Error::MissingAt(pos, _) | Error::MissingPlus(pos, _) | Error::Incomplete(pos) | Error::LengthMismatch(pos, _, _) | Error::InvalidQuality(pos, _) | Error::InvalidRecord(pos, _) | Error::Warning(pos, _) => Some(pos),
+			Error::Io(_) => None,
+		}
+	}
+}
+
+impl From<ParseError> for Error {
+	fn from(e: ParseError) -> Error {
+		match e {
+			ParseError::NoAt(pos, b) => Error::MissingAt(pos, Some(b)),
+			ParseError::NoPlus(pos, _, b) => Error::MissingPlus(pos, Some(b)),
+			ParseError::Incomplete(pos, _) => Error::Incomplete(pos),
+			ParseError::LengthMismatch(pos, seq, qual) => Error::LengthMismatch(pos, seq.len(), qual.len()),
+			ParseError::InvalidQuality(pos, value) => Error::InvalidQuality(pos, value),
+			ParseError::Warning(pos, warning) => Error::Warning(pos, warning),
+			ParseError::Io(_, e) => Error::Io(e),
+		}
+	}
+}
+
+impl From<unfancy_parser::Error> for Error {
+	fn from(e: unfancy_parser::Error) -> Error {
+		match e {
+			unfancy_parser::Error::MissingAt(pos) => Error::MissingAt(pos, None),
+			unfancy_parser::Error::MissingPlus(pos) => Error::MissingPlus(pos, None),
+			unfancy_parser::Error::IncompleteRecord(pos) => Error::Incomplete(pos),
+			unfancy_parser::Error::InvalidQuality(pos, value) => Error::InvalidQuality(pos, value),
+			unfancy_parser::Error::InvalidRecord(pos, reason) => Error::InvalidRecord(pos, reason),
+			unfancy_parser::Error::Warning(pos, warning) => Error::Warning(pos, warning),
+			unfancy_parser::Error::Io(e) => Error::Io(e),
+		}
+	}
+}
+
+/// How an `Error` is serialized: like itself, with I/O errors as their message.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+enum ErrorRepr {
+	MissingAt(Position, Option<u8>),
+	MissingPlus(Position, Option<u8>),
+	Incomplete(Position),
+	LengthMismatch(Position, usize, usize),
+	InvalidQuality(Position, String),
+	InvalidRecord(Position, String),
+	Warning(Position, Warning),
+	Io(String),
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match *self {
+			Error::MissingAt(pos, b) => ErrorRepr::MissingAt(pos, b),
+			Error::MissingPlus(pos, b) => ErrorRepr::MissingPlus(pos, b),
+			Error::Incomplete(pos) => ErrorRepr::Incomplete(pos),
+			Error::LengthMismatch(pos, s, q) => ErrorRepr::LengthMismatch(pos, s, q),
+			Error::InvalidQuality(pos, ref value) => ErrorRepr::InvalidQuality(pos, value.clone()),
+			Error::InvalidRecord(pos, ref reason) => ErrorRepr::InvalidRecord(pos, reason.clone()),
+			Error::Warning(pos, ref warning) => ErrorRepr::Warning(pos, warning.clone()),
+			Error::Io(ref e) => ErrorRepr::Io(e.to_string()),
+		}.serialize(serializer)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Error {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Error, D::Error> {
+		Ok(match ErrorRepr::deserialize(deserializer)? {
+			ErrorRepr::MissingAt(pos, b) => Error::MissingAt(pos, b),
+			ErrorRepr::MissingPlus(pos, b) => Error::MissingPlus(pos, b),
+			ErrorRepr::Incomplete(pos) => Error::Incomplete(pos),
+			ErrorRepr::LengthMismatch(pos, s, q) => Error::LengthMismatch(pos, s, q),
+			ErrorRepr::InvalidQuality(pos, value) => Error::InvalidQuality(pos, value),
+			ErrorRepr::InvalidRecord(pos, reason) => Error::InvalidRecord(pos, reason),
+			ErrorRepr::Warning(pos, warning) => Error::Warning(pos, warning),
+			ErrorRepr::Io(e) => Error::Io(io::Error::other(e)),
+		})
+	}
+}
@@ -0,0 +1,66 @@
+//! Hooks seeing every error before it is returned, to log or quarantine bad input in one place,
+//! see `unfancy_parser::Reader::on_error` and `on_error` for pipelines.
+
+use super::Position;
+use super::unfancy_parser::{Record, Error};
+
+/// What an error hook is told about an error.
+#[derive(Debug)]
+pub struct ErrorContext<'a> {
+	/// The error about to be returned.
+	pub error: &'a Error,
+	/// Where the error was found, if known.
+	pub position: Option<Position>,
+	/// The lines of the failing record as far as they were read, including line breaks.
+	/// Empty if they are not known, e.g. in a pipeline, where only the error is passed on.
+	pub raw: &'a [u8],
+}
+
+type Callback = Box<dyn FnMut(&ErrorContext) + Send>;
+
+/// The error hook of a reader.
+pub(crate) struct ErrorHook {
+	callback: Option<Callback>,
+}
+
+impl ErrorHook {
+	pub(crate) fn new() -> Self {
+		ErrorHook { callback: None }
+	}
+	
+	pub(crate) fn set<F>(&mut self, callback: F) where F: FnMut(&ErrorContext) + Send + 'static {
+		self.callback = Some(Box::new(callback));
+	}
+	
+	/// Call the hook with an error found at `pos`, and the raw lines collected by `raw` if it is set.
+	pub(crate) fn fire<'a, I>(&mut self, error: &Error, pos: Position, raw: I) where I: IntoIterator<Item=&'a [u8]> {
+		if let Some(callback) = &mut self.callback {
+			let raw: Vec<u8> = raw.into_iter().flatten().copied().collect();
+			callback(&ErrorContext { error, position: Some(error.position().unwrap_or(pos)), raw: &raw });
+		}
+	}
+}
+
+/// An iterator adapter calling a hook on the errors passing through, see `on_error`.
+pub struct OnError<I, F> {
+	records: I,
+	hook: F,
+}
+
+impl<I, F> Iterator for OnError<I, F> where I: Iterator<Item=Result<Record, Error>>, F: FnMut(&ErrorContext) {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		let next = self.records.next()?;
+		if let Err(error) = &next {
+			(self.hook)(&ErrorContext { error, position: error.position(), raw: &[] });
+		}
+		Some(next)
+	}
+}
+
+/// Call `hook` on every error of a pipeline stage before passing it on, however it is handled later.
+/// Raw bytes are only known to the reader, so hook that to see them.
+pub fn on_error<I, F>(records: I, hook: F) -> OnError<I::IntoIter, F> where I: IntoIterator<Item=Result<Record, Error>>, F: FnMut(&ErrorContext) {
+	OnError { records: records.into_iter(), hook }
+}
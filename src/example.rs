@@ -0,0 +1,22 @@
+//! Tiny FastQ datasets embedded in the crate, to try the API and write examples without data files.
+//!
+//! `unfancy_parser::Reader::example()` and the other readers’ `example()` read `FASTQ`.
+
+/// Eight Illumina records of 50 or 76 bases, some with a description.
+pub const FASTQ: &str = include_str!("data/example.fastq");
+
+/// The records of `FASTQ` as two concatenated gzip members, the way `bgzip` or appending to a `.gz` file writes them.
+#[cfg(feature = "gzip")]
+pub const FASTQ_GZ: &[u8] = include_bytes!("data/example.fastq.gz");
+
+/// The first mates of four read pairs of 50 bases.
+pub const PAIRED_R1: &str = include_str!("data/example_r1.fastq");
+
+/// The second mates of the pairs in `PAIRED_R1`, in the same order.
+pub const PAIRED_R2: &str = include_str!("data/example_r2.fastq");
+
+/// The number of records in `FASTQ`.
+pub const RECORDS: usize = 8;
+
+/// The number of pairs in `PAIRED_R1` and `PAIRED_R2`.
+pub const PAIRS: usize = 4;
@@ -0,0 +1,114 @@
+//! Typed side data that travels along with records.
+
+use std::any::{Any, TypeId};
+use std::fmt;
+
+/// A value that can be attached to a record, at most one per type.
+pub trait Extension: Any + Clone + Send + Sync {
+	/// Render the value as a `key=value` description field,
+	/// or return `None` to keep it out of serialized descriptions.
+	fn to_tag(&self) -> Option<String> { None }
+}
+
+trait AnyExtension: Send + Sync {
+	fn as_any(&self) -> &dyn Any;
+	fn as_any_mut(&mut self) -> &mut dyn Any;
+	fn clone_box(&self) -> Box<dyn AnyExtension>;
+	fn tag(&self) -> Option<String>;
+}
+
+impl<T: Extension> AnyExtension for T {
+	fn as_any(&self) -> &dyn Any { self }
+	fn as_any_mut(&mut self) -> &mut dyn Any { self }
+	fn clone_box(&self) -> Box<dyn AnyExtension> { Box::new(self.clone()) }
+	fn tag(&self) -> Option<String> { self.to_tag() }
+}
+
+/// A set of extensions keyed by type, kept in insertion order.
+///
+/// Extensions do not take part in record equality.
+#[derive(Default)]
+pub struct Extensions {
+	values: Vec<(TypeId, Box<dyn AnyExtension>)>,
+}
+
+impl Extensions {
+	/// Create an empty set.
+	pub fn new() -> Extensions { Extensions::default() }
+	
+	/// Attach a value, returning the previous value of that type.
+	pub fn insert<T: Extension>(&mut self, value: T) -> Option<T> {
+		match self.get_mut::<T>() {
+			Some(old) => Some(std::mem::replace(old, value)),
+			None => { self.values.push((TypeId::of::<T>(), Box::new(value))); None },
+		}
+	}
+	
+	/// Get the value of a type.
+	pub fn get<T: Extension>(&self) -> Option<&T> {
+		self.values.iter().find(|&&(id, _)| id == TypeId::of::<T>()).and_then(|(_, v)| v.as_any().downcast_ref())
+	}
+	
+	/// Get the value of a type mutably.
+	pub fn get_mut<T: Extension>(&mut self) -> Option<&mut T> {
+		self.values.iter_mut().find(|&&mut (id, _)| id == TypeId::of::<T>()).and_then(|(_, v)| v.as_any_mut().downcast_mut())
+	}
+	
+	/// Remove and return the value of a type.
+	pub fn remove<T: Extension>(&mut self) -> Option<T> {
+		let i = self.values.iter().position(|&(id, _)| id == TypeId::of::<T>())?;
+		let (_, value) = self.values.remove(i);
+		value.as_any().downcast_ref().cloned()
+	}
+	
+	/// The description fields of all extensions that render one.
+	pub fn tags(&self) -> Vec<String> {
+		self.values.iter().filter_map(|(_, v)| v.tag()).collect()
+	}
+	
+	/// Number of attached extensions.
+	pub fn len(&self) -> usize { self.values.len() }
+	
+	/// Check if no extensions are attached.
+	pub fn is_empty(&self) -> bool { self.values.is_empty() }
+	
+	/// Remove all extensions.
+	pub fn clear(&mut self) { self.values.clear() }
+}
+
+impl Clone for Extensions {
+	fn clone(&self) -> Extensions {
+		Extensions { values: self.values.iter().map(|(id, v)| (*id, v.clone_box())).collect() }
+	}
+}
+
+impl PartialEq for Extensions {
+	fn eq(&self, _: &Extensions) -> bool { true }
+}
+
+impl fmt::Debug for Extensions {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Extensions({} values)", self.values.len())
+	}
+}
+
+/// A taxonomic classification, serialized as `taxid=<id>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaxLabel {
+	/// NCBI taxonomy ID.
+	pub taxid: u32,
+	/// Optional scientific name of the taxon.
+	pub name: Option<String>,
+}
+
+impl Extension for TaxLabel {
+	fn to_tag(&self) -> Option<String> { Some(format!("taxid={}", self.taxid)) }
+}
+
+/// A unique molecular identifier, serialized as `umi=<sequence>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Umi(pub String);
+
+impl Extension for Umi {
+	fn to_tag(&self) -> Option<String> { Some(format!("umi={}", self.0)) }
+}
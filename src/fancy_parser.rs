@@ -1,32 +1,60 @@
-use std::ascii::AsciiExt;
-use std::error::Error;
-use std::io::{self,Write,BufRead};
+use std::collections::VecDeque;
+use std::io::{self,BufRead};
+use std::path::Path;
 
+use super::Record as RecordTrait;
+use super::buffer;
+use super::builder::{self, RecordError};
+use super::input::Input;
+use super::quality::numeric_to_phred33;
+use super::unfancy_parser;
+use super::progress::ProgressHook;
+use super::{Position, Progress, ReaderOptions, TextPolicy, Warning};
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Record {
 	id: String,
 	desc: Option<String>,
 	seq: String,
 	qual: String,
+	#[cfg_attr(feature = "serde", serde(default))]
+	offset: Option<u64>,
 }
 
 impl Record {
 	pub fn from_strings(id: String, desc: Option<String>, seq: String, qual: String) -> Record {
-		Record { id: id, desc: desc, seq: seq, qual: qual }
+		Record { id, desc, seq, qual, offset: None }
+	}
+	
+	/// Set the byte offset the record was read from.
+	pub(crate) fn at_offset(self, offset: u64) -> Record {
+		Record { offset: Some(offset), ..self }
+	}
+}
+
+impl From<unfancy_parser::Record> for Record {
+	fn from(r: unfancy_parser::Record) -> Record {
+		let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+		let offset = r.offset();
+		let id = r.id().unwrap_or("").to_owned();
+		Record { id, desc: r.desc().map(str::to_owned), seq: text(r.seq()), qual: text(r.qual()), offset }
 	}
 }
 
 impl super::Record for Record {
 	fn new() -> Record {
-		Record { id: String::new(), desc: None, seq: String::new(), qual: String::new() }
+		Record { id: String::new(), desc: None, seq: String::new(), qual: String::new(), offset: None }
 	}
 	
 	fn id(&self) -> Option<&str> { Some(self.id.as_ref()) }
-	fn desc(&self) -> Option<&str> { self.desc.as_ref().map(|s| s.as_str()) }
+	fn desc(&self) -> Option<&str> { self.desc.as_deref() }
 	fn seq(&self) -> &[u8] { self.seq.as_bytes() }
 	fn qual(&self) -> &[u8] { self.qual.as_bytes() }
+	fn offset(&self) -> Option<u64> { self.offset }
 	
 	fn check(&self) -> Result<(), &'static str> {
-		if !self.id.is_empty() {
+		if self.id.is_empty() {
 			return Err("Expecting id for FastQ record.");
 		}
 		if !self.seq.is_ascii() {
@@ -51,32 +79,62 @@ impl super::Record for Record {
 		self.desc = None;
 		self.seq.clear();
 		self.qual.clear();
+		self.offset = None;
+	}
+}
+
+impl super::RecordMut for Record {
+	fn set_id(&mut self, id: &str) -> Result<(), RecordError> {
+		builder::check_id(id)?;
+		self.id = id.to_owned();
+		Ok(())
+	}
+	
+	fn set_desc(&mut self, desc: Option<&str>) -> Result<(), RecordError> {
+		if let Some(desc) = desc { builder::check_desc(desc)? }
+		self.desc = desc.filter(|d| !d.is_empty()).map(str::to_owned);
+		Ok(())
+	}
+	
+	fn set_seq_qual(&mut self, seq: &[u8], qual: &[u8]) -> Result<(), RecordError> {
+		builder::check_seq_qual(seq, qual)?;
+		let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+		self.seq = text(seq);
+		self.qual = text(qual);
+		Ok(())
 	}
 }
 
 quick_error!(
 	#[derive(Debug)]
 	pub enum ParseError {
-		NoAt(byte: u8) {
+		NoAt(pos: Position, byte: u8) {
 			description("No @ at FASTQ start")
-			display("Encountered {:?} instead of @", byte)
+			display("{}: Encountered {:?} instead of @", pos, byte)
 		}
-		NoPlus(prev: String, byte: u8) {
+		NoPlus(pos: Position, prev: String, byte: u8) {
 			description("No + after FASTQ sequence")
-			display("Encountered {:?} instead of + after {:?}", byte, prev)
+			display("{}: Encountered {:?} instead of + after {:?}", pos, byte, prev)
 		}
-		Incomplete(prev: String) {
+		Incomplete(pos: Position, prev: String) {
 			description("Incomplete FASTQ record")
-			display("Premature EOF after {:?}", prev)
+			display("{}: Premature EOF after {:?}", pos, prev)
 		}
-		LengthMismatch(a: String, b: String) {
+		LengthMismatch(pos: Position, a: String, b: String) {
 			description("FASTQ with differing length")
-			display("The lengths do not match:\nseq:  {:?}\nqual: {:?}", a, b)
+			display("{}: The lengths do not match:\nseq:  {:?}\nqual: {:?}", pos, a, b)
+		}
+		InvalidQuality(pos: Position, value: String) {
+			description("Invalid numeric FASTQ quality")
+			display("{}: Invalid numeric quality {:?}", pos, value)
+		}
+		Warning(pos: Position, warning: Warning) {
+			description("FASTQ warning treated as error")
+			display("{}: {}", pos, warning)
 		}
-		Io(err: io::Error) {
-			from()
+		Io(pos: Position, err: io::Error) {
 			cause(err)
-			description(err.description())
+			display("{}: {}", pos, err)
 		}
 	}
 );
@@ -91,74 +149,613 @@ quick_error!(
 	}
 );
 
-impl<'e> From<&'e io::Error> for ParseError {
-	fn from(e: &io::Error) -> ParseError {
-		ParseError::Io(io::Error::new(e.kind(), FakeError::Inner(e.description().to_owned(), format!("{}", e))))
+impl ParseError {
+	/// Where in the input the error was found.
+	pub fn position(&self) -> Position {
+		use self::ParseError::*;
+		match *self {
+			NoAt(pos, _) | NoPlus(pos, _, _) | Incomplete(pos, _) | LengthMismatch(pos, _, _) | InvalidQuality(pos, _) | Warning(pos, _) | Io(pos, _) => pos,
+		}
 	}
 }
 
+/// A copy of an I/O error, keeping its kind and message.
+fn clone_io(e: &io::Error) -> io::Error {
+	io::Error::new(e.kind(), FakeError::Inner(e.to_string(), format!("{}", e)))
+}
+
 impl Clone for ParseError {
 	fn clone(&self) -> ParseError {
 		use self::ParseError::*;
 		match *self {
-			NoAt(b)	=> NoAt(b),
-			NoPlus(ref p, b)	=> NoPlus(p.clone(), b),
-			Incomplete(ref p)	=> Incomplete(p.clone()),
-			LengthMismatch(ref s, ref q)	=> LengthMismatch(s.clone(), q.clone()),
-			Io(ref e)	=> e.into(),
+			NoAt(pos, b)	=> NoAt(pos, b),
+			NoPlus(pos, ref p, b)	=> NoPlus(pos, p.clone(), b),
+			Incomplete(pos, ref p)	=> Incomplete(pos, p.clone()),
+			LengthMismatch(pos, ref s, ref q)	=> LengthMismatch(pos, s.clone(), q.clone()),
+			InvalidQuality(pos, ref v)	=> InvalidQuality(pos, v.clone()),
+			Warning(pos, ref w)	=> Warning(pos, w.clone()),
+			Io(pos, ref e)	=> Io(pos, clone_io(e)),
 		}
 	}
 }
 
+/// How a `ParseError` is serialized: like itself, with I/O errors as their message.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+enum ParseErrorRepr {
+	NoAt(Position, u8),
+	NoPlus(Position, String, u8),
+	Incomplete(Position, String),
+	LengthMismatch(Position, String, String),
+	InvalidQuality(Position, String),
+	Warning(Position, Warning),
+	Io(Position, String),
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ParseError {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		use self::ParseError::*;
+		match self.clone() {
+			NoAt(pos, b)	=> ParseErrorRepr::NoAt(pos, b),
+			NoPlus(pos, p, b)	=> ParseErrorRepr::NoPlus(pos, p, b),
+			Incomplete(pos, p)	=> ParseErrorRepr::Incomplete(pos, p),
+			LengthMismatch(pos, s, q)	=> ParseErrorRepr::LengthMismatch(pos, s, q),
+			InvalidQuality(pos, v)	=> ParseErrorRepr::InvalidQuality(pos, v),
+			Warning(pos, w)	=> ParseErrorRepr::Warning(pos, w),
+			Io(pos, e)	=> ParseErrorRepr::Io(pos, e.to_string()),
+		}.serialize(serializer)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ParseError {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<ParseError, D::Error> {
+		use self::ParseError::*;
+		Ok(match ParseErrorRepr::deserialize(deserializer)? {
+			ParseErrorRepr::NoAt(pos, b)	=> NoAt(pos, b),
+			ParseErrorRepr::NoPlus(pos, p, b)	=> NoPlus(pos, p, b),
+			ParseErrorRepr::Incomplete(pos, p)	=> Incomplete(pos, p),
+			ParseErrorRepr::LengthMismatch(pos, s, q)	=> LengthMismatch(pos, s, q),
+			ParseErrorRepr::InvalidQuality(pos, v)	=> InvalidQuality(pos, v),
+			ParseErrorRepr::Warning(pos, w)	=> Warning(pos, w),
+			ParseErrorRepr::Io(pos, e)	=> Io(pos, io::Error::other(e)),
+		})
+	}
+}
+
 macro_rules! try_some(($expr:expr) => {
 	match $expr {
 		Ok(v)  => v,
-		Err(e) => return Some(Err(e.into())),
+		Err(e) => return Some(Err(e)),
 	}
 });
 
-pub struct FastqReader<R>(pub R);
+/// A reader producing `Record`s, keeping track of its position for error messages.
+pub struct FastqReader<R> {
+	reader: R,
+	pos: Position,
+	numeric_quality: bool,
+	strip_whitespace: bool,
+	text: TextPolicy,
+	/// Whether `read_record` stripped whitespace from the last record, until `Records` warns about it.
+	stripped: bool,
+	progress: ProgressHook<R>,
+	/// The warning about what `skip_preamble` skipped, until `Records` takes it.
+	preamble: Option<Warning>,
+}
 
-impl<R: BufRead> Iterator for FastqReader<R> {
-	type Item = Result<Record, ParseError>;
+impl FastqReader<io::BufReader<Input>> {
+	/// Read from a given file, decompressing it if it is gzip or bgzip compressed.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Input::open(path).map(FastqReader::from_input)
+	}
 	
-	fn next(&mut self) -> Option<Result<Record, ParseError>> {
-		let &mut FastqReader(ref mut it) = self;
+	/// Read from a given gzip or bgzip compressed file.
+	pub fn from_gz<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Input::open_gz(path).map(FastqReader::from_input)
+	}
+	
+	fn from_input(input: Input) -> Self {
+		let size = input.file_size();
+		let mut reader = FastqReader::new(io::BufReader::new(input));
+		reader.progress = reader.progress.sized(size, |r| r.get_ref().compressed_offset());
+		reader
+	}
+}
+
+#[cfg(feature = "example-data")]
+impl FastqReader<&'static [u8]> {
+	/// Read the eight records of `example::FASTQ`, to try things out without a file.
+	pub fn example() -> Self {
+		FastqReader::new(super::example::FASTQ.as_bytes())
+	}
+}
+
+impl<R> FastqReader<R> {
+	/// Read from a given `BufRead`.
+	pub fn new(reader: R) -> Self {
+		FastqReader { reader, pos: Position::start(), numeric_quality: false, strip_whitespace: false, text: TextPolicy::Utf8, stripped: false, progress: ProgressHook::new(), preamble: None }
+	}
+	
+	/// Call `callback` every `interval` records and at the end of input with how far reading got.
+	/// Percent complete is only known for readers created with `from_file` or `from_gz`.
+	pub fn with_progress<F>(mut self, interval: u64, callback: F) -> Self where F: FnMut(&Progress) + Send + 'static {
+		self.progress.set(interval, callback);
+		self
+	}
+	
+	/// The position of the next line to be read.
+	/// Where `Iterator::position` would be picked instead, call it as `FastqReader::position(&reader)`.
+	pub fn position(&self) -> Position { self.pos }
+	
+	/// Return the underlying reader.
+	pub fn into_inner(self) -> R { self.reader }
+}
+
+impl<R: BufRead> FastqReader<R> {
+	/// Iterate over records, parsing as configured by `options`.
+	pub fn records_with_options(mut self, options: ReaderOptions) -> Records<R> {
+		self.numeric_quality = options.numeric_quality;
+		self.strip_whitespace = options.strip_whitespace;
+		self.text = options.text;
+		Records { reader: self, options, warnings: Vec::new(), count: 0 }
+	}
+	
+	/// Iterate over records, skipping to the next plausible record after a malformed one
+	/// instead of giving up.
+	pub fn records_resilient(self) -> ResilientRecords<R> {
+		ResilientRecords {
+			reader: self.reader,
+			window: VecDeque::new(),
+			pos: self.pos,
+			records: self.pos.record,
+			eof: false,
+			warnings: Vec::new(),
+			numeric_quality: self.numeric_quality,
+			strip_whitespace: self.strip_whitespace,
+			text: self.text,
+			progress: self.progress,
+		}
+	}
+	
+	/// Iterate over records like `records_resilient`, reading text, qualities and whitespace as configured by `options`.
+	pub fn records_resilient_with_options(mut self, options: ReaderOptions) -> ResilientRecords<R> {
+		self.numeric_quality = options.numeric_quality;
+		self.strip_whitespace = options.strip_whitespace;
+		self.text = options.text;
+		self.records_resilient()
+	}
+	
+	/// Skip a byte order mark and blank lines at the start of input.
+	fn skip_preamble(&mut self) -> Result<(), ParseError> {
+		if self.pos.line == 1 && self.pos.record == 0 {
+			self.preamble = buffer::skip_preamble(&mut self.reader, &mut self.pos).map_err(|e| ParseError::Io(self.pos, e))?;
+		}
+		Ok(())
+	}
+	
+	/// Read a line including its line break, advancing the position.
+	fn read_line(&mut self, buf: &mut String) -> Result<usize, ParseError> {
+		let n = self.text.read_line(&mut self.reader, buf).map_err(|e| ParseError::Io(self.pos, e))?;
+		self.pos.advance_line(n);
+		Ok(n)
+	}
+	
+	/// Read the next line without line break, failing with `Incomplete(prev())` if it is empty or missing.
+	fn line_without_nl<F: Fn() -> String>(&mut self, prev: F) -> Result<String, ParseError> {
+		let pos = self.pos;
+		let mut line = String::new();
+		self.read_line(&mut line)?;
+		if line.ends_with('\n') { line.pop(); }
+		if line.is_empty() { return Err(ParseError::Incomplete(pos, prev())) }
+		Ok(line)
+	}
+	
+	/// Read a four-line record.
+	fn read_record(&mut self) -> Option<Result<Record, ParseError>> {
+		try_some!(self.skip_preamble());
+		let start = self.pos;
+		let mut header = String::new();
+		if try_some!(self.read_line(&mut header)) == 0 { return None }
+		if !header.starts_with('@') { return Some(Err(ParseError::NoAt(start, header.as_bytes()[0]))) }
+		let mut header = header[1..].trim_end_matches('\n').to_owned();
+		if header.is_empty() { return Some(Err(ParseError::Incomplete(start, "@<nothing>".to_owned()))) }
 		
-		let mut at = [0];
-		if try_some!(it.read(&mut at)) == 0 { return None };
-		if at[0] != b'@' { return Some(Err(ParseError::NoAt(at[0]))) };
+		let desc = split_desc(&mut header);
 		
-		let mut header = try_some!(read_line_without_nl(it, || "@<nothing>".to_owned()));
+		let mut seq = try_some!(self.line_without_nl(|| format!("@{}\n<nothing>\n+\n<nothing>", header)));
 		
-		let desc = header.split_whitespace().next_back().map(|desc| desc.to_owned());
-		if let Some(ref desc) = desc {
-			let l = header.len();
-			header.truncate(l - desc.len() - 1);
+		let pos = self.pos;
+		let mut qual_head = String::new();
+		try_some!(self.read_line(&mut qual_head));
+		if !qual_head.starts_with('+') {
+			let byte = qual_head.bytes().next().unwrap_or(b'\n');
+			return Some(Err(ParseError::NoPlus(pos, format!("@{}\n{}", header, seq), byte)));
 		}
 		
-		let seq = try_some!(read_line_without_nl(it, || format!("@{}\n<nothing>\n+\n<nothing>", header)));
+		let pos = self.pos;
+		let mut qual = try_some!(self.line_without_nl(|| format!("@{}\n{}\n+\n<nothing>", header, seq)));
+		if self.numeric_quality {
+			qual = try_some!(numeric_to_phred33(&qual).map_err(|v| ParseError::InvalidQuality(pos, v)));
+		}
+		if self.strip_whitespace {
+			let (seq_len, qual_len) = (seq.len(), qual.len());
+			seq.retain(|c| c != ' ' && c != '\t');
+			qual.retain(|c| c != ' ' && c != '\t');
+			self.stripped = seq.len() != seq_len || qual.len() != qual_len;
+		}
+		
+		Some(if seq.len() == qual.len() {
+			Ok(Record::from_strings(header, desc, seq, qual).at_offset(start.offset))
+		} else {
+			Err(ParseError::LengthMismatch(pos, seq, qual))
+		})
+	}
+}
+
+/// An iterator over records parsed with `ReaderOptions`.
+pub struct Records<R> {
+	reader: FastqReader<R>,
+	options: ReaderOptions,
+	warnings: Vec<Warning>,
+	count: usize,
+}
+
+impl<R: BufRead> Records<R> {
+	/// Take the warnings collected so far, leaving none behind.
+	pub fn take_warnings(&mut self) -> Vec<Warning> {
+		std::mem::take(&mut self.warnings)
+	}
 	
-		let mut qual_head = String::new();
-		if let Err(e) = it.read_line(&mut qual_head) { return Some(Err(e.into())) };
-		if !qual_head.starts_with('+') { return Some(Err(ParseError::NoPlus(format!("@{}\n{}", header, seq), qual_head.bytes().next().unwrap()))) }
+	/// The position of the next line to be read.
+	pub fn position(&self) -> Position { self.reader.position() }
+	
+	/// Collect a warning, or fail with it if warnings are fatal.
+	fn warn(&mut self, pos: Position, warning: Warning) -> Result<(), ParseError> {
+		if self.options.warnings_fatal { return Err(ParseError::Warning(pos, warning)) }
+		self.warnings.push(warning);
+		Ok(())
+	}
+	
+	/// Read a record whose sequence and qualities may span several lines,
+	/// stripping whitespace while reading if configured.
+	fn next_wrapped(&mut self) -> Option<Result<Record, ParseError>> {
+		let strip = self.options.strip_whitespace;
+		let mut stripped = false;
+		let mut push_line = |s: &mut String, line: &str| {
+			let line = line.trim_end_matches(['\r', '\n']);
+			if strip && line.contains([' ', '\t']) {
+				stripped = true;
+				s.extend(line.chars().filter(|&c| c != ' ' && c != '\t'));
+			} else {
+				s.push_str(line);
+			}
+		};
+		let it = &mut self.reader;
+		let start = it.pos;
+		let mut header = String::new();
+		if try_some!(it.read_line(&mut header)) == 0 { return None }
+		if !header.starts_with('@') { return Some(Err(ParseError::NoAt(start, header.as_bytes()[0]))) }
+		let mut header = header[1..].trim_end_matches(['\r', '\n']).to_owned();
+		let desc = split_desc(&mut header);
+		
+		let mut seq = String::new();
+		let mut line = String::new();
+		loop {
+			line.clear();
+			let pos = it.pos;
+			if try_some!(it.read_line(&mut line)) == 0 { return Some(Err(ParseError::Incomplete(pos, format!("@{}\n{}", header, seq)))) }
+			if line.starts_with('+') { break }
+			push_line(&mut seq, &line);
+		}
 		
-		let qual = try_some!(read_line_without_nl(it, || format!("@{}\n{}\n+\n<nothing>", header, seq)));
+		let qual_start = it.pos;
+		let mut qual = String::new();
+		let mut lines = 0;
+		while lines == 0 || qual.len() < seq.len() {
+			line.clear();
+			let pos = it.pos;
+			if try_some!(it.read_line(&mut line)) == 0 { break }
+			if it.numeric_quality {
+				qual.push_str(&try_some!(numeric_to_phred33(&line).map_err(|v| ParseError::InvalidQuality(pos, v))));
+			} else {
+				push_line(&mut qual, &line);
+			}
+			lines += 1;
+		}
+		if lines == 0 { return Some(Err(ParseError::Incomplete(qual_start, format!("@{}\n{}\n+", header, seq)))) }
+		if stripped { try_some!(self.warn(start, Warning::StrippedWhitespace { record: self.count })) }
 		
 		Some(if seq.len() == qual.len() {
-			Ok(Record::from_strings(header, desc, seq, qual))
+			Ok(Record::from_strings(header, desc, seq, qual).at_offset(start.offset))
 		} else {
-			Err(ParseError::LengthMismatch(seq, qual))
+			Err(ParseError::LengthMismatch(qual_start, seq, qual))
 		})
 	}
 }
 
-#[inline]
-fn read_line_without_nl<R, F>(r: &mut R, f: F) -> Result<String, ParseError> where R: BufRead, F: Fn() -> String {
-	let mut string = String::new();
-	try!(r.read_line(&mut string));
-	if string.len() <= 1 { return Err(ParseError::Incomplete(f())) }
-	assert!(string.ends_with('\n'));
-	string.pop();
-	Ok(string)
+impl<R: BufRead> Iterator for Records<R> {
+	type Item = Result<Record, ParseError>;
+	
+	fn next(&mut self) -> Option<Result<Record, ParseError>> {
+		try_some!(self.reader.skip_preamble());
+		if let Some(warning) = self.reader.preamble.take() { try_some!(self.warn(Position::start(), warning)) }
+		let start = self.reader.pos;
+		let next = if self.options.allow_multiline {
+			let next = self.next_wrapped();
+			if next.is_some() {
+				self.reader.pos.record += 1;
+				self.reader.progress.record(self.reader.pos, &self.reader.reader);
+			} else {
+				self.reader.progress.finish(self.reader.pos, &self.reader.reader);
+			}
+			next
+		} else {
+			self.reader.next()
+		};
+		// four-line records are stripped before their lengths are checked, wrapped ones warn themselves
+		let stripped = std::mem::take(&mut self.reader.stripped);
+		let record = match next? {
+			Ok(record) => record,
+			Err(e) => return Some(Err(e)),
+		};
+		if stripped {
+			try_some!(self.warn(start, Warning::StrippedWhitespace { record: self.count }));
+		}
+		self.count += 1;
+		Some(Ok(record))
+	}
+}
+
+/// A parse error encountered by `ResilientRecords`, after which reading resumed.
+#[derive(Debug, Clone)]
+pub struct RecoveredError {
+	/// Byte offset of the start of the malformed record.
+	pub offset: u64,
+	pub error: ParseError,
+}
+
+/// An iterator over records that resynchronizes after malformed records.
+///
+/// After an error, lines are skipped until one starts with `@`, the line after next starts with `+`,
+/// sequence and quality have equal length and the following line (if any) starts with `@` again.
+/// Each malformed record is followed by a `Warning::SkippedInput` telling how much input was skipped.
+pub struct ResilientRecords<R> {
+	reader: R,
+	window: VecDeque<(Position, String)>,
+	pos: Position,
+	records: u64,
+	eof: bool,
+	warnings: Vec<Warning>,
+	numeric_quality: bool,
+	strip_whitespace: bool,
+	text: TextPolicy,
+	progress: ProgressHook<R>,
+}
+
+impl<R: BufRead> ResilientRecords<R> {
+	/// Take the warnings collected so far, leaving none behind.
+	pub fn take_warnings(&mut self) -> Vec<Warning> {
+		std::mem::take(&mut self.warnings)
+	}
+	
+	/// Byte offset of the first line not yet returned as part of a record or skipped.
+	pub fn offset(&self) -> u64 {
+		ResilientRecords::position(self).offset
+	}
+	
+	/// The position of the first line not yet returned as part of a record or skipped.
+	/// Inside iterator chains, call it as `ResilientRecords::position(&records)`.
+	pub fn position(&self) -> Position {
+		let pos = self.window.front().map_or(self.pos, |l| l.0);
+		Position { record: self.records, ..pos }
+	}
+	
+	fn fill(&mut self, lines: usize) -> io::Result<()> {
+		while self.window.len() < lines && !self.eof {
+			let mut line = String::new();
+			let n = self.text.read_line(&mut self.reader, &mut line)?;
+			if n == 0 { self.eof = true; break }
+			if line.ends_with('\n') { line.pop(); }
+			self.window.push_back((self.pos, line));
+			self.pos.advance_line(n);
+		}
+		Ok(())
+	}
+	
+	/// Sequence and qualities as the record will have them, or the quality value that could not be converted.
+	fn fields(&self, seq: &str, qual: &str) -> Result<(String, String), String> {
+		let mut qual = if self.numeric_quality { numeric_to_phred33(qual)? } else { qual.to_owned() };
+		let mut seq = seq.to_owned();
+		if self.strip_whitespace {
+			seq.retain(|c| c != ' ' && c != '\t');
+			qual.retain(|c| c != ' ' && c != '\t');
+		}
+		Ok((seq, qual))
+	}
+	
+	/// Whether sequence and qualities make a record once converted and stripped as configured.
+	fn fields_match(&self, seq: &str, qual: &str) -> bool {
+		if !self.numeric_quality && !self.strip_whitespace { return seq.len() == qual.len() }
+		self.fields(seq, qual).is_ok_and(|(seq, qual)| seq.len() == qual.len())
+	}
+	
+	fn looks_like_record(&self, i: usize) -> bool {
+		let line = |j: usize| self.window.get(i + j).map(|l| l.1.as_str());
+		match (line(0), line(1), line(2), line(3)) {
+			(Some(h), Some(s), Some(p), Some(q)) => h.starts_with('@') && p.starts_with('+') && self.fields_match(s, q) && line(4).is_none_or(|n| n.starts_with('@')),
+			_ => false,
+		}
+	}
+	
+	/// The error describing why the lines at the start of the window are no record.
+	fn diagnose(&self) -> ParseError {
+		let line = |j: usize| self.window.get(j).map(|l| l.1.as_str());
+		let pos = |j: usize| Position { record: self.records, ..self.window.get(j).map_or(self.pos, |l| l.0) };
+		let head = line(0).unwrap_or("");
+		if !head.starts_with('@') { return ParseError::NoAt(pos(0), head.bytes().next().unwrap_or(b'\n')) }
+		match (line(1), line(2), line(3)) {
+			(Some(seq), Some(plus), _) if !plus.starts_with('+') => ParseError::NoPlus(pos(2), format!("{}\n{}", head, seq), plus.bytes().next().unwrap_or(b'\n')),
+			(Some(seq), Some(_), Some(qual)) => match self.fields(seq, qual) {
+				Ok((seq, qual)) => ParseError::LengthMismatch(pos(3), seq, qual),
+				Err(value) => ParseError::InvalidQuality(pos(3), value),
+			},
+			_ => ParseError::Incomplete(pos(0), self.window.iter().map(|l| l.1.as_str()).collect::<Vec<_>>().join("\n")),
+		}
+	}
+	
+	/// Drop lines until the window starts with a plausible record or input is exhausted.
+	fn resync(&mut self) -> io::Result<()> {
+		self.window.pop_front();
+		loop {
+			self.fill(5)?;
+			if self.window.is_empty() || self.looks_like_record(0) { return Ok(()) }
+			self.window.pop_front();
+		}
+	}
+}
+
+impl<R: BufRead> Iterator for ResilientRecords<R> {
+	type Item = Result<Record, RecoveredError>;
+	
+	fn next(&mut self) -> Option<Result<Record, RecoveredError>> {
+		let pos = ResilientRecords::position(self);
+		if let Err(e) = self.fill(5) {
+			self.eof = true;
+			self.window.clear();
+			return Some(Err(RecoveredError { offset: pos.offset, error: ParseError::Io(pos, e) }));
+		}
+		if self.window.is_empty() {
+			self.progress.finish(pos, &self.reader);
+			return None;
+		}
+		let structured = {
+			let line = |j: usize| self.window.get(j).map(|l| l.1.as_str());
+			match (line(0), line(1), line(2), line(3)) {
+				(Some(h), Some(s), Some(p), Some(q)) => h.starts_with('@') && p.starts_with('+') && self.fields_match(s, q),
+				_ => false,
+			}
+		};
+		let start = ResilientRecords::position(self);
+		let offset = start.offset;
+		if structured {
+			self.records += 1;
+			let mut lines = self.window.drain(..4).map(|l| l.1);
+			let (mut header, mut seq, _, mut qual) = (lines.next()?, lines.next()?, lines.next()?, lines.next()?);
+			drop(lines);
+			header.remove(0);
+			let desc = split_desc(&mut header);
+			if self.numeric_quality {
+				// checked to convert by `fields_match`
+				qual = numeric_to_phred33(&qual).ok()?;
+			}
+			if self.strip_whitespace && (seq.contains([' ', '\t']) || qual.contains([' ', '\t'])) {
+				seq.retain(|c| c != ' ' && c != '\t');
+				qual.retain(|c| c != ' ' && c != '\t');
+				self.warnings.push(Warning::StrippedWhitespace { record: start.record as usize });
+			}
+			self.progress.record(ResilientRecords::position(self), &self.reader);
+			return Some(Ok(Record::from_strings(header, desc, seq, qual).at_offset(offset)));
+		}
+		let error = self.diagnose();
+		self.records += 1;
+		if let Err(e) = self.resync() {
+			self.eof = true;
+			self.window.clear();
+			let pos = Position { record: self.records, ..self.pos };
+			return Some(Err(RecoveredError { offset: pos.offset, error: ParseError::Io(pos, e) }));
+		}
+		let end = ResilientRecords::position(self);
+		self.warnings.push(Warning::SkippedInput {
+			record: start.record as usize,
+			offset,
+			bytes: end.offset - offset,
+			lines: end.line - start.line,
+		});
+		Some(Err(RecoveredError { offset, error }))
+	}
+}
+
+impl<R: BufRead> Iterator for FastqReader<R> {
+	type Item = Result<Record, ParseError>;
+	
+	fn next(&mut self) -> Option<Result<Record, ParseError>> {
+		let Some(next) = self.read_record() else {
+			self.progress.finish(self.pos, &self.reader);
+			return None;
+		};
+		self.pos.record += 1;
+		self.progress.record(self.pos, &self.reader);
+		Some(next)
+	}
+}
+
+/// Cut the description off a header line, returning it.
+pub(crate) fn split_desc(header: &mut String) -> Option<String> {
+	header.find(char::is_whitespace).map(|i| {
+		let desc = header[i + 1..].to_owned();
+		header.truncate(i);
+		desc
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Record as RecordTrait;
+	
+	fn records(data: &'static [u8], options: ReaderOptions) -> Records<&'static [u8]> {
+		FastqReader::new(data).records_with_options(options)
+	}
+	
+	#[test]
+	fn strips_whitespace_before_checking_lengths() {
+		let mut records = records(b"@r1\nAC GT\n+\nIIII\n@r2\nACGT\n+\nI I I I\n", ReaderOptions::new().strip_whitespace(true));
+		for _ in 0..2 {
+			let record = records.next().unwrap().unwrap();
+			assert_eq!(record.seq(), b"ACGT");
+			assert_eq!(record.qual(), b"IIII");
+		}
+		assert!(records.next().is_none());
+		assert_eq!(records.take_warnings().len(), 2);
+	}
+	
+	#[test]
+	fn keeps_whitespace_unless_stripping() {
+		let mut records = records(b"@r1\nAC GT\n+\nIIII\n", ReaderOptions::new());
+		assert!(matches!(records.next(), Some(Err(ParseError::LengthMismatch(..)))));
+	}
+	
+	#[test]
+	fn does_not_warn_about_a_failed_record() {
+		let mut records = records(b"@r1\nAC GT\n+\nIII\n@r2\nACGT\n+\nIIII\n", ReaderOptions::new().strip_whitespace(true));
+		assert!(matches!(records.next(), Some(Err(ParseError::LengthMismatch(..)))));
+		assert!(records.next().unwrap().is_ok());
+		assert!(records.take_warnings().is_empty());
+	}
+	
+	#[test]
+	fn resilient_records_follow_options() {
+		let data = b"@r1\nAC GT\n+\n40 40 40 40\n@r2\nACGT\n+\nIIII\n@r3\nA\n+\n30\n";
+		let options = ReaderOptions::new().numeric_quality(true).strip_whitespace(true);
+		let mut records = FastqReader::new(&data[..]).records_resilient_with_options(options);
+		let record = records.next().unwrap().unwrap();
+		assert_eq!((record.seq(), record.qual()), (&b"ACGT"[..], &b"IIII"[..]));
+		assert!(matches!(records.next(), Some(Err(RecoveredError { error: ParseError::InvalidQuality(..), .. }))));
+		assert_eq!(records.next().unwrap().unwrap().qual(), b"?");
+		assert!(records.next().is_none());
+		let warnings = records.take_warnings();
+		assert!(matches!(warnings[..], [Warning::StrippedWhitespace { record: 0 }, Warning::SkippedInput { record: 1, .. }]), "{:?}", warnings);
+	}
+	
+	#[test]
+	fn resilient_records_follow_text_policy() {
+		let data = b"@r1 \xff\nACGT\n+\nIIII\n";
+		let record = FastqReader::new(&data[..]).records_resilient_with_options(ReaderOptions { text: TextPolicy::Bytes, ..ReaderOptions::new() }).next().unwrap().unwrap();
+		assert_eq!(record.desc(), Some("\u{FFFD}"));
+		let mut records = FastqReader::new(&data[..]).records_resilient_with_options(ReaderOptions { text: TextPolicy::Ascii, ..ReaderOptions::new() });
+		assert!(matches!(records.next(), Some(Err(RecoveredError { error: ParseError::Io(..), .. }))));
+		assert!(records.next().is_none());
+	}
 }
@@ -0,0 +1,256 @@
+//! FASTA reading and writing, and conversion from and to FastQ.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use super::{Record as RecordTrait, SeqRecord};
+use super::input::Input;
+use super::unfancy_parser;
+use super::paths::long_path;
+
+quick_error! {
+	/// An error encountered while reading FASTA records.
+	#[derive(Debug)]
+	pub enum Error {
+		/// Input did not start with a `>` header line.
+		MissingGt {
+			description("No > at record start")
+			display("Expected > at record start.")
+		}
+		/// Reading from the underlying source failed.
+		Io(err: io::Error) {
+			from()
+			cause(err)
+			display("{}", err)
+		}
+	}
+}
+
+/// A FASTA record.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Record {
+	id: String,
+	desc: Option<String>,
+	seq: Vec<u8>,
+}
+
+impl Record {
+	/// Create a record from its parts.
+	pub fn new(id: String, desc: Option<String>, seq: Vec<u8>) -> Record {
+		Record { id, desc, seq }
+	}
+	
+	/// Convert a FastQ record, dropping its qualities.
+	pub fn from_fastq<R: RecordTrait>(record: &R) -> Record {
+		Record::new(record.id().unwrap_or("").to_owned(), record.desc().map(str::to_owned), record.seq().to_vec())
+	}
+	
+	/// Convert into a FastQ record, giving every base the quality `qual` (e.g. `b'I'`).
+	pub fn to_fastq(&self, qual: u8) -> unfancy_parser::Record {
+		let header = match self.desc {
+			Some(ref desc) => format!("@{} {}\n", self.id, desc),
+			None => format!("@{}\n", self.id),
+		};
+		let seq = String::from_utf8_lossy(&self.seq).into_owned() + "\n";
+		let qual = String::from_utf8_lossy(&vec![qual; seq.len() - 1]).into_owned() + "\n";
+		unfancy_parser::Record::from_lines(header, seq, qual)
+	}
+}
+
+impl SeqRecord for Record {
+	fn id(&self) -> Option<&str> { Some(&self.id) }
+	fn desc(&self) -> Option<&str> { self.desc.as_deref() }
+	fn seq(&self) -> &[u8] { &self.seq }
+}
+
+/// A FASTA reader. Sequences may span multiple lines.
+pub struct Reader<R: io::Read> {
+	reader: io::BufReader<R>,
+	next_header: Option<String>,
+	started: bool,
+}
+
+impl Reader<Input> {
+	/// Read from a given file, decompressing it if it is gzip or bgzip compressed.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Input::open(path).map(Reader::new)
+	}
+	
+	/// Read from a given gzip or bgzip compressed file.
+	pub fn from_gz<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Input::open_gz(path).map(Reader::new)
+	}
+}
+
+impl<R: io::Read> Reader<R> {
+	/// Read from a given `io::Read`.
+	pub fn new(reader: R) -> Self {
+		Reader { reader: io::BufReader::new(reader), next_header: None, started: false }
+	}
+	
+	/// Read the next record, or `None` at the end of input.
+	pub fn read(&mut self) -> Result<Option<Record>, Error> {
+		let mut line = String::new();
+		if !self.started {
+			self.started = true;
+			loop {
+				line.clear();
+				if self.reader.read_line(&mut line)? == 0 { return Ok(None) }
+				if line.starts_with(';') || line.trim().is_empty() { continue }
+				if !line.starts_with('>') { return Err(Error::MissingGt) }
+				self.next_header = Some(line.trim_end().to_owned());
+				break;
+			}
+		}
+		let header = match self.next_header.take() {
+			Some(header) => header,
+			None => return Ok(None),
+		};
+		let mut seq = Vec::new();
+		loop {
+			line.clear();
+			if self.reader.read_line(&mut line)? == 0 { break }
+			if line.starts_with('>') {
+				self.next_header = Some(line.trim_end().to_owned());
+				break;
+			}
+			if line.starts_with(';') { continue }
+			seq.extend(line.bytes().filter(|b| !b.is_ascii_whitespace()));
+		}
+		let header = &header[1..];
+		let record = match header.split_once(char::is_whitespace) {
+			Some((id, desc)) => Record::new(id.to_owned(), Some(desc.to_owned()), seq),
+			None => Record::new(header.to_owned(), None, seq),
+		};
+		Ok(Some(record))
+	}
+	
+	/// Return an iterator over the records of this FASTA file.
+	pub fn records(self) -> Records<R> {
+		Records { reader: self }
+	}
+}
+
+/// An iterator over the records of a FASTA file.
+pub struct Records<R: io::Read> {
+	reader: Reader<R>,
+}
+
+impl<R: io::Read> Iterator for Records<R> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		self.reader.read().transpose()
+	}
+}
+
+/// A FASTA writer.
+pub struct Writer<W: io::Write> {
+	writer: io::BufWriter<W>,
+	line_width: Option<usize>,
+}
+
+impl Writer<fs::File> {
+	/// Write to a given file, creating or truncating it.
+	pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		fs::File::create(long_path(path)).map(Writer::new)
+	}
+}
+
+impl<W: io::Write> Writer<W> {
+	/// Write to a given `io::Write`, putting each sequence on a single line.
+	pub fn new(writer: W) -> Self {
+		Writer { writer: io::BufWriter::new(writer), line_width: None }
+	}
+	
+	/// Like `new`, buffering `capacity` bytes. With 0, every line goes straight to `writer`.
+	pub fn with_capacity(writer: W, capacity: usize) -> Self {
+		Writer { writer: io::BufWriter::with_capacity(capacity, writer), line_width: None }
+	}
+	
+	/// Wrap sequence lines after `width` bases (e.g. 60 or 80).
+	pub fn with_line_width(mut self, width: usize) -> Self {
+		self.line_width = Some(width.max(1));
+		self
+	}
+	
+	/// Write a FASTA or FastQ record, dropping qualities.
+	pub fn write_record<S: SeqRecord + ?Sized>(&mut self, record: &S) -> io::Result<()> {
+		self.write(record.id().unwrap_or(""), record.desc(), record.seq())
+	}
+	
+	/// Write a record given as its parts.
+	pub fn write(&mut self, id: &str, desc: Option<&str>, seq: &[u8]) -> io::Result<()> {
+		match desc {
+			Some(desc) => writeln!(self.writer, ">{} {}", id, desc)?,
+			None => writeln!(self.writer, ">{}", id)?,
+		}
+		let width = self.line_width.unwrap_or(seq.len()).max(1);
+		for line in seq.chunks(width) {
+			self.writer.write_all(line)?;
+			self.writer.write_all(b"\n")?;
+		}
+		Ok(())
+	}
+	
+	/// Flush the underlying writer.
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.writer.flush()
+	}
+	
+	/// Flush and return the underlying writer.
+	pub fn into_inner(self) -> io::Result<W> {
+		self.writer.into_inner().map_err(|e| e.into_error())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn read_all(text: &str) -> Result<Vec<Record>, Error> {
+		Reader::new(text.as_bytes()).records().collect()
+	}
+	
+	#[test]
+	fn reads_wrapped_sequences() {
+		let records = read_all("; comment\n\n>a first read\nACGT\nAC\r\n\n>b\r\nGG TT\n;skipped\nA\n>c\n").unwrap();
+		assert_eq!(records, [
+			Record::new("a".to_owned(), Some("first read".to_owned()), b"ACGTAC".to_vec()),
+			Record::new("b".to_owned(), None, b"GGTTA".to_vec()),
+			Record::new("c".to_owned(), None, Vec::new()),
+		]);
+		assert!(read_all("").unwrap().is_empty());
+		assert!(matches!(read_all("ACGT\n>a\nACGT\n"), Err(Error::MissingGt)));
+	}
+	
+	#[test]
+	fn writes_lines_of_given_width() {
+		let record = Record::new("a".to_owned(), Some("desc".to_owned()), b"ACGTACGTAC".to_vec());
+		let write = |writer: Writer<Vec<u8>>| {
+			let mut writer = writer;
+			writer.write_record(&record).unwrap();
+			writer.write("b", None, b"ACG").unwrap();
+			String::from_utf8(writer.into_inner().unwrap()).unwrap()
+		};
+		assert_eq!(write(Writer::new(Vec::new())), ">a desc\nACGTACGTAC\n>b\nACG\n");
+		assert_eq!(write(Writer::new(Vec::new()).with_line_width(4)), ">a desc\nACGT\nACGT\nAC\n>b\nACG\n");
+		assert_eq!(write(Writer::new(Vec::new()).with_line_width(5)), ">a desc\nACGTA\nCGTAC\n>b\nACG\n");
+		let wrapped = write(Writer::new(Vec::new()).with_line_width(3));
+		assert_eq!(read_all(&wrapped).unwrap()[0], record);
+	}
+	
+	#[test]
+	fn converts_from_and_to_fastq() {
+		let fastq = unfancy_parser::Record::from_lines("@r1 x=1\n".to_owned(), "ACGT\n".to_owned(), "#5?I\n".to_owned());
+		let record = Record::from_fastq(&fastq);
+		assert_eq!(record, Record::new("r1".to_owned(), Some("x=1".to_owned()), b"ACGT".to_vec()));
+		let back = record.to_fastq(b'I');
+		assert_eq!(RecordTrait::header(&back), "r1 x=1");
+		assert_eq!((RecordTrait::seq(&back), RecordTrait::qual(&back)), (&b"ACGT"[..], &b"IIII"[..]));
+		assert!(back.check().is_ok());
+		let empty = Record::new("e".to_owned(), None, Vec::new()).to_fastq(b'!');
+		assert_eq!((RecordTrait::header(&empty), RecordTrait::seq(&empty), RecordTrait::qual(&empty)), ("e".into(), &b""[..], &b""[..]));
+	}
+}
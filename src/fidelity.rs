@@ -0,0 +1,238 @@
+//! Checking that a conversion of a FastQ file changed nothing it should not have.
+//!
+//! `verify_conversion` compares the records of the original and the converted file by ID,
+//! the quality encodings detected in both, and their summary statistics, and sums it all up in a verdict.
+//! What a conversion may change is set in `FidelityOptions`: a re-compression has to keep everything,
+//! while a trimming step may shorten and drop reads. The statistics are a cross-check independent of IDs,
+//! e.g. catching reads duplicated under the same ID.
+
+use std::fmt;
+use std::path::Path;
+
+use super::compare::{compare, CompareOptions, CompareReport};
+use super::input::Input;
+use super::quality::{detect_encoding_iter, normalize_phred33, Encoding};
+use super::stats::{metric_value, MetricChange, StatsCollector, StatsDelta, StatsReport};
+use super::unfancy_parser::{Reader, Error};
+
+/// What a conversion may change. The default allows nothing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FidelityOptions {
+	/// Sequences may be shortened to a stretch of the original, with the qualities of that stretch.
+	pub allow_trimming: bool,
+	/// Reads may be missing from the converted file.
+	pub allow_dropped: bool,
+	/// Reads may be in another order.
+	pub allow_reordering: bool,
+	/// Quality scores may change, e.g. by binning. Changes of the encoding alone are not quality changes.
+	pub allow_quality_change: bool,
+	/// Descriptions may change or be dropped.
+	pub allow_desc_change: bool,
+	/// The converted file may use another quality encoding.
+	pub allow_encoding_change: bool,
+	/// The encoding of the original, detected from its first reads if `None`.
+	/// Data with only high qualities looks like Phred+64 to `quality::EncodingDetector`, so set it for such files.
+	pub encoding_original: Option<Encoding>,
+	/// The encoding of the converted file, detected from its first reads if `None`.
+	pub encoding_converted: Option<Encoding>,
+	/// The number of reads encodings are detected from.
+	pub encoding_sample: usize,
+	/// Maximum number of record differences to keep in the report.
+	pub max_reported: usize,
+}
+
+impl Default for FidelityOptions {
+	fn default() -> FidelityOptions {
+		FidelityOptions {
+			allow_trimming: false, allow_dropped: false, allow_reordering: false,
+			allow_quality_change: false, allow_desc_change: false, allow_encoding_change: false,
+			encoding_original: None, encoding_converted: None, encoding_sample: 10_000, max_reported: 100,
+		}
+	}
+}
+
+impl FidelityOptions {
+	/// For a re-compression or copy, which has to keep everything.
+	pub fn recompression() -> FidelityOptions { FidelityOptions::default() }
+	
+	/// For quality trimming and filtering, which may shorten and drop reads but change nothing else.
+	pub fn trimming() -> FidelityOptions {
+		FidelityOptions { allow_trimming: true, allow_dropped: true, ..FidelityOptions::default() }
+	}
+}
+
+/// A change a conversion was not allowed to make.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Violation {
+	/// The quality encoding changed.
+	EncodingChanged { original: Encoding, converted: Encoding },
+	/// Reads are in the converted file only.
+	Added(usize),
+	/// Reads are missing from the converted file.
+	Dropped(usize),
+	/// Sequences changed, other than by allowed trimming.
+	SequenceChanged(usize),
+	/// Quality scores changed.
+	QualityChanged(usize),
+	/// Descriptions changed.
+	DescriptionChanged(usize),
+	/// Reads moved relative to the others.
+	Reordered(usize),
+	/// A summary metric changed in a way none of the allowed changes explain.
+	MetricChanged(MetricChange),
+}
+
+impl fmt::Display for Violation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Violation::EncodingChanged { original, converted } => write!(f, "quality encoding changed from {} to {}", original, converted),
+			Violation::Added(n) => write!(f, "{} reads not in the original", n),
+			Violation::Dropped(n) => write!(f, "{} reads dropped", n),
+			Violation::SequenceChanged(n) => write!(f, "{} sequences changed", n),
+			Violation::QualityChanged(n) => write!(f, "{} quality strings changed", n),
+			Violation::DescriptionChanged(n) => write!(f, "{} descriptions changed", n),
+			Violation::Reordered(n) => write!(f, "{} reads reordered", n),
+			Violation::MetricChanged(ref c) => write!(f, "{} changed from {} to {} ({:+.4}%)", c.metric, metric_value(c.before), metric_value(c.after), c.relative_change() * 100.),
+		}
+	}
+}
+
+/// The verdict of `verify_conversion` and what it is based on.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FidelityReport {
+	/// The encoding of the original, as given or detected. `None` if it had no qualities to detect it from.
+	pub encoding_original: Option<Encoding>,
+	/// The encoding of the converted file, as given or detected.
+	pub encoding_converted: Option<Encoding>,
+	/// The record by record comparison, with qualities compared as Phred+33.
+	pub comparison: CompareReport,
+	/// The statistics of the original, with qualities read as Phred+33.
+	pub stats_original: StatsReport,
+	/// The statistics of the converted file, with qualities read as Phred+33.
+	pub stats_converted: StatsReport,
+	/// How the statistics changed.
+	pub delta: StatsDelta,
+	/// What changed although it was not allowed to, empty if the conversion is faithful.
+	pub violations: Vec<Violation>,
+}
+
+impl FidelityReport {
+	/// Check if the conversion changed nothing it was not allowed to.
+	pub fn is_faithful(&self) -> bool { self.violations.is_empty() }
+}
+
+impl fmt::Display for FidelityReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let encoding = |e: Option<Encoding>| e.map_or("unknown".to_owned(), |e| e.to_string());
+		writeln!(f, "verdict: {}", if self.is_faithful() { "PASS" } else { "FAIL" })?;
+		for violation in &self.violations { writeln!(f, "  {}", violation)? }
+		writeln!(f)?;
+		writeln!(f, "encoding of original:    {}", encoding(self.encoding_original))?;
+		writeln!(f, "encoding of converted:   {}", encoding(self.encoding_converted))?;
+		write!(f, "{}", self.comparison)?;
+		if !self.delta.is_empty() {
+			writeln!(f)?;
+			write!(f, "{}", self.delta)?;
+		}
+		Ok(())
+	}
+}
+
+/// Check that `converted`, the result of converting the FastQ file `original`, kept what `options` say it has to.
+/// Both files may be gzip compressed, and pipes, see `Input::peek`. Fails with the first parse error of either file.
+pub fn verify_conversion<P, Q>(original: P, converted: Q, options: &FidelityOptions) -> Result<FidelityReport, Error> where P: AsRef<Path>, Q: AsRef<Path> {
+	// detecting an encoding reads the start of a file, which is then read again
+	let open = |path: &Path, given| -> Result<(Option<Encoding>, Input), Error> {
+		match given {
+			Some(encoding) => Ok((Some(encoding), Input::open(path)?)),
+			None => {
+				let (detected, input) = Input::peek(path, |start| detect_encoding_iter(Reader::new(start).records(), options.encoding_sample))?;
+				Ok((detected?, input))
+			},
+		}
+	};
+	let (encoding_original, original) = open(original.as_ref(), options.encoding_original)?;
+	let (encoding_converted, converted) = open(converted.as_ref(), options.encoding_converted)?;
+	
+	let compare_options = CompareOptions {
+		ignore_quality: options.allow_quality_change,
+		ignore_desc: options.allow_desc_change,
+		allow_trimming: options.allow_trimming,
+		detect_reordering: !options.allow_reordering,
+		max_reported: options.max_reported,
+		..CompareOptions::default()
+	};
+	let (mut stats_original, mut stats_converted) = (StatsCollector::new(), StatsCollector::new());
+	// compare stops at the first parse error of either file, which is returned instead
+	let (mut error_original, mut error_converted) = (None, None);
+	let comparison = compare(
+		normalize_phred33(Reader::new(original).records(), encoding_original.unwrap_or(Encoding::Sanger))
+			.map_while(|r| r.map_err(|e| error_original = Some(e)).ok())
+			.inspect(|r| stats_original.add(r)),
+		normalize_phred33(Reader::new(converted).records(), encoding_converted.unwrap_or(Encoding::Sanger))
+			.map_while(|r| r.map_err(|e| error_converted = Some(e)).ok())
+			.inspect(|r| stats_converted.add(r)),
+		&compare_options,
+	);
+	if let Some(e) = error_original.or(error_converted) { return Err(e) }
+	
+	let (stats_original, stats_converted) = (stats_original.report(), stats_converted.report());
+	let delta = stats_original.diff(&stats_converted);
+	let mut violations = Vec::new();
+	if let (Some(original), Some(converted)) = (encoding_original, encoding_converted) {
+		if original != converted && !options.allow_encoding_change { violations.push(Violation::EncodingChanged { original, converted }) }
+	}
+	let mut count = |n, violation: fn(usize) -> Violation| if n > 0 { violations.push(violation(n)) };
+	count(comparison.missing_in_a, Violation::Added);
+	if !options.allow_dropped { count(comparison.missing_in_b, Violation::Dropped) }
+	// quality and description mismatches and reordering are only counted if they are not allowed
+	count(comparison.sequence_mismatches, Violation::SequenceChanged);
+	count(comparison.quality_mismatches, Violation::QualityChanged);
+	count(comparison.description_mismatches, Violation::DescriptionChanged);
+	count(comparison.reordered, Violation::Reordered);
+	for change in &delta.changes {
+		if !metric_change_allowed(change, options) { violations.push(Violation::MetricChanged(change.clone())) }
+	}
+	Ok(FidelityReport { encoding_original, encoding_converted, comparison, stats_original, stats_converted, delta, violations })
+}
+
+/// Check if the allowed changes can explain how a summary metric changed.
+/// Neither trimming nor dropping adds reads, bases or `N`s, or makes reads longer.
+fn metric_change_allowed(change: &MetricChange, options: &FidelityOptions) -> bool {
+	let shrinking = options.allow_trimming || options.allow_dropped;
+	let grew = change.after > change.before;
+	match change.metric.as_str() {
+		"reads" => options.allow_dropped && !grew,
+		"bases" | "n_count" | "max_length" => shrinking && !grew,
+		"q20_fraction" | "q30_fraction" => shrinking || options.allow_quality_change,
+		_ => shrinking,
+	}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+	use std::fs;
+	use std::process::{self, Command};
+	use std::thread;
+	
+	#[test]
+	fn detects_encodings_of_pipes() {
+		let dir = std::env::temp_dir().join(format!("fastq-fidelity-test-{}", process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let reads: String = (0..50).map(|i| format!("@r{}\nACGT\n+\n#5?I\n", i)).collect();
+		let feeders: Vec<_> = ["a", "b"].iter().map(|name| {
+			let path = dir.join(name);
+			assert!(Command::new("mkfifo").arg(&path).status().unwrap().success());
+			let reads = reads.clone();
+			thread::spawn(move || fs::write(path, reads).unwrap())
+		}).collect();
+		let report = verify_conversion(dir.join("a"), dir.join("b"), &FidelityOptions::default()).unwrap();
+		for feeder in feeders { feeder.join().unwrap() }
+		assert!(report.is_faithful(), "{}", report);
+		assert_eq!((report.encoding_original, report.stats_converted.reads), (Some(Encoding::Sanger), 50));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,140 @@
+//! Dropping unusable reads: too many `N`s, too short or long, or of low complexity.
+//!
+//! Filters run after trimming in a typical preprocessing pipeline. Each adapter counts the reads it discarded.
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Record, Error};
+
+pub use super::complexity::{dust_score, entropy};
+
+/// Decides whether to keep a read.
+pub trait ReadFilter {
+	/// Check if the read passes, given its sequence and qualities.
+	fn keep(&self, seq: &[u8], qual: &[u8]) -> bool;
+}
+
+/// The most `N` bases a read may have, see `max_n`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaxN {
+	/// At most this many.
+	Count(usize),
+	/// At most this fraction of the read’s bases.
+	Fraction(f64),
+}
+
+impl From<usize> for MaxN {
+	fn from(count: usize) -> MaxN { MaxN::Count(count) }
+}
+
+impl From<f64> for MaxN {
+	fn from(fraction: f64) -> MaxN { MaxN::Fraction(fraction) }
+}
+
+impl ReadFilter for MaxN {
+	fn keep(&self, seq: &[u8], _qual: &[u8]) -> bool {
+		let n = seq.iter().filter(|b| b.eq_ignore_ascii_case(&b'N')).count();
+		match *self {
+			MaxN::Count(max) => n <= max,
+			MaxN::Fraction(max) => n as f64 <= max * seq.len() as f64,
+		}
+	}
+}
+
+/// Keeps reads of at least this many bases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinLength(pub usize);
+
+impl ReadFilter for MinLength {
+	fn keep(&self, seq: &[u8], _qual: &[u8]) -> bool { seq.len() >= self.0 }
+}
+
+/// Keeps reads of at most this many bases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxLength(pub usize);
+
+impl ReadFilter for MaxLength {
+	fn keep(&self, seq: &[u8], _qual: &[u8]) -> bool { seq.len() <= self.0 }
+}
+
+/// How low complexity is measured. Both look at the read’s trinucleotides, skipping those with `N`s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Complexity {
+	/// Drop reads whose DUST score, `Σ c(c - 1) / 2 / (l - 1)` over trinucleotide counts `c`
+	/// of `l` trinucleotides, is above this. Random reads score about 1, homopolymers about `l / 2`.
+	Dust(f64),
+	/// Drop reads whose trinucleotide Shannon entropy, relative to the most their length allows,
+	/// is below this fraction.
+	Entropy(f64),
+}
+
+impl ReadFilter for Complexity {
+	fn keep(&self, seq: &[u8], _qual: &[u8]) -> bool {
+		match *self {
+			Complexity::Dust(max) => dust_score(seq) <= max,
+			Complexity::Entropy(min) => entropy(seq) >= min,
+		}
+	}
+}
+
+/// Counts of what a `Filtered` adapter did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterStats {
+	/// Reads seen.
+	pub reads: usize,
+	/// Reads dropped.
+	pub discarded: usize,
+}
+
+/// An iterator adapter dropping records a `ReadFilter` rejects.
+pub struct Filtered<I, F> {
+	records: I,
+	filter: F,
+	stats: FilterStats,
+}
+
+impl<I, F> Filtered<I, F> {
+	/// What was discarded so far.
+	pub fn stats(&self) -> FilterStats { self.stats }
+}
+
+impl<I, F> Iterator for Filtered<I, F> where I: Iterator<Item=Result<Record, Error>>, F: ReadFilter {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		loop {
+			let record = match self.records.next()? {
+				Ok(record) => record,
+				Err(e) => return Some(Err(e)),
+			};
+			self.stats.reads += 1;
+			if self.filter.keep(record.seq(), record.qual()) { return Some(Ok(record)) }
+			self.stats.discarded += 1;
+		}
+	}
+}
+
+/// Filter records with any `ReadFilter`.
+pub fn filter_with<I, F>(records: I, filter: F) -> Filtered<I::IntoIter, F> where I: IntoIterator<Item=Result<Record, Error>>, F: ReadFilter {
+	Filtered { records: records.into_iter(), filter, stats: FilterStats::default() }
+}
+
+/// Drop reads with more `N`s than a count (`max_n(records, 5)`) or fraction (`max_n(records, 0.1)`).
+pub fn max_n<I, N>(records: I, max: N) -> Filtered<I::IntoIter, MaxN> where I: IntoIterator<Item=Result<Record, Error>>, N: Into<MaxN> {
+	filter_with(records, max.into())
+}
+
+/// Drop reads shorter than `len`, e.g. after trimming.
+pub fn min_length<I>(records: I, len: usize) -> Filtered<I::IntoIter, MinLength> where I: IntoIterator<Item=Result<Record, Error>> {
+	filter_with(records, MinLength(len))
+}
+
+/// Drop reads longer than `len`.
+pub fn max_length<I>(records: I, len: usize) -> Filtered<I::IntoIter, MaxLength> where I: IntoIterator<Item=Result<Record, Error>> {
+	filter_with(records, MaxLength(len))
+}
+
+/// Drop reads of low complexity, such as homopolymers and short tandem repeats.
+pub fn low_complexity<I>(records: I, complexity: Complexity) -> Filtered<I::IntoIter, Complexity> where I: IntoIterator<Item=Result<Record, Error>> {
+	filter_with(records, complexity)
+}
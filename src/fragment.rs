@@ -0,0 +1,122 @@
+//! Splitting long reads into fixed-size fragments, the usual way to feed long reads
+//! to tools that only handle short ones.
+//!
+//! Fragments are named `<id>:<start>-<end>` with 1-based, inclusive coordinates in the original read,
+//! like a samtools region, and keep the read’s description.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Record, Error};
+
+/// Cuts reads into fragments of a fixed size, optionally overlapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fragmenter {
+	size: usize,
+	step: usize,
+	min_size: usize,
+}
+
+impl Fragmenter {
+	/// Cut reads into consecutive fragments of `size` bases (at least 1).
+	/// The last fragment of a read is shorter unless the read length is a multiple of `size`.
+	pub fn new(size: usize) -> Fragmenter {
+		let size = size.max(1);
+		Fragmenter { size, step: size, min_size: 1 }
+	}
+	
+	/// Let consecutive fragments share `overlap` bases, at most `size - 1`.
+	pub fn overlap(mut self, overlap: usize) -> Self {
+		self.step = self.size - overlap.min(self.size - 1);
+		self
+	}
+	
+	/// Drop fragments shorter than `len` bases, such as a short last fragment.
+	pub fn min_size(mut self, len: usize) -> Self {
+		self.min_size = len.max(1);
+		self
+	}
+	
+	/// The ranges of the fragments of a read of `len` bases.
+	pub fn ranges(&self, len: usize) -> Vec<Range<usize>> {
+		let mut ranges = Vec::new();
+		let mut start = 0;
+		while start < len {
+			let end = (start + self.size).min(len);
+			if end - start >= self.min_size { ranges.push(start..end) }
+			if end == len { break }
+			start += self.step;
+		}
+		ranges
+	}
+	
+	/// The fragments of a read.
+	pub fn split(&self, record: &Record) -> Vec<Record> {
+		cut(record, self.ranges(record.seq_len()))
+	}
+}
+
+fn cut(record: &Record, ranges: Vec<Range<usize>>) -> Vec<Record> {
+	let id = record.id().unwrap_or("");
+	ranges.into_iter().map(|range| {
+		let mut header = format!("{}:{}-{}", id, range.start + 1, range.end);
+		if let Some(desc) = record.desc() {
+			header.push(' ');
+			header.push_str(desc);
+		}
+		let mut fragment = record.clone();
+		fragment.keep_range(range);
+		fragment.set_header(&header);
+		fragment
+	}).collect()
+}
+
+/// Counts of what a `Fragments` adapter did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FragmentStats {
+	/// Reads seen.
+	pub reads: usize,
+	/// Fragments produced.
+	pub fragments: usize,
+	/// Bases in no fragment, because they were in one shorter than the minimum size.
+	pub bases_dropped: usize,
+}
+
+/// An iterator adapter replacing each read by its fragments, see `fragment`.
+pub struct Fragments<I> {
+	records: I,
+	fragmenter: Fragmenter,
+	pending: VecDeque<Record>,
+	stats: FragmentStats,
+}
+
+impl<I> Fragments<I> {
+	/// What was split so far.
+	pub fn stats(&self) -> FragmentStats { self.stats }
+}
+
+impl<I> Iterator for Fragments<I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		while self.pending.is_empty() {
+			let record = match self.records.next()? {
+				Ok(record) => record,
+				Err(e) => return Some(Err(e)),
+			};
+			let ranges = self.fragmenter.ranges(record.seq_len());
+			self.stats.reads += 1;
+			self.stats.fragments += ranges.len();
+			self.stats.bases_dropped += record.seq_len() - ranges.last().map_or(0, |r| r.end);
+			self.pending.extend(cut(&record, ranges));
+		}
+		self.pending.pop_front().map(Ok)
+	}
+}
+
+/// Split each read into fragments, e.g. `fragment(records, Fragmenter::new(150).overlap(50))`.
+pub fn fragment<I>(records: I, fragmenter: Fragmenter) -> Fragments<I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	Fragments { records: records.into_iter(), fragmenter, pending: VecDeque::new(), stats: FragmentStats::default() }
+}
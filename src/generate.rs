@@ -0,0 +1,226 @@
+//! Seeded generation of synthetic FastQ data, for benchmarks and for fuzzing the parsers.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::unfancy_parser::Record;
+use super::paths::long_path;
+use super::rng::Rng;
+
+/// How read lengths are distributed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthDistribution {
+	/// Every read has the same length.
+	Fixed(usize),
+	/// Lengths uniformly distributed in `min..=max`.
+	Uniform { min: usize, max: usize },
+	/// Normally distributed lengths, at least 1.
+	Normal { mean: f64, sd: f64 },
+}
+
+/// How Phred quality scores are distributed along a read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityProfile {
+	/// Every base has the same score.
+	Constant(u8),
+	/// Scores uniformly distributed in `min..=max`.
+	Uniform { min: u8, max: u8 },
+	/// Scores falling linearly from `start` to `end` over the read, varied by up to `noise`,
+	/// like Illumina reads.
+	Decaying { start: u8, end: u8, noise: u8 },
+}
+
+/// How read IDs are formed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdScheme {
+	/// The prefix followed by the record number, e.g. `read17`.
+	Numbered(String),
+	/// Casava 1.8 style, e.g. `M00123:42:000000000-A1B2C:1:1101:15589:1331 1:N:0:1`.
+	Illumina { instrument: String, run: u32, flowcell: String },
+	/// Random UUIDs like Oxford Nanopore read IDs.
+	Uuid,
+}
+
+/// A way to break a record in the generated text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Corruption {
+	/// The record ends after its header, sequence or separator line.
+	Truncated,
+	/// The separator line does not start with `+`.
+	BadSeparator,
+	/// The quality line is one character shorter or longer than the sequence.
+	LengthMismatch,
+}
+
+/// A seeded generator of random FastQ records.
+///
+/// As an iterator, it endlessly yields valid records. Corruptions only apply to the text
+/// written by `write_to`, `to_bytes` and `to_file`, as most of them cannot be represented by a `Record`.
+#[derive(Debug, Clone)]
+pub struct RecordGenerator {
+	rng: Rng,
+	lengths: LengthDistribution,
+	qualities: QualityProfile,
+	ids: IdScheme,
+	error_rate: f64,
+	corruptions: Vec<Corruption>,
+	count: u64,
+}
+
+impl RecordGenerator {
+	/// A generator of 150 base reads with uniform qualities and numbered IDs.
+	pub fn new(seed: u64) -> RecordGenerator {
+		RecordGenerator {
+			rng: Rng::new(seed),
+			lengths: LengthDistribution::Fixed(150),
+			qualities: QualityProfile::Uniform { min: 0, max: 41 },
+			ids: IdScheme::Numbered("read".to_owned()),
+			error_rate: 0.0,
+			corruptions: Vec::new(),
+			count: 0,
+		}
+	}
+	
+	/// Set the read length distribution.
+	pub fn lengths(mut self, lengths: LengthDistribution) -> Self {
+		self.lengths = lengths;
+		self
+	}
+	
+	/// Set the quality profile.
+	pub fn qualities(mut self, qualities: QualityProfile) -> Self {
+		self.qualities = qualities;
+		self
+	}
+	
+	/// Set the ID scheme.
+	pub fn ids(mut self, ids: IdScheme) -> Self {
+		self.ids = ids;
+		self
+	}
+	
+	/// Corrupt a fraction `rate` of the written records, picking one of `corruptions` at random.
+	pub fn corrupt(mut self, rate: f64, corruptions: &[Corruption]) -> Self {
+		self.error_rate = rate.clamp(0.0, 1.0);
+		self.corruptions = corruptions.to_vec();
+		self
+	}
+	
+	/// The number of records generated so far.
+	pub fn generated(&self) -> u64 { self.count }
+	
+	fn length(&mut self) -> usize {
+		match self.lengths {
+			LengthDistribution::Fixed(len) => len,
+			LengthDistribution::Uniform { min, max } => min + self.rng.below((max.max(min) - min) as u64 + 1) as usize,
+			LengthDistribution::Normal { mean, sd } => {
+				// Box–Muller
+				let (u1, u2) = (1.0 - self.rng.unit(), self.rng.unit());
+				let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+				(mean + sd * z).round().max(1.0) as usize
+			},
+		}
+	}
+	
+	fn quality(&mut self, pos: usize, len: usize) -> u8 {
+		let q = match self.qualities {
+			QualityProfile::Constant(q) => q,
+			QualityProfile::Uniform { min, max } => min + self.rng.below((max.max(min) - min) as u64 + 1) as u8,
+			QualityProfile::Decaying { start, end, noise } => {
+				let frac = if len > 1 { pos as f64 / (len - 1) as f64 } else { 0.0 };
+				let q = start as f64 + (end as f64 - start as f64) * frac;
+				let noise = self.rng.below(2 * noise as u64 + 1) as f64 - noise as f64;
+				(q + noise).round().clamp(0.0, 93.0) as u8
+			},
+		};
+		b'!' + q.min(93)
+	}
+	
+	fn header(&mut self) -> String {
+		let n = self.count;
+		match self.ids {
+			IdScheme::Numbered(ref prefix) => format!("{}{}", prefix, n),
+			IdScheme::Illumina { ref instrument, run, ref flowcell } => {
+				let (lane, tile) = (1 + n % 8, 1101 + (n / 8) % 20);
+				let (x, y) = (self.rng.below(30000), self.rng.below(30000));
+				format!("{}:{}:{}:{}:{}:{}:{} 1:N:0:1", instrument, run, flowcell, lane, tile, x, y)
+			},
+			IdScheme::Uuid => {
+				let (a, b) = (self.rng.next_u64(), self.rng.next_u64());
+				format!("{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}", a >> 32, (a >> 16) & 0xffff, a & 0xfff, 0x8000 | (b >> 48) & 0x3fff, b & 0xffff_ffff_ffff)
+			},
+		}
+	}
+	
+	/// Generate the header (without `@`), sequence and qualities of the next record.
+	fn parts(&mut self) -> (String, Vec<u8>, Vec<u8>) {
+		let header = self.header();
+		let len = self.length();
+		let seq = (0..len).map(|_| b"ACGT"[self.rng.below(4) as usize]).collect();
+		let qual = (0..len).map(|pos| self.quality(pos, len)).collect();
+		self.count += 1;
+		(header, seq, qual)
+	}
+	
+	/// Generate the next record.
+	pub fn next_record(&mut self) -> Record {
+		let (header, seq, qual) = self.parts();
+		Record::from_lines(
+			format!("@{}\n", header),
+			String::from_utf8(seq).expect("bases are ASCII") + "\n",
+			String::from_utf8(qual).expect("qualities are ASCII") + "\n",
+		)
+	}
+	
+	/// Write `n` records as FastQ text, corrupting some if configured.
+	/// Returns the number of corrupted records.
+	pub fn write_to<W: Write>(&mut self, mut out: W, n: usize) -> io::Result<usize> {
+		let mut corrupted = 0;
+		for _ in 0..n {
+			let (header, seq, mut qual) = self.parts();
+			let corruption = if !self.corruptions.is_empty() && self.rng.unit() < self.error_rate {
+				Some(self.corruptions[self.rng.below(self.corruptions.len() as u64) as usize])
+			} else {
+				None
+			};
+			let mut lines = 4;
+			let mut sep: &[u8] = b"+";
+			match corruption {
+				None => {},
+				Some(Corruption::Truncated) => lines = 1 + self.rng.below(3) as usize,
+				Some(Corruption::BadSeparator) => sep = b"-",
+				Some(Corruption::LengthMismatch) => if qual.is_empty() || self.rng.below(2) == 0 { qual.push(b'I') } else { qual.pop(); },
+			}
+			corrupted += corruption.is_some() as usize;
+			for (i, line) in [header.as_bytes(), &seq, sep, &qual].iter().take(lines).enumerate() {
+				if i == 0 { out.write_all(b"@")? }
+				out.write_all(line)?;
+				out.write_all(b"\n")?;
+			}
+		}
+		out.flush()?;
+		Ok(corrupted)
+	}
+	
+	/// Generate `n` records as FastQ text in memory.
+	pub fn to_bytes(&mut self, n: usize) -> Vec<u8> {
+		let mut out = Vec::new();
+		self.write_to(&mut out, n).expect("writing to a Vec cannot fail");
+		out
+	}
+	
+	/// Write `n` records to a file, creating or truncating it.
+	/// Returns the number of corrupted records.
+	pub fn to_file<P: AsRef<Path>>(&mut self, path: P, n: usize) -> io::Result<usize> {
+		self.write_to(io::BufWriter::new(fs::File::create(long_path(path))?), n)
+	}
+}
+
+impl Iterator for RecordGenerator {
+	type Item = Record;
+	
+	fn next(&mut self) -> Option<Record> {
+		Some(self.next_record())
+	}
+}
@@ -0,0 +1,99 @@
+//! Structured Illumina read headers, e.g. for tile-level QC.
+//!
+//! Understands Casava 1.8+ (`@instrument:run:flowcell:lane:tile:x:y[:umi] read:filtered:control:index`)
+//! and older (`@instrument:lane:tile:x:y#index/read`) headers.
+
+/// The fields of an Illumina read header, see `Record::illumina_header`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IlluminaHeader {
+	pub instrument: String,
+	/// Run number, only in Casava 1.8+ headers.
+	pub run: Option<u32>,
+	/// Flowcell ID, only in Casava 1.8+ headers.
+	pub flowcell: Option<String>,
+	pub lane: u32,
+	pub tile: u32,
+	/// Cluster coordinates within the tile.
+	pub x: u32,
+	pub y: u32,
+	/// UMI as an eighth ID field, as written by demultiplexers that extract UMIs.
+	pub umi: Option<String>,
+	/// 1 or 2 for the mates of a pair (3 for a third read), if given.
+	pub read: Option<u8>,
+	/// Whether the read failed the chastity filter, only in Casava 1.8+ headers.
+	pub filtered: Option<bool>,
+	/// Control number, 0 for none, only in Casava 1.8+ headers.
+	pub control: Option<u32>,
+	/// Index (barcode) sequence or sample number, dual indices joined by `+`, if given.
+	pub index: Option<String>,
+}
+
+impl IlluminaHeader {
+	/// Parse a header given as ID and optional description, if it is an Illumina header.
+	pub fn parse(id: &str, desc: Option<&str>) -> Option<IlluminaHeader> {
+		let fields: Vec<&str> = id.split(':').collect();
+		match fields.len() {
+			7 | 8 => IlluminaHeader::parse_casava(&fields, desc),
+			5 => IlluminaHeader::parse_legacy(&fields),
+			_ => None,
+		}
+	}
+	
+	/// `instrument:run:flowcell:lane:tile:x:y[:umi]` with `read:filtered:control:index` description.
+	fn parse_casava(fields: &[&str], desc: Option<&str>) -> Option<IlluminaHeader> {
+		let mut header = IlluminaHeader {
+			instrument: non_empty(fields[0])?.to_owned(),
+			run: Some(fields[1].parse().ok()?),
+			flowcell: Some(non_empty(fields[2])?.to_owned()),
+			lane: fields[3].parse().ok()?,
+			tile: fields[4].parse().ok()?,
+			x: fields[5].parse().ok()?,
+			y: fields[6].parse().ok()?,
+			umi: fields.get(7).map(|&u| u.to_owned()),
+			read: None,
+			filtered: None,
+			control: None,
+			index: None,
+		};
+		let first = desc.and_then(|d| d.split_whitespace().next()).unwrap_or("");
+		if let [read, filtered, control, index] = first.split(':').collect::<Vec<_>>()[..] {
+			header.read = read.parse().ok();
+			header.filtered = match filtered { "Y" => Some(true), "N" => Some(false), _ => None };
+			header.control = control.parse().ok();
+			header.index = non_empty(index).map(str::to_owned);
+		}
+		Some(header)
+	}
+	
+	/// `instrument:lane:tile:x:y[#index][/read]`
+	fn parse_legacy(fields: &[&str]) -> Option<IlluminaHeader> {
+		let (last, read) = match fields[4].rsplit_once('/') {
+			Some((last, read)) => (last, Some(read.parse().ok()?)),
+			None => (fields[4], None),
+		};
+		let (y, index) = match last.split_once('#') {
+			// `#0` means no index
+			Some((y, index)) => (y, Some(index).filter(|i| !i.chars().all(|c| c == '0')).map(str::to_owned)),
+			None => (last, None),
+		};
+		Some(IlluminaHeader {
+			instrument: non_empty(fields[0])?.to_owned(),
+			run: None,
+			flowcell: None,
+			lane: fields[1].parse().ok()?,
+			tile: fields[2].parse().ok()?,
+			x: fields[3].parse().ok()?,
+			y: y.parse().ok()?,
+			umi: None,
+			read,
+			filtered: None,
+			control: None,
+			index,
+		})
+	}
+}
+
+fn non_empty(s: &str) -> Option<&str> {
+	if s.is_empty() { None } else { Some(s) }
+}
@@ -0,0 +1,169 @@
+//! Push-style parsing of FastQ data arriving in arbitrary chunks.
+
+use std::io;
+
+use super::Position;
+use super::unfancy_parser::{Record, Error};
+
+/// A FastQ parser that is fed bytes as they arrive, e.g. from a socket.
+///
+/// Records may be split across `feed` calls at any byte;
+/// incomplete lines are kept until the rest of them arrives.
+/// Blank lines after the last record are ignored.
+#[derive(Debug, Default)]
+pub struct IncrementalParser {
+	buf: Vec<u8>,
+	/// How much of `buf` is known to hold no line break.
+	scanned: usize,
+	lines: Vec<String>,
+	/// Position and total length of blank lines after the last record,
+	/// which are only an error if another line follows.
+	blank: Option<(Position, usize)>,
+	/// Position of the next line.
+	pos: Position,
+	/// Position of the first pending line.
+	start: Position,
+}
+
+impl IncrementalParser {
+	/// Create a parser with no pending input.
+	pub fn new() -> IncrementalParser {
+		IncrementalParser::default()
+	}
+	
+	/// Feed a chunk of input, returning the records completed by it.
+	pub fn feed(&mut self, data: &[u8]) -> Vec<Result<Record, Error>> {
+		self.buf.extend_from_slice(data);
+		let mut records = Vec::new();
+		let mut start = 0;
+		let mut from = self.scanned;
+		while let Some(nl) = self.buf[from..].iter().position(|&b| b == b'\n') {
+			let end = from + nl + 1;
+			let line = self.buf[start..end].to_vec();
+			start = end;
+			from = end;
+			self.push_line(line, &mut records);
+		}
+		self.buf.drain(..start);
+		self.scanned = self.buf.len();
+		records
+	}
+	
+	/// Signal the end of input, returning the last record if the input lacked a final newline.
+	/// Returns an error if input ended in the middle of a record.
+	pub fn finish(mut self) -> Result<Option<Record>, Error> {
+		let mut last = Vec::new();
+		if !self.buf.is_empty() {
+			let line = std::mem::take(&mut self.buf);
+			self.push_line(line, &mut last);
+		}
+		if !self.lines.is_empty() {
+			return Err(Error::IncompleteRecord(self.start));
+		}
+		last.pop().transpose()
+	}
+	
+	/// The position of the next line to be completed.
+	pub fn position(&self) -> Position { self.pos }
+	
+	/// Number of bytes received but not yet part of a returned record.
+	pub fn pending(&self) -> usize {
+		self.buf.len() + self.blank.map_or(0, |(_, len)| len) + self.lines.iter().map(String::len).sum::<usize>()
+	}
+	
+	/// Parse a complete line, pushing the records or errors it completes to `out`.
+	fn push_line(&mut self, line: Vec<u8>, out: &mut Vec<Result<Record, Error>>) {
+		let here = self.pos;
+		self.pos.advance_line(line.len());
+		if self.lines.is_empty() {
+			if line.iter().all(u8::is_ascii_whitespace) {
+				self.blank.get_or_insert((here, 0)).1 += line.len();
+				return;
+			}
+			if let Some((blank, _)) = self.blank.take() { out.push(Err(Error::MissingAt(blank))) }
+			self.start = here;
+		}
+		let line = match String::from_utf8(line) {
+			Ok(line) => line,
+			Err(e) => {
+				self.lines.clear();
+				self.pos.record += 1;
+				out.push(Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", here, e)).into()));
+				return;
+			},
+		};
+		match self.lines.len() {
+			0 if !line.starts_with('@') => return out.push(Err(Error::MissingAt(here))),
+			2 if !line.starts_with('+') => {
+				self.lines.clear();
+				self.pos.record += 1;
+				return out.push(Err(Error::MissingPlus(here)));
+			},
+			_ => {},
+		}
+		self.lines.push(line);
+		if self.lines.len() < 4 { return }
+		let mut lines = self.lines.drain(..);
+		let (Some(header), Some(seq), _, Some(qual)) = (lines.next(), lines.next(), lines.next(), lines.next()) else { return };
+		drop(lines);
+		self.pos.record += 1;
+		let mut record = Record::from_lines(header, seq, qual);
+		record.set_offset(Some(self.start.offset));
+		out.push(Ok(record));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Record as RecordTrait;
+	
+	const DATA: &[u8] = b"@r1\nACGT\n+\nIIII\n@r2\nGG\n+\nII\n";
+	
+	fn ids(records: &[Result<Record, Error>]) -> Vec<String> {
+		records.iter().map(|r| r.as_ref().unwrap().id().unwrap().to_owned()).collect()
+	}
+	
+	#[test]
+	fn parses_byte_by_byte() {
+		let mut parser = IncrementalParser::new();
+		let mut records = Vec::new();
+		for b in DATA.chunks(1) { records.extend(parser.feed(b)) }
+		assert_eq!(ids(&records), ["r1", "r2"]);
+		assert_eq!(parser.pending(), 0);
+		assert!(parser.finish().unwrap().is_none());
+	}
+	
+	#[test]
+	fn returns_last_record_without_newline() {
+		let mut parser = IncrementalParser::new();
+		assert_eq!(ids(&parser.feed(&DATA[..DATA.len() - 1])), ["r1"]);
+		assert_eq!(parser.finish().unwrap().unwrap().qual(), b"II");
+	}
+	
+	#[test]
+	fn tolerates_trailing_blank_lines() {
+		let mut parser = IncrementalParser::new();
+		let records = parser.feed(DATA);
+		assert!(parser.feed(b"\n  \n\t").is_empty());
+		assert_eq!(ids(&records), ["r1", "r2"]);
+		assert_eq!(parser.pending(), 5);
+		assert!(parser.finish().unwrap().is_none());
+	}
+	
+	#[test]
+	fn reports_blank_lines_between_records() {
+		let mut parser = IncrementalParser::new();
+		let records = parser.feed(b"@r1\nA\n+\nI\n\n@r2\nC\n+\nI\n");
+		assert!(matches!(records[1], Err(Error::MissingAt(Position { line: 5, .. }))));
+		assert_eq!(records.len(), 3);
+		assert!(records[2].is_ok());
+	}
+	
+	#[test]
+	fn fails_on_incomplete_record() {
+		let mut parser = IncrementalParser::new();
+		parser.feed(b"@r1\nACGT\n");
+		assert!(matches!(parser.finish(), Err(Error::IncompleteRecord(_))));
+	}
+}
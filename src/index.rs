@@ -0,0 +1,223 @@
+//! A byte-offset index for random access into plain FastQ files.
+//!
+//! The index is saved as a small TSV file, by convention next to the FastQ file with `.fqi` appended.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::Position;
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Reader, Record, Error};
+use super::input::require_seekable;
+use super::paths::long_path;
+use super::scan::ScanSummary;
+
+/// Offsets of every `every`th record of a FastQ file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+	every: usize,
+	records: usize,
+	entries: Vec<(String, u64)>,
+}
+
+impl Index {
+	/// Index every `every`th record of a FastQ stream (every record for 1).
+	pub fn build<R: Read>(reader: R, every: usize) -> Result<Index, Error> {
+		let every = every.max(1);
+		let mut index = Index { every, records: 0, entries: Vec::new() };
+		for record in Reader::new(reader).records() {
+			let record = record?;
+			if index.records.is_multiple_of(every) {
+				index.entries.push((record.id().unwrap_or("").to_owned(), record.offset().unwrap_or(0)));
+			}
+			index.records += 1;
+		}
+		Ok(index)
+	}
+	
+	/// Take the index from a scan that collected checkpoints, which is quicker than parsing every record.
+	/// `None` if the scan collected none, see `scan::Scanner::checkpoints`.
+	pub fn from_scan(summary: &ScanSummary) -> Option<Index> {
+		if summary.checkpoint_every == 0 { return None }
+		Some(Index { every: summary.checkpoint_every, records: summary.records as usize, entries: summary.checkpoints.clone() })
+	}
+	
+	/// Index a plain FastQ file.
+	pub fn from_file<P: AsRef<Path>>(path: P, every: usize) -> Result<Index, Error> {
+		Index::build(fs::File::open(long_path(path))?, every)
+	}
+	
+	/// The number of records in the indexed file.
+	pub fn len(&self) -> usize { self.records }
+	
+	/// Check if the indexed file has no records.
+	pub fn is_empty(&self) -> bool { self.records == 0 }
+	
+	/// The distance between indexed records.
+	pub fn every(&self) -> usize { self.every }
+	
+	/// Write the index as TSV: a `#every` line, then one `id`/`offset` line per indexed record.
+	pub fn write_to<W: Write>(&self, out: W) -> io::Result<()> {
+		let mut out = io::BufWriter::new(out);
+		writeln!(out, "#every\t{}\trecords\t{}", self.every, self.records)?;
+		for (id, offset) in &self.entries {
+			writeln!(out, "{}\t{}", id, offset)?;
+		}
+		out.flush()
+	}
+	
+	/// Read an index written by `write_to`.
+	pub fn read_from<R: Read>(reader: R) -> io::Result<Index> {
+		let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid FastQ index: {}", msg));
+		let mut lines = io::BufReader::new(reader).lines();
+		let head = lines.next().ok_or_else(|| invalid("empty"))??;
+		let fields: Vec<&str> = head.split('\t').collect();
+		let (every, records) = match fields[..] {
+			["#every", every, "records", records] => (
+				every.parse().ok().filter(|&every| every > 0).ok_or_else(|| invalid("bad interval"))?,
+				records.parse().map_err(|_| invalid("bad record count"))?,
+			),
+			_ => return Err(invalid("missing header")),
+		};
+		let mut entries = Vec::new();
+		for line in lines {
+			let line = line?;
+			let (id, offset) = line.rsplit_once('\t').ok_or_else(|| invalid("missing offset"))?;
+			entries.push((id.to_owned(), offset.parse().map_err(|_| invalid("bad offset"))?));
+		}
+		Ok(Index { every, records, entries })
+	}
+	
+	/// Save the index to a file.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write_to(fs::File::create(long_path(path))?)
+	}
+	
+	/// Load an index from a file.
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Index> {
+		Index::read_from(fs::File::open(long_path(path))?)
+	}
+	
+	/// The conventional index path for a FastQ file: its path with `.fqi` appended.
+	pub fn path_for<P: AsRef<Path>>(fastq: P) -> PathBuf {
+		let mut path = fastq.as_ref().as_os_str().to_owned();
+		path.push(".fqi");
+		path.into()
+	}
+}
+
+/// A reader fetching records by id or number using an `Index`.
+pub struct IndexedReader<R: Read + Seek> {
+	inner: R,
+	index: Index,
+	ids: Option<HashMap<String, usize>>,
+}
+
+impl IndexedReader<fs::File> {
+	/// Open a plain FastQ file, loading its `.fqi` index or building and saving one with `every`.
+	/// Fails with `Unsupported` for pipes and other files that cannot seek.
+	pub fn open<P: AsRef<Path>>(path: P, every: usize) -> Result<Self, Error> {
+		require_seekable(path.as_ref(), "Reading records by number or ID")?;
+		let index_path = Index::path_for(&path);
+		let index = match Index::load(&index_path) {
+			Ok(index) => index,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+				let index = Index::from_file(&path, every)?;
+				index.save(&index_path)?;
+				index
+			},
+			Err(e) => return Err(e.into()),
+		};
+		Ok(IndexedReader::new(fs::File::open(long_path(path))?, index))
+	}
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+	/// Read from a seekable FastQ source with a matching index.
+	pub fn new(inner: R, index: Index) -> Self {
+		IndexedReader { inner, index, ids: None }
+	}
+	
+	/// The index in use.
+	pub fn index(&self) -> &Index { &self.index }
+	
+	/// Fetch `count` records starting with the record number `start` (counting from 0).
+	/// Fewer records are returned if the file ends first.
+	pub fn fetch_range(&mut self, start: usize, count: usize) -> Result<Vec<Record>, Error> {
+		if count == 0 || start >= self.index.records { return Ok(Vec::new()) }
+		let entry = start / self.index.every;
+		let offset = match self.index.entries.get(entry) {
+			Some(&(_, offset)) => offset,
+			None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("FastQ index has no entry for record {}, its record count is wrong", start)).into()),
+		};
+		self.inner.seek(SeekFrom::Start(offset))?;
+		let records = Reader::new(&mut self.inner).starting_at(Position { offset, ..Position::start() }).records();
+		records.skip(start - entry * self.index.every).take(count).collect()
+	}
+	
+	/// Fetch a single record by its number.
+	pub fn fetch(&mut self, n: usize) -> Result<Option<Record>, Error> {
+		Ok(self.fetch_range(n, 1)?.pop())
+	}
+	
+	/// Fetch a record with the given id.
+	///
+	/// With an index of every record, this is a hash lookup and a single seek.
+	/// Otherwise the file is scanned unless the id happens to be that of an indexed record.
+	pub fn fetch_by_id(&mut self, id: &str) -> Result<Option<Record>, Error> {
+		if self.index.every == 1 {
+			let index = &self.index;
+			let ids = self.ids.get_or_insert_with(|| {
+				let mut ids = HashMap::with_capacity(index.entries.len());
+				for (n, (id, _)) in index.entries.iter().enumerate() { ids.entry(id.clone()).or_insert(n); }
+				ids
+			});
+			return match ids.get(id) {
+				Some(&n) => self.fetch(n),
+				None => Ok(None),
+			};
+		}
+		if let Some(n) = self.index.entries.iter().position(|e| e.0 == id) {
+			return self.fetch(n * self.index.every);
+		}
+		self.inner.seek(SeekFrom::Start(0))?;
+		for record in Reader::new(&mut self.inner).records() {
+			let record = record?;
+			if record.id() == Some(id) { return Ok(Some(record)) }
+		}
+		Ok(None)
+	}
+	
+	/// Return the underlying reader.
+	pub fn into_inner(self) -> R { self.inner }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	const DATA: &[u8] = b"@r0\nA\n+\nI\n@r1\nC\n+\nI\n@r2\nG\n+\nI\n";
+	
+	#[test]
+	fn fetches_by_number_and_id() {
+		let index = Index::build(DATA, 2).unwrap();
+		let mut reader = IndexedReader::new(io::Cursor::new(DATA), index);
+		assert_eq!(reader.fetch(1).unwrap().unwrap().id(), Some("r1"));
+		assert_eq!(reader.fetch_by_id("r2").unwrap().unwrap().seq(), b"G");
+		assert!(reader.fetch(3).unwrap().is_none());
+	}
+	
+	#[test]
+	fn rejects_inconsistent_index() {
+		let index = Index::read_from(&b"#every\t1\trecords\t5\nr0\t0\n"[..]).unwrap();
+		let mut reader = IndexedReader::new(io::Cursor::new(DATA), index);
+		match reader.fetch(3) {
+			Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+			other => panic!("expected invalid data, got {:?}", other),
+		}
+		let zero = Index::read_from(&b"#every\t0\trecords\t1\nr0\t0\n"[..]);
+		assert_eq!(zero.unwrap_err().kind(), io::ErrorKind::InvalidData);
+	}
+}
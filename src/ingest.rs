@@ -0,0 +1,405 @@
+//! Processing FastQ files as they appear in a directory, e.g. next to an instrument that drops files as a run progresses.
+//!
+//! An `Ingest` runs a `Pipeline` on every FastQ file in its input directory, once the file stopped growing,
+//! and writes the processed reads and their statistics to an output directory.
+//! Every processed file is appended to a ledger there, so a restarted `Ingest` skips what it already did.
+//! `Ingest::process_pending` scans the directory once; `Ingest::watch` keeps at it, woken by file system events.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use notify::Watcher as _;
+
+use super::demux::Output;
+use super::filter::{max_n, min_length, MaxN};
+use super::input::Input;
+use super::paths::{long_dir, long_path};
+use super::quality::Encoding;
+use super::stats::{fastq_files, StatsCollector};
+use super::trim::{trim_preset, TrimPreset};
+use super::unfancy_parser::{Reader, Error, Writer};
+
+/// The name of the ledger in the output directory.
+pub const LEDGER_FILE: &str = "ledger.tsv";
+
+const LEDGER_HEADER: &str = "file\tsize\tmodified\treads\tkept\tstatus";
+
+quick_error! {
+	/// An error that stopped an `Ingest`. Files that fail to parse do not, they are recorded as failed.
+	#[derive(Debug)]
+	pub enum IngestError {
+		/// Reading the input directory or writing the outputs failed.
+		Io(err: io::Error) {
+			from()
+			cause(err)
+			description("I/O error while ingesting")
+			display("{}", err)
+		}
+		/// Watching the input directory failed.
+		Watch(err: notify::Error) {
+			from()
+			cause(err)
+			description("Error watching the input directory")
+			display("Watching failed: {}", err)
+		}
+	}
+}
+
+/// What is done to each file: trimming by a preset, then dropping reads that are too short or have too many `N`s.
+/// The default keeps every read as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+	/// Trim reads by this preset.
+	pub preset: Option<TrimPreset>,
+	/// The quality encoding of the files.
+	pub encoding: Encoding,
+	/// Drop reads shorter than this after trimming.
+	pub min_length: usize,
+	/// Drop reads with more `N`s than this.
+	pub max_n: Option<MaxN>,
+}
+
+impl Default for Pipeline {
+	fn default() -> Pipeline {
+		Pipeline { preset: None, encoding: Encoding::Sanger, min_length: 0, max_n: None }
+	}
+}
+
+impl Pipeline {
+	/// Trim by `preset` and drop the reads it says are too short.
+	pub fn preset(preset: TrimPreset) -> Pipeline {
+		Pipeline { preset: Some(preset), min_length: preset.min_length(), ..Pipeline::default() }
+	}
+	
+	/// Process `input`, writing the kept reads to `output`, gzip compressed if it ends with `.gz`,
+	/// and their statistics to `stats`. Returns the reads read and kept.
+	fn run(&self, input: &Path, output: &Path, stats: &Path) -> Result<(u64, u64), Error> {
+		let mut reads = 0;
+		let records = Reader::new(Input::open(input)?).records().inspect(|_| reads += 1);
+		let records: Box<dyn Iterator<Item=_>> = match self.preset {
+			Some(preset) => Box::new(trim_preset(records, preset, self.encoding)),
+			None => Box::new(records),
+		};
+		let records: Box<dyn Iterator<Item=_>> = match self.max_n {
+			Some(max) => Box::new(max_n(records, max)),
+			None => records,
+		};
+		let mut collector = StatsCollector::with_offset(self.encoding.offset());
+		let mut writer = Writer::new(BufWriter::new(Output::create(&long_path(output), &[])?));
+		for record in min_length(records, self.min_length) {
+			let record = record?;
+			collector.add(&record);
+			writer.write_record(&record)?;
+		}
+		writer.into_inner()?.into_inner().map_err(io::IntoInnerError::into_error)?.finish()?;
+		let report = collector.report();
+		report.write_tsv(BufWriter::new(File::create(long_path(stats))?))?;
+		Ok((reads, report.reads))
+	}
+}
+
+/// Whether a file was processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+	/// Its reads and statistics were written.
+	Done,
+	/// It could not be processed, for this reason. It is not retried unless it changes.
+	Failed(String),
+}
+
+impl fmt::Display for Status {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Status::Done => write!(f, "done"),
+			Status::Failed(ref reason) => write!(f, "failed: {}", reason),
+		}
+	}
+}
+
+/// A line of the ledger: a processed file and what came of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+	/// The file’s name in the input directory.
+	pub file: String,
+	/// Its size in bytes when it was processed.
+	pub size: u64,
+	/// Its modification time when it was processed, in milliseconds since the Unix epoch.
+	pub modified: u64,
+	/// The number of reads in it.
+	pub reads: u64,
+	/// The number of reads the pipeline kept.
+	pub kept: u64,
+	/// Whether it was processed.
+	pub status: Status,
+}
+
+impl Entry {
+	fn parse(line: &str) -> Option<Entry> {
+		let fields: Vec<_> = line.splitn(6, '\t').collect();
+		let &[file, size, modified, reads, kept, status] = &fields[..] else { return None };
+		let status = match status.strip_prefix("failed: ") {
+			Some(reason) => Status::Failed(reason.to_owned()),
+			None if status == "done" => Status::Done,
+			None => return None,
+		};
+		Some(Entry {
+			file: file.to_owned(), size: size.parse().ok()?, modified: modified.parse().ok()?,
+			reads: reads.parse().ok()?, kept: kept.parse().ok()?, status,
+		})
+	}
+}
+
+impl fmt::Display for Entry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}\t{}\t{}\t{}\t{}\t{}", self.file, self.size, self.modified, self.reads, self.kept, self.status)
+	}
+}
+
+/// Runs a `Pipeline` on the FastQ files appearing in a directory, see the module documentation.
+///
+/// The file `reads.fq.gz` results in `reads.fq.gz`, compressed like the input, and `reads.fq.gz.stats.tsv`
+/// in the output directory, which must not be the input directory.
+#[derive(Debug)]
+pub struct Ingest {
+	input: PathBuf,
+	output: PathBuf,
+	pipeline: Pipeline,
+	settle: Duration,
+	/// The processed files by name, with their size and modification time.
+	processed: HashMap<String, (u64, u64)>,
+	/// The files not processed yet, with their size and when it was last seen to change.
+	growing: HashMap<PathBuf, (u64, Instant)>,
+}
+
+impl Ingest {
+	/// Ingest the files in `input` into `output`, which is created if necessary.
+	/// Files already in the ledger in `output` are skipped.
+	pub fn new<P, Q>(input: P, output: Q, pipeline: Pipeline) -> io::Result<Ingest> where P: AsRef<Path>, Q: AsRef<Path> {
+		let (input, output) = (long_dir(input), long_dir(output));
+		fs::create_dir_all(&output)?;
+		if fs::canonicalize(&input)? == fs::canonicalize(&output)? {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "the output directory is the input directory"))
+		}
+		let mut processed = HashMap::new();
+		match File::open(output.join(LEDGER_FILE)) {
+			Ok(ledger) => for line in BufReader::new(ledger).lines().skip(1) {
+				let line = line?;
+				let entry = Entry::parse(&line).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("invalid ledger line {:?}", line)))?;
+				processed.insert(entry.file, (entry.size, entry.modified));
+			},
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+			Err(e) => return Err(e),
+		}
+		Ok(Ingest { input, output, pipeline, settle: Duration::from_secs(10), processed, growing: HashMap::new() })
+	}
+	
+	/// How long a file’s size has to stay the same before it is processed, 10 seconds by default.
+	pub fn settle(mut self, settle: Duration) -> Self {
+		self.settle = settle;
+		self
+	}
+	
+	/// Process the files in the input directory that are new or changed and have settled, in order of their names.
+	/// Returns their ledger entries, which were also appended to the ledger.
+	pub fn process_pending(&mut self) -> io::Result<Vec<Entry>> {
+		self.process_files(fastq_files(&self.input)?, Instant::now())
+	}
+	
+	/// Process those of `paths` that are new or changed and have settled by `now`.
+	/// Files that disappeared since they were listed are skipped.
+	fn process_files(&mut self, paths: Vec<PathBuf>, now: Instant) -> io::Result<Vec<Entry>> {
+		let mut entries = Vec::new();
+		for path in paths {
+			let Some(file) = path.file_name().and_then(|n| n.to_str()).map(str::to_owned) else { continue };
+			let metadata = match fs::metadata(&path) {
+				Ok(metadata) => metadata,
+				Err(e) if e.kind() == io::ErrorKind::NotFound => { self.growing.remove(&path); continue },
+				Err(e) => return Err(e),
+			};
+			let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+			if self.processed.get(&file) == Some(&(metadata.len(), modified)) { continue }
+			let &mut (size, since) = self.growing.entry(path.clone())
+				.and_modify(|seen| if seen.0 != metadata.len() { *seen = (metadata.len(), now) })
+				.or_insert((metadata.len(), now));
+			if now.duration_since(since) < self.settle || size != metadata.len() { continue }
+			self.growing.remove(&path);
+			entries.extend(self.process(&path, file, size, modified)?);
+		}
+		Ok(entries)
+	}
+	
+	/// Process a file, unless it disappeared.
+	fn process(&mut self, path: &Path, file: String, size: u64, modified: u64) -> io::Result<Option<Entry>> {
+		let output = self.output.join(&file);
+		let stats = self.output.join(format!("{}.stats.tsv", file));
+		// written under temporary names, so that whatever reads the outputs never sees a partial file;
+		// `.part` goes before `.gz`, which says how to write the reads
+		let partial = match file.strip_suffix(".gz") {
+			Some(stem) => self.output.join(format!("{}.part.gz", stem)),
+			None => self.output.join(format!("{}.part", file)),
+		};
+		let partial_stats = self.output.join(format!("{}.stats.tsv.part", file));
+		let (reads, kept, status) = match self.pipeline.run(path, &partial, &partial_stats) {
+			Ok((reads, kept)) => {
+				fs::rename(&partial, output)?;
+				fs::rename(&partial_stats, stats)?;
+				(reads, kept, Status::Done)
+			},
+			Err(e) => {
+				let _ = fs::remove_file(&partial);
+				let _ = fs::remove_file(&partial_stats);
+				match e {
+					Error::Io(e) if e.kind() == io::ErrorKind::NotFound && !path.exists() => return Ok(None),
+					// broken input, e.g. a corrupt gzip stream, is the file’s problem; anything else stops the ingest
+					Error::Io(e) if !matches!(e.kind(), io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput | io::ErrorKind::UnexpectedEof) => return Err(e),
+					e => (0, 0, Status::Failed(e.to_string().replace(['\t', '\n', '\r'], " "))),
+				}
+			},
+		};
+		let entry = Entry { file, size, modified, reads, kept, status };
+		let ledger = self.output.join(LEDGER_FILE);
+		let new = !ledger.exists();
+		let mut ledger = OpenOptions::new().create(true).append(true).open(ledger)?;
+		if new { writeln!(ledger, "{}", LEDGER_HEADER)? }
+		writeln!(ledger, "{}", entry)?;
+		self.processed.insert(entry.file.clone(), (size, modified));
+		Ok(Some(entry))
+	}
+	
+	/// Process files as they appear until `processed` returns `false` for one, starting with those already there.
+	/// Events of the input directory trigger a scan, which is repeated while files are settling.
+	pub fn watch<F>(&mut self, mut processed: F) -> Result<(), IngestError> where F: FnMut(&Entry) -> bool {
+		let (sender, events) = mpsc::channel();
+		// watching starts before the first scan, so files created in between are not missed
+		let mut watcher = notify::recommended_watcher(sender)?;
+		watcher.watch(&self.input, notify::RecursiveMode::NonRecursive)?;
+		let poll = self.settle.max(Duration::from_millis(100));
+		loop {
+			for entry in self.process_pending()? {
+				if !processed(&entry) { return Ok(()) }
+			}
+			match events.recv_timeout(poll) {
+				Ok(event) => { event?; },
+				Err(mpsc::RecvTimeoutError::Timeout) => {},
+				Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+			}
+			// a single write can cause many events
+			while let Ok(event) = events.try_recv() { event?; }
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::process;
+	
+	fn dirs(name: &str) -> (PathBuf, PathBuf) {
+		let dir = std::env::temp_dir().join(format!("fastq-ingest-{}-test-{}", name, process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(dir.join("in")).unwrap();
+		(dir.join("in"), dir.join("out"))
+	}
+	
+	#[test]
+	fn processes_new_and_changed_files_once() {
+		let (input, output) = dirs("pending");
+		fs::write(input.join("a.fq"), "@a\nACGTACGT\n+\nIIIIIIII\n@b\nACG\n+\nIII\n").unwrap();
+		fs::write(input.join("bad.fastq"), "@c\nACGT\nIIII\n").unwrap();
+		fs::write(input.join("notes.txt"), "").unwrap();
+		let pipeline = Pipeline { min_length: 5, ..Pipeline::default() };
+		let mut ingest = Ingest::new(&input, &output, pipeline.clone()).unwrap().settle(Duration::ZERO);
+	
+		let entries = ingest.process_pending().unwrap();
+		assert_eq!(entries.iter().map(|e| (&e.file[..], e.reads, e.kept)).collect::<Vec<_>>(), [("a.fq", 2, 1), ("bad.fastq", 0, 0)]);
+		assert_eq!(entries[0].status, Status::Done);
+		assert!(matches!(entries[1].status, Status::Failed(_)));
+		assert_eq!(fs::read_to_string(output.join("a.fq")).unwrap(), "@a\nACGTACGT\n+\nIIIIIIII\n");
+		assert!(fs::read_to_string(output.join("a.fq.stats.tsv")).unwrap().contains("reads\t1\n"));
+		assert!(!output.join("bad.fastq").exists() && !output.join("bad.fastq.part").exists());
+		assert!(ingest.process_pending().unwrap().is_empty());
+	
+		// a restarted ingest reads the ledger, and picks up only the file that changed
+		let ledger = fs::read_to_string(output.join(LEDGER_FILE)).unwrap();
+		assert_eq!(ledger.lines().count(), 3);
+		assert_eq!(Entry::parse(ledger.lines().nth(1).unwrap()).as_ref(), Some(&entries[0]));
+		fs::write(input.join("bad.fastq"), "@c\nACGTA\n+\nIIIII\n").unwrap();
+		let mut ingest = Ingest::new(&input, &output, pipeline).unwrap().settle(Duration::ZERO);
+		let entries = ingest.process_pending().unwrap();
+		assert_eq!(entries.iter().map(|e| (&e.file[..], e.kept, &e.status)).collect::<Vec<_>>(), [("bad.fastq", 1, &Status::Done)]);
+		fs::remove_dir_all(input.parent().unwrap()).unwrap();
+	}
+	
+	#[test]
+	fn waits_for_files_to_settle() {
+		let (input, output) = dirs("settle");
+		let path = input.join("a.fq");
+		fs::write(&path, "@a\nACGT\n+\nIIII\n").unwrap();
+		let mut ingest = Ingest::new(&input, &output, Pipeline::default()).unwrap().settle(Duration::from_secs(10));
+		let start = Instant::now();
+		assert!(ingest.process_files(vec![path.clone()], start).unwrap().is_empty());
+		// a file that grew starts settling anew
+		fs::write(&path, "@a\nACGT\n+\nIIII\n@b\nACGT\n+\nIIII\n").unwrap();
+		assert!(ingest.process_files(vec![path.clone()], start + Duration::from_secs(6)).unwrap().is_empty());
+		assert!(ingest.process_files(vec![path.clone()], start + Duration::from_secs(12)).unwrap().is_empty());
+		let entries = ingest.process_files(vec![path], start + Duration::from_secs(16)).unwrap();
+		assert_eq!(entries.iter().map(|e| (&e.file[..], e.reads)).collect::<Vec<_>>(), [("a.fq", 2)]);
+		assert!(Ingest::new(&input, &input, Pipeline::default()).is_err());
+		fs::remove_dir_all(input.parent().unwrap()).unwrap();
+	}
+	
+	#[test]
+	fn skips_files_that_disappeared() {
+		let (input, output) = dirs("gone");
+		fs::write(input.join("b.fq"), "@b\nACGT\n+\nIIII\n").unwrap();
+		let mut ingest = Ingest::new(&input, &output, Pipeline::default()).unwrap().settle(Duration::ZERO);
+		let entries = ingest.process_files(vec![input.join("a.fq"), input.join("b.fq")], Instant::now()).unwrap();
+		assert_eq!(entries.iter().map(|e| &e.file[..]).collect::<Vec<_>>(), ["b.fq"]);
+		fs::remove_dir_all(input.parent().unwrap()).unwrap();
+	}
+	
+	#[cfg(feature = "gzip")]
+	#[test]
+	fn keeps_compressed_and_plain_outputs_apart() {
+		use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+		use std::io::Read;
+		
+		let (input, output) = dirs("gzip");
+		fs::write(input.join("reads.fq"), "@plain\nACGT\n+\nIIII\n").unwrap();
+		let mut gz = GzEncoder::new(File::create(input.join("reads.fq.gz")).unwrap(), Compression::default());
+		gz.write_all(b"@gzip\nACGT\n+\nIIII\n").unwrap();
+		gz.finish().unwrap();
+		let mut ingest = Ingest::new(&input, &output, Pipeline::default()).unwrap().settle(Duration::ZERO);
+		assert_eq!(ingest.process_pending().unwrap().len(), 2);
+		assert_eq!(fs::read_to_string(output.join("reads.fq")).unwrap(), "@plain\nACGT\n+\nIIII\n");
+		let mut reads = String::new();
+		MultiGzDecoder::new(File::open(output.join("reads.fq.gz")).unwrap()).read_to_string(&mut reads).unwrap();
+		assert_eq!(reads, "@gzip\nACGT\n+\nIIII\n");
+		assert!(output.join("reads.fq.stats.tsv").exists() && output.join("reads.fq.gz.stats.tsv").exists());
+		fs::remove_dir_all(input.parent().unwrap()).unwrap();
+	}
+	
+	#[test]
+	fn watches_for_new_files() {
+		let (input, output) = dirs("watch");
+		fs::write(input.join("a.fq"), "@a\nACGT\n+\nIIII\n").unwrap();
+		let mut ingest = Ingest::new(&input, &output, Pipeline::default()).unwrap().settle(Duration::ZERO);
+		let mut files = Vec::new();
+		ingest.watch(|entry| {
+			files.push(entry.file.clone());
+			if entry.file == "a.fq" {
+				// moved into place while watching, as it would be processed half-written without settling
+				fs::write(input.join("b.tmp"), "@b\nACGT\n+\nIIII\n").unwrap();
+				fs::rename(input.join("b.tmp"), input.join("b.fq")).unwrap();
+			}
+			files.len() < 2
+		}).unwrap();
+		assert_eq!(files, ["a.fq", "b.fq"]);
+		fs::remove_dir_all(input.parent().unwrap()).unwrap();
+	}
+}
@@ -0,0 +1,241 @@
+//! Opening input files with transparent decompression.
+//!
+//! Pipes and other files that cannot seek, e.g. from process substitution (`<(zcat a.fq.gz)`), are read as streams.
+//! `is_seekable` tells if a file supports features that need to seek, and `Input::peek` reads the start
+//! of a file twice either way, spilling it to a temporary file if the file cannot be opened again.
+
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::paths::long_path;
+use super::tempstore::{TempFile, TempStore};
+
+#[cfg(feature = "gzip")]
+use flate2::read::MultiGzDecoder;
+
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// An input file, decompressed on the fly if it is gzip or bgzip compressed.
+pub enum Input {
+	Plain(fs::File),
+	/// A pipe or other uncompressed file that cannot seek.
+	Stream(io::BufReader<fs::File>),
+	#[cfg(feature = "gzip")]
+	Gzip(Box<MultiGzDecoder<io::BufReader<fs::File>>>),
+	/// What `Input::peek` read, spilled to a temporary file, followed by the rest of the input.
+	Replayed(Box<io::Chain<TempFile, Input>>),
+}
+
+impl Input {
+	/// Open a file, detecting gzip/bgzf compression by its magic bytes.
+	pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Input> {
+		let mut file = fs::File::open(long_path(path))?;
+		if !file.metadata()?.is_file() {
+			// look at the magic bytes in the buffer, as they cannot be read again
+			let mut reader = io::BufReader::new(file);
+			let gzip = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+			return if gzip { Input::gzip_buffered(reader) } else { Ok(Input::Stream(reader)) };
+		}
+		let mut magic = [0; 2];
+		let n = read_up_to(&mut file, &mut magic)?;
+		file.seek(SeekFrom::Start(0))?;
+		if n == 2 && magic == GZIP_MAGIC { Input::gzip(file) } else { Ok(Input::Plain(file)) }
+	}
+	
+	/// Open a gzip or bgzip compressed file without sniffing.
+	pub fn open_gz<P: AsRef<Path>>(path: P) -> io::Result<Input> {
+		Input::gzip(fs::File::open(long_path(path))?)
+	}
+	
+	/// Open a file and read its start with `peek`, e.g. to detect the quality encoding,
+	/// then return what `peek` found and the file to read from the start.
+	///
+	/// Regular files are opened again. Pipes cannot be, so what `peek` read from them is spilled
+	/// to a temporary file, which is read before the rest of the pipe.
+	pub fn peek<P, T, F>(path: P, peek: F) -> io::Result<(T, Input)> where P: AsRef<Path>, F: FnOnce(&mut dyn Read) -> T {
+		let path = path.as_ref();
+		let mut input = Input::open(path)?;
+		if is_seekable(path) { return Ok((peek(&mut input), Input::open(path)?)) }
+		let mut spilling = Spilling { inner: input, spill: TempStore::new()?.file()? };
+		let found = peek(&mut spilling);
+		let Spilling { inner, mut spill } = spilling;
+		spill.rewind()?;
+		Ok((found, Input::Replayed(Box::new(spill.chain(inner)))))
+	}
+	
+	/// Check if the input is being decompressed.
+	pub fn is_compressed(&self) -> bool {
+		match *self {
+			Input::Plain(_) | Input::Stream(_) => false,
+			#[cfg(feature = "gzip")]
+			Input::Gzip(_) => true,
+			Input::Replayed(ref chain) => chain.get_ref().1.is_compressed(),
+		}
+	}
+	
+	/// The size of the file.
+	pub(crate) fn file_size(&self) -> Option<u64> {
+		let metadata = match *self {
+			Input::Plain(ref f) => f.metadata(),
+			#[cfg(feature = "gzip")]
+			Input::Gzip(ref d) => d.get_ref().get_ref().metadata(),
+			Input::Stream(_) | Input::Replayed(_) => return None,
+		};
+		metadata.ok().filter(|m| m.is_file()).map(|m| m.len())
+	}
+	
+	/// How far into a compressed file decompression got, `None` for uncompressed input.
+	pub(crate) fn compressed_offset(&self) -> Option<u64> {
+		match *self {
+			Input::Plain(_) | Input::Stream(_) | Input::Replayed(_) => None,
+			#[cfg(feature = "gzip")]
+			Input::Gzip(ref d) => {
+				let buffered = d.get_ref().buffer().len() as u64;
+				let mut file = d.get_ref().get_ref();
+				file.stream_position().ok().map(|p| p.saturating_sub(buffered))
+			},
+		}
+	}
+	
+	fn gzip(file: fs::File) -> io::Result<Input> {
+		Input::gzip_buffered(io::BufReader::new(file))
+	}
+	
+	#[cfg(feature = "gzip")]
+	fn gzip_buffered(reader: io::BufReader<fs::File>) -> io::Result<Input> {
+		Ok(Input::Gzip(Box::new(MultiGzDecoder::new(reader))))
+	}
+	
+	#[cfg(not(feature = "gzip"))]
+	fn gzip_buffered(_: io::BufReader<fs::File>) -> io::Result<Input> {
+		Err(io::Error::new(io::ErrorKind::Unsupported, "gzip support is not compiled in (feature \"gzip\")."))
+	}
+}
+
+impl Read for Input {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match *self {
+			Input::Plain(ref mut f) => f.read(buf),
+			Input::Stream(ref mut r) => r.read(buf),
+			#[cfg(feature = "gzip")]
+			Input::Gzip(ref mut d) => d.read(buf),
+			Input::Replayed(ref mut chain) => chain.read(buf),
+		}
+	}
+}
+
+/// Check if a file is a regular file, which features seeking in it need, rather than a pipe or device.
+pub fn is_seekable<P: AsRef<Path>>(path: P) -> bool {
+	fs::metadata(long_path(path)).is_ok_and(|m| m.is_file())
+}
+
+/// Fail with `Unsupported` if `feature` cannot work on a file because it is not seekable.
+#[cfg(feature = "pipeline")]
+pub(crate) fn require_seekable(path: &Path, feature: &str) -> io::Result<()> {
+	if is_seekable(path) { return Ok(()) }
+	Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} needs a regular file, but {} is a pipe or device", feature, path.display())))
+}
+
+/// A reader copying what is read to a temporary file, see `Input::peek`.
+struct Spilling {
+	inner: Input,
+	spill: TempFile,
+}
+
+impl Read for Spilling {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.spill.write_all(&buf[..n])?;
+		Ok(n)
+	}
+}
+
+/// Like `read_exact`, but returns how much was read at EOF instead of failing.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+	let mut n = 0;
+	while n < buf.len() {
+		match reader.read(&mut buf[n..]) {
+			Ok(0) => break,
+			Ok(m) => n += m,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(n)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+	use std::process::{self, Command};
+	use std::thread;
+	
+	const FASTQ: &str = "@r1\nACGT\n+\nIIII\n@r2\nGGCC\n+\n!!!!\n";
+	
+	/// A named pipe in a fresh directory, fed `data` by a thread each time it is opened, `times` times.
+	fn fifo(name: &str, data: &'static [u8], times: usize) -> (PathBuf, thread::JoinHandle<()>) {
+		let dir = std::env::temp_dir().join(format!("fastq-input-test-{}-{}", process::id(), name));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("pipe");
+		assert!(Command::new("mkfifo").arg(&path).status().unwrap().success());
+		let writer = path.clone();
+		let feeder = thread::spawn(move || for _ in 0..times { fs::write(&writer, data).unwrap() });
+		(path, feeder)
+	}
+	
+	#[test]
+	fn pipes_are_read_as_streams() {
+		let (path, feeder) = fifo("plain", FASTQ.as_bytes(), 1);
+		assert!(!is_seekable(&path) && !is_seekable(path.with_file_name("missing")));
+		let mut input = Input::open(&path).unwrap();
+		assert!(!input.is_compressed() && input.file_size().is_none());
+		assert_eq!(io::read_to_string(&mut input).unwrap(), FASTQ);
+		feeder.join().unwrap();
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+	
+	#[cfg(feature = "gzip")]
+	#[test]
+	fn compressed_pipes() {
+		let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+		gz.write_all(FASTQ.as_bytes()).unwrap();
+		let (path, feeder) = fifo("gzip", gz.finish().unwrap().leak(), 1);
+		let mut input = Input::open(&path).unwrap();
+		assert!(input.is_compressed());
+		assert_eq!(io::read_to_string(&mut input).unwrap(), FASTQ);
+		feeder.join().unwrap();
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+	
+	#[test]
+	fn peeking_replays_pipes() {
+		let peek = |start: &mut dyn Read| {
+			let mut first = [0; 8];
+			start.read_exact(&mut first).map(|()| first)
+		};
+		let (path, feeder) = fifo("peek", FASTQ.as_bytes(), 1);
+		let (first, mut input) = Input::peek(&path, peek).unwrap();
+		assert!(matches!(input, Input::Replayed(_)));
+		assert_eq!(&first.unwrap(), b"@r1\nACGT");
+		assert_eq!(io::read_to_string(&mut input).unwrap(), FASTQ);
+		feeder.join().unwrap();
+		// regular files are opened again
+		let file = path.with_file_name("file.fq");
+		fs::write(&file, FASTQ).unwrap();
+		assert!(is_seekable(&file));
+		let (first, mut input) = Input::peek(&file, peek).unwrap();
+		assert!(matches!(input, Input::Plain(_)));
+		assert_eq!(&first.unwrap(), b"@r1\nACGT");
+		assert_eq!(io::read_to_string(&mut input).unwrap(), FASTQ);
+		#[cfg(feature = "pipeline")]
+		{
+			let unsupported = |e: &io::Error| e.kind() == io::ErrorKind::Unsupported;
+			assert!(crate::parallel::split_ranges(&path, 2).is_err_and(|e| unsupported(&e)));
+			assert!(crate::index::IndexedReader::open(&path, 1).is_err_and(|e| matches!(e, crate::unfancy_parser::Error::Io(ref e) if unsupported(e))));
+			assert_eq!(crate::parallel::split_ranges(&file, 1).unwrap().len(), 1);
+		}
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+}
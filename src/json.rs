@@ -0,0 +1,17 @@
+//! Writing JSON by hand, for the few reports that have a JSON form without the `serde` feature.
+
+/// Quote and escape a string for JSON.
+pub fn json_string(s: &str) -> String {
+	let mut out = String::with_capacity(s.len() + 2);
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
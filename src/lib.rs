@@ -1,9 +1,153 @@
+//! FastQ parsers and the record types they produce.
+//!
+//! With `default-features = false`, only the parsers and what they need are built.
+//! The `stats` feature adds statistics, comparison, validation and benchmarking,
+//! `pipeline` the record processing adapters and file formats built on the parsers,
+//! `gzip` compressed input, `async` the async readers, `mmap` memory-mapped reading,
+//! `bam` writing unaligned BAM, `fetch` downloading benchmark datasets,
+//! `service` an HTTP quality control endpoint, `zstd` zstd-compressed record batches in `codec` and `.zst` demux outputs,
+//! `notify` watching a directory for new FastQ files to process
+//! and `example-data` the small datasets of `example`.
+
 #[macro_use] extern crate quick_error;
 
+use std::borrow::Cow;
+use std::ops::Range;
+
 pub mod fancy_parser;
 pub mod unfancy_parser;
+pub mod borrowed_parser;
+pub mod incremental;
+#[cfg(feature = "async")]
+pub mod async_reader;
+pub mod input;
+pub mod buffer;
+pub mod pool;
+pub mod extensions;
+pub mod quality;
+pub mod error;
+pub mod error_hook;
+pub mod options;
+pub mod position;
+pub mod scan;
+pub mod progress;
+pub mod warning;
+pub mod valid;
+pub mod builder;
+pub mod illumina;
+#[cfg(feature = "example-data")]
+pub mod example;
+
+pub mod tempstore;
+#[cfg(feature = "pipeline")]
+pub mod readgroup;
+#[cfg(feature = "pipeline")]
+pub mod translate;
+#[cfg(feature = "pipeline")]
+pub mod trim;
+#[cfg(feature = "pipeline")]
+pub mod fragment;
+#[cfg(feature = "pipeline")]
+pub mod filter;
+#[cfg(feature = "pipeline")]
+pub mod queue;
+#[cfg(feature = "pipeline")]
+pub mod shuffle;
+#[cfg(feature = "pipeline")]
+pub mod sort;
+#[cfg(feature = "pipeline")]
+pub mod limit;
+#[cfg(feature = "pipeline")]
+pub mod sample;
+#[cfg(feature = "pipeline")]
+pub mod dedup;
+#[cfg(feature = "pipeline")]
+pub mod projection;
+#[cfg(feature = "pipeline")]
+pub mod parallel;
+#[cfg(feature = "pipeline")]
+pub mod paired;
+#[cfg(feature = "pipeline")]
+pub mod fasta;
+#[cfg(feature = "pipeline")]
+pub mod sam;
+#[cfg(feature = "pipeline")]
+pub mod index;
+#[cfg(feature = "pipeline")]
+pub mod checksum;
+#[cfg(feature = "pipeline")]
+pub mod codec;
+#[cfg(feature = "pipeline")]
+pub mod rename;
+#[cfg(feature = "pipeline")]
+pub mod route;
+#[cfg(feature = "pipeline")]
+pub mod cluster;
+#[cfg(feature = "pipeline")]
+pub mod umi;
+#[cfg(feature = "pipeline")]
+pub mod orient;
+#[cfg(feature = "pipeline")]
+pub mod demux;
+#[cfg(feature = "pipeline")]
+pub mod amplicon;
+#[cfg(feature = "pipeline")]
+pub mod merge;
+#[cfg(all(feature = "pipeline", feature = "gzip"))]
+pub mod upload;
+
+#[cfg(any(feature = "pipeline", feature = "stats"))]
+pub mod json;
+#[cfg(any(feature = "pipeline", feature = "stats"))]
+pub mod order;
+#[cfg(any(feature = "pipeline", feature = "stats"))]
+pub mod seqcmp;
+#[cfg(feature = "stats")]
+pub mod compare;
+#[cfg(feature = "stats")]
+pub mod sketch;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "stats")]
+pub mod validate;
+#[cfg(feature = "stats")]
+pub mod degradation;
+#[cfg(feature = "stats")]
+pub mod roundtrip;
+#[cfg(feature = "stats")]
+pub mod fidelity;
+#[cfg(feature = "stats")]
+pub mod memory;
+#[cfg(feature = "stats")]
+pub mod storage;
+#[cfg(feature = "stats")]
+pub mod bench;
+#[cfg(feature = "stats")]
+pub mod generate;
+#[cfg(feature = "service")]
+pub mod service;
+#[cfg(feature = "notify")]
+pub mod ingest;
 
-trait Record {
+#[cfg(any(feature = "pipeline", feature = "stats"))]
+mod rng;
+#[cfg(any(feature = "pipeline", feature = "stats"))]
+mod complexity;
+mod paths;
+#[cfg(all(feature = "pipeline", feature = "gzip"))]
+mod md5;
+
+pub use builder::{RecordBuilder, RecordError};
+pub use error::Error;
+pub use illumina::IlluminaHeader;
+pub use options::{ReaderOptions, TextPolicy};
+pub use position::Position;
+pub use progress::Progress;
+pub use valid::Valid;
+pub use warning::Warning;
+
+/// Common interface of the record types of all parsers.
+pub trait Record {
 	/// Create a new, empty FastQ record.
 	fn new() -> Self;
 	
@@ -13,6 +157,9 @@ trait Record {
 	/// Check validity of FastQ record.
 	fn check(&self) -> Result<(), &str>;
 	
+	/// Check if the record is known to have passed `check()` since it last changed.
+	fn is_validated(&self) -> bool { false }
+	
 	/// Return the id of the record.
 	fn id(&self) -> Option<&str>;
 	/// Return descriptions if present.
@@ -22,6 +169,105 @@ trait Record {
 	/// Return the base qualities of the record.
 	fn qual(&self) -> &[u8];
 	
+	/// Return the header line without `@` and line break, i.e. id and description.
+	fn header(&self) -> Cow<'_, str> {
+		match (self.id(), self.desc()) {
+			(id, Some(desc)) => Cow::Owned(format!("{} {}", id.unwrap_or(""), desc)),
+			(id, None) => Cow::Borrowed(id.unwrap_or("")),
+		}
+	}
+	/// Return the length of the sequence.
+	fn seq_len(&self) -> usize { self.seq().len() }
+	/// Return the byte offset of the record in its input, if the parser tracked it.
+	fn offset(&self) -> Option<u64> { None }
+	/// Return the Phred+33 qualities as `(score, run length)` pairs, see `quality::rle_encode`.
+	fn qual_rle(&self) -> Vec<(u8, usize)> { quality::rle_encode(self.qual(), quality::Encoding::Sanger) }
+	/// Parse the header into its fields if it is an Illumina header, see `IlluminaHeader::parse`.
+	fn illumina_header(&self) -> Option<IlluminaHeader> { IlluminaHeader::parse(self.id()?, self.desc()) }
+	
+	/// Check if another record, of any type, has the same sequence.
+	fn eq_sequence<R: Record + ?Sized>(&self, other: &R) -> bool { self.seq() == other.seq() }
+	/// Check if another record, of any type, has the same ID, description and sequence.
+	fn eq_ignoring_quality<R: Record + ?Sized>(&self, other: &R) -> bool {
+		self.id() == other.id() && self.desc() == other.desc() && self.eq_sequence(other)
+	}
+	
 	/// Clear the record.
 	fn clear(&mut self);
 }
+
+/// Common interface of records with a sequence, FastQ or FASTA.
+pub trait SeqRecord {
+	/// Return the id of the record.
+	fn id(&self) -> Option<&str>;
+	/// Return descriptions if present.
+	fn desc(&self) -> Option<&str>;
+	/// Return the sequence of the record.
+	fn seq(&self) -> &[u8];
+}
+
+impl<R: Record> SeqRecord for R {
+	fn id(&self) -> Option<&str> { Record::id(self) }
+	fn desc(&self) -> Option<&str> { Record::desc(self) }
+	fn seq(&self) -> &[u8] { Record::seq(self) }
+}
+
+/// Setters of the owned record types, see `RecordBuilder` to create records.
+pub trait RecordMut: Record {
+	/// Replace the ID, keeping the description.
+	fn set_id(&mut self, id: &str) -> Result<(), RecordError>;
+	/// Replace or remove the description. An empty description is removed.
+	fn set_desc(&mut self, desc: Option<&str>) -> Result<(), RecordError>;
+	/// Replace sequence and qualities, which must have the same length.
+	fn set_seq_qual(&mut self, seq: &[u8], qual: &[u8]) -> Result<(), RecordError>;
+	
+	/// Append `suffix` to the ID, e.g. `/1` to mark the first mate.
+	fn append_suffix_to_id(&mut self, suffix: &str) -> Result<(), RecordError> {
+		let id = format!("{}{}", self.id().unwrap_or(""), suffix);
+		self.set_id(&id)
+	}
+	
+	/// Reverse-complement the sequence and reverse the qualities to match.
+	/// Fails only if sequence and qualities differed in length before.
+	fn rev_comp(&mut self) -> Result<(), RecordError> {
+		let seq: Vec<u8> = self.seq().iter().rev().map(|&b| complement(b)).collect();
+		let qual: Vec<u8> = self.qual().iter().rev().copied().collect();
+		self.set_seq_qual(&seq, &qual)
+	}
+	
+	/// A copy of the record cut down to a range of its bases, clamped to the sequence length.
+	fn slice(&self, range: Range<usize>) -> Result<Self, RecordError> where Self: Clone {
+		let clamp = |len: usize| {
+			let end = range.end.min(len);
+			range.start.min(end)..end
+		};
+		let mut record = self.clone();
+		record.set_seq_qual(&self.seq()[clamp(self.seq().len())], &self.qual()[clamp(self.qual().len())])?;
+		Ok(record)
+	}
+	
+	/// Replace bases with a Phred+33 quality below `min_q` by `mask`, e.g. `b'N'`,
+	/// returning how many bases changed.
+	fn mask_low_quality(&mut self, min_q: u8, mask: u8) -> Result<usize, RecordError> {
+		let mut seq = self.seq().to_vec();
+		let mut masked = 0;
+		for (base, &q) in seq.iter_mut().zip(self.qual()) {
+			if q.saturating_sub(33) < min_q && *base != mask {
+				*base = mask;
+				masked += 1;
+			}
+		}
+		let qual = self.qual().to_vec();
+		self.set_seq_qual(&seq, &qual)?;
+		Ok(masked)
+	}
+}
+
+/// The complementary base, keeping case. `U` pairs with `A`, anything else is kept.
+pub(crate) fn complement(base: u8) -> u8 {
+	match base {
+		b'A' => b'T', b'T' | b'U' => b'A', b'C' => b'G', b'G' => b'C',
+		b'a' => b't', b't' | b'u' => b'a', b'c' => b'g', b'g' => b'c',
+		b => b,
+	}
+}
@@ -0,0 +1,154 @@
+//! Stopping record streams early, e.g. for previews and smoke tests.
+
+use std::time::{Duration, Instant};
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Record, Error};
+
+/// Why a limited stream stopped before its input ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+	MaxRecords,
+	MaxDuration,
+}
+
+/// An iterator adapter that stops after a number of items or an amount of time.
+pub struct Limited<I> {
+	inner: I,
+	max_records: Option<usize>,
+	max_duration: Option<Duration>,
+	start: Instant,
+	count: usize,
+	stopped: Option<StopReason>,
+	done: bool,
+}
+
+impl<I: Iterator> Limited<I> {
+	/// Stop after `n` items.
+	pub fn max_records(mut self, n: usize) -> Self {
+		self.max_records = Some(n);
+		self
+	}
+	
+	/// Stop once `d` has passed since the adapter was created.
+	pub fn max_duration(mut self, d: Duration) -> Self {
+		self.max_duration = Some(d);
+		self
+	}
+	
+	/// Number of items passed through.
+	pub fn count(&self) -> usize { self.count }
+	
+	/// The limit that cut the stream short, if any.
+	/// Set only when input remained after the limit was reached.
+	pub fn stopped(&self) -> Option<StopReason> { self.stopped }
+	
+	/// Check if the stream was cut short, i.e. any results based on it are partial.
+	pub fn is_partial(&self) -> bool { self.stopped.is_some() }
+	
+	fn limit_reached(&self) -> Option<StopReason> {
+		if self.max_records.is_some_and(|n| self.count >= n) {
+			Some(StopReason::MaxRecords)
+		} else if self.max_duration.is_some_and(|d| self.start.elapsed() >= d) {
+			Some(StopReason::MaxDuration)
+		} else {
+			None
+		}
+	}
+}
+
+impl<I: Iterator> Iterator for Limited<I> {
+	type Item = I::Item;
+	
+	fn next(&mut self) -> Option<I::Item> {
+		if self.done { return None }
+		if let Some(reason) = self.limit_reached() {
+			self.done = true;
+			if self.inner.next().is_some() { self.stopped = Some(reason) }
+			return None;
+		}
+		let item = self.inner.next();
+		match item {
+			Some(_) => self.count += 1,
+			None => self.done = true,
+		}
+		item
+	}
+}
+
+/// Wrap an iterator so limits can be set on it. Without limits, all items pass.
+pub fn limit<I: IntoIterator>(records: I) -> Limited<I::IntoIter> {
+	Limited {
+		inner: records.into_iter(),
+		max_records: None,
+		max_duration: None,
+		start: Instant::now(),
+		count: 0,
+		stopped: None,
+		done: false,
+	}
+}
+
+/// Running totals of the records a `TakeUntil` passed through.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Totals {
+	pub records: usize,
+	pub bases: u64,
+	/// Sum of all (Phred+33) quality scores.
+	pub quality_sum: u64,
+}
+
+impl Totals {
+	/// Mean quality score over all bases so far.
+	pub fn mean_quality(&self) -> f64 {
+		if self.bases == 0 { 0. } else { self.quality_sum as f64 / self.bases as f64 }
+	}
+	
+	fn add(&mut self, record: &Record) {
+		self.records += 1;
+		self.bases += record.seq().len() as u64;
+		self.quality_sum += record.qual().iter().map(|&q| q.saturating_sub(33) as u64).sum::<u64>();
+	}
+}
+
+/// An iterator adapter that stops once a condition on the running totals holds.
+pub struct TakeUntil<I, F> {
+	inner: I,
+	condition: F,
+	totals: Totals,
+	done: bool,
+}
+
+impl<I, F> TakeUntil<I, F> {
+	/// Totals of the records passed through so far.
+	pub fn totals(&self) -> &Totals { &self.totals }
+}
+
+impl<I, F> Iterator for TakeUntil<I, F> where I: Iterator<Item=Result<Record, Error>>, F: FnMut(&Totals) -> bool {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		if self.done || (self.condition)(&self.totals) {
+			self.done = true;
+			return None;
+		}
+		let item = self.inner.next();
+		if let Some(Ok(ref record)) = item { self.totals.add(record) }
+		item
+	}
+}
+
+/// Pass records until `condition` holds for the totals of the records passed so far.
+/// The rest of the input is not read.
+pub fn take_until<I, F>(records: I, condition: F) -> TakeUntil<I::IntoIter, F>
+	where I: IntoIterator<Item=Result<Record, Error>>, F: FnMut(&Totals) -> bool
+{
+	TakeUntil { inner: records.into_iter(), condition, totals: Totals::default(), done: false }
+}
+
+/// Pass records until they add up to at least `max_bases` bases.
+pub fn take_while_bases<I>(records: I, max_bases: u64) -> TakeUntil<I::IntoIter, impl FnMut(&Totals) -> bool>
+	where I: IntoIterator<Item=Result<Record, Error>>
+{
+	take_until(records, move |totals: &Totals| totals.bases >= max_bases)
+}
@@ -0,0 +1,147 @@
+//! MD5 digests, as upload portals like ENA and SRA expect them for submitted files.
+//!
+//! Only used to check file integrity, not for anything security related.
+
+use std::io::{self, Write};
+
+const S: [u32; 64] = [
+	7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+	5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+	4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+	6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+const K: [u32; 64] = [
+	0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+	0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+	0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+	0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+	0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+	0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+	0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+	0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// An incremental MD5 hasher.
+#[derive(Debug, Clone)]
+pub(crate) struct Md5 {
+	state: [u32; 4],
+	buffer: [u8; 64],
+	buffered: usize,
+	len: u64,
+}
+
+impl Md5 {
+	pub(crate) fn new() -> Md5 {
+		Md5 { state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476], buffer: [0; 64], buffered: 0, len: 0 }
+	}
+	
+	pub(crate) fn update(&mut self, mut data: &[u8]) {
+		self.len = self.len.wrapping_add(data.len() as u64);
+		if self.buffered > 0 {
+			let n = data.len().min(64 - self.buffered);
+			self.buffer[self.buffered..self.buffered + n].copy_from_slice(&data[..n]);
+			self.buffered += n;
+			data = &data[n..];
+			if self.buffered < 64 { return }
+			let block = self.buffer;
+			self.block(&block);
+			self.buffered = 0;
+		}
+		let mut blocks = data.chunks_exact(64);
+		for block in &mut blocks { self.block(block.try_into().unwrap()) }
+		let rest = blocks.remainder();
+		self.buffer[..rest.len()].copy_from_slice(rest);
+		self.buffered = rest.len();
+	}
+	
+	/// The digest as lowercase hex, like `md5sum` prints it.
+	pub(crate) fn hex(mut self) -> String {
+		let bits = self.len.wrapping_mul(8);
+		self.update(&[0x80]);
+		while self.buffered != 56 { self.update(&[0]) }
+		self.update(&bits.to_le_bytes());
+		self.state.iter().flat_map(|w| w.to_le_bytes()).map(|b| format!("{:02x}", b)).collect()
+	}
+	
+	fn block(&mut self, block: &[u8; 64]) {
+		let m: Vec<u32> = block.chunks_exact(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect();
+		let [mut a, mut b, mut c, mut d] = self.state;
+		for i in 0..64 {
+			let (f, g) = match i / 16 {
+				0 => ((b & c) | (!b & d), i),
+				1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+				2 => (b ^ c ^ d, (3 * i + 5) % 16),
+				_ => (c ^ (b | !d), (7 * i) % 16),
+			};
+			let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+			a = d;
+			d = c;
+			c = b;
+			b = b.wrapping_add(f.rotate_left(S[i]));
+		}
+		for (s, v) in self.state.iter_mut().zip([a, b, c, d]) { *s = s.wrapping_add(v) }
+	}
+}
+
+/// A writer hashing and counting everything written through it.
+pub(crate) struct Md5Writer<W> {
+	pub(crate) inner: W,
+	pub(crate) md5: Md5,
+	pub(crate) bytes: u64,
+}
+
+impl<W: Write> Md5Writer<W> {
+	pub(crate) fn new(inner: W) -> Md5Writer<W> {
+		Md5Writer { inner, md5: Md5::new(), bytes: 0 }
+	}
+}
+
+impl<W: Write> Write for Md5Writer<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = self.inner.write(buf)?;
+		self.md5.update(&buf[..n]);
+		self.bytes += n as u64;
+		Ok(n)
+	}
+	
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn md5(data: &[u8]) -> String {
+		let mut md5 = Md5::new();
+		md5.update(data);
+		md5.hex()
+	}
+	
+	/// The test suite of RFC 1321, plus inputs around the 56 and 64 byte padding boundaries.
+	#[test]
+	fn known_answers() {
+		assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+		assert_eq!(md5(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+		assert_eq!(md5(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(md5(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+		assert_eq!(md5(b"abcdefghijklmnopqrstuvwxyz"), "c3fcd3d76192e4007dfb496cca67e13b");
+		assert_eq!(md5(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"), "d174ab98d277d9f5a5611c2c9f419d9f");
+		assert_eq!(md5("1234567890".repeat(8).as_bytes()), "57edf4a22be3c955ac49da2e2107b67a");
+		assert_eq!(md5(&[b'a'; 55]), "ef1772b6dff9a122358552954ad0df65");
+		assert_eq!(md5(&[b'a'; 56]), "3b0c8ac703f828b04c6c197006d17218");
+		assert_eq!(md5(&[b'a'; 64]), "014842d480b571495a4a0363793f7367");
+	}
+	
+	#[test]
+	fn writer_hashes_in_pieces() {
+		let data = "1234567890".repeat(20);
+		let mut writer = Md5Writer::new(Vec::new());
+		for piece in data.as_bytes().chunks(7) { writer.write_all(piece).unwrap() }
+		assert_eq!(writer.bytes, 200);
+		assert_eq!(writer.inner, data.as_bytes());
+		assert_eq!(writer.md5.hex(), md5(data.as_bytes()));
+	}
+}
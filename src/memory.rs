@@ -0,0 +1,115 @@
+//! Predicting the peak memory use of operations before running them.
+//!
+//! Estimates are rough upper bounds meant for choosing budgets and spill strategies,
+//! not exact accounting: they model the record data and the main per-record overheads.
+
+use std::mem;
+
+use super::stats::StatsReport;
+use super::unfancy_parser::Record;
+
+/// Bytes the allocator and string capacity slack add per heap-allocated line, on average.
+const LINE_OVERHEAD: u64 = 24;
+/// Buffer of each reader opened on a spilled run.
+const RUN_READER_BYTES: u64 = 8 * 1024;
+/// Assumed header length if it is not known.
+const DEFAULT_HEADER_LEN: f64 = 40.;
+
+/// An operation to estimate memory use for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+	/// `shuffle::shuffle` with the given memory budget.
+	Shuffle { memory_budget: usize },
+	/// `sort::sort` with the given memory budget.
+	Sort { memory_budget: usize },
+	/// Removing duplicate sequences, exactly with a hash set of the sequences,
+	/// or approximately with a Bloom filter of the given false positive rate.
+	Dedup { false_positive_rate: Option<f64> },
+}
+
+/// What is known about the input of an operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputStats {
+	pub records: u64,
+	/// Mean sequence length.
+	pub mean_length: f64,
+	/// Mean header length, without `@` and line break.
+	pub mean_header_length: f64,
+}
+
+impl InputStats {
+	/// Input of `records` reads with the given mean length and a typical header length.
+	pub fn new(records: u64, mean_length: f64) -> InputStats {
+		InputStats { records, mean_length, mean_header_length: DEFAULT_HEADER_LEN }
+	}
+	
+	/// Take record count and mean length from a QC report.
+	pub fn from_report(report: &StatsReport) -> InputStats {
+		let mean_length = if report.reads == 0 { 0. } else { report.mean_length };
+		InputStats::new(report.reads, mean_length)
+	}
+	
+	/// Guess the record count of an uncompressed FastQ file of `bytes` bytes.
+	pub fn from_file_size(bytes: u64, mean_length: f64) -> InputStats {
+		let stats = InputStats::new(0, mean_length);
+		InputStats { records: (bytes as f64 / stats.record_text_bytes()).ceil() as u64, ..stats }
+	}
+	
+	/// Bytes of a record in FastQ text form.
+	fn record_text_bytes(&self) -> f64 {
+		self.mean_header_length + 2. * self.mean_length + 6.
+	}
+	
+	/// Bytes a parsed record takes in memory.
+	fn record_memory_bytes(&self) -> f64 {
+		self.record_text_bytes() + (mem::size_of::<Record>() as u64 + 3 * LINE_OVERHEAD) as f64
+	}
+}
+
+/// The predicted resource use of an operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+	/// Peak memory in bytes.
+	pub peak_bytes: u64,
+	/// Number of runs spilled to temporary files.
+	pub spill_runs: u64,
+	/// Bytes written to temporary files.
+	pub spill_bytes: u64,
+}
+
+/// Predict the peak memory of running `op` on input described by `input`.
+pub fn estimate_memory(op: &Operation, input: &InputStats) -> MemoryEstimate {
+	let records = input.records as f64;
+	match *op {
+		Operation::Shuffle { memory_budget } | Operation::Sort { memory_budget } => {
+			let text = input.record_text_bytes();
+			let total_text = records * text;
+			let budget = memory_budget.max(1) as f64;
+			if total_text < budget {
+				return MemoryEstimate { peak_bytes: buffered_bytes(records, input), spill_runs: 0, spill_bytes: 0 };
+			}
+			// runs fill up to the budget plus the record crossing it
+			let per_run = (budget / text).floor() + 1.;
+			let runs = (records / per_run).ceil();
+			let buffering = buffered_bytes(per_run.min(records), input);
+			let merging = runs * (RUN_READER_BYTES as f64 + input.record_memory_bytes());
+			MemoryEstimate { peak_bytes: buffering.max(merging as u64), spill_runs: runs as u64, spill_bytes: total_text as u64 }
+		},
+		Operation::Dedup { false_positive_rate: Some(p) } => {
+			let p = p.clamp(f64::MIN_POSITIVE, 1.);
+			let bits = (-records * p.ln() / (2f64.ln() * 2f64.ln())).ceil();
+			MemoryEstimate { peak_bytes: (bits / 8.).ceil() as u64 + input.record_memory_bytes() as u64, ..MemoryEstimate::default() }
+		},
+		Operation::Dedup { false_positive_rate: None } => {
+			// a String per key, the slot, and the hash table’s load factor
+			let key = input.mean_length + (mem::size_of::<String>() as u64 + LINE_OVERHEAD) as f64;
+			let table = records * key * 8. / 7.;
+			MemoryEstimate { peak_bytes: table as u64 + input.record_memory_bytes() as u64, ..MemoryEstimate::default() }
+		},
+	}
+}
+
+/// Memory of `n` records held in a `Vec`, whose capacity may be up to twice its length.
+fn buffered_bytes(n: f64, input: &InputStats) -> u64 {
+	(n * input.record_memory_bytes() + 2. * n * mem::size_of::<Record>() as f64) as u64
+}
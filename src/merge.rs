@@ -0,0 +1,223 @@
+//! Merging inputs that are sorted by read ID into one sorted stream.
+//!
+//! IDs are compared bytewise unless another `IdOrder` is set with `Merge::id_order`.
+//! This combines e.g. pre-sorted per-lane files, and the sorted runs of an external sort.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io;
+use std::path::Path;
+
+use super::Record as RecordTrait;
+use super::input::Input;
+use super::order::IdOrder;
+use super::unfancy_parser::{self, Reader, Record, Records};
+
+quick_error! {
+	/// An error encountered while merging.
+	#[derive(Debug)]
+	pub enum MergeError {
+		/// An input could not be parsed.
+		Parse(input: usize, err: unfancy_parser::Error) {
+			cause(err)
+			description("Parse error in merge input")
+			display("Input {}: {}", input, err)
+		}
+		/// An input was not sorted by ID.
+		Unsorted(input: usize, prev: String, id: String) {
+			description("Merge input not sorted")
+			display("Input {}: {:?} comes after {:?}", input, id, prev)
+		}
+		/// An ID occurred more than once with `DuplicateIds::Fail`.
+		Duplicate(id: String) {
+			description("Duplicate read ID")
+			display("Duplicate read ID {:?}", id)
+		}
+	}
+}
+
+/// What to do with records whose ID was already merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateIds {
+	/// Keep all of them, those from earlier inputs first.
+	#[default]
+	KeepAll,
+	/// Keep only the first, i.e. the one from the earliest input.
+	KeepFirst,
+	/// Stop with `MergeError::Duplicate`.
+	Fail,
+}
+
+/// The next record of an input, ordered by ID, then input.
+struct Head {
+	id: String,
+	input: usize,
+	record: Record,
+	order: IdOrder,
+}
+
+impl PartialEq for Head {
+	fn eq(&self, other: &Head) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+	fn partial_cmp(&self, other: &Head) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Head {
+	fn cmp(&self, other: &Head) -> Ordering {
+		self.order.compare(&self.id, &other.id).then(self.input.cmp(&other.input))
+	}
+}
+
+/// An iterator merging ID-sorted inputs, see `merge`.
+pub struct Merge<I> {
+	inputs: Vec<I>,
+	heap: BinaryHeap<Reverse<Head>>,
+	prev: Vec<Option<String>>,
+	last: Option<String>,
+	duplicates: DuplicateIds,
+	order: IdOrder,
+	dropped: usize,
+	started: bool,
+	done: bool,
+}
+
+impl<I> Merge<I> where I: Iterator<Item=Result<Record, unfancy_parser::Error>> {
+	/// Set what to do with duplicate IDs.
+	pub fn duplicates(mut self, policy: DuplicateIds) -> Self {
+		self.duplicates = policy;
+		self
+	}
+	
+	/// Set the order the inputs are sorted in. IDs that compare equal in it count as duplicates.
+	pub fn id_order(mut self, order: IdOrder) -> Self {
+		self.order = order;
+		self
+	}
+	
+	/// Number of records dropped as duplicates so far.
+	pub fn dropped(&self) -> usize { self.dropped }
+	
+	/// Read the next record of an input into the heap, checking the input’s order.
+	fn refill(&mut self, input: usize) -> Result<(), MergeError> {
+		let record = match self.inputs[input].next() {
+			None => return Ok(()),
+			Some(record) => record.map_err(|e| MergeError::Parse(input, e))?,
+		};
+		let id = record.id().unwrap_or("").to_owned();
+		if let Some(prev) = self.prev[input].take() {
+			if self.order.compare(&id, &prev) == Ordering::Less { return Err(MergeError::Unsorted(input, prev, id)) }
+		}
+		self.prev[input] = Some(id.clone());
+		self.heap.push(Reverse(Head { id, input, record, order: self.order }));
+		Ok(())
+	}
+}
+
+impl<I> Iterator for Merge<I> where I: Iterator<Item=Result<Record, unfancy_parser::Error>> {
+	type Item = Result<Record, MergeError>;
+	
+	fn next(&mut self) -> Option<Result<Record, MergeError>> {
+		if self.done { return None }
+		if !self.started {
+			self.started = true;
+			for input in 0..self.inputs.len() {
+				if let Err(e) = self.refill(input) {
+					self.done = true;
+					return Some(Err(e));
+				}
+			}
+		}
+		loop {
+			let Reverse(head) = self.heap.pop()?;
+			if let Err(e) = self.refill(head.input) {
+				self.done = true;
+				return Some(Err(e));
+			}
+			if self.last.as_ref().is_some_and(|last| self.order.compare(last, &head.id) == Ordering::Equal) {
+				match self.duplicates {
+					DuplicateIds::KeepAll => {},
+					DuplicateIds::KeepFirst => { self.dropped += 1; continue },
+					DuplicateIds::Fail => { self.done = true; return Some(Err(MergeError::Duplicate(head.id))) },
+				}
+			}
+			self.last = Some(head.id);
+			return Some(Ok(head.record));
+		}
+	}
+}
+
+/// Merge inputs sorted by ID into one stream sorted by ID, checking that each input is sorted.
+pub fn merge<I>(inputs: Vec<I>) -> Merge<I::IntoIter> where I: IntoIterator<Item=Result<Record, unfancy_parser::Error>> {
+	let inputs: Vec<_> = inputs.into_iter().map(IntoIterator::into_iter).collect();
+	Merge {
+		heap: BinaryHeap::with_capacity(inputs.len()),
+		prev: vec![None; inputs.len()],
+		inputs,
+		last: None,
+		duplicates: DuplicateIds::default(),
+		order: IdOrder::default(),
+		dropped: 0,
+		started: false,
+		done: false,
+	}
+}
+
+/// Merge files sorted by ID (decompressing them if needed).
+pub fn merge_files<P: AsRef<Path>>(paths: &[P]) -> io::Result<Merge<Records<Input>>> {
+	let inputs = paths.iter().map(|p| Ok(Reader::from_file(p)?.records())).collect::<io::Result<Vec<_>>>()?;
+	Ok(merge(inputs))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	/// Records with the given IDs, each with the number of its input as sequence.
+	fn input(n: usize, ids: &[&str]) -> Vec<Result<Record, unfancy_parser::Error>> {
+		let text: String = ids.iter().map(|id| format!("@{} x\n{}\n+\nI\n", id, n)).collect();
+		Reader::new(io::Cursor::new(text)).records().collect()
+	}
+	
+	/// IDs and inputs of the merged records.
+	fn merged<I: Iterator<Item=Result<Record, MergeError>>>(merge: I) -> Result<Vec<(String, String)>, MergeError> {
+		merge.map(|r| r.map(|r| (r.id().unwrap().to_owned(), String::from_utf8_lossy(r.seq()).into_owned()))).collect()
+	}
+	
+	fn pairs(expected: &[(&str, usize)]) -> Vec<(String, String)> {
+		expected.iter().map(|&(id, n)| (id.to_owned(), n.to_string())).collect()
+	}
+	
+	#[test]
+	fn merges_in_id_then_input_order() {
+		let inputs = vec![input(0, &["a", "c", "e"]), input(1, &[]), input(2, &["b", "c", "f"])];
+		let expected = pairs(&[("a", 0), ("b", 2), ("c", 0), ("c", 2), ("e", 0), ("f", 2)]);
+		assert_eq!(merged(merge(inputs)).unwrap(), expected);
+	}
+	
+	#[test]
+	fn duplicate_policies() {
+		let inputs = || vec![input(0, &["a", "b"]), input(1, &["a", "b", "c"]), input(2, &["b"])];
+		let mut keep_first = merge(inputs()).duplicates(DuplicateIds::KeepFirst);
+		assert_eq!(merged(keep_first.by_ref()).unwrap(), pairs(&[("a", 0), ("b", 0), ("c", 1)]));
+		assert_eq!(keep_first.dropped(), 3);
+		let failed = merged(merge(inputs()).duplicates(DuplicateIds::Fail));
+		assert!(matches!(failed, Err(MergeError::Duplicate(ref id)) if id == "a"), "{:?}", failed);
+		// a duplicate within one input counts as well
+		let within = merged(merge(vec![input(0, &["a", "a"])]).duplicates(DuplicateIds::KeepFirst)).unwrap();
+		assert_eq!(within, pairs(&[("a", 0)]));
+	}
+	
+	#[test]
+	fn checks_input_order() {
+		let result = merged(merge(vec![input(0, &["a", "b"]), input(1, &["b", "a"])]));
+		assert!(matches!(result, Err(MergeError::Unsorted(1, ref prev, ref id)) if prev == "b" && id == "a"), "{:?}", result);
+		// the natural order sorts numbers by value
+		let natural = merge(vec![input(0, &["r2", "r10"]), input(1, &["r9"])]).id_order(IdOrder::Natural);
+		assert_eq!(merged(natural).unwrap(), pairs(&[("r2", 0), ("r9", 1), ("r10", 0)]));
+		assert!(merged(merge(vec![input(0, &["r2", "r10"])])).is_err());
+	}
+}
@@ -0,0 +1,167 @@
+//! Opt-in deviations from strict FastQ parsing.
+
+use std::fmt;
+use std::io::{self, BufRead};
+use std::{mem, str};
+
+use super::buffer::BufferSize;
+
+/// How readers treat bytes outside ASCII, see `ReaderOptions::text`.
+///
+/// FastQ needs text only in descriptions, so checking less than UTF-8 can be worth it,
+/// which `bench::Benchmark::text_policies` measures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextPolicy {
+	/// Validate every line as UTF-8, failing on invalid ones.
+	#[default]
+	Utf8,
+	/// Fail on any byte outside ASCII, which is cheaper to check.
+	Ascii,
+	/// Take any bytes. Readers keeping text as `String` replace invalid UTF-8 by U+FFFD,
+	/// `borrowed_parser::Reader` hands out the bytes as they are.
+	Bytes,
+}
+
+impl TextPolicy {
+	pub const ALL: [TextPolicy; 3] = [TextPolicy::Utf8, TextPolicy::Ascii, TextPolicy::Bytes];
+	
+	/// The error for text this policy does not accept.
+	pub(crate) fn error(self) -> io::Error {
+		let msg = match self { TextPolicy::Ascii => "stream did not contain only ASCII", _ => "stream did not contain valid UTF-8" };
+		io::Error::new(io::ErrorKind::InvalidData, msg)
+	}
+	
+	/// Check raw bytes, e.g. a whole record.
+	pub(crate) fn check(self, bytes: &[u8]) -> io::Result<()> {
+		let valid = match self {
+			TextPolicy::Utf8 => str::from_utf8(bytes).is_ok(),
+			TextPolicy::Ascii => bytes.is_ascii(),
+			TextPolicy::Bytes => true,
+		};
+		if valid { Ok(()) } else { Err(self.error()) }
+	}
+	
+	/// Append a line and its line break to `buf` like `BufRead::read_line`, returning the number of bytes read.
+	/// On errors, `buf` is left as it was.
+	pub(crate) fn read_line<B: BufRead>(self, reader: &mut B, buf: &mut String) -> io::Result<usize> {
+		if self == TextPolicy::Utf8 { return reader.read_line(buf) }
+		let mut bytes = mem::take(buf).into_bytes();
+		let start = bytes.len();
+		let result = reader.read_until(b'\n', &mut bytes).and_then(|n| match self {
+			TextPolicy::Ascii if !bytes[start..].is_ascii() => Err(self.error()),
+			TextPolicy::Bytes if str::from_utf8(&bytes[start..]).is_err() => {
+				let line = String::from_utf8_lossy(&bytes[start..]).into_owned();
+				bytes.truncate(start);
+				bytes.extend_from_slice(line.as_bytes());
+				Ok(n)
+			},
+			_ => Ok(n),
+		});
+		if result.is_err() { bytes.truncate(start) }
+		// SAFETY: what was in `buf` is UTF-8, and the line appended to it ASCII or checked to be UTF-8
+		*buf = unsafe { String::from_utf8_unchecked(bytes) };
+		result
+	}
+}
+
+impl fmt::Display for TextPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.pad(match *self { TextPolicy::Utf8 => "utf8", TextPolicy::Ascii => "ascii", TextPolicy::Bytes => "bytes" })
+	}
+}
+
+/// Options controlling how lenient a reader is.
+///
+/// The default is to parse plain four-line FastQ without any normalization.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReaderOptions {
+	/// Remove spaces and tabs inside sequence and quality lines, emitting a warning.
+	pub strip_whitespace: bool,
+	/// Accept sequence and qualities wrapped over several lines.
+	/// Sequence lines are read up to the `+` separator, quality lines until they match its length.
+	pub allow_multiline: bool,
+	/// Read qualities as whitespace-separated numeric Phred scores, converting them to Phred+33.
+	pub numeric_quality: bool,
+	/// The size of the read buffer, adapting to the read lengths by default.
+	pub buffer: BufferSize,
+	/// Fail with an error instead of emitting any warning, for input that has to be canonical.
+	pub warnings_fatal: bool,
+	/// How bytes outside ASCII are treated, validating UTF-8 by default.
+	pub text: TextPolicy,
+}
+
+impl ReaderOptions {
+	/// Options for plain four-line FastQ.
+	pub fn new() -> ReaderOptions { ReaderOptions::default() }
+	
+	/// Set whether whitespace inside sequence and quality lines is removed.
+	pub fn strip_whitespace(mut self, yes: bool) -> ReaderOptions {
+		self.strip_whitespace = yes;
+		self
+	}
+	
+	/// Set whether wrapped sequence and quality lines are accepted.
+	pub fn allow_multiline(mut self, yes: bool) -> ReaderOptions {
+		self.allow_multiline = yes;
+		self
+	}
+	
+	/// Set whether qualities are read as numeric scores.
+	pub fn numeric_quality(mut self, yes: bool) -> ReaderOptions {
+		self.numeric_quality = yes;
+		self
+	}
+	
+	/// Set whether warnings are turned into errors.
+	pub fn warnings_fatal(mut self, yes: bool) -> ReaderOptions {
+		self.warnings_fatal = yes;
+		self
+	}
+	
+	/// Set how bytes outside ASCII are treated.
+	pub fn text(mut self, policy: TextPolicy) -> ReaderOptions {
+		self.text = policy;
+		self
+	}
+	
+	/// Set the size of the read buffer.
+	pub fn buffer(mut self, size: BufferSize) -> ReaderOptions {
+		self.buffer = size;
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{borrowed_parser, fancy_parser::FastqReader, unfancy_parser, Record};
+	
+	const INPUT: &[&[u8]] = &[b"@r1\nACGT\n+\nIIII\n", "@r2 caf\u{e9}\nACGT\n+\nIIII\n".as_bytes(), b"@r3 \xff\nACGT\n+\nIIII\n", b"@r4\nACGT\n+\nIIII\n"];
+	
+	/// The descriptions each parser reads from `INPUT` until its first error, which is `None`.
+	fn descriptions(text: TextPolicy) -> [Vec<Option<String>>; 3] {
+		let input = INPUT.concat();
+		let fancy = FastqReader::new(&input[..]).records_with_options(ReaderOptions::new().text(text))
+			.map(|r| r.ok().map(|r| r.desc().unwrap_or("").to_owned())).collect();
+		let unfancy = unfancy_parser::Reader::with_options(&input[..], ReaderOptions::new().text(text)).records()
+			.map(|r| r.ok().map(|r| r.desc().unwrap_or("").to_owned())).collect();
+		let mut reader = borrowed_parser::Reader::new(&input[..]).text(text);
+		let mut borrowed = Vec::new();
+		while let Some(r) = reader.next() { borrowed.push(r.ok().map(|r| String::from_utf8_lossy(r.head()).split_once(' ').map_or("", |(_, d)| d).to_owned())) }
+		[fancy, unfancy, borrowed].map(|descs: Vec<_>| descs.into_iter().take_while(Option::is_some).collect())
+	}
+	
+	#[test]
+	fn text_policies() {
+		let descs = |d: &[&str]| d.iter().map(|&d| Some(d.to_owned())).collect::<Vec<_>>();
+		assert_eq!(descriptions(TextPolicy::Utf8), [descs(&["", "caf\u{e9}"]), descs(&["", "caf\u{e9}"]), descs(&["", "caf\u{e9}"])]);
+		assert_eq!(descriptions(TextPolicy::Ascii), [descs(&[""]), descs(&[""]), descs(&[""])]);
+		let all = descs(&["", "caf\u{e9}", "\u{fffd}", ""]);
+		assert_eq!(descriptions(TextPolicy::Bytes), [all.clone(), all.clone(), all]);
+		// offsets count the bytes read, not the replacement characters
+		let input = INPUT.concat();
+		let records: Vec<_> = unfancy_parser::Reader::with_options(&input[..], ReaderOptions::new().text(TextPolicy::Bytes)).records().collect();
+		assert_eq!(records[3].as_ref().unwrap().offset(), Some((input.len() - INPUT[3].len()) as u64));
+	}
+}
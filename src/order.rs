@@ -0,0 +1,60 @@
+//! Orderings of read IDs, for sorting and merging by ID.
+//!
+//! Bytewise order puts `read10` before `read2`, which breaks when read numbers are not zero-padded.
+//! Natural order compares runs of digits by their value instead.
+
+use std::cmp::Ordering;
+
+/// How read IDs are ordered.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum IdOrder {
+	/// Bytewise, e.g. `read10` < `read2`.
+	#[default]
+	Bytewise,
+	/// Runs of digits by numeric value, see `natural_cmp`.
+	Natural,
+	/// Bytewise by a key derived from each ID.
+	Key(fn(&str) -> String),
+	/// By a custom comparison.
+	Custom(fn(&str, &str) -> Ordering),
+}
+
+impl IdOrder {
+	/// Compare two IDs in this order.
+	pub fn compare(&self, a: &str, b: &str) -> Ordering {
+		match *self {
+			IdOrder::Bytewise => a.cmp(b),
+			IdOrder::Natural => natural_cmp(a, b),
+			IdOrder::Key(key) => key(a).cmp(&key(b)),
+			IdOrder::Custom(cmp) => cmp(a, b),
+		}
+	}
+}
+
+/// Compare strings so that runs of digits compare by their numeric value, e.g. `read2` < `read10`.
+/// Strings that only differ in leading zeros are ordered bytewise.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+	let (mut x, mut y) = (a.as_bytes(), b.as_bytes());
+	while let (Some(&cx), Some(&cy)) = (x.first(), y.first()) {
+		let ordering = if cx.is_ascii_digit() && cy.is_ascii_digit() {
+			let (nx, rest_x) = split_digits(x);
+			let (ny, rest_y) = split_digits(y);
+			(x, y) = (rest_x, rest_y);
+			let (nx, ny) = (trim_zeros(nx), trim_zeros(ny));
+			nx.len().cmp(&ny.len()).then_with(|| nx.cmp(ny))
+		} else {
+			(x, y) = (&x[1..], &y[1..]);
+			cx.cmp(&cy)
+		};
+		if ordering != Ordering::Equal { return ordering }
+	}
+	x.len().cmp(&y.len()).then_with(|| a.cmp(b))
+}
+
+fn split_digits(s: &[u8]) -> (&[u8], &[u8]) {
+	s.split_at(s.iter().position(|b| !b.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+	&digits[digits.iter().position(|&d| d != b'0').unwrap_or(digits.len())..]
+}
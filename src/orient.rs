@@ -0,0 +1,168 @@
+//! Normalizing read orientation against a known anchor, e.g. an amplicon primer.
+
+use super::Record as RecordTrait;
+use super::complement;
+use super::unfancy_parser::{Record, Error};
+
+/// The strand an anchor was found on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Orientation {
+	Forward,
+	Reverse,
+	/// The anchor was not found in either orientation.
+	Unknown,
+}
+
+/// The reverse complement of a sequence.
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+	seq.iter().rev().map(|&b| complement(b)).collect()
+}
+
+/// The fewest mismatches of `anchor` anywhere in `seq`, if at most `max_mismatches`.
+fn best_match(seq: &[u8], anchor: &[u8], max_mismatches: usize) -> Option<usize> {
+	if anchor.is_empty() || anchor.len() > seq.len() { return None }
+	seq.windows(anchor.len())
+		.map(|w| w.iter().zip(anchor).filter(|(a, b)| !a.eq_ignore_ascii_case(b)).count())
+		.filter(|&m| m <= max_mismatches)
+		.min()
+}
+
+/// Finds an anchor in either orientation of a read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Orienter {
+	anchor: Vec<u8>,
+	anchor_rc: Vec<u8>,
+	max_mismatches: usize,
+}
+
+impl Orienter {
+	/// Look for exact matches of `anchor`.
+	pub fn new(anchor: &[u8]) -> Orienter {
+		Orienter { anchor: anchor.to_vec(), anchor_rc: reverse_complement(anchor), max_mismatches: 0 }
+	}
+	
+	/// Allow up to `n` mismatches in the anchor.
+	pub fn max_mismatches(mut self, n: usize) -> Self {
+		self.max_mismatches = n;
+		self
+	}
+	
+	/// The strand the anchor is on. If it matches both, the one with fewer mismatches wins, forward on ties.
+	pub fn orientation(&self, seq: &[u8]) -> Orientation {
+		let forward = best_match(seq, &self.anchor, self.max_mismatches);
+		let reverse = best_match(seq, &self.anchor_rc, self.max_mismatches);
+		match (forward, reverse) {
+			(Some(f), Some(r)) if r < f => Orientation::Reverse,
+			(Some(_), _) => Orientation::Forward,
+			(None, Some(_)) => Orientation::Reverse,
+			(None, None) => Orientation::Unknown,
+		}
+	}
+	
+	/// Reverse-complement the record if the anchor is on its reverse strand, returning the orientation found.
+	pub fn orient(&self, record: &mut Record) -> Orientation {
+		let orientation = self.orientation(record.seq());
+		if orientation == Orientation::Reverse {
+			record.map_seq_qual(|seq, qual| {
+				seq.reverse();
+				for b in seq.iter_mut() { *b = complement(*b) }
+				qual.reverse();
+			});
+		}
+		orientation
+	}
+}
+
+/// Number of reads found in each orientation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrientCounts {
+	pub forward: usize,
+	pub reversed: usize,
+	pub unknown: usize,
+}
+
+/// An iterator adapter orienting records, see `orient`.
+pub struct Oriented<I> {
+	records: I,
+	orienter: Orienter,
+	drop_unknown: bool,
+	counts: OrientCounts,
+}
+
+impl<I> Oriented<I> {
+	/// Drop reads in which the anchor was not found instead of passing them through unchanged.
+	pub fn drop_unknown(mut self, yes: bool) -> Self {
+		self.drop_unknown = yes;
+		self
+	}
+	
+	/// Number of reads so far per orientation.
+	pub fn counts(&self) -> OrientCounts { self.counts }
+}
+
+impl<I> Iterator for Oriented<I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		loop {
+			let mut record = match self.records.next()? {
+				Ok(record) => record,
+				Err(e) => return Some(Err(e)),
+			};
+			match self.orienter.orient(&mut record) {
+				Orientation::Forward => self.counts.forward += 1,
+				Orientation::Reverse => self.counts.reversed += 1,
+				Orientation::Unknown => {
+					self.counts.unknown += 1;
+					if self.drop_unknown { continue }
+				},
+			}
+			return Some(Ok(record));
+		}
+	}
+}
+
+/// Reverse-complement reads as needed so that the anchor is always on the forward strand.
+pub fn orient<I>(records: I, orienter: Orienter) -> Oriented<I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	Oriented { records: records.into_iter(), orienter, drop_unknown: false, counts: OrientCounts::default() }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::Reader;
+	
+	fn records(data: &'static [u8]) -> Vec<Result<Record, Error>> {
+		Reader::new(data).records().collect()
+	}
+	
+	#[test]
+	fn orients_reads_by_anchor() {
+		let data = b"@fwd\nTTCCCGTACGTAA\n+\nABCDEFGHIJKLM\n@rev\nTTACGTACGGGAA\n+\nABCDEFGHIJKLM\n@none\nAAAAAAAAAAAAA\n+\nIIIIIIIIIIIII\n";
+		let mut oriented = orient(records(data), Orienter::new(b"CCCGTACGT"));
+		let fwd = oriented.next().unwrap().unwrap();
+		assert_eq!((fwd.seq(), fwd.qual()), (&b"TTCCCGTACGTAA"[..], &b"ABCDEFGHIJKLM"[..]));
+		let rev = oriented.next().unwrap().unwrap();
+		assert_eq!((rev.seq(), rev.qual()), (&b"TTCCCGTACGTAA"[..], &b"MLKJIHGFEDCBA"[..]));
+		assert_eq!(oriented.next().unwrap().unwrap().seq(), b"AAAAAAAAAAAAA");
+		assert!(oriented.next().is_none());
+		assert_eq!(oriented.counts(), OrientCounts { forward: 1, reversed: 1, unknown: 1 });
+	}
+	
+	#[test]
+	fn drops_unknown_reads_and_allows_mismatches() {
+		let data = b"@one\nTTCCCGTTCGTAA\n+\nIIIIIIIIIIIII\n@none\nAAAAAAAAAAAAA\n+\nIIIIIIIIIIIII\n";
+		assert_eq!(Orienter::new(b"CCCGTACGT").orientation(b"TTCCCGTTCGTAA"), Orientation::Unknown);
+		let oriented: Vec<_> = orient(records(data), Orienter::new(b"CCCGTACGT").max_mismatches(1)).drop_unknown(true).map(Result::unwrap).collect();
+		assert_eq!(oriented.iter().map(|r| r.id()).collect::<Vec<_>>(), [Some("one")]);
+	}
+	
+	#[test]
+	fn reverses_non_ascii_reads_without_panicking() {
+		let data = "@r\néTTTTACGTACGGG\n+\nIIIIIIIIIIIIIII\n".as_bytes();
+		let mut record = Reader::new(data).records().next().unwrap().unwrap();
+		assert_eq!(Orienter::new(b"CCCGTACGT").orient(&mut record), Orientation::Reverse);
+		assert!(record.check().is_err());
+	}
+}
@@ -0,0 +1,459 @@
+//! Synchronized reading of paired-end files, and (de)interleaving them.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use super::Record as RecordTrait;
+use super::input::Input;
+use super::unfancy_parser::{self, Reader, Record, Records, Writer};
+
+/// One of the two mates of a read pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mate {
+	R1,
+	R2,
+}
+
+impl Mate {
+	fn index(self) -> usize {
+		match self { Mate::R1 => 0, Mate::R2 => 1 }
+	}
+}
+
+impl fmt::Display for Mate {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self { Mate::R1 => "R1", Mate::R2 => "R2" })
+	}
+}
+
+quick_error! {
+	/// An error encountered while reading a pair of files.
+	#[derive(Debug)]
+	pub enum PairError {
+		/// One of the files could not be parsed.
+		Parse(mate: Mate, index: usize, err: unfancy_parser::Error) {
+			description("Parse error in paired input")
+			display("{} record {}: {}", mate, index, err)
+			cause(err)
+		}
+		/// The mates of a pair have different read IDs.
+		IdMismatch(index: usize, id1: String, id2: String) {
+			description("Mate IDs differ")
+			display("Pair {}: IDs {:?} and {:?} do not match", index, id1, id2)
+		}
+		/// The files could not be brought back in sync after a skipped record or ID mismatch.
+		Unsynchronized(index: usize, window: usize) {
+			description("Mate files out of sync")
+			display("Pair {}: no matching mate IDs within the next {} records", index, window)
+		}
+		/// One file ended before the other.
+		UnequalLength(longer: Mate, index: usize) {
+			description("Paired files have different numbers of records")
+			display("{} has more records than its mate file ({} pairs read)", longer, index)
+		}
+		/// Writing the pairs failed.
+		Io(err: io::Error) {
+			from()
+			description("IO error writing pairs")
+			display("{}", err)
+			cause(err)
+		}
+	}
+}
+
+/// Strip a `/1` or `/2` mate suffix from a read ID.
+pub fn mate_base_id(id: &str) -> &str {
+	id.strip_suffix("/1").or_else(|| id.strip_suffix("/2")).unwrap_or(id)
+}
+
+/// Check if two read IDs name mates of the same fragment.
+///
+/// Casava 1.8+ IDs are identical for both mates (the read number is in the description),
+/// older ones end in `/1` and `/2`.
+pub fn mates_match(id1: &str, id2: &str) -> bool {
+	mate_base_id(id1) == mate_base_id(id2)
+}
+
+/// How a mate file’s parse errors are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatePolicy {
+	/// Report parse errors.
+	#[default]
+	Strict,
+	/// Skip broken records, then find the mates in sync again by ID.
+	Lenient,
+}
+
+/// Reads pairs from two record streams, checking that they stay in sync.
+///
+/// If a file is read leniently, broken records in it are skipped. As this leaves their mates
+/// without partner, and on ID mismatches if any file is lenient, the reader resynchronizes:
+/// it looks ahead in both files for the nearest records with matching IDs and drops those before.
+pub struct PairedReader<I1, I2> {
+	r1: I1,
+	r2: I2,
+	index: usize,
+	done: bool,
+	policies: [MatePolicy; 2],
+	window: usize,
+	pending: [VecDeque<Record>; 2],
+	skipped: [usize; 2],
+	resyncs: usize,
+}
+
+impl PairedReader<Records<Input>, Records<Input>> {
+	/// Read pairs from two files (decompressing them if needed).
+	pub fn from_files<P1: AsRef<Path>, P2: AsRef<Path>>(r1: P1, r2: P2) -> io::Result<Self> {
+		Ok(PairedReader::new(Reader::from_file(r1)?.records(), Reader::from_file(r2)?.records()))
+	}
+}
+
+#[cfg(feature = "example-data")]
+impl PairedReader<Records<&'static [u8]>, Records<&'static [u8]>> {
+	/// Read the four pairs of `example::PAIRED_R1` and `example::PAIRED_R2`, to try things out without files.
+	pub fn example() -> Self {
+		PairedReader::new(Reader::new(super::example::PAIRED_R1.as_bytes()).records(), Reader::new(super::example::PAIRED_R2.as_bytes()).records())
+	}
+}
+
+impl<I1, I2> PairedReader<I1, I2> where I1: Iterator<Item=Result<Record, unfancy_parser::Error>>, I2: Iterator<Item=Result<Record, unfancy_parser::Error>> {
+	/// Read pairs from the records of two mate files.
+	pub fn new(r1: I1, r2: I2) -> Self {
+		PairedReader {
+			r1, r2, index: 0, done: false,
+			policies: [MatePolicy::Strict; 2], window: 1000,
+			pending: [VecDeque::new(), VecDeque::new()], skipped: [0; 2], resyncs: 0,
+		}
+	}
+	
+	/// Set how parse errors of each file are handled.
+	pub fn policies(mut self, r1: MatePolicy, r2: MatePolicy) -> Self {
+		self.policies = [r1, r2];
+		self
+	}
+	
+	/// Look at most `window` records ahead in each file when resynchronizing (default 1000).
+	pub fn resync_window(mut self, window: usize) -> Self {
+		self.window = window.max(1);
+		self
+	}
+	
+	/// Number of pairs read so far.
+	pub fn pairs_read(&self) -> usize { self.index }
+	
+	/// Number of records of a file dropped so far, broken or without mate.
+	pub fn skipped(&self, mate: Mate) -> usize { self.skipped[mate.index()] }
+	
+	/// Number of times the files were brought back in sync.
+	pub fn resyncs(&self) -> usize { self.resyncs }
+	
+	/// The next record read from a file, skipping broken ones if it is lenient.
+	fn pull(&mut self, mate: Mate) -> Result<Option<Record>, PairError> {
+		loop {
+			let next = match mate { Mate::R1 => self.r1.next(), Mate::R2 => self.r2.next() };
+			match next {
+				None => return Ok(None),
+				Some(Ok(record)) => return Ok(Some(record)),
+				Some(Err(_)) if self.policies[mate.index()] == MatePolicy::Lenient => self.skipped[mate.index()] += 1,
+				Some(Err(e)) => return Err(PairError::Parse(mate, self.index, e)),
+			}
+		}
+	}
+	
+	/// The next record of a file, looked ahead at or newly read.
+	fn next_mate(&mut self, mate: Mate) -> Result<Option<Record>, PairError> {
+		match self.pending[mate.index()].pop_front() {
+			Some(record) => Ok(Some(record)),
+			None => self.pull(mate),
+		}
+	}
+	
+	/// Read ahead until records with matching IDs are found, and drop the records before them.
+	fn resync(&mut self) -> Result<(), PairError> {
+		let base = |r: &Record| mate_base_id(r.id().unwrap_or("")).to_owned();
+		let mut ids: [HashMap<String, usize>; 2] = Default::default();
+		let mut found = None;
+		for side in 0..2 {
+			for (k, record) in self.pending[side].iter().enumerate() {
+				let id = base(record);
+				if let Some(&other) = ids[1 - side].get(&id) {
+					found = found.or(Some(if side == 0 { (k, other) } else { (other, k) }));
+				}
+				ids[side].entry(id).or_insert(k);
+			}
+		}
+		while found.is_none() {
+			let mut progressed = false;
+			for mate in [Mate::R1, Mate::R2] {
+				let side = mate.index();
+				if self.pending[side].len() >= self.window { continue }
+				let record = match self.pull(mate)? {
+					Some(record) => record,
+					None => continue,
+				};
+				progressed = true;
+				let (id, k) = (base(&record), self.pending[side].len());
+				self.pending[side].push_back(record);
+				if let Some(&other) = ids[1 - side].get(&id) {
+					found = Some(if side == 0 { (k, other) } else { (other, k) });
+					break;
+				}
+				ids[side].entry(id).or_insert(k);
+			}
+			if !progressed && found.is_none() { return Err(PairError::Unsynchronized(self.index, self.window)) }
+		}
+		let (i, j) = found.unwrap();
+		self.pending[0].drain(..i);
+		self.pending[1].drain(..j);
+		self.skipped[0] += i;
+		self.skipped[1] += j;
+		self.resyncs += 1;
+		Ok(())
+	}
+}
+
+impl<I1, I2> Iterator for PairedReader<I1, I2> where I1: Iterator<Item=Result<Record, unfancy_parser::Error>>, I2: Iterator<Item=Result<Record, unfancy_parser::Error>> {
+	type Item = Result<(Record, Record), PairError>;
+	
+	fn next(&mut self) -> Option<Result<(Record, Record), PairError>> {
+		loop {
+			if self.done { return None }
+			let index = self.index;
+			let (a, b) = (self.next_mate(Mate::R1), self.next_mate(Mate::R2));
+			let (a, b) = match (a, b) {
+				(Err(e), _) | (_, Err(e)) => { self.index += 1; return Some(Err(e)) },
+				(Ok(None), Ok(None)) => { self.done = true; return None },
+				(Ok(Some(_)), Ok(None)) => { self.done = true; return Some(Err(PairError::UnequalLength(Mate::R1, index))) },
+				(Ok(None), Ok(Some(_))) => { self.done = true; return Some(Err(PairError::UnequalLength(Mate::R2, index))) },
+				(Ok(Some(a)), Ok(Some(b))) => (a, b),
+			};
+			if !mates_match(a.id().unwrap_or(""), b.id().unwrap_or("")) && self.policies.contains(&MatePolicy::Lenient) {
+				self.pending[0].push_front(a);
+				self.pending[1].push_front(b);
+				if let Err(e) = self.resync() {
+					self.done = true;
+					return Some(Err(e));
+				}
+				continue;
+			}
+			self.index += 1;
+			let (id1, id2) = (a.id().unwrap_or(""), b.id().unwrap_or(""));
+			if !mates_match(id1, id2) {
+				return Some(Err(PairError::IdMismatch(index, id1.to_owned(), id2.to_owned())));
+			}
+			return Some(Ok((a, b)));
+		}
+	}
+}
+
+/// An iterator over the records of two mate streams in alternating order, see `interleave`.
+pub struct Interleave<I1, I2> {
+	pairs: PairedReader<I1, I2>,
+	mate2: Option<Record>,
+}
+
+impl<I1, I2> Iterator for Interleave<I1, I2> where I1: Iterator<Item=Result<Record, unfancy_parser::Error>>, I2: Iterator<Item=Result<Record, unfancy_parser::Error>> {
+	type Item = Result<Record, PairError>;
+	
+	fn next(&mut self) -> Option<Result<Record, PairError>> {
+		if let Some(r2) = self.mate2.take() { return Some(Ok(r2)) }
+		Some(self.pairs.next()?.map(|(r1, r2)| {
+			self.mate2 = Some(r2);
+			r1
+		}))
+	}
+}
+
+/// Interleave the records of two mate streams: R1, R2, R1, R2, …
+///
+/// Mates are checked like by `PairedReader`.
+pub fn interleave<I1, I2>(r1: I1, r2: I2) -> Interleave<I1::IntoIter, I2::IntoIter>
+	where I1: IntoIterator<Item=Result<Record, unfancy_parser::Error>>, I2: IntoIterator<Item=Result<Record, unfancy_parser::Error>>
+{
+	Interleave { pairs: PairedReader::new(r1.into_iter(), r2.into_iter()), mate2: None }
+}
+
+/// An iterator over the pairs of an interleaved stream, see `deinterleave`.
+pub struct Deinterleave<I> {
+	records: I,
+	index: usize,
+	done: bool,
+}
+
+impl<I> Deinterleave<I> {
+	/// Number of pairs read so far.
+	pub fn pairs_read(&self) -> usize { self.index }
+}
+
+impl<I> Iterator for Deinterleave<I> where I: Iterator<Item=Result<Record, unfancy_parser::Error>> {
+	type Item = Result<(Record, Record), PairError>;
+	
+	fn next(&mut self) -> Option<Result<(Record, Record), PairError>> {
+		if self.done { return None }
+		let index = self.index;
+		let a = match self.records.next() {
+			None => { self.done = true; return None },
+			Some(Ok(a)) => a,
+			Some(Err(e)) => { self.done = true; return Some(Err(PairError::Parse(Mate::R1, index, e))) },
+		};
+		let b = match self.records.next() {
+			None => { self.done = true; return Some(Err(PairError::UnequalLength(Mate::R1, index))) },
+			Some(Ok(b)) => b,
+			Some(Err(e)) => { self.done = true; return Some(Err(PairError::Parse(Mate::R2, index, e))) },
+		};
+		self.index += 1;
+		let (id1, id2) = (a.id().unwrap_or(""), b.id().unwrap_or(""));
+		if !mates_match(id1, id2) {
+			return Some(Err(PairError::IdMismatch(index, id1.to_owned(), id2.to_owned())));
+		}
+		Some(Ok((a, b)))
+	}
+}
+
+/// Split an interleaved stream into pairs, checking that consecutive records are mates.
+///
+/// Parse errors end the iteration, since the stream could no longer be split reliably.
+pub fn deinterleave<I>(records: I) -> Deinterleave<I::IntoIter> where I: IntoIterator<Item=Result<Record, unfancy_parser::Error>> {
+	Deinterleave { records: records.into_iter(), index: 0, done: false }
+}
+
+/// Split an interleaved stream into two mate files, returning the number of pairs.
+pub fn deinterleave_to<I, W1, W2>(records: I, r1: &mut Writer<W1>, r2: &mut Writer<W2>) -> Result<usize, PairError>
+	where I: IntoIterator<Item=Result<Record, unfancy_parser::Error>>, W1: io::Write, W2: io::Write
+{
+	let mut n = 0;
+	for pair in deinterleave(records) {
+		let (a, b) = pair?;
+		r1.write_record(&a)?;
+		r2.write_record(&b)?;
+		n += 1;
+	}
+	r1.flush()?;
+	r2.flush()?;
+	Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Position;
+	
+	/// Parse records, turning those with IDs starting with `broken` into parse errors.
+	fn records(text: &str) -> Vec<Result<Record, unfancy_parser::Error>> {
+		Reader::new(text.as_bytes()).records().map(|r| {
+			let record = r.unwrap();
+			if record.id().unwrap().starts_with("broken") {
+				Err(unfancy_parser::Error::MissingPlus(Position { line: 0, record: 0, offset: 0 }))
+			} else {
+				Ok(record)
+			}
+		}).collect()
+	}
+	
+	fn fastq(ids: &[&str]) -> String {
+		ids.iter().map(|id| format!("@{}\nACGT\n+\nIIII\n", id)).collect()
+	}
+	
+	fn ids(pair: &(Record, Record)) -> (String, String) {
+		(pair.0.id().unwrap().to_owned(), pair.1.id().unwrap().to_owned())
+	}
+	
+	#[test]
+	fn reads_pairs_with_matching_ids() {
+		let r1 = records(&fastq(&["a/1", "b/1", "c"]));
+		let r2 = records(&fastq(&["a/2", "b/2", "c"]));
+		let mut pairs = PairedReader::new(r1.into_iter(), r2.into_iter());
+		let read: Vec<_> = pairs.by_ref().map(|p| ids(&p.unwrap())).collect();
+		assert_eq!(read, [("a/1", "a/2"), ("b/1", "b/2"), ("c", "c")].map(|(a, b)| (a.to_owned(), b.to_owned())));
+		assert_eq!(pairs.pairs_read(), 3);
+	}
+	
+	#[test]
+	fn reports_mismatched_mate_ids() {
+		let r1 = records(&fastq(&["a/1", "b/1", "c/1"]));
+		let r2 = records(&fastq(&["a/2", "x/2", "c/2"]));
+		let results: Vec<_> = PairedReader::new(r1.into_iter(), r2.into_iter()).collect();
+		assert_eq!(results.len(), 3);
+		assert!(results[0].is_ok());
+		match &results[1] {
+			Err(PairError::IdMismatch(1, id1, id2)) => assert_eq!((id1.as_str(), id2.as_str()), ("b/1", "x/2")),
+			other => panic!("Expected ID mismatch, got {:?}", other),
+		}
+		assert!(results[2].is_ok());
+	}
+	
+	#[test]
+	fn reports_which_file_is_longer() {
+		for (n1, n2, longer) in [(3, 2, Mate::R1), (1, 2, Mate::R2)] {
+			let (ids1, ids2) = (["a", "b", "c"], ["a", "b"]);
+			let r1 = records(&fastq(&ids1[..n1]));
+			let r2 = records(&fastq(&ids2[..n2]));
+			let results: Vec<_> = PairedReader::new(r1.into_iter(), r2.into_iter()).collect();
+			assert_eq!(results.len(), n1.min(n2) + 1);
+			match results.last().unwrap() {
+				Err(PairError::UnequalLength(mate, index)) => assert_eq!((*mate, *index), (longer, n1.min(n2))),
+				other => panic!("Expected unequal length, got {:?}", other),
+			}
+		}
+	}
+	
+	#[test]
+	fn propagates_parse_errors_of_either_file() {
+		let r1 = records(&fastq(&["a", "b", "c"]));
+		let r2 = records(&fastq(&["a", "broken", "c"]));
+		let results: Vec<_> = PairedReader::new(r1.into_iter(), r2.into_iter()).collect();
+		match &results[1] {
+			Err(PairError::Parse(Mate::R2, 1, unfancy_parser::Error::MissingPlus(_))) => {},
+			other => panic!("Expected parse error in R2, got {:?}", other),
+		}
+		
+		let r1 = records(&fastq(&["broken"]));
+		let r2 = records(&fastq(&["a"]));
+		let mut pairs = PairedReader::new(r1.into_iter(), r2.into_iter());
+		assert!(matches!(pairs.next(), Some(Err(PairError::Parse(Mate::R1, 0, _)))));
+	}
+	
+	#[test]
+	fn lenient_reader_resyncs_after_broken_record() {
+		let r1 = records(&fastq(&["a/1", "broken", "c/1", "d/1"]));
+		let r2 = records(&fastq(&["a/2", "b/2", "c/2", "d/2"]));
+		let mut pairs = PairedReader::new(r1.into_iter(), r2.into_iter()).policies(MatePolicy::Lenient, MatePolicy::Strict);
+		let read: Vec<_> = pairs.by_ref().map(|p| ids(&p.unwrap())).collect();
+		assert_eq!(read, [("a/1", "a/2"), ("c/1", "c/2"), ("d/1", "d/2")].map(|(a, b)| (a.to_owned(), b.to_owned())));
+		assert_eq!((pairs.skipped(Mate::R1), pairs.skipped(Mate::R2)), (1, 1));
+		assert_eq!(pairs.resyncs(), 1);
+		assert_eq!(pairs.pairs_read(), 3);
+	}
+	
+	#[test]
+	fn lenient_reader_resyncs_by_id_in_either_file() {
+		let r1 = records(&fastq(&["a", "b", "c", "d", "e"]));
+		let r2 = records(&fastq(&["a", "broken", "broken", "d", "e"]));
+		let mut pairs = PairedReader::new(r1.into_iter(), r2.into_iter()).policies(MatePolicy::Strict, MatePolicy::Lenient);
+		let read: Vec<_> = pairs.by_ref().map(|p| ids(&p.unwrap()).0).collect();
+		assert_eq!(read, ["a", "d", "e"]);
+		assert_eq!((pairs.skipped(Mate::R1), pairs.skipped(Mate::R2)), (2, 2));
+	}
+	
+	#[test]
+	fn strict_file_still_reports_its_errors() {
+		let r1 = records(&fastq(&["a", "broken", "c"]));
+		let r2 = records(&fastq(&["a", "b", "c"]));
+		let mut pairs = PairedReader::new(r1.into_iter(), r2.into_iter()).policies(MatePolicy::Strict, MatePolicy::Lenient);
+		assert!(pairs.next().unwrap().is_ok());
+		assert!(matches!(pairs.next(), Some(Err(PairError::Parse(Mate::R1, 1, _)))));
+	}
+	
+	#[test]
+	fn gives_up_resyncing_beyond_window() {
+		let r1 = records(&fastq(&["a", "b", "c", "d", "e"]));
+		let r2 = records(&fastq(&["a", "v", "w", "x", "e"]));
+		let mut pairs = PairedReader::new(r1.into_iter(), r2.into_iter()).policies(MatePolicy::Lenient, MatePolicy::Lenient).resync_window(2);
+		assert!(pairs.next().unwrap().is_ok());
+		assert!(matches!(pairs.next(), Some(Err(PairError::Unsynchronized(1, 2)))));
+		assert!(pairs.next().is_none());
+	}
+}
@@ -0,0 +1,593 @@
+//! Parallel parsing of FastQ input.
+//!
+//! Uncompressed files can be split into byte ranges starting at record boundaries,
+//! found by a quick scan around evenly spaced offsets, and every range parsed by its own thread
+//! (`par_for_each`). Any other input is read sequentially in record-aligned chunks
+//! that are parsed by a pool of worker threads (`par_records`, `for_each_parallel`).
+//!
+//! Panicking workers, and in `par_records` chunks that take too long, are reported as
+//! `ParallelError`s naming the records concerned, while the remaining input is still processed.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec;
+
+use super::buffer;
+use super::unfancy_parser::{Reader, Record, Error};
+use super::input::require_seekable;
+use super::paths::long_path;
+use super::Position;
+
+/// Split a plain FastQ file into at most `n` byte ranges that each start at a record.
+/// Fails with `Unsupported` for pipes and other files that cannot seek, which `par_records` can read instead.
+pub fn split_ranges<P: AsRef<Path>>(path: P, n: usize) -> io::Result<Vec<Range<u64>>> {
+	require_seekable(path.as_ref(), "Splitting a file into ranges")?;
+	let mut file = fs::File::open(long_path(path))?;
+	let len = file.metadata()?.len();
+	let mut starts = vec![0];
+	for i in 1..n.max(1) {
+		let guess = len * i as u64 / n as u64;
+		if guess <= *starts.last().unwrap() { continue }
+		match next_record_start(&mut file, guess)? {
+			Some(start) if start > *starts.last().unwrap() && start < len => starts.push(start),
+			_ => {},
+		}
+	}
+	starts.dedup();
+	let ends = starts.iter().skip(1).cloned().chain(Some(len));
+	Ok(starts.iter().cloned().zip(ends).map(|(s, e)| s..e).collect())
+}
+
+/// Find the first record starting at or after `offset`.
+///
+/// A line is taken to be a header if it starts with `@`, the line after next starts with `+`,
+/// sequence and quality lines have the same length and the following line (if any) starts with `@`.
+/// Blank lines are skipped, so they do not throw off the four-line window.
+fn next_record_start<F: Read + Seek>(file: &mut F, offset: u64) -> io::Result<Option<u64>> {
+	file.seek(SeekFrom::Start(offset))?;
+	let mut reader = io::BufReader::new(file);
+	let mut pos = offset;
+	let mut lines: Vec<(u64, String)> = Vec::new();
+	if offset > 0 {
+		// skip the partial line we landed in
+		let mut partial = Vec::new();
+		pos += reader.read_until(b'\n', &mut partial)? as u64;
+	}
+	loop {
+		while lines.len() < 5 {
+			let mut line = String::new();
+			let n = reader.read_line(&mut line)?;
+			if n == 0 { break }
+			if !line.trim_end().is_empty() { lines.push((pos, line)) }
+			pos += n as u64;
+		}
+		if lines.len() < 4 { return Ok(None) }
+		let l = |i: usize| lines[i].1.trim_end();
+		let next_ok = lines.len() < 5 || l(4).starts_with('@');
+		if l(0).starts_with('@') && l(2).starts_with('+') && l(1).len() == l(3).len() && next_ok {
+			return Ok(Some(lines[0].0));
+		}
+		lines.remove(0);
+	}
+}
+
+/// The part of the input a chunk of work covered, for locating failures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSpan {
+	/// Byte range of the chunk.
+	pub bytes: Range<u64>,
+	/// Indices of the records in the chunk, if known.
+	pub records: Option<Range<u64>>,
+}
+
+impl fmt::Display for ChunkSpan {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.records {
+			Some(ref records) => write!(f, "records {}..{} (bytes {}..{})", records.start, records.end, self.bytes.start, self.bytes.end),
+			None => write!(f, "bytes {}..{}", self.bytes.start, self.bytes.end),
+		}
+	}
+}
+
+quick_error! {
+	/// An error encountered while parsing in parallel.
+	#[derive(Debug)]
+	pub enum ParallelError {
+		/// The input could not be read or parsed.
+		Parse(err: Error) {
+			from()
+			from(err: io::Error) -> (Error::Io(err))
+			cause(err)
+			display("{}", err)
+		}
+		/// Parsing or handling the records of a chunk panicked. The other chunks are unaffected.
+		Panicked(span: ChunkSpan, message: String) {
+			description("Worker thread panicked")
+			display("Worker panicked on {}: {}", span, message)
+		}
+		/// A chunk took longer than `ParallelOptions::stall_timeout`. Its records are skipped.
+		Stalled(span: ChunkSpan, timeout: Duration) {
+			description("Worker thread stalled")
+			display("Worker stalled on {} for more than {:?}", span, timeout)
+		}
+	}
+}
+
+impl ParallelError {
+	/// The chunk a worker failed on, if a worker failed.
+	pub fn span(&self) -> Option<&ChunkSpan> {
+		match *self {
+			ParallelError::Panicked(ref span, _) | ParallelError::Stalled(ref span, _) => Some(span),
+			ParallelError::Parse(_) => None,
+		}
+	}
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+	match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+		(Some(s), _) => s.to_string(),
+		(_, Some(s)) => s.clone(),
+		_ => "unknown panic payload".to_owned(),
+	}
+}
+
+/// Run `work`, turning a panic into an error about `span`.
+fn catch_panic<T, F>(span: &ChunkSpan, work: F) -> Result<T, ParallelError> where F: FnOnce() -> Result<T, ParallelError> {
+	panic::catch_unwind(AssertUnwindSafe(work))
+		.unwrap_or_else(|payload| Err(ParallelError::Panicked(span.clone(), panic_message(&*payload))))
+}
+
+/// Parse a plain FastQ file with `threads` threads, calling `f` for every record.
+///
+/// Records are handed to `f` concurrently and in no particular order.
+/// Returns the number of records, or the error of the first range that failed.
+/// A panic in `f` ends only its own range, and is returned as `ParallelError::Panicked`.
+pub fn par_for_each<P, F>(path: P, threads: usize, f: F) -> Result<usize, ParallelError>
+	where P: AsRef<Path>, F: Fn(Record) + Sync
+{
+	let path = &long_path(path);
+	let ranges = split_ranges(path, threads)?;
+	let f = &f;
+	thread::scope(|scope| {
+		let workers: Vec<_> = ranges.iter().cloned().map(|range| scope.spawn(move || {
+			let span = ChunkSpan { bytes: range.clone(), records: None };
+			catch_panic(&span, || {
+				let mut file = fs::File::open(path)?;
+				file.seek(SeekFrom::Start(range.start))?;
+				let mut n = 0;
+				let start = Position { offset: range.start, ..Position::start() };
+				for record in Reader::new(file.take(range.end - range.start)).starting_at(start).records() {
+					f(record?);
+					n += 1;
+				}
+				Ok(n)
+			})
+		})).collect();
+		// every range counts lines and records from its start: move the first error behind the ranges before it
+		let mut records = 0;
+		for (range, worker) in ranges.iter().zip(workers) {
+			match worker.join().expect("parser thread panicked") {
+				Ok(n) => records += n,
+				Err(ParallelError::Parse(mut e)) => {
+					if let Some(pos) = e.position_mut() {
+						pos.line += count_lines(path, range.start)?;
+						pos.record += records as u64;
+					}
+					return Err(e.into());
+				},
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(records)
+	})
+}
+
+/// Count the line breaks in the first `len` bytes of a file.
+fn count_lines(path: &Path, len: u64) -> io::Result<u64> {
+	let mut file = io::BufReader::new(fs::File::open(path)?.take(len));
+	let mut lines = 0;
+	loop {
+		let buf = file.fill_buf()?;
+		if buf.is_empty() { return Ok(lines) }
+		lines += buf.iter().filter(|&&b| b == b'\n').count() as u64;
+		let n = buf.len();
+		file.consume(n);
+	}
+}
+
+/// Settings of the chunked worker pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParallelOptions {
+	/// Number of parsing threads.
+	pub threads: usize,
+	/// Approximate number of bytes per chunk.
+	pub chunk_size: usize,
+	/// How long `par_records` waits for a chunk being parsed before reporting it as stalled.
+	/// The stalled thread is abandoned and replaced. `None` waits forever.
+	pub stall_timeout: Option<Duration>,
+}
+
+impl Default for ParallelOptions {
+	fn default() -> ParallelOptions {
+		let threads = thread::available_parallelism().map_or(1, |n| n.get());
+		ParallelOptions { threads, chunk_size: 1 << 20, stall_timeout: None }
+	}
+}
+
+type Chunk = (usize, Position, ChunkSpan, io::Result<Vec<u8>>);
+
+/// Messages from the worker pool to `ParRecords`.
+enum Parsed {
+	/// The records of the chunk with this index.
+	Chunk(usize, Vec<Result<Record, ParallelError>>),
+	/// The input had this many chunks.
+	End(usize),
+}
+
+/// Read `reader` in chunks that end at record boundaries (every fourth line break, not counting blank lines),
+/// passing each with the position it starts at and the span it covers to `emit` until it returns false.
+/// A byte order mark and blank lines at the start are skipped, as the readers do.
+fn read_chunks<R: Read, F: FnMut(Position, ChunkSpan, io::Result<Vec<u8>>) -> bool>(reader: R, chunk_size: usize, mut emit: F) {
+	let mut carry = Vec::new();
+	let mut pos = Position::start();
+	let mut reader = io::BufReader::new(reader);
+	if let Err(e) = buffer::skip_preamble(&mut reader, &mut pos) {
+		emit(pos, ChunkSpan { bytes: pos.offset..pos.offset, records: Some(0..0) }, Err(e));
+		return;
+	}
+	let span = |pos: Position, len: usize, records| ChunkSpan { bytes: pos.offset..pos.offset + len as u64, records: Some(pos.record..pos.record + records) };
+	loop {
+		let mut chunk = std::mem::take(&mut carry);
+		let start = chunk.len();
+		chunk.resize(start + chunk_size.max(1), 0);
+		let n = match reader.read(&mut chunk[start..]) {
+			Ok(n) => n,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => { chunk.truncate(start); carry = chunk; continue },
+			Err(e) => { emit(pos, span(pos, 0, 0), Err(e)); return },
+		};
+		chunk.truncate(start + n);
+		if n == 0 {
+			if !chunk.is_empty() {
+				let lines = chunk.split(|&b| b == b'\n').filter(|line| !is_blank(line)).count();
+				emit(pos, span(pos, chunk.len(), lines.div_ceil(4) as u64), Ok(chunk));
+			}
+			return;
+		}
+		match record_boundary(&chunk) {
+			Some((end, records, lines)) => {
+				carry = chunk.split_off(end);
+				let (chunk_pos, chunk_span) = (pos, span(pos, end, records));
+				pos = Position { line: pos.line + lines, record: pos.record + records, offset: pos.offset + end as u64 };
+				if !emit(chunk_pos, chunk_span, Ok(chunk)) { return }
+			},
+			None => carry = chunk,
+		}
+	}
+}
+
+fn is_blank(line: &[u8]) -> bool {
+	line.iter().all(u8::is_ascii_whitespace)
+}
+
+/// The offset after the last complete four-line record in `data`, the number of records before it and of lines.
+/// Blank lines are skipped like `next_record_start` does, so they do not shift the boundaries.
+fn record_boundary(data: &[u8]) -> Option<(usize, u64, u64)> {
+	let (mut lines, mut record_lines, mut line_start) = (0, 0, 0);
+	let mut boundary = None;
+	for (i, &b) in data.iter().enumerate() {
+		if b != b'\n' { continue }
+		lines += 1;
+		if !is_blank(&data[line_start..i]) {
+			record_lines += 1;
+			if record_lines % 4 == 0 { boundary = Some((i + 1, record_lines / 4, lines)) }
+		}
+		line_start = i + 1;
+	}
+	boundary
+}
+
+/// Parse the records of a chunk starting at `start`, so that positions are counted from the start of input.
+fn parse_chunk(start: Position, chunk: io::Result<Vec<u8>>) -> Vec<Result<Record, Error>> {
+	match chunk {
+		Ok(chunk) => Reader::new(&chunk[..]).starting_at(start).records().collect(),
+		Err(e) => vec![Err(e.into())],
+	}
+}
+
+/// What the threads of `par_records` share.
+///
+/// Workers only hold a weak reference to the chunk queue while parsing,
+/// so that it closes when `ParRecords` is dropped even if a worker never returns.
+#[derive(Clone)]
+struct Pool {
+	chunks: Arc<Mutex<mpsc::Receiver<Chunk>>>,
+	parsed: mpsc::SyncSender<Parsed>,
+	/// Chunks being parsed, by index, with the time parsing started.
+	started: Arc<Mutex<HashMap<usize, (ChunkSpan, Instant)>>>,
+}
+
+fn spawn_worker(pool: &Pool) {
+	let chunks = Arc::downgrade(&pool.chunks);
+	let (parsed, started) = (pool.parsed.clone(), pool.started.clone());
+	thread::spawn(move || loop {
+		let Some(chunks) = chunks.upgrade() else { return };
+		let next = chunks.lock().unwrap_or_else(PoisonError::into_inner).recv();
+		drop(chunks);
+		let Ok((i, start, span, chunk)) = next else { return };
+		started.lock().unwrap_or_else(PoisonError::into_inner).insert(i, (span.clone(), Instant::now()));
+		let records = catch_panic(&span, || Ok(parse_chunk(start, chunk)))
+			.map_or_else(|e| vec![Err(e)], |records| records.into_iter().map(|r| r.map_err(Into::into)).collect());
+		started.lock().unwrap_or_else(PoisonError::into_inner).remove(&i);
+		if parsed.send(Parsed::Chunk(i, records)).is_err() { return }
+	});
+}
+
+/// An iterator over records parsed by worker threads, in input order.
+pub struct ParRecords {
+	pool: Pool,
+	parsed: mpsc::Receiver<Parsed>,
+	stall_timeout: Option<Duration>,
+	waiting: BTreeMap<usize, Vec<Result<Record, ParallelError>>>,
+	next_chunk: usize,
+	chunks: Option<usize>,
+	current: vec::IntoIter<Result<Record, ParallelError>>,
+}
+
+impl ParRecords {
+	/// Wait for the next message from the pool,
+	/// or return the span of the next chunk if it has been parsed for longer than the stall timeout.
+	fn receive(&self) -> Result<Parsed, ChunkSpan> {
+		let Some(timeout) = self.stall_timeout else {
+			return Ok(self.parsed.recv().expect("ParRecords holds a sender"));
+		};
+		loop {
+			let started = self.pool.started.lock().unwrap_or_else(PoisonError::into_inner).get(&self.next_chunk).cloned();
+			let wait = match started {
+				Some((span, start)) if start.elapsed() >= timeout => return Err(span),
+				Some((_, start)) => timeout - start.elapsed(),
+				None => timeout,
+			};
+			match self.parsed.recv_timeout(wait) {
+				Ok(parsed) => return Ok(parsed),
+				Err(mpsc::RecvTimeoutError::Timeout) => continue,
+				Err(mpsc::RecvTimeoutError::Disconnected) => unreachable!("ParRecords holds a sender"),
+			}
+		}
+	}
+}
+
+impl Iterator for ParRecords {
+	type Item = Result<Record, ParallelError>;
+	
+	fn next(&mut self) -> Option<Result<Record, ParallelError>> {
+		loop {
+			if let Some(record) = self.current.next() { return Some(record) }
+			if self.chunks == Some(self.next_chunk) { return None }
+			if let Some(records) = self.waiting.remove(&self.next_chunk) {
+				self.current = records.into_iter();
+				self.next_chunk += 1;
+				continue;
+			}
+			match self.receive() {
+				// results of chunks reported as stalled are dropped
+				Ok(Parsed::Chunk(i, records)) => if i >= self.next_chunk { self.waiting.insert(i, records); },
+				Ok(Parsed::End(n)) => self.chunks = Some(n),
+				Err(span) => {
+					self.next_chunk += 1;
+					spawn_worker(&self.pool);
+					return Some(Err(ParallelError::Stalled(span, self.stall_timeout.unwrap_or_default())));
+				},
+			}
+		}
+	}
+}
+
+/// Parse records on a pool of worker threads, yielding them in input order.
+///
+/// The input is read by a background thread. Dropping the iterator stops all threads.
+/// A chunk whose parsing panics or stalls yields one error in place of its records,
+/// after which the following chunks are yielded as usual.
+pub fn par_records<R: Read + Send + 'static>(reader: R, options: &ParallelOptions) -> ParRecords {
+	let threads = options.threads.max(1);
+	let (chunk_tx, chunk_rx) = mpsc::sync_channel::<Chunk>(threads * 2);
+	let (parsed_tx, parsed_rx) = mpsc::sync_channel(threads * 2);
+	let pool = Pool { chunks: Arc::new(Mutex::new(chunk_rx)), parsed: parsed_tx, started: Arc::default() };
+	for _ in 0..threads { spawn_worker(&pool) }
+	let chunk_size = options.chunk_size;
+	let parsed = pool.parsed.clone();
+	thread::spawn(move || {
+		let mut i = 0;
+		let mut done = ChunkSpan { bytes: 0..0, records: Some(0..0) };
+		let read = panic::catch_unwind(AssertUnwindSafe(|| read_chunks(reader, chunk_size, |start, span, chunk| {
+			done = span.clone();
+			i += 1;
+			chunk_tx.send((i - 1, start, span, chunk)).is_ok()
+		})));
+		if let Err(payload) = read {
+			// the input itself panicked: report it after the last chunk read
+			let end = done.bytes.end;
+			let record = done.records.map_or(0, |r| r.end);
+			let span = ChunkSpan { bytes: end..end, records: Some(record..record) };
+			let error = ParallelError::Panicked(span, panic_message(&*payload));
+			if parsed.send(Parsed::Chunk(i, vec![Err(error)])).is_err() { return }
+			i += 1;
+		}
+		let _ = parsed.send(Parsed::End(i));
+	});
+	ParRecords {
+		pool,
+		parsed: parsed_rx,
+		stall_timeout: options.stall_timeout,
+		waiting: BTreeMap::new(),
+		next_chunk: 0,
+		chunks: None,
+		current: Vec::new().into_iter(),
+	}
+}
+
+/// Parse records on a pool of worker threads, calling `f` for each record on the thread that parsed it.
+///
+/// Records are handed to `f` concurrently and in no particular order.
+/// Returns the number of records, or the first error encountered, after which the other threads stop early.
+/// A panic in `f` is returned as `ParallelError::Panicked`. `ParallelOptions::stall_timeout` does not apply,
+/// as this function cannot return before `f` does.
+pub fn for_each_parallel<R, F>(reader: R, options: &ParallelOptions, f: F) -> Result<usize, ParallelError>
+	where R: Read + Send, F: Fn(Record) + Sync
+{
+	let (chunk_tx, chunk_rx) = mpsc::sync_channel::<(Position, ChunkSpan, io::Result<Vec<u8>>)>(options.threads.max(1) * 2);
+	let chunk_rx = Mutex::new(chunk_rx);
+	let failed = AtomicBool::new(false);
+	let f = &f;
+	thread::scope(|scope| {
+		let workers: Vec<_> = (0..options.threads.max(1)).map(|_| {
+			let (chunk_rx, failed) = (&chunk_rx, &failed);
+			scope.spawn(move || -> Result<usize, ParallelError> {
+				let mut n = 0;
+				while !failed.load(Ordering::Relaxed) {
+					let next = chunk_rx.lock().unwrap_or_else(PoisonError::into_inner).recv();
+					let Ok((start, span, chunk)) = next else { break };
+					let parsed = catch_panic(&span, || {
+						let mut n = 0;
+						for record in parse_chunk(start, chunk) {
+							f(record?);
+							n += 1;
+						}
+						Ok(n)
+					});
+					match parsed {
+						Ok(parsed) => n += parsed,
+						Err(e) => { failed.store(true, Ordering::Relaxed); return Err(e) },
+					}
+				}
+				Ok(n)
+			})
+		}).collect();
+		read_chunks(reader, options.chunk_size, |start, span, chunk| !failed.load(Ordering::Relaxed) && chunk_tx.send((start, span, chunk)).is_ok());
+		drop(chunk_tx);
+		workers.into_iter().map(|w| w.join().expect("parser thread panicked")).sum()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Record as RecordTrait;
+	
+	/// 40 records with an invalid separator in record 33, whose separator is on line 135.
+	fn input() -> Vec<u8> {
+		let mut data = Vec::new();
+		for i in 0..40 {
+			let sep = if i == 33 { "-" } else { "+" };
+			data.extend(format!("@r{}\nACGT\n{}\nIIII\n", i, sep).into_bytes());
+		}
+		data
+	}
+	
+	#[test]
+	fn par_records_reports_positions_from_start_of_input() {
+		let options = ParallelOptions { threads: 3, chunk_size: 64, stall_timeout: None };
+		let errors: Vec<_> = par_records(io::Cursor::new(input()), &options).filter_map(Result::err).collect();
+		assert_eq!(errors.len(), 1);
+		let ParallelError::Parse(ref e) = errors[0] else { panic!("{:?}", errors[0]) };
+		let pos = e.position().unwrap();
+		assert_eq!((pos.line, pos.record), (135, 33));
+		assert_eq!(&input()[pos.offset as usize..][..2], b"-\n");
+	}
+	
+	#[test]
+	fn par_for_each_reports_positions_from_start_of_input() {
+		let path = std::env::temp_dir().join(format!("fastq-parallel-test-{}.fq", std::process::id()));
+		fs::write(&path, input()).unwrap();
+		let result = par_for_each(&path, 4, |_| {});
+		fs::remove_file(&path).unwrap();
+		let Err(ParallelError::Parse(e)) = result else { panic!("{:?}", result) };
+		let pos = e.position().unwrap();
+		assert_eq!((pos.line, pos.record), (135, 33));
+		assert_eq!(&input()[pos.offset as usize..][..2], b"-\n");
+	}
+	
+	#[test]
+	fn chunks_end_at_records_despite_blank_lines() {
+		let data = b"@a\nACGT\n+\nIIII\n\n@b\nAC\n+\nII\n@c\nA\n";
+		assert_eq!(record_boundary(data), Some((27, 2, 9)));
+		assert_eq!(record_boundary(b"@a\nA\n+\n\n"), None);
+	}
+	
+	#[test]
+	fn par_records_fails_on_blank_lines_like_reading_in_sequence() {
+		let mut data = Vec::new();
+		for i in 0..40 {
+			data.extend(format!("@r{}\nACGT\n+\nIIII\n", i).into_bytes());
+			if i == 20 { data.push(b'\n') }
+		}
+		let ids = |records: Vec<Result<Record, Option<Position>>>| -> Vec<_> {
+			records.into_iter().map(|r| r.map(|r| r.id().unwrap().to_owned())).collect()
+		};
+		let expected = ids(Reader::new(&data[..]).records().map(|r| r.map_err(|e| e.position())).collect());
+		assert!(expected.iter().any(Result::is_err));
+		let options = ParallelOptions { threads: 2, chunk_size: 64, stall_timeout: None };
+		let parallel = par_records(io::Cursor::new(data), &options).map(|r| r.map_err(|e| match e {
+			ParallelError::Parse(e) => e.position(),
+			e => panic!("{:?}", e),
+		}));
+		assert_eq!(ids(parallel.collect()), expected);
+	}
+	
+	/// `input()` after a byte order mark and two blank lines.
+	fn input_with_preamble() -> Vec<u8> {
+		let mut data = b"\xEF\xBB\xBF\n\r\n".to_vec();
+		data.extend(input());
+		data
+	}
+	
+	/// 50 valid records after a blank line.
+	fn valid_after_blank_line() -> Vec<u8> {
+		let mut data = b"\n".to_vec();
+		for i in 0..50 { data.extend(format!("@r{}\nACGT\n+\nIIII\n", i).into_bytes()) }
+		data
+	}
+	
+	#[test]
+	fn par_records_skips_preamble() {
+		let options = ParallelOptions { threads: 2, chunk_size: 64, stall_timeout: None };
+		let records: Vec<_> = par_records(io::Cursor::new(valid_after_blank_line()), &options).collect::<Result<_, _>>().unwrap();
+		assert_eq!(records.len(), 50);
+		assert_eq!(records[49].id(), Some("r49"));
+		
+		let errors: Vec<_> = par_records(io::Cursor::new(input_with_preamble()), &options).filter_map(Result::err).collect();
+		let [ParallelError::Parse(ref e)] = errors[..] else { panic!("{:?}", errors) };
+		let pos = e.position().unwrap();
+		assert_eq!((pos.line, pos.record), (137, 33));
+		assert_eq!(&input_with_preamble()[pos.offset as usize..][..2], b"-\n");
+	}
+	
+	#[test]
+	fn for_each_parallel_skips_preamble() {
+		let options = ParallelOptions { threads: 3, chunk_size: 64, stall_timeout: None };
+		let last = Mutex::new(None);
+		let n = for_each_parallel(&valid_after_blank_line()[..], &options, |r| if r.id() == Some("r49") { *last.lock().unwrap() = r.offset() }).unwrap();
+		assert_eq!(n, 50);
+		assert_eq!(*last.lock().unwrap(), Some(1 + 10 * 16 + 39 * 17));
+		
+		let Err(ParallelError::Parse(e)) = for_each_parallel(&input_with_preamble()[..], &options, |_| {}) else { panic!() };
+		assert_eq!(e.position().map(|pos| (pos.line, pos.record)), Some((137, 33)));
+	}
+	
+	#[test]
+	fn resync_skips_blank_lines() {
+		let data = b"@a\nACGT\n+\nIIII\n@b\nAC\n+\nII\n\n@c\nA\n+\nI\n";
+		let start = next_record_start(&mut io::Cursor::new(&data[..]), 1).unwrap();
+		assert_eq!(start, Some(15));
+		assert_eq!(&data[15..17], b"@b");
+	}
+}
@@ -0,0 +1,54 @@
+//! Path handling shared by everything that opens files.
+//!
+//! On Windows, paths longer than `MAX_PATH` can only be opened without the long path opt-in
+//! if they are absolute and have the `\\?\` prefix. The conversion works on the OS string directly,
+//! so paths that are not valid UTF-8 are fine. On other platforms, paths are left as they are.
+
+use std::path::{Path, PathBuf};
+
+/// Prepare a path for opening a file, prefixing it on Windows if it is too long.
+pub(crate) fn long_path<P: AsRef<Path>>(path: P) -> PathBuf {
+	#[cfg(windows)]
+	{
+		use std::os::windows::ffi::OsStrExt;
+		const MAX_PATH: usize = 260;
+		if path.as_ref().as_os_str().encode_wide().count() >= MAX_PATH { return verbatim(path.as_ref()) }
+	}
+	path.as_ref().to_owned()
+}
+
+/// Prepare a path for a directory that files will be created in, prefixing it on Windows
+/// whatever its length, since the paths of the files could be too long.
+pub(crate) fn long_dir<P: AsRef<Path>>(path: P) -> PathBuf {
+	#[cfg(windows)]
+	{
+		verbatim(path.as_ref())
+	}
+	#[cfg(not(windows))]
+	{
+		path.as_ref().to_owned()
+	}
+}
+
+/// Make a path absolute and add the `\\?\` or `\\?\UNC\` prefix.
+#[cfg(windows)]
+fn verbatim(path: &Path) -> PathBuf {
+	use std::ffi::OsString;
+	use std::os::windows::ffi::{OsStrExt, OsStringExt};
+	
+	let mut prefixed: Vec<u16> = r"\\?\".encode_utf16().collect();
+	if path.as_os_str().encode_wide().take(4).eq(prefixed.iter().copied()) { return path.to_owned() }
+	let absolute = match std::path::absolute(path) {
+		Ok(absolute) => absolute,
+		Err(_) => return path.to_owned(),
+	};
+	let wide: Vec<u16> = absolute.as_os_str().encode_wide().collect();
+	if wide.starts_with(&prefixed) { return absolute }
+	if wide.starts_with(&[b'\\' as u16, b'\\' as u16]) {
+		prefixed.extend(r"UNC\".encode_utf16());
+		prefixed.extend_from_slice(&wide[2..]);
+	} else {
+		prefixed.extend_from_slice(&wide);
+	}
+	PathBuf::from(OsString::from_wide(&prefixed))
+}
@@ -0,0 +1,74 @@
+//! Recycling of owned records between pipeline stages.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Reader, Record, Error};
+
+/// A shared pool of cleared records that can be checked out and returned.
+///
+/// Cloning the pool yields another handle to the same storage,
+/// so records checked out on one thread can be checked back in on another.
+#[derive(Clone)]
+pub struct RecordPool {
+	free: Arc<Mutex<Vec<Record>>>,
+	capacity: usize,
+}
+
+impl RecordPool {
+	/// Create a pool keeping at most `capacity` idle records around.
+	pub fn new(capacity: usize) -> RecordPool {
+		RecordPool { free: Arc::new(Mutex::new(Vec::with_capacity(capacity))), capacity }
+	}
+	
+	/// Take an empty record from the pool, allocating a new one if none is idle.
+	pub fn checkout(&self) -> Record {
+		self.free.lock().unwrap().pop().unwrap_or_else(Record::new)
+	}
+	
+	/// Return a record to the pool. Its buffers are kept, unless the pool is full.
+	pub fn checkin(&self, mut record: Record) {
+		let mut free = self.free.lock().unwrap();
+		if free.len() < self.capacity {
+			record.clear();
+			free.push(record);
+		}
+	}
+	
+	/// Return all records of an iterator to the pool.
+	pub fn checkin_all<I: IntoIterator<Item=Record>>(&self, records: I) {
+		for record in records { self.checkin(record) }
+	}
+	
+	/// Number of idle records in the pool.
+	pub fn len(&self) -> usize { self.free.lock().unwrap().len() }
+	
+	/// Check if no records are idle.
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+/// An iterator over the records of a FastQ file, reusing records from a `RecordPool`.
+pub struct PooledRecords<R: io::Read> {
+	reader: Reader<R>,
+	pool: RecordPool,
+}
+
+impl<R: io::Read> PooledRecords<R> {
+	pub(crate) fn new(reader: Reader<R>, pool: &RecordPool) -> PooledRecords<R> {
+		PooledRecords { reader, pool: pool.clone() }
+	}
+}
+
+impl<R: io::Read> Iterator for PooledRecords<R> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		let mut record = self.pool.checkout();
+		match self.reader.read(&mut record) {
+			Ok(()) if record.is_empty() => { self.pool.checkin(record); None },
+			Ok(()) => Some(Ok(record)),
+			Err(err) => { self.pool.checkin(record); Some(Err(err)) },
+		}
+	}
+}
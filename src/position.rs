@@ -0,0 +1,40 @@
+//! Where in the input a reader is, for locating errors in large files.
+
+use std::fmt;
+
+/// A place in FastQ input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+	/// 1-based line number.
+	pub line: u64,
+	/// 0-based index of the record.
+	pub record: u64,
+	/// Byte offset of the start of the line.
+	pub offset: u64,
+}
+
+impl Position {
+	/// The start of the input.
+	pub fn start() -> Position {
+		Position { line: 1, record: 0, offset: 0 }
+	}
+	
+	/// Move past a line of `len` bytes, including its line break.
+	pub(crate) fn advance_line(&mut self, len: usize) {
+		if len > 0 {
+			self.line += 1;
+			self.offset += len as u64;
+		}
+	}
+}
+
+impl Default for Position {
+	fn default() -> Position { Position::start() }
+}
+
+impl fmt::Display for Position {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {} (record {}, byte {})", self.line, self.record, self.offset)
+	}
+}
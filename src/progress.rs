@@ -0,0 +1,86 @@
+//! Progress reports of readers working through long inputs, see e.g. `unfancy_parser::Reader::with_progress`.
+
+use std::time::{Duration, Instant};
+
+use super::Position;
+
+/// How far a reader got.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+	/// Records parsed.
+	pub records: u64,
+	/// Bytes of FastQ consumed, after decompression.
+	pub bytes: u64,
+	/// How far through its input file the reader is, from 0 to 100, if the file size is known.
+	/// For compressed files, this is measured in compressed bytes.
+	pub percent: Option<f64>,
+	/// Time since progress reporting was set up.
+	pub elapsed: Duration,
+}
+
+impl Progress {
+	/// Records parsed per second so far.
+	pub fn records_per_sec(&self) -> f64 {
+		self.records as f64 / self.elapsed.as_secs_f64().max(1e-9)
+	}
+	
+	/// Bytes consumed per second so far.
+	pub fn bytes_per_sec(&self) -> f64 {
+		self.bytes as f64 / self.elapsed.as_secs_f64().max(1e-9)
+	}
+}
+
+type Callback = Box<dyn FnMut(&Progress) + Send>;
+
+/// A progress callback of a reader over `R`, and what is known about the reader’s input.
+pub(crate) struct ProgressHook<R> {
+	interval: u64,
+	callback: Option<Callback>,
+	/// The input file’s size.
+	size: Option<u64>,
+	/// How to tell the offset in the input file if it differs from the bytes parsed.
+	offset: fn(&R) -> Option<u64>,
+	start: Instant,
+	reported: Option<u64>,
+}
+
+impl<R> ProgressHook<R> {
+	pub(crate) fn new() -> Self {
+		ProgressHook { interval: 1, callback: None, size: None, offset: |_| None, start: Instant::now(), reported: None }
+	}
+	
+	/// Note the size of the input file and how to find the reader’s offset in it.
+	pub(crate) fn sized(mut self, size: Option<u64>, offset: fn(&R) -> Option<u64>) -> Self {
+		self.size = size;
+		self.offset = offset;
+		self
+	}
+	
+	pub(crate) fn set<F>(&mut self, interval: u64, callback: F) where F: FnMut(&Progress) + Send + 'static {
+		self.interval = interval.max(1);
+		self.callback = Some(Box::new(callback));
+		self.start = Instant::now();
+	}
+	
+	/// Report after a record was read if a multiple of the interval was reached.
+	pub(crate) fn record(&mut self, pos: Position, reader: &R) {
+		if self.callback.is_some() && pos.record.is_multiple_of(self.interval) { self.report(pos, reader) }
+	}
+	
+	/// Report at the end of input, unless that was already reported.
+	pub(crate) fn finish(&mut self, pos: Position, reader: &R) {
+		if self.reported != Some(pos.record) { self.report(pos, reader) }
+	}
+	
+	fn report(&mut self, pos: Position, reader: &R) {
+		let percent = self.size.map(|size| {
+			let done = (self.offset)(reader).unwrap_or(pos.offset);
+			if size == 0 { 100. } else { (done as f64 * 100. / size as f64).min(100.) }
+		});
+		let elapsed = self.start.elapsed();
+		if let Some(callback) = &mut self.callback {
+			self.reported = Some(pos.record);
+			callback(&Progress { records: pos.record, bytes: pos.offset, percent, elapsed });
+		}
+	}
+}
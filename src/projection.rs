@@ -0,0 +1,68 @@
+//! Writers emitting only one field of each record, in flat formats.
+
+use std::io::{self, Write};
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::Record;
+
+/// Writes each record’s sequence on its own line.
+pub struct SeqWriter<W: Write> {
+	out: W,
+}
+
+impl<W: Write> SeqWriter<W> {
+	/// Write sequences to `out`.
+	pub fn new(out: W) -> Self { SeqWriter { out } }
+	
+	/// Write the sequence of a record.
+	pub fn write(&mut self, record: &Record) -> io::Result<()> {
+		check(record)?;
+		self.out.write_all(record.seq())?;
+		self.out.write_all(b"\n")
+	}
+	
+	/// Return the underlying writer.
+	pub fn into_inner(self) -> W { self.out }
+}
+
+/// Writes each record’s qualities as a line of numeric scores.
+pub struct QualWriter<W: Write> {
+	out: W,
+	offset: u8,
+	separator: u8,
+}
+
+impl<W: Write> QualWriter<W> {
+	/// Write Phred+33 qualities to `out` as space-separated numbers.
+	pub fn new(out: W) -> Self { QualWriter { out, offset: 33, separator: b' ' } }
+	
+	/// Set the ASCII offset of the input qualities, e.g. 64 for old Illumina data.
+	pub fn offset(mut self, offset: u8) -> Self {
+		self.offset = offset;
+		self
+	}
+	
+	/// Set the byte written between scores, e.g. `b'\t'` or `b','`.
+	pub fn separator(mut self, separator: u8) -> Self {
+		self.separator = separator;
+		self
+	}
+	
+	/// Write the qualities of a record.
+	pub fn write(&mut self, record: &Record) -> io::Result<()> {
+		check(record)?;
+		for (i, &q) in record.qual().iter().enumerate() {
+			if i > 0 { self.out.write_all(&[self.separator])? }
+			write!(self.out, "{}", q as i16 - self.offset as i16)?;
+		}
+		self.out.write_all(b"\n")
+	}
+	
+	/// Return the underlying writer.
+	pub fn into_inner(self) -> W { self.out }
+}
+
+/// Validate records unless they are already known to be valid.
+fn check(record: &Record) -> io::Result<()> {
+	record.ensure_valid().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
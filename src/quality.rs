@@ -0,0 +1,551 @@
+//! Quality score scales and conversions between them.
+//!
+//! Phred scores encode `Q = -10 log10(p)` for an error probability `p`,
+//! the legacy Solexa scale encodes the odds instead: `Q = -10 log10(p / (1 - p))`.
+//! The two agree for high qualities but differ considerably below Q10.
+//!
+//! `Quality` looks Phred error probabilities up in `PHRED_TO_PROBABILITY` instead of computing them per base.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use super::{Position, Record as RecordTrait};
+use super::paths::long_path;
+use super::unfancy_parser::{Record, Error};
+
+/// A quality score scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+	Phred,
+	Solexa,
+}
+
+impl Scale {
+	/// The error probability a score stands for on this scale.
+	pub fn error_probability(self, q: f64) -> f64 {
+		let x = 10f64.powf(-q / 10.);
+		match self {
+			Scale::Phred => x,
+			Scale::Solexa => x / (1. + x),
+		}
+	}
+	
+	/// The score standing for an error probability on this scale.
+	pub fn score(self, p: f64) -> f64 {
+		match self {
+			Scale::Phred => -10. * p.log10(),
+			Scale::Solexa => -10. * (p / (1. - p)).log10(),
+		}
+	}
+}
+
+/// Convert a Solexa score to the nearest Phred score.
+pub fn solexa_to_phred(q: i8) -> u8 {
+	(10. * (10f64.powf(q as f64 / 10.) + 1.).log10()).round() as u8
+}
+
+/// Convert a Phred score to the nearest Solexa score, which cannot go below -5.
+pub fn phred_to_solexa(q: u8) -> i8 {
+	if q == 0 { return -5 }
+	let s = 10. * (10f64.powf(q as f64 / 10.) - 1.).log10();
+	s.round().max(-5.) as i8
+}
+
+/// The lowest and highest Solexa score a printable Solexa+64 character can hold, `;` and `~`.
+const SOLEXA_RANGE: (i16, i16) = (-5, 62);
+
+/// Rewrite a Solexa+64 quality string in place as Phred+33. Scores below -5 count as -5.
+/// Fails with the first byte that is no printable ASCII character, leaving the string unchanged.
+pub fn solexa64_to_phred33(qual: &mut [u8]) -> Result<(), u8> {
+	check_printable(qual)?;
+	for q in qual {
+		let score = (*q as i16 - 64).clamp(SOLEXA_RANGE.0, SOLEXA_RANGE.1);
+		*q = solexa_to_phred(score as i8) + 33;
+	}
+	Ok(())
+}
+
+/// Rewrite a Phred+33 quality string in place as Solexa+64, capping scores at the highest printable one.
+pub fn phred33_to_solexa64(qual: &mut [u8]) {
+	for q in qual {
+		let score = phred_to_solexa(q.saturating_sub(33).min(MAX_PHRED)) as i16;
+		*q = (score.clamp(SOLEXA_RANGE.0, SOLEXA_RANGE.1) + 64) as u8;
+	}
+}
+
+/// Convert a record read from a Solexa+64 file to Phred+33 qualities, see `solexa64_to_phred33`.
+pub fn record_solexa64_to_phred33(record: &mut Record) -> Result<(), u8> {
+	to_phred33(record, Encoding::Solexa)
+}
+
+/// Check that all bytes are printable ASCII characters, failing with the first that is not.
+fn check_printable(qual: &[u8]) -> Result<(), u8> {
+	match qual.iter().find(|&&q| !(b'!'..=b'~').contains(&q)) {
+		Some(&q) => Err(q),
+		None => Ok(()),
+	}
+}
+
+/// Delta-encode a quality string: the first byte relative to zero, each further byte
+/// relative to its predecessor, with differences zigzag-mapped so small changes give small bytes
+/// (0 → 0, -1 → 1, 1 → 2, -2 → 3, …).
+pub fn delta_encode(qual: &[u8]) -> Vec<u8> {
+	let mut prev = 0u8;
+	qual.iter().map(|&q| {
+		let d = q.wrapping_sub(prev) as i8;
+		prev = q;
+		((d << 1) ^ (d >> 7)) as u8
+	}).collect()
+}
+
+/// Decode a quality string encoded by `delta_encode`.
+pub fn delta_decode(deltas: &[u8]) -> Vec<u8> {
+	let mut prev = 0u8;
+	deltas.iter().map(|&z| {
+		let d = (z >> 1) as i8 ^ -((z & 1) as i8);
+		prev = prev.wrapping_add(d as u8);
+		prev
+	}).collect()
+}
+
+/// Run-length encode a quality string as `(score, run length)` pairs,
+/// with scores decoded from the given encoding (Solexa scores below 0 become 0).
+pub fn rle_encode(qual: &[u8], encoding: Encoding) -> Vec<(u8, usize)> {
+	let offset = encoding.offset();
+	let mut runs: Vec<(u8, usize)> = Vec::new();
+	for &q in qual {
+		let score = q.saturating_sub(offset);
+		match runs.last_mut() {
+			Some(run) if run.0 == score => run.1 += 1,
+			_ => runs.push((score, 1)),
+		}
+	}
+	runs
+}
+
+/// Expand `(score, run length)` pairs into a quality string in the given encoding.
+pub fn rle_decode(runs: &[(u8, usize)], encoding: Encoding) -> Vec<u8> {
+	let offset = encoding.offset();
+	let mut qual = Vec::with_capacity(runs.iter().map(|r| r.1).sum());
+	for &(score, len) in runs {
+		qual.extend(std::iter::repeat_n(score + offset, len));
+	}
+	qual
+}
+
+/// Convert a line of whitespace-separated numeric Phred scores, as in some legacy 454 and Sanger files,
+/// to Phred+33 characters. Fails with the first value that is not an integer from 0 to 93.
+pub fn numeric_to_phred33(line: &str) -> Result<String, String> {
+	line.split_ascii_whitespace().map(|value| match value.parse::<u8>() {
+		Ok(q) if q <= b'~' - Encoding::Sanger.offset() => Ok((q + Encoding::Sanger.offset()) as char),
+		_ => Err(value.to_owned()),
+	}).collect()
+}
+
+/// An ASCII encoding of quality scores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Encoding {
+	/// Phred+33, used by Sanger and Illumina 1.8+.
+	Sanger,
+	/// Phred+64, used by Illumina 1.3 to 1.7.
+	Illumina13,
+	/// Solexa+64, used by Solexa and Illumina before 1.3.
+	Solexa,
+}
+
+impl Encoding {
+	/// The ASCII value of score 0.
+	pub fn offset(self) -> u8 {
+		match self { Encoding::Sanger => 33, Encoding::Illumina13 | Encoding::Solexa => 64 }
+	}
+	
+	/// The score scale of the encoding.
+	pub fn scale(self) -> Scale {
+		match self { Encoding::Solexa => Scale::Solexa, _ => Scale::Phred }
+	}
+	
+	/// The error probability a quality character stands for, from `PHRED_TO_PROBABILITY` for Phred scores.
+	/// Characters below score 0 count as score 0.
+	pub fn error_probability(self, c: u8) -> f64 {
+		match self.scale() {
+			Scale::Phred => PHRED_TO_PROBABILITY[c.saturating_sub(self.offset()).min(MAX_PHRED) as usize],
+			Scale::Solexa => Scale::Solexa.error_probability(c as f64 - self.offset() as f64),
+		}
+	}
+}
+
+impl fmt::Display for Encoding {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			Encoding::Sanger => "Phred+33",
+			Encoding::Illumina13 => "Phred+64",
+			Encoding::Solexa => "Solexa+64",
+		})
+	}
+}
+
+/// The highest Phred score a printable Phred+33 character can hold, `~`.
+pub const MAX_PHRED: u8 = 93;
+
+/// The error probability of each Phred score from 0 to `MAX_PHRED`, `10^(-Q/10)`.
+pub const PHRED_TO_PROBABILITY: [f64; MAX_PHRED as usize + 1] = [
+	1.0, 0.7943282347242815, 0.6309573444801932, 0.5011872336272722, 0.3981071705534972, 0.31622776601683794,
+	0.251188643150958, 0.19952623149688797, 0.15848931924611134, 0.12589254117941673, 0.1, 0.07943282347242814,
+	0.06309573444801933, 0.05011872336272722, 0.039810717055349734, 0.03162277660168379, 0.025118864315095794, 0.0199526231496888,
+	0.015848931924611134, 0.012589254117941675, 0.01, 0.007943282347242814, 0.00630957344480193, 0.005011872336272725,
+	0.003981071705534973, 0.0031622776601683794, 0.0025118864315095794, 0.001995262314968879, 0.001584893192461114, 0.0012589254117941675,
+	0.001, 0.0007943282347242813, 0.000630957344480193, 0.0005011872336272725, 0.00039810717055349735, 0.00031622776601683794,
+	0.00025118864315095795, 0.00019952623149688788, 0.00015848931924611142, 0.00012589254117941674, 0.0001, 7.943282347242822e-5,
+	6.309573444801929e-5, 5.011872336272725e-5, 3.9810717055349695e-5, 3.1622776601683795e-5, 2.5118864315095822e-5, 1.9952623149688786e-5,
+	1.584893192461114e-5, 1.2589254117941661e-5, 1e-5, 7.943282347242822e-6, 6.30957344480193e-6, 5.011872336272725e-6,
+	3.981071705534969e-6, 3.162277660168379e-6, 2.5118864315095823e-6, 1.9952623149688787e-6, 1.584893192461114e-6, 1.2589254117941661e-6,
+	1e-6, 7.943282347242822e-7, 6.30957344480193e-7, 5.011872336272725e-7, 3.981071705534969e-7, 3.162277660168379e-7,
+	2.5118864315095823e-7, 1.9952623149688787e-7, 1.584893192461114e-7, 1.2589254117941662e-7, 1e-7, 7.943282347242822e-8,
+	6.30957344480193e-8, 5.011872336272725e-8, 3.981071705534969e-8, 3.162277660168379e-8, 2.511886431509582e-8, 1.9952623149688786e-8,
+	1.5848931924611143e-8, 1.2589254117941661e-8, 1e-8, 7.943282347242822e-9, 6.309573444801943e-9, 5.011872336272715e-9,
+	3.981071705534969e-9, 3.1622776601683795e-9, 2.511886431509582e-9, 1.9952623149688828e-9, 1.584893192461111e-9, 1.2589254117941663e-9,
+	1e-9, 7.943282347242822e-10, 6.309573444801942e-10, 5.011872336272714e-10,
+];
+
+/// The error probabilities halfway, on the score scale, between consecutive Phred scores:
+/// entry `Q` is `10^(-(Q + 0.5)/10)`. A probability rounds to the lowest score whose threshold it exceeds.
+pub const PROBABILITY_TO_PHRED: [f64; MAX_PHRED as usize] = [
+	0.8912509381337456, 0.7079457843841379, 0.5623413251903491, 0.44668359215096315, 0.35481338923357547, 0.28183829312644537,
+	0.22387211385683395, 0.1778279410038923, 0.14125375446227545, 0.11220184543019636, 0.08912509381337455, 0.0707945784384138,
+	0.05623413251903491, 0.0446683592150963, 0.03548133892335755, 0.028183829312644536, 0.0223872113856834, 0.01778279410038923,
+	0.01412537544622754, 0.011220184543019636, 0.008912509381337459, 0.00707945784384138, 0.005623413251903491, 0.0044668359215096305,
+	0.0035481338923357532, 0.002818382931264455, 0.00223872113856834, 0.0017782794100389228, 0.001412537544622754, 0.001122018454301963,
+	0.0008912509381337459, 0.000707945784384138, 0.0005623413251903491, 0.00044668359215096305, 0.0003548133892335753, 0.0002818382931264455,
+	0.000223872113856834, 0.00017782794100389227, 0.0001412537544622754, 0.0001122018454301963, 8.912509381337459e-5, 7.079457843841373e-5,
+	5.623413251903491e-5, 4.466835921509635e-5, 3.5481338923357534e-5, 2.818382931264455e-5, 2.238721138568338e-5, 1.778279410038923e-5,
+	1.4125375446227555e-5, 1.122018454301963e-5, 8.91250938133746e-6, 7.079457843841373e-6, 5.623413251903491e-6, 4.466835921509635e-6,
+	3.548133892335753e-6, 2.818382931264455e-6, 2.2387211385683376e-6, 1.778279410038923e-6, 1.4125375446227554e-6, 1.122018454301963e-6,
+	8.912509381337459e-7, 7.079457843841374e-7, 5.62341325190349e-7, 4.466835921509635e-7, 3.548133892335753e-7, 2.818382931264455e-7,
+	2.2387211385683377e-7, 1.7782794100389227e-7, 1.4125375446227555e-7, 1.122018454301963e-7, 8.912509381337459e-8, 7.079457843841373e-8,
+	5.6234132519034905e-8, 4.4668359215096346e-8, 3.548133892335753e-8, 2.818382931264455e-8, 2.2387211385683378e-8, 1.7782794100389228e-8,
+	1.4125375446227554e-8, 1.122018454301963e-8, 8.912509381337441e-9, 7.0794578438413736e-9, 5.623413251903491e-9, 4.466835921509635e-9,
+	3.5481338923357603e-9, 2.8183829312644493e-9, 2.2387211385683377e-9, 1.7782794100389228e-9, 1.4125375446227555e-9, 1.1220184543019653e-9,
+	8.912509381337441e-10, 7.079457843841373e-10, 5.623413251903491e-10,
+];
+
+/// A Phred quality score from 0 to `MAX_PHRED`, not an ASCII character.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quality(u8);
+
+impl Quality {
+	/// The score, clamped to `MAX_PHRED`.
+	pub fn new(q: u8) -> Quality { Quality(q.min(MAX_PHRED)) }
+	
+	/// The score of a quality character, converting Solexa scores to Phred.
+	/// `None` if the character is below score 0, or below -5 for Solexa.
+	pub fn from_ascii(c: u8, encoding: Encoding) -> Option<Quality> {
+		match encoding.scale() {
+			Scale::Phred => c.checked_sub(encoding.offset()).map(Quality::new),
+			Scale::Solexa => {
+				let s = c as i16 - encoding.offset() as i16;
+				(s >= -5).then(|| Quality::new(solexa_to_phred(s.min(i8::MAX as i16) as i8)))
+			},
+		}
+	}
+	
+	/// The nearest score of an error probability, clamped to 0 and `MAX_PHRED`.
+	pub fn from_probability(p: f64) -> Quality {
+		Quality(PROBABILITY_TO_PHRED.partition_point(|&threshold| p <= threshold) as u8)
+	}
+	
+	/// The score as a number.
+	pub fn score(self) -> u8 { self.0 }
+	
+	/// The quality character in an encoding, converting to Solexa scores if needed.
+	pub fn to_ascii(self, encoding: Encoding) -> u8 {
+		match encoding.scale() {
+			Scale::Phred => (self.0 + encoding.offset()).min(b'~'),
+			Scale::Solexa => (phred_to_solexa(self.0) as i16 + encoding.offset() as i16).min(b'~' as i16) as u8,
+		}
+	}
+	
+	/// The probability that the base call is wrong, looked up in `PHRED_TO_PROBABILITY`.
+	pub fn error_probability(self) -> f64 { PHRED_TO_PROBABILITY[self.0 as usize] }
+	
+	/// The probability that the base call is right.
+	pub fn accuracy(self) -> f64 { 1. - self.error_probability() }
+	
+	/// Add to the score, stopping at `MAX_PHRED`.
+	pub fn saturating_add(self, n: u8) -> Quality { Quality::new(self.0.saturating_add(n)) }
+	
+	/// Subtract from the score, stopping at 0.
+	pub fn saturating_sub(self, n: u8) -> Quality { Quality(self.0.saturating_sub(n)) }
+	
+	/// The score of the mean error probability of some scores, which is lower than their mean score.
+	/// `None` if there are none.
+	pub fn mean<I: IntoIterator<Item=Quality>>(qualities: I) -> Option<Quality> {
+		let (sum, n) = qualities.into_iter().fold((0., 0usize), |(sum, n), q| (sum + q.error_probability(), n + 1));
+		(n > 0).then(|| Quality::from_probability(sum / n as f64))
+	}
+}
+
+impl From<Quality> for u8 {
+	fn from(q: Quality) -> u8 { q.0 }
+}
+
+impl fmt::Display for Quality {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Q{}", self.0)
+	}
+}
+
+/// The expected number of wrong base calls in a quality string, the sum of their error probabilities.
+pub fn expected_errors(qual: &[u8], encoding: Encoding) -> f64 {
+	qual.iter().map(|&c| encoding.error_probability(c)).sum()
+}
+
+/// Narrows down the quality encoding from the range of quality characters seen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EncodingDetector {
+	min: Option<u8>,
+	max: Option<u8>,
+}
+
+impl EncodingDetector {
+	/// Create a detector that has seen no qualities.
+	pub fn new() -> EncodingDetector { EncodingDetector::default() }
+	
+	/// Account for a quality string.
+	pub fn add(&mut self, qual: &[u8]) {
+		if let (Some(&lo), Some(&hi)) = (qual.iter().min(), qual.iter().max()) {
+			self.min = Some(self.min.map_or(lo, |m| m.min(lo)));
+			self.max = Some(self.max.map_or(hi, |m| m.max(hi)));
+		}
+	}
+	
+	/// The most plausible encoding for the qualities seen, if any were seen.
+	///
+	/// Characters below `;` only occur in Phred+33; `;` to `?` are negative Solexa scores.
+	/// Data whose lowest quality is at least `@` is taken as Phred+64,
+	/// which is ambiguous for high-quality Solexa data, but their scores agree there.
+	pub fn encoding(&self) -> Option<Encoding> {
+		match self.min? {
+			0..=58 => Some(Encoding::Sanger),
+			59..=63 => Some(Encoding::Solexa),
+			_ => Some(Encoding::Illumina13),
+		}
+	}
+}
+
+/// Guess the quality encoding of a single record.
+pub fn detect_encoding<R: RecordTrait>(record: &R) -> Option<Encoding> {
+	let mut detector = EncodingDetector::new();
+	detector.add(record.qual());
+	detector.encoding()
+}
+
+/// Guess the quality encoding from the first `sample` records of a stream.
+pub fn detect_encoding_iter<I>(records: I, sample: usize) -> Result<Option<Encoding>, Error> where I: IntoIterator<Item=Result<Record, Error>> {
+	let mut detector = EncodingDetector::new();
+	for record in records.into_iter().take(sample) {
+		detector.add(record?.qual());
+		if detector.encoding() == Some(Encoding::Sanger) { break }
+	}
+	Ok(detector.encoding())
+}
+
+/// Rewrite a quality string in place from the given encoding to Phred+33. Scores below 0 count as 0.
+/// Fails with the first byte that is no printable ASCII character, leaving the string unchanged.
+/// Phred+33 strings are left as they are without checking.
+pub fn qual_to_phred33(qual: &mut [u8], from: Encoding) -> Result<(), u8> {
+	match from {
+		Encoding::Sanger => Ok(()),
+		Encoding::Illumina13 => {
+			check_printable(qual)?;
+			for q in qual { *q = q.saturating_sub(31).max(b'!') }
+			Ok(())
+		},
+		Encoding::Solexa => solexa64_to_phred33(qual),
+	}
+}
+
+/// Rewrite a record’s qualities from the given encoding to Phred+33, see `qual_to_phred33`.
+pub fn to_phred33(record: &mut Record, from: Encoding) -> Result<(), u8> {
+	let mut result = Ok(());
+	if from != Encoding::Sanger { record.map_qual(|qual| result = qual_to_phred33(qual, from)) }
+	result
+}
+
+/// An iterator adapter rewriting all qualities of a stream to Phred+33.
+pub struct ToPhred33<I> {
+	records: I,
+	from: Encoding,
+	index: u64,
+}
+
+impl<I> Iterator for ToPhred33<I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		let mut record = self.records.next()?;
+		if let Ok(ref mut r) = record {
+			if let Err(q) = to_phred33(r, self.from) {
+				// the line assumes four-line records
+				let pos = Position { line: 4 * self.index + 4, record: self.index, offset: r.offset().unwrap_or(0) };
+				record = Err(Error::InvalidQuality(pos, std::ascii::escape_default(q).to_string()));
+			}
+		}
+		self.index += 1;
+		Some(record)
+	}
+}
+
+/// Normalize the qualities of a record stream from the given encoding to Phred+33.
+pub fn normalize_phred33<I>(records: I, from: Encoding) -> ToPhred33<I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	ToPhred33 { records: records.into_iter(), from, index: 0 }
+}
+
+/// Per-base quality recalibration, e.g. from an empirically derived table.
+///
+/// Works on Phred scores, not ASCII characters. Closures `Fn(pos, base, q) -> q` are recalibrators too.
+pub trait Recalibrator {
+	/// The new quality for a base at the 0-based read position `pos` that had quality `q`.
+	fn recalibrate(&self, pos: usize, base: u8, q: u8) -> u8;
+}
+
+impl<F: Fn(usize, u8, u8) -> u8> Recalibrator for F {
+	fn recalibrate(&self, pos: usize, base: u8, q: u8) -> u8 { self(pos, base, q) }
+}
+
+/// A recalibration table mapping reported to empirical qualities.
+///
+/// The text form has one mapping per line: position (0-based), base, reported and new quality,
+/// separated by tabs. Position and base can be `*` to match any. The most specific entry wins,
+/// with an exact position taking precedence over an exact base. Qualities without entry are kept.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecalibrationTable {
+	entries: HashMap<(Option<usize>, Option<u8>, u8), u8>,
+}
+
+impl RecalibrationTable {
+	/// Create an empty table, which keeps all qualities.
+	pub fn new() -> RecalibrationTable { RecalibrationTable::default() }
+	
+	/// Read a table from its text form.
+	pub fn from_reader<R: BufRead>(reader: R) -> io::Result<RecalibrationTable> {
+		let mut table = RecalibrationTable::new();
+		for (i, line) in reader.lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() || line.starts_with('#') { continue }
+			let bad_line = || io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: expected <pos>\\t<base>\\t<q>\\t<new q>", i + 1));
+			let fields: Vec<&str> = line.trim_end().split('\t').collect();
+			if fields.len() != 4 { return Err(bad_line()) }
+			let pos = match fields[0] { "*" => None, p => Some(p.parse().map_err(|_| bad_line())?) };
+			let base = match fields[1].as_bytes() { b"*" => None, &[b] => Some(b.to_ascii_uppercase()), _ => return Err(bad_line()) };
+			let q = fields[2].parse().map_err(|_| bad_line())?;
+			let new_q = fields[3].parse().map_err(|_| bad_line())?;
+			table.insert(pos, base, q, new_q);
+		}
+		Ok(table)
+	}
+	
+	/// Read a table from a file.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<RecalibrationTable> {
+		RecalibrationTable::from_reader(io::BufReader::new(fs::File::open(long_path(path))?))
+	}
+	
+	/// Map quality `q` to `new_q`, at a position and for a base or for any if `None`.
+	pub fn insert(&mut self, pos: Option<usize>, base: Option<u8>, q: u8, new_q: u8) {
+		self.entries.insert((pos, base.map(|b| b.to_ascii_uppercase()), q), new_q);
+	}
+	
+	/// Number of mappings in the table.
+	pub fn len(&self) -> usize { self.entries.len() }
+	
+	/// Check if the table has no mappings.
+	pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+}
+
+impl Recalibrator for RecalibrationTable {
+	fn recalibrate(&self, pos: usize, base: u8, q: u8) -> u8 {
+		let base = base.to_ascii_uppercase();
+		[(Some(pos), Some(base)), (Some(pos), None), (None, Some(base)), (None, None)].iter()
+			.find_map(|&(p, b)| self.entries.get(&(p, b, q)).copied())
+			.unwrap_or(q)
+	}
+}
+
+/// Recalibrate the Phred+33 qualities of a record.
+pub fn recalibrate_record<C: Recalibrator + ?Sized>(record: &mut Record, recalibrator: &C) {
+	record.map_qual_with_seq(|seq, qual| {
+		for (pos, (q, &base)) in qual.iter_mut().zip(seq).enumerate() {
+			let new_q = recalibrator.recalibrate(pos, base, q.saturating_sub(33));
+			*q = new_q.min(93) + 33;
+		}
+	})
+}
+
+/// An iterator adapter recalibrating the qualities of a stream, see `recalibrate`.
+pub struct Recalibrated<I, C> {
+	records: I,
+	recalibrator: C,
+}
+
+impl<I, C> Iterator for Recalibrated<I, C> where I: Iterator<Item=Result<Record, Error>>, C: Recalibrator {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		let mut record = self.records.next()?;
+		if let Ok(ref mut record) = record { recalibrate_record(record, &self.recalibrator) }
+		Some(record)
+	}
+}
+
+/// Recalibrate the Phred+33 qualities of a record stream.
+pub fn recalibrate<I, C>(records: I, recalibrator: C) -> Recalibrated<I::IntoIter, C> where I: IntoIterator<Item=Result<Record, Error>>, C: Recalibrator {
+	Recalibrated { records: records.into_iter(), recalibrator }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::Reader;
+	
+	#[test]
+	fn solexa_scores_are_clamped() {
+		let mut qual = *b"!;@h~";
+		solexa64_to_phred33(&mut qual).unwrap();
+		assert_eq!(qual, [b'!' + 1, b'!' + 1, b'!' + 3, b'!' + 40, b'!' + 62]);
+	}
+	
+	#[test]
+	fn invalid_bytes_fail_without_changes() {
+		for encoding in [Encoding::Solexa, Encoding::Illumina13] {
+			let mut qual = [b'h', 0xc3, 0xa9, 0];
+			assert_eq!(qual_to_phred33(&mut qual, encoding), Err(0xc3));
+			assert_eq!(qual, [b'h', 0xc3, 0xa9, 0]);
+		}
+	}
+	
+	#[test]
+	fn phred_to_solexa_stays_printable() {
+		let mut qual = [b'!', b'I', b'~', 0xff];
+		phred33_to_solexa64(&mut qual);
+		assert_eq!(qual, [b';', b'h', b'~', b'~']);
+	}
+	
+	#[test]
+	fn normalizing_reports_invalid_qualities() {
+		let records = Reader::new(&b"@r1\nAC\n+\nhh\n@r2\nAC\n+\nh\xc3\xa9\n"[..]).records();
+		let mut normalized = normalize_phred33(records, Encoding::Illumina13);
+		assert_eq!(normalized.next().unwrap().unwrap().qual(), b"II");
+		match normalized.next() {
+			Some(Err(Error::InvalidQuality(pos, value))) => {
+				assert_eq!(pos.record, 1);
+				assert_eq!(value, "\\xc3");
+			},
+			other => panic!("expected an invalid quality, got {:?}", other),
+		}
+	}
+}
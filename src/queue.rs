@@ -0,0 +1,175 @@
+//! A persistent, disk-backed record queue.
+//!
+//! A `QueueWriter` appends records to segment files in a directory.
+//! Full segments are sealed and become visible to `QueueReader`s,
+//! which may live in other processes: each sealed segment is claimed by exactly one reader
+//! through an atomic rename, drained, and deleted once acknowledged.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::input::Input;
+use super::unfancy_parser::{Reader, Record, Records};
+use super::paths::long_dir;
+
+const SEALED: &str = "seg";
+const OPEN: &str = "seg.open";
+const CLAIMED: &str = "seg.claimed-";
+
+fn segment_number(path: &Path) -> Option<u64> {
+	path.file_name()?.to_str()?.split('.').next()?.parse().ok()
+}
+
+fn segment_path(dir: &Path, number: u64, suffix: &str) -> PathBuf {
+	dir.join(format!("{:016}.{}", number, suffix))
+}
+
+/// Appends records to a queue directory.
+pub struct QueueWriter {
+	dir: PathBuf,
+	segment_bytes: u64,
+	next: u64,
+	current: Option<(u64, io::BufWriter<fs::File>, u64)>,
+}
+
+impl QueueWriter {
+	/// Open a queue directory for appending, creating it if needed.
+	/// Segments are sealed once they hold at least `segment_bytes` bytes.
+	pub fn open<P: AsRef<Path>>(dir: P, segment_bytes: u64) -> io::Result<QueueWriter> {
+		let dir = long_dir(dir);
+		fs::create_dir_all(&dir)?;
+		let mut next = 0;
+		for entry in fs::read_dir(&dir)? {
+			if let Some(n) = segment_number(&entry?.path()) { next = next.max(n + 1) }
+		}
+		Ok(QueueWriter { dir, segment_bytes, next, current: None })
+	}
+	
+	/// Append a record, sealing the current segment if it is full.
+	pub fn push(&mut self, record: &Record) -> io::Result<()> {
+		if self.current.is_none() {
+			let path = segment_path(&self.dir, self.next, OPEN);
+			self.current = Some((self.next, io::BufWriter::new(fs::File::create(path)?), 0));
+			self.next += 1;
+		}
+		let full = {
+			let (_, ref mut out, ref mut bytes) = *self.current.as_mut().unwrap();
+			*bytes += record.write_raw(out)? as u64;
+			*bytes >= self.segment_bytes
+		};
+		if full { self.seal()?; }
+		Ok(())
+	}
+	
+	/// Seal the current segment, making its records visible to readers.
+	pub fn seal(&mut self) -> io::Result<()> {
+		if let Some((number, mut out, _)) = self.current.take() {
+			out.flush()?;
+			fs::rename(segment_path(&self.dir, number, OPEN), segment_path(&self.dir, number, SEALED))?;
+		}
+		Ok(())
+	}
+}
+
+impl Drop for QueueWriter {
+	fn drop(&mut self) {
+		let _ = self.seal();
+	}
+}
+
+/// Claims and drains sealed segments of a queue directory.
+pub struct QueueReader {
+	dir: PathBuf,
+	name: String,
+}
+
+impl QueueReader {
+	/// Open a queue directory for consuming. `name` identifies this consumer in claimed segment names,
+	/// so it may only consist of ASCII letters, digits, `_` and `-`.
+	pub fn open<P: AsRef<Path>>(dir: P, name: &str) -> io::Result<QueueReader> {
+		if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid queue consumer name {:?}", name)));
+		}
+		let dir = long_dir(dir);
+		fs::create_dir_all(&dir)?;
+		Ok(QueueReader { dir, name: name.to_owned() })
+	}
+	
+	/// Claim the oldest sealed segment, or return `None` if none is available.
+	pub fn claim(&self) -> io::Result<Option<Segment>> {
+		let mut sealed: Vec<u64> = fs::read_dir(&self.dir)?
+			.filter_map(|e| e.ok())
+			.map(|e| e.path())
+			.filter(|p| p.extension().is_some_and(|e| e == SEALED))
+			.filter_map(|p| segment_number(&p))
+			.collect();
+		sealed.sort_unstable();
+		for number in sealed {
+			let claimed = segment_path(&self.dir, number, &format!("{}{}", CLAIMED, self.name));
+			match fs::rename(segment_path(&self.dir, number, SEALED), &claimed) {
+				Ok(()) => return Ok(Some(Segment { dir: self.dir.clone(), number, path: claimed })),
+				Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(None)
+	}
+	
+	/// Release segments this consumer claimed but never acknowledged, e.g. after a crash.
+	pub fn recover(&self) -> io::Result<usize> {
+		let suffix = format!("{}{}", CLAIMED, self.name);
+		let mut n = 0;
+		for entry in fs::read_dir(&self.dir)? {
+			let path = entry?.path();
+			let ours = path.file_name().and_then(|f| f.to_str()).is_some_and(|f| f.ends_with(&suffix));
+			if let (true, Some(number)) = (ours, segment_number(&path)) {
+				fs::rename(&path, segment_path(&self.dir, number, SEALED))?;
+				n += 1;
+			}
+		}
+		Ok(n)
+	}
+}
+
+/// A segment claimed by a `QueueReader`.
+pub struct Segment {
+	dir: PathBuf,
+	number: u64,
+	path: PathBuf,
+}
+
+impl Segment {
+	/// The position of this segment in the queue.
+	pub fn number(&self) -> u64 { self.number }
+	
+	/// Iterate over the records of the segment.
+	pub fn records(&self) -> io::Result<Records<Input>> {
+		Reader::from_file(&self.path).map(Reader::records)
+	}
+	
+	/// Mark the segment as consumed, deleting it.
+	pub fn ack(self) -> io::Result<()> {
+		fs::remove_file(&self.path)
+	}
+	
+	/// Give the segment back to the queue for another consumer.
+	pub fn release(self) -> io::Result<()> {
+		fs::rename(&self.path, segment_path(&self.dir, self.number, SEALED))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn rejects_consumer_names_with_separators() {
+		let dir = std::env::temp_dir().join(format!("fastq-queue-test-{}", std::process::id()));
+		for name in ["", "a.seg", "a/b", "..", "a b"] {
+			assert_eq!(QueueReader::open(&dir, name).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput), "{:?}", name);
+		}
+		assert!(QueueReader::open(&dir, "worker_1-a").is_ok());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
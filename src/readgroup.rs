@@ -0,0 +1,148 @@
+//! Inference of SAM read-group fields from FastQ headers.
+//!
+//! Understands Casava 1.8+ (`@instrument:run:flowcell:lane:tile:x:y`),
+//! older Illumina (`@instrument:lane:tile:x:y#index/read`)
+//! and Oxford Nanopore (`runid=… flow_cell_id=… start_time=…`) headers.
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Record, Error};
+use super::json::json_string;
+
+/// Read-group fields proposed from a sample of headers.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReadGroup {
+	/// Read group identifier, `flowcell.lane` where known.
+	pub id: String,
+	/// Sequencing platform as used in SAM’s `PL` tag.
+	pub platform: Option<String>,
+	pub instrument: Option<String>,
+	pub run: Option<String>,
+	pub flowcell: Option<String>,
+	pub lane: Option<String>,
+	/// Run start date, if the headers carry one.
+	pub date: Option<String>,
+	/// Number of sampled reads belonging to this group.
+	pub reads: usize,
+}
+
+impl ReadGroup {
+	/// Platform unit, `flowcell.lane` or whatever part of it is known.
+	pub fn platform_unit(&self) -> Option<String> {
+		match (&self.flowcell, &self.lane) {
+			(Some(f), Some(l)) => Some(format!("{}.{}", f, l)),
+			(Some(f), None) => Some(f.clone()),
+			(None, Some(l)) => self.instrument.as_ref().map(|i| format!("{}.{}", i, l)),
+			(None, None) => None,
+		}
+	}
+	
+	/// Render as a SAM `@RG` header line (without newline).
+	pub fn to_sam_header(&self) -> String {
+		let mut line = format!("@RG\tID:{}", self.id);
+		if let Some(ref pl) = self.platform { line.push_str(&format!("\tPL:{}", pl)) }
+		if let Some(pu) = self.platform_unit() { line.push_str(&format!("\tPU:{}", pu)) }
+		if let Some(ref pm) = self.instrument { line.push_str(&format!("\tPM:{}", pm)) }
+		if let Some(ref dt) = self.date { line.push_str(&format!("\tDT:{}", dt)) }
+		line
+	}
+	
+	/// Render as a JSON object.
+	pub fn to_json(&self) -> String {
+		let fields = [
+			("platform", &self.platform), ("instrument", &self.instrument), ("run", &self.run),
+			("flowcell", &self.flowcell), ("lane", &self.lane), ("date", &self.date),
+		];
+		let mut json = format!("{{\"id\":{}", json_string(&self.id));
+		for &(name, value) in &fields {
+			match *value {
+				Some(ref v) => json.push_str(&format!(",\"{}\":{}", name, json_string(v))),
+				None => json.push_str(&format!(",\"{}\":null", name)),
+			}
+		}
+		json.push_str(&format!(",\"reads\":{}}}", self.reads));
+		json
+	}
+	
+	fn same_unit(&self, other: &ReadGroup) -> bool {
+		self.instrument == other.instrument && self.run == other.run && self.flowcell == other.flowcell && self.lane == other.lane
+	}
+}
+
+/// Collects read groups from headers fed to it.
+#[derive(Debug, Default)]
+pub struct ReadGroupInferrer {
+	groups: Vec<ReadGroup>,
+}
+
+impl ReadGroupInferrer {
+	/// Create an inferrer that has seen no headers.
+	pub fn new() -> ReadGroupInferrer { ReadGroupInferrer::default() }
+	
+	/// Account for a record’s header.
+	pub fn add(&mut self, record: &Record) {
+		if let Some(id) = record.id() { self.add_header(id, record.desc()) }
+	}
+	
+	/// Account for a header given as id and optional description.
+	pub fn add_header(&mut self, id: &str, desc: Option<&str>) {
+		let group = parse_header(id, desc);
+		match self.groups.iter_mut().find(|g| g.same_unit(&group)) {
+			Some(existing) => {
+				existing.reads += 1;
+				if existing.date.is_none() { existing.date = group.date }
+			},
+			None => self.groups.push(ReadGroup { reads: 1, ..group }),
+		}
+	}
+	
+	/// The read groups seen so far, in order of first appearance.
+	pub fn read_groups(&self) -> &[ReadGroup] { &self.groups }
+	
+	/// Consume the inferrer, returning the read groups.
+	pub fn into_read_groups(self) -> Vec<ReadGroup> { self.groups }
+}
+
+/// Propose read groups from the first `sample` records of a record iterator.
+pub fn infer_read_groups<I>(records: I, sample: usize) -> Result<Vec<ReadGroup>, Error> where I: IntoIterator<Item=Result<Record, Error>> {
+	let mut inferrer = ReadGroupInferrer::new();
+	for record in records.into_iter().take(sample) {
+		inferrer.add(&record?);
+	}
+	Ok(inferrer.into_read_groups())
+}
+
+fn parse_header(id: &str, desc: Option<&str>) -> ReadGroup {
+	let mut group = ReadGroup::default();
+	let tags: Vec<(&str, &str)> = desc.unwrap_or("").split_whitespace().filter_map(|t| t.split_once('=')).collect();
+	if tags.iter().any(|&(k, _)| k == "runid" || k == "flow_cell_id" || k == "ch") {
+		group.platform = Some("ONT".to_owned());
+		for &(k, v) in &tags {
+			match k {
+				"runid" => group.run = Some(v.to_owned()),
+				"flow_cell_id" => group.flowcell = Some(v.to_owned()),
+				"start_time" => group.date = Some(v.to_owned()),
+				_ => {},
+			}
+		}
+	} else {
+		let id = id.split(['#', '/']).next().unwrap_or(id);
+		let fields: Vec<&str> = id.split(':').collect();
+		match fields.len() {
+			7 => {
+				group.platform = Some("ILLUMINA".to_owned());
+				group.instrument = Some(fields[0].to_owned());
+				group.run = Some(fields[1].to_owned());
+				group.flowcell = Some(fields[2].to_owned());
+				group.lane = Some(fields[3].to_owned());
+			},
+			5 => {
+				group.platform = Some("ILLUMINA".to_owned());
+				group.instrument = Some(fields[0].to_owned());
+				group.lane = Some(fields[1].to_owned());
+			},
+			_ => {},
+		}
+	}
+	group.id = group.platform_unit().or_else(|| group.run.clone()).unwrap_or_else(|| "1".to_owned());
+	group
+}
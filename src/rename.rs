@@ -0,0 +1,132 @@
+//! Renaming reads the way the Sequence Read Archive does.
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Record, Error};
+use super::paired::PairError;
+
+/// Assigns SRA-style names `<accession>.<n> <original header>`, numbering reads from 1.
+///
+/// Both mates of a pair get the same number, as in files dumped by `fasterq-dump`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SraRenamer {
+	accession: String,
+	next: u64,
+	keep_original: bool,
+	append_length: bool,
+}
+
+impl SraRenamer {
+	/// Create a renamer for the given accession, e.g. `SRR001666`.
+	pub fn new<S: Into<String>>(accession: S) -> SraRenamer {
+		SraRenamer { accession: accession.into(), next: 1, keep_original: true, append_length: false }
+	}
+	
+	/// Start numbering at `n` instead of 1, e.g. to continue a previous run.
+	pub fn start_at(mut self, n: u64) -> Self {
+		self.next = n;
+		self
+	}
+	
+	/// Keep the original header as description (the default).
+	pub fn keep_original(mut self, yes: bool) -> Self {
+		self.keep_original = yes;
+		self
+	}
+	
+	/// Append `length=<n>` to the description, like `fastq-dump` does.
+	pub fn append_length(mut self, yes: bool) -> Self {
+		self.append_length = yes;
+		self
+	}
+	
+	/// The number the next read will get.
+	pub fn next_number(&self) -> u64 { self.next }
+	
+	/// Rename a single-end read.
+	pub fn rename(&mut self, record: &mut Record) {
+		let n = self.take_number();
+		self.apply(n, record);
+	}
+	
+	/// Rename both mates of a pair with the same number.
+	pub fn rename_pair(&mut self, r1: &mut Record, r2: &mut Record) {
+		let n = self.take_number();
+		self.apply(n, r1);
+		self.apply(n, r2);
+	}
+	
+	fn take_number(&mut self) -> u64 {
+		self.next += 1;
+		self.next - 1
+	}
+	
+	fn apply(&self, n: u64, record: &mut Record) {
+		let mut header = format!("{}.{}", self.accession, n);
+		if self.keep_original {
+			header.push(' ');
+			header.push_str(&record.header());
+		}
+		if self.append_length {
+			header.push_str(&format!(" length={}", record.seq_len()));
+		}
+		record.set_header(&header);
+	}
+}
+
+/// An iterator adapter renaming records, see `sra_rename`.
+pub struct SraRename<I> {
+	records: I,
+	renamer: SraRenamer,
+}
+
+impl<I> SraRename<I> {
+	/// The renamer, e.g. to continue numbering in another file.
+	pub fn renamer(&self) -> &SraRenamer { &self.renamer }
+}
+
+impl<I> Iterator for SraRename<I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		let mut record = match self.records.next()? {
+			Ok(record) => record,
+			Err(e) => return Some(Err(e)),
+		};
+		self.renamer.rename(&mut record);
+		Some(Ok(record))
+	}
+}
+
+/// Rename each record SRA-style.
+pub fn sra_rename<I>(records: I, renamer: SraRenamer) -> SraRename<I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	SraRename { records: records.into_iter(), renamer }
+}
+
+/// An iterator adapter renaming read pairs, see `sra_rename_pairs`.
+pub struct SraRenamePairs<I> {
+	pairs: I,
+	renamer: SraRenamer,
+}
+
+impl<I> SraRenamePairs<I> {
+	/// The renamer, e.g. to continue numbering in another file pair.
+	pub fn renamer(&self) -> &SraRenamer { &self.renamer }
+}
+
+impl<I> Iterator for SraRenamePairs<I> where I: Iterator<Item=Result<(Record, Record), PairError>> {
+	type Item = Result<(Record, Record), PairError>;
+	
+	fn next(&mut self) -> Option<Result<(Record, Record), PairError>> {
+		let (mut r1, mut r2) = match self.pairs.next()? {
+			Ok(pair) => pair,
+			Err(e) => return Some(Err(e)),
+		};
+		self.renamer.rename_pair(&mut r1, &mut r2);
+		Some(Ok((r1, r2)))
+	}
+}
+
+/// Rename read pairs, e.g. from a `PairedReader`, giving both mates the same number.
+pub fn sra_rename_pairs<I>(pairs: I, renamer: SraRenamer) -> SraRenamePairs<I::IntoIter> where I: IntoIterator<Item=Result<(Record, Record), PairError>> {
+	SraRenamePairs { pairs: pairs.into_iter(), renamer }
+}
@@ -0,0 +1,51 @@
+//! A small seeded random number generator.
+//!
+//! Seeded operations have to give the same results across releases,
+//! so this does not depend on an external generator whose streams may change.
+
+/// SplitMix64, statistically sound for sampling and shuffling.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+	pub(crate) fn new(seed: u64) -> Rng { Rng(seed) }
+	
+	pub(crate) fn next_u64(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^ (z >> 31)
+	}
+	
+	/// A uniformly distributed number in `0..n`.
+	pub(crate) fn below(&mut self, n: u64) -> u64 {
+		((self.next_u64() as u128 * n as u128) >> 64) as u64
+	}
+	
+	/// A uniformly distributed number in `[0, 1)`.
+	pub(crate) fn unit(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+	
+	/// Shuffle a slice uniformly (Fisher–Yates).
+	#[cfg(feature = "pipeline")]
+	pub(crate) fn shuffle<T>(&mut self, items: &mut [T]) {
+		for i in (1..items.len()).rev() {
+			items.swap(i, self.below(i as u64 + 1) as usize);
+		}
+	}
+}
+
+/// FNV-1a, finalized with the SplitMix64 mixer so that all bits are usable.
+#[cfg(feature = "pipeline")]
+pub(crate) fn hash64(data: &[u8], seed: u64) -> u64 {
+	let mut h = 0xcbf2_9ce4_8422_2325 ^ seed;
+	for &b in data {
+		h ^= b as u64;
+		h = h.wrapping_mul(0x0100_0000_01b3);
+	}
+	h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	h ^ (h >> 31)
+}
@@ -0,0 +1,203 @@
+//! Checking that parsing and writing FastQ reproduces the input.
+//!
+//! Each record is written back with given `WriterOptions` and compared to the bytes it was parsed from,
+//! so normalizations the reader or writer apply silently show up as classified changes.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use super::{ReaderOptions, Record as RecordTrait};
+use super::unfancy_parser::{self, Reader, Record, Error, WriterOptions};
+
+/// How many changed records a `RoundtripReport` lists.
+const MAX_REPORTED: usize = 100;
+
+/// A way in which a written record differs from its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Change {
+	/// A line ending changed, e.g. `\r\n` to `\n` or a missing final line break added.
+	LineEnding,
+	/// The header after `+` was dropped or added.
+	SeparatorHeader,
+	/// Whitespace was removed or collapsed.
+	Whitespace,
+	/// Sequence or qualities wrapped over several lines were joined. Other changes of the record are not classified.
+	Unwrapped,
+	/// The record is invalid and could not be written.
+	Invalid,
+	/// Any other change.
+	Other,
+}
+
+impl fmt::Display for Change {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			Change::LineEnding => "line ending",
+			Change::SeparatorHeader => "header after +",
+			Change::Whitespace => "whitespace",
+			Change::Unwrapped => "line wrapping",
+			Change::Invalid => "invalid",
+			Change::Other => "other",
+		})
+	}
+}
+
+/// A record that was not written back as it was read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChangedRecord {
+	/// The record number, counting from 0.
+	pub index: usize,
+	/// The byte offset of the record in the input.
+	pub offset: u64,
+	pub changes: Vec<Change>,
+}
+
+/// Summary of a round trip. Each count is of records with that change.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoundtripReport {
+	pub records: usize,
+	/// Records written back byte for byte.
+	pub identical: usize,
+	pub line_ending: usize,
+	pub separator_header: usize,
+	pub whitespace: usize,
+	pub unwrapped: usize,
+	pub invalid: usize,
+	pub other: usize,
+	/// The first 100 changed records.
+	pub changed: Vec<ChangedRecord>,
+}
+
+impl RoundtripReport {
+	/// Check if every record was written back byte for byte.
+	pub fn is_exact(&self) -> bool { self.identical == self.records }
+	
+	fn add(&mut self, index: usize, offset: u64, changes: Vec<Change>) {
+		self.records += 1;
+		if changes.is_empty() {
+			self.identical += 1;
+			return;
+		}
+		for change in &changes {
+			*match *change {
+				Change::LineEnding => &mut self.line_ending,
+				Change::SeparatorHeader => &mut self.separator_header,
+				Change::Whitespace => &mut self.whitespace,
+				Change::Unwrapped => &mut self.unwrapped,
+				Change::Invalid => &mut self.invalid,
+				Change::Other => &mut self.other,
+			} += 1;
+		}
+		if self.changed.len() < MAX_REPORTED { self.changed.push(ChangedRecord { index, offset, changes }) }
+	}
+}
+
+impl fmt::Display for RoundtripReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "records:           {}", self.records)?;
+		writeln!(f, "identical:         {}", self.identical)?;
+		writeln!(f, "line ending:       {}", self.line_ending)?;
+		writeln!(f, "header after +:    {}", self.separator_header)?;
+		writeln!(f, "whitespace:        {}", self.whitespace)?;
+		writeln!(f, "line wrapping:     {}", self.unwrapped)?;
+		writeln!(f, "invalid:           {}", self.invalid)?;
+		writeln!(f, "other:             {}", self.other)?;
+		for record in &self.changed {
+			let changes: Vec<_> = record.changes.iter().map(Change::to_string).collect();
+			writeln!(f, "  record {} at byte {}: {}", record.index, record.offset, changes.join(", "))?;
+		}
+		Ok(())
+	}
+}
+
+/// Passes reads through, keeping a copy of the bytes read until they are taken.
+struct Recording<R> {
+	inner: R,
+	seen: Rc<RefCell<Vec<u8>>>,
+}
+
+impl<R: Read> Read for Recording<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.seen.borrow_mut().extend_from_slice(&buf[..n]);
+		Ok(n)
+	}
+}
+
+/// Split a line into its text and line break.
+fn split_eol(line: &[u8]) -> (&[u8], &[u8]) {
+	let text = line.strip_suffix(b"\n").map_or(line, |l| l.strip_suffix(b"\r").unwrap_or(l));
+	line.split_at(text.len())
+}
+
+fn without_whitespace(text: &[u8]) -> Vec<u8> {
+	text.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect()
+}
+
+/// Compare a record’s input with its output line by line.
+fn classify(original: &[u8], written: &[u8]) -> Vec<Change> {
+	let mut changes = Vec::new();
+	if original == written { return changes }
+	let lines_a: Vec<_> = original.split_inclusive(|&b| b == b'\n').collect();
+	let lines_b: Vec<_> = written.split_inclusive(|&b| b == b'\n').collect();
+	if lines_a.len() != lines_b.len() {
+		changes.push(Change::Unwrapped);
+		return changes;
+	}
+	for (i, (a, b)) in lines_a.into_iter().zip(lines_b).enumerate() {
+		let ((text_a, eol_a), (text_b, eol_b)) = (split_eol(a), split_eol(b));
+		let mut found = Vec::new();
+		if eol_a != eol_b { found.push(Change::LineEnding) }
+		if text_a != text_b {
+			found.push(if i == 2 {
+				Change::SeparatorHeader
+			} else if without_whitespace(text_a) == without_whitespace(text_b) {
+				Change::Whitespace
+			} else {
+				Change::Other
+			});
+		}
+		for change in found {
+			if !changes.contains(&change) { changes.push(change) }
+		}
+	}
+	changes
+}
+
+/// Parse FastQ, write each record back with `options` and compare the result to the input.
+pub fn verify_roundtrip<R: Read>(reader: R, options: &WriterOptions) -> Result<RoundtripReport, Error> {
+	verify_roundtrip_with(reader, ReaderOptions::default(), options)
+}
+
+/// Like `verify_roundtrip`, parsing as configured by `reader_options`.
+pub fn verify_roundtrip_with<R: Read>(reader: R, reader_options: ReaderOptions, options: &WriterOptions) -> Result<RoundtripReport, Error> {
+	let seen = Rc::new(RefCell::new(Vec::new()));
+	let mut reader = Reader::with_options(Recording { inner: reader, seen: seen.clone() }, reader_options);
+	let mut report = RoundtripReport::default();
+	let (mut record, mut written) = (Record::new(), Vec::new());
+	// the offset of the first byte in `seen`
+	let mut start = 0;
+	loop {
+		reader.read(&mut record)?;
+		if record.is_empty() { break }
+		let end = reader.position().offset;
+		let mut seen = seen.borrow_mut();
+		let original: Vec<u8> = seen.drain(..(end - start) as usize).collect();
+		let changes = match record.check() {
+			Ok(()) => {
+				written.clear();
+				unfancy_parser::write_parts(&mut written, options, record.id().unwrap_or(""), record.desc(), record.seq(), record.qual())?;
+				classify(&original, &written)
+			},
+			Err(_) => vec![Change::Invalid],
+		};
+		report.add(report.records, start, changes);
+		start = end;
+	}
+	Ok(report)
+}
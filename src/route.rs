@@ -0,0 +1,78 @@
+//! Routing reads into separate outputs by length.
+
+use std::io::Write;
+
+use super::Record as RecordTrait;
+use super::unfancy_parser::{Record, Error, Writer};
+
+/// The output a read is routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LengthBin {
+	Short,
+	Middle,
+	Long,
+}
+
+/// Sorts reads into short and long ones, optionally with a middle bin in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthRouter {
+	short_below: usize,
+	long_from: usize,
+}
+
+impl LengthRouter {
+	/// Reads shorter than `threshold` are short, all others long.
+	pub fn new(threshold: usize) -> LengthRouter {
+		LengthRouter { short_below: threshold, long_from: threshold }
+	}
+	
+	/// Reads shorter than `short_below` are short, reads of at least `long_from` long,
+	/// and those in between go to the middle bin.
+	pub fn with_middle(short_below: usize, long_from: usize) -> LengthRouter {
+		LengthRouter { short_below, long_from: long_from.max(short_below) }
+	}
+	
+	/// The bin a read belongs into.
+	pub fn route<R: RecordTrait>(&self, record: &R) -> LengthBin {
+		let len = record.seq_len();
+		if len < self.short_below {
+			LengthBin::Short
+		} else if len < self.long_from {
+			LengthBin::Middle
+		} else {
+			LengthBin::Long
+		}
+	}
+}
+
+/// Number of reads routed into each bin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteCounts {
+	pub short: usize,
+	pub middle: usize,
+	pub long: usize,
+}
+
+/// Write each record to the `short` or `long` writer, or to `middle` if it falls in between.
+/// Middle reads are dropped (but counted) if there is no `middle` writer.
+pub fn split_by_length<I, W>(records: I, router: &LengthRouter, short: &mut Writer<W>, mut middle: Option<&mut Writer<W>>, long: &mut Writer<W>) -> Result<RouteCounts, Error>
+	where I: IntoIterator<Item=Result<Record, Error>>, W: Write
+{
+	let mut counts = RouteCounts::default();
+	for record in records {
+		let record = record?;
+		match router.route(&record) {
+			LengthBin::Short => { counts.short += 1; short.write_record(&record)? },
+			LengthBin::Middle => {
+				counts.middle += 1;
+				if let Some(ref mut middle) = middle { middle.write_record(&record)? }
+			},
+			LengthBin::Long => { counts.long += 1; long.write_record(&record)? },
+		}
+	}
+	short.flush()?;
+	if let Some(middle) = middle { middle.flush()? }
+	long.flush()?;
+	Ok(counts)
+}
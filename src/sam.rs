@@ -0,0 +1,374 @@
+//! Writing records as unaligned SAM, or BAM with the `bam` feature, e.g. for archiving as uBAM.
+//!
+//! Reads are written unmapped: mate suffixes like `/1` are stripped from the ID, which becomes the query name,
+//! and flags mark reads as unmapped and pairs as first and second mate. Descriptions are not kept.
+
+use std::io::{self, Write};
+
+#[cfg(feature = "bam")]
+use flate2::{Compression, Crc, write::DeflateEncoder};
+
+use super::Record as RecordTrait;
+use super::paired::mate_base_id;
+use super::valid::Valid;
+
+/// The read is paired.
+pub const FLAG_PAIRED: u16 = 0x1;
+/// The read is unmapped.
+pub const FLAG_UNMAPPED: u16 = 0x4;
+/// The mate is unmapped.
+pub const FLAG_MATE_UNMAPPED: u16 = 0x8;
+/// The read is the first mate.
+pub const FLAG_READ1: u16 = 0x40;
+/// The read is the second mate.
+pub const FLAG_READ2: u16 = 0x80;
+
+/// The flags of an unpaired read.
+const SINGLE: u16 = FLAG_UNMAPPED;
+/// The flags of the mates of a pair.
+const MATES: [u16; 2] = [
+	FLAG_PAIRED | FLAG_UNMAPPED | FLAG_MATE_UNMAPPED | FLAG_READ1,
+	FLAG_PAIRED | FLAG_UNMAPPED | FLAG_MATE_UNMAPPED | FLAG_READ2,
+];
+
+/// The header and read group settings shared by SAM and BAM writers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Header {
+	read_group: Option<(String, Option<String>)>,
+}
+
+impl Header {
+	/// The header text: `@HD`, and `@RG` if a read group is set.
+	fn text(&self) -> String {
+		let mut text = "@HD\tVN:1.6\tSO:unsorted\n".to_owned();
+		if let Some((id, sample)) = &self.read_group {
+			text.push_str("@RG\tID:");
+			text.push_str(id);
+			if let Some(sample) = sample {
+				text.push_str("\tSM:");
+				text.push_str(sample);
+			}
+			text.push('\n');
+		}
+		text
+	}
+	
+	fn read_group_id(&self) -> Option<&str> {
+		self.read_group.as_ref().map(|(id, _)| id.as_str())
+	}
+}
+
+/// Fail with `InvalidData` for records that would not make valid SAM.
+fn checked<R: RecordTrait>(record: &R) -> io::Result<&str> {
+	if !record.is_validated() {
+		record.check().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	}
+	let name = mate_base_id(record.id().unwrap_or(""));
+	if name.is_empty() || name.starts_with('@') || name.len() > 254 {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{:?} is no valid SAM query name", name)));
+	}
+	Ok(name)
+}
+
+/// Writes records as unaligned SAM text.
+pub struct SamWriter<W: Write> {
+	writer: io::BufWriter<W>,
+	header: Header,
+	started: bool,
+}
+
+impl<W: Write> SamWriter<W> {
+	/// Write to a given `io::Write`. The header is written with the first record.
+	pub fn new(writer: W) -> Self {
+		SamWriter { writer: io::BufWriter::new(writer), header: Header::default(), started: false }
+	}
+	
+	/// Declare a read group with an optional sample name in the header and tag every read with it.
+	pub fn read_group(mut self, id: &str, sample: Option<&str>) -> Self {
+		self.header.read_group = Some((id.to_owned(), sample.map(str::to_owned)));
+		self
+	}
+	
+	fn start(&mut self) -> io::Result<()> {
+		if !self.started {
+			self.started = true;
+			self.writer.write_all(self.header.text().as_bytes())?;
+		}
+		Ok(())
+	}
+	
+	fn write_line<R: RecordTrait>(&mut self, record: &R, flag: u16) -> io::Result<()> {
+		let name = checked(record)?;
+		self.start()?;
+		let (seq, qual) = match record.seq() {
+			b"" => (&b"*"[..], &b"*"[..]),
+			seq => (seq, record.qual()),
+		};
+		write!(self.writer, "{}\t{}\t*\t0\t0\t*\t*\t0\t0\t", name, flag)?;
+		self.writer.write_all(seq)?;
+		self.writer.write_all(b"\t")?;
+		self.writer.write_all(qual)?;
+		if let Some(rg) = self.header.read_group_id() { write!(self.writer, "\tRG:Z:{}", rg)? }
+		self.writer.write_all(b"\n")
+	}
+	
+	/// Write an unpaired read.
+	pub fn write_record<R: RecordTrait>(&mut self, record: &R) -> io::Result<()> {
+		self.write_line(record, SINGLE)
+	}
+	
+	/// Write both mates of a pair.
+	pub fn write_pair<R1: RecordTrait, R2: RecordTrait>(&mut self, r1: &R1, r2: &R2) -> io::Result<()> {
+		self.write_line(r1, MATES[0])?;
+		self.write_line(r2, MATES[1])
+	}
+	
+	/// Write an unpaired read that passed `Record::check`, for exports that must not contain unchecked data.
+	pub fn write_valid<R: RecordTrait>(&mut self, record: &Valid<R>) -> io::Result<()> {
+		self.write_record(&**record)
+	}
+	
+	/// Write both mates of a pair that passed `Record::check`.
+	pub fn write_valid_pair<R1: RecordTrait, R2: RecordTrait>(&mut self, r1: &Valid<R1>, r2: &Valid<R2>) -> io::Result<()> {
+		self.write_pair(&**r1, &**r2)
+	}
+	
+	/// Flush the underlying writer, writing the header if no record was written.
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.start()?;
+		self.writer.flush()
+	}
+	
+	/// Flush and return the underlying writer.
+	pub fn into_inner(mut self) -> io::Result<W> {
+		self.start()?;
+		self.writer.into_inner().map_err(|e| e.into_error())
+	}
+}
+
+/// The most uncompressed bytes in a BGZF block, as in htslib.
+#[cfg(feature = "bam")]
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block marking the end of a BGZF file.
+#[cfg(feature = "bam")]
+const BGZF_EOF: [u8; 28] = [
+	0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0, 0x1b, 0,
+	3, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Compresses into BGZF blocks, the blocked gzip format of BAM.
+#[cfg(feature = "bam")]
+struct Bgzf<W: Write> {
+	inner: W,
+	buf: Vec<u8>,
+}
+
+#[cfg(feature = "bam")]
+impl<W: Write> Bgzf<W> {
+	fn new(inner: W) -> Self {
+		Bgzf { inner, buf: Vec::with_capacity(BGZF_BLOCK_SIZE) }
+	}
+	
+	/// Compress the buffered data into one block.
+	fn write_block(&mut self) -> io::Result<()> {
+		if self.buf.is_empty() { return Ok(()) }
+		let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+		deflate.write_all(&self.buf)?;
+		let data = deflate.finish()?;
+		let mut crc = Crc::new();
+		crc.update(&self.buf);
+		// 18 bytes of header with the BC extra field, the data, and 8 bytes of trailer
+		let block_size = (18 + data.len() + 8 - 1) as u16;
+		self.inner.write_all(&[0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0])?;
+		self.inner.write_all(&block_size.to_le_bytes())?;
+		self.inner.write_all(&data)?;
+		self.inner.write_all(&crc.sum().to_le_bytes())?;
+		self.inner.write_all(&(self.buf.len() as u32).to_le_bytes())?;
+		self.buf.clear();
+		Ok(())
+	}
+	
+	/// Write the last block and the end marker.
+	fn finish(mut self) -> io::Result<W> {
+		self.write_block()?;
+		self.inner.write_all(&BGZF_EOF)?;
+		self.inner.flush()?;
+		Ok(self.inner)
+	}
+}
+
+#[cfg(feature = "bam")]
+impl<W: Write> Write for Bgzf<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = buf.len().min(BGZF_BLOCK_SIZE - self.buf.len());
+		self.buf.extend_from_slice(&buf[..n]);
+		if self.buf.len() == BGZF_BLOCK_SIZE { self.write_block()? }
+		Ok(n)
+	}
+	
+	fn flush(&mut self) -> io::Result<()> {
+		self.write_block()?;
+		self.inner.flush()
+	}
+}
+
+/// The 4-bit BAM code of a base, `N` for anything unknown.
+#[cfg(feature = "bam")]
+fn base_code(base: u8) -> u8 {
+	b"=ACMGRSVTWYHKDBN".iter().position(|&b| b == base.to_ascii_uppercase()).unwrap_or(15) as u8
+}
+
+/// Writes records as unaligned BAM.
+#[cfg(feature = "bam")]
+pub struct BamWriter<W: Write> {
+	writer: Bgzf<W>,
+	header: Header,
+	started: bool,
+	record: Vec<u8>,
+}
+
+#[cfg(feature = "bam")]
+impl<W: Write> BamWriter<W> {
+	/// Write to a given `io::Write`. The header is written with the first record.
+	/// Call `finish` to write the end of file marker.
+	pub fn new(writer: W) -> Self {
+		BamWriter { writer: Bgzf::new(writer), header: Header::default(), started: false, record: Vec::new() }
+	}
+	
+	/// Declare a read group with an optional sample name in the header and tag every read with it.
+	pub fn read_group(mut self, id: &str, sample: Option<&str>) -> Self {
+		self.header.read_group = Some((id.to_owned(), sample.map(str::to_owned)));
+		self
+	}
+	
+	fn start(&mut self) -> io::Result<()> {
+		if !self.started {
+			self.started = true;
+			let text = self.header.text();
+			self.writer.write_all(b"BAM\x01")?;
+			self.writer.write_all(&(text.len() as i32).to_le_bytes())?;
+			self.writer.write_all(text.as_bytes())?;
+			// no reference sequences
+			self.writer.write_all(&0i32.to_le_bytes())?;
+		}
+		Ok(())
+	}
+	
+	fn write_alignment<R: RecordTrait>(&mut self, record: &R, flag: u16) -> io::Result<()> {
+		let name = checked(record)?;
+		self.start()?;
+		let (seq, qual) = (record.seq(), record.qual());
+		let r = &mut self.record;
+		r.clear();
+		r.extend_from_slice(&(-1i32).to_le_bytes()); // reference
+		r.extend_from_slice(&(-1i32).to_le_bytes()); // position
+		r.push(name.len() as u8 + 1);
+		r.push(0); // mapping quality
+		r.extend_from_slice(&4680u16.to_le_bytes()); // bin of unmapped reads
+		r.extend_from_slice(&0u16.to_le_bytes()); // CIGAR operations
+		r.extend_from_slice(&flag.to_le_bytes());
+		r.extend_from_slice(&(seq.len() as i32).to_le_bytes());
+		r.extend_from_slice(&(-1i32).to_le_bytes()); // mate reference
+		r.extend_from_slice(&(-1i32).to_le_bytes()); // mate position
+		r.extend_from_slice(&0i32.to_le_bytes()); // template length
+		r.extend_from_slice(name.as_bytes());
+		r.push(0);
+		for pair in seq.chunks(2) {
+			r.push(base_code(pair[0]) << 4 | pair.get(1).map_or(0, |&b| base_code(b)));
+		}
+		r.extend(qual.iter().map(|&q| q.saturating_sub(33)));
+		if let Some(rg) = self.header.read_group_id() {
+			r.extend_from_slice(b"RGZ");
+			r.extend_from_slice(rg.as_bytes());
+			r.push(0);
+		}
+		self.writer.write_all(&(self.record.len() as i32).to_le_bytes())?;
+		self.writer.write_all(&self.record)
+	}
+	
+	/// Write an unpaired read.
+	pub fn write_record<R: RecordTrait>(&mut self, record: &R) -> io::Result<()> {
+		self.write_alignment(record, SINGLE)
+	}
+	
+	/// Write both mates of a pair.
+	pub fn write_pair<R1: RecordTrait, R2: RecordTrait>(&mut self, r1: &R1, r2: &R2) -> io::Result<()> {
+		self.write_alignment(r1, MATES[0])?;
+		self.write_alignment(r2, MATES[1])
+	}
+	
+	/// Write an unpaired read that passed `Record::check`, for exports that must not contain unchecked data.
+	pub fn write_valid<R: RecordTrait>(&mut self, record: &Valid<R>) -> io::Result<()> {
+		self.write_record(&**record)
+	}
+	
+	/// Write both mates of a pair that passed `Record::check`.
+	pub fn write_valid_pair<R1: RecordTrait, R2: RecordTrait>(&mut self, r1: &Valid<R1>, r2: &Valid<R2>) -> io::Result<()> {
+		self.write_pair(&**r1, &**r2)
+	}
+	
+	/// Write the remaining data and the end of file marker, returning the underlying writer.
+	pub fn finish(mut self) -> io::Result<W> {
+		self.start()?;
+		self.writer.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::{Reader, Record};
+	
+	fn records(text: &str) -> Vec<Record> {
+		Reader::new(text.as_bytes()).records().collect::<Result<_, _>>().unwrap()
+	}
+	
+	#[test]
+	fn sam_text() {
+		let pair = records("@p/1 1:N\nACG\n+\nIII\n@p/2\nT\n+\n#\n");
+		let mut writer = SamWriter::new(Vec::new()).read_group("rg1", Some("s"));
+		writer.write_pair(&pair[0], &pair[1]).unwrap();
+		writer.write_record(&records("@e\n\n+\n\n")[0]).unwrap();
+		assert_eq!(String::from_utf8(writer.into_inner().unwrap()).unwrap(), "\
+			@HD\tVN:1.6\tSO:unsorted\n@RG\tID:rg1\tSM:s\n\
+			p\t77\t*\t0\t0\t*\t*\t0\t0\tACG\tIII\tRG:Z:rg1\n\
+			p\t141\t*\t0\t0\t*\t*\t0\t0\tT\t#\tRG:Z:rg1\n\
+			e\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\tRG:Z:rg1\n");
+		assert!(SamWriter::new(Vec::new()).write_record(&records("@@x\nA\n+\nI\n")[0]).is_err());
+	}
+	
+	#[cfg(feature = "bam")]
+	#[test]
+	fn bam_bytes() {
+		use std::io::Read;
+		
+		let mut writer = BamWriter::new(Vec::new());
+		writer.write_record(&records("@r/1\nACGTN\n+\nI#I#5\n")[0]).unwrap();
+		let bgzf = writer.finish().unwrap();
+		
+		// one data block whose BSIZE is its length minus 1, then the end of file marker
+		assert_eq!(&bgzf[..16], &[0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0]);
+		let block_size = u16::from_le_bytes([bgzf[16], bgzf[17]]) as usize + 1;
+		assert_eq!(&bgzf[block_size..], &BGZF_EOF);
+		
+		let mut bam = Vec::new();
+		flate2::read::MultiGzDecoder::new(&bgzf[..]).read_to_end(&mut bam).unwrap();
+		let text = b"@HD\tVN:1.6\tSO:unsorted\n";
+		let mut expected = b"BAM\x01".to_vec();
+		expected.extend_from_slice(&(text.len() as i32).to_le_bytes());
+		expected.extend_from_slice(text);
+		expected.extend_from_slice(&0i32.to_le_bytes());
+		expected.extend_from_slice(&42i32.to_le_bytes());
+		expected.extend_from_slice(&[
+			0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // reference and position
+			2, 0, 0x48, 0x12, 0, 0, 4, 0, // name length, mapping quality, bin, CIGAR operations, flag
+			5, 0, 0, 0, // sequence length
+			0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, // mate reference and position, template length
+			b'r', 0,
+			0x12, 0x48, 0xf0, // ACGTN
+			40, 2, 40, 2, 20,
+		]);
+		assert_eq!(bam, expected);
+	}
+}
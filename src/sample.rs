@@ -0,0 +1,138 @@
+//! Downsampling record streams, e.g. for quick QC of huge runs.
+//!
+//! The adapters work on any iterator of `Result`s: errors always pass through, and only
+//! successful items are sampled. Used on a `PairedReader`, they keep or drop both mates together.
+//! `sample_fragments` decides by fragment ID instead, which keeps mates together in interleaved streams,
+//! and picks the same pairs from R1 and R2 files sampled separately with the same seed.
+
+use super::Record as RecordTrait;
+use super::paired::mate_base_id;
+use super::rng::{hash64, Rng};
+
+/// An iterator adapter keeping each item with a fixed probability, see `sample_fraction`.
+pub struct SampleFraction<I> {
+	inner: I,
+	fraction: f64,
+	rng: Rng,
+}
+
+impl<I, T, E> Iterator for SampleFraction<I> where I: Iterator<Item=Result<T, E>> {
+	type Item = Result<T, E>;
+	
+	fn next(&mut self) -> Option<Result<T, E>> {
+		loop {
+			match self.inner.next()? {
+				Ok(item) => if self.rng.unit() < self.fraction { return Some(Ok(item)) },
+				Err(e) => return Some(Err(e)),
+			}
+		}
+	}
+}
+
+/// Keep each item with probability `fraction`, deterministically for a given seed.
+pub fn sample_fraction<I, T, E>(records: I, fraction: f64, seed: u64) -> SampleFraction<I::IntoIter> where I: IntoIterator<Item=Result<T, E>> {
+	SampleFraction { inner: records.into_iter(), fraction, rng: Rng::new(seed) }
+}
+
+/// An iterator adapter keeping records by their fragment ID, see `sample_fragments`.
+pub struct SampleFragments<I> {
+	inner: I,
+	fraction: f64,
+	seed: u64,
+}
+
+impl<I, R, E> Iterator for SampleFragments<I> where I: Iterator<Item=Result<R, E>>, R: RecordTrait {
+	type Item = Result<R, E>;
+	
+	fn next(&mut self) -> Option<Result<R, E>> {
+		loop {
+			match self.inner.next()? {
+				Ok(record) => if keep_fragment(record.id().unwrap_or(""), self.fraction, self.seed) { return Some(Ok(record)) },
+				Err(e) => return Some(Err(e)),
+			}
+		}
+	}
+}
+
+/// Check if the fragment a read ID belongs to is in the sample, the same for both mates.
+fn keep_fragment(id: &str, fraction: f64, seed: u64) -> bool {
+	let h = hash64(mate_base_id(id).as_bytes(), seed);
+	((h >> 11) as f64 / (1u64 << 53) as f64) < fraction
+}
+
+/// Keep each fragment with probability `fraction`, deciding by its ID and the seed alone.
+/// Both mates of a fragment are kept or dropped together, whether they are interleaved or in separate streams.
+pub fn sample_fragments<I, R, E>(records: I, fraction: f64, seed: u64) -> SampleFragments<I::IntoIter> where I: IntoIterator<Item=Result<R, E>>, R: RecordTrait {
+	SampleFragments { inner: records.into_iter(), fraction, seed }
+}
+
+/// An iterator adapter keeping every `n`th item, see `every_nth`.
+pub struct EveryNth<I> {
+	inner: I,
+	n: usize,
+	seen: usize,
+}
+
+impl<I, T, E> Iterator for EveryNth<I> where I: Iterator<Item=Result<T, E>> {
+	type Item = Result<T, E>;
+	
+	fn next(&mut self) -> Option<Result<T, E>> {
+		loop {
+			match self.inner.next()? {
+				Ok(item) => {
+					let keep = self.seen.is_multiple_of(self.n);
+					self.seen += 1;
+					if keep { return Some(Ok(item)) }
+				},
+				Err(e) => return Some(Err(e)),
+			}
+		}
+	}
+}
+
+/// Keep the first item and every `n`th after it.
+pub fn every_nth<I, T, E>(records: I, n: usize) -> EveryNth<I::IntoIter> where I: IntoIterator<Item=Result<T, E>> {
+	EveryNth { inner: records.into_iter(), n: n.max(1), seen: 0 }
+}
+
+/// Pick `n` items uniformly at random (reservoir sampling), keeping their input order.
+/// Holds only the `n` picked items in memory. Stops at the first error.
+pub fn sample_count<I, T, E>(records: I, n: usize, seed: u64) -> Result<Vec<T>, E> where I: IntoIterator<Item=Result<T, E>> {
+	let mut rng = Rng::new(seed);
+	let mut reservoir: Vec<(usize, T)> = Vec::with_capacity(n);
+	for (i, item) in records.into_iter().enumerate() {
+		let item = item?;
+		if reservoir.len() < n {
+			reservoir.push((i, item));
+		} else {
+			let j = rng.below(i as u64 + 1) as usize;
+			if j < n { reservoir[j] = (i, item) }
+		}
+	}
+	reservoir.sort_by_key(|&(i, _)| i);
+	Ok(reservoir.into_iter().map(|(_, item)| item).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::Reader;
+	
+	fn ids(text: &str, fraction: f64, seed: u64) -> Vec<String> {
+		sample_fragments(Reader::new(text.as_bytes()).records(), fraction, seed).map(|r| r.unwrap().id().unwrap().to_owned()).collect()
+	}
+	
+	#[test]
+	fn fragments_keep_mates_together() {
+		let mates = |mate: usize| -> String { (0..200).map(|i| format!("@f{}/{}\nA\n+\nI\n", i, mate)).collect() };
+		let interleaved: String = (0..200).map(|i| format!("@f{}/1\nA\n+\nI\n@f{}/2\nA\n+\nI\n", i, i)).collect();
+		let (r1, r2, both) = (ids(&mates(1), 0.3, 42), ids(&mates(2), 0.3, 42), ids(&interleaved, 0.3, 42));
+		assert!((40..80).contains(&r1.len()), "{}", r1.len());
+		assert_eq!(r1.iter().map(|id| mate_base_id(id)).collect::<Vec<_>>(), r2.iter().map(|id| mate_base_id(id)).collect::<Vec<_>>());
+		assert_eq!(both.len(), 2 * r1.len());
+		assert!(both.chunks(2).all(|pair| pair[0] == pair[1].replace("/2", "/1")));
+		assert_ne!(r1, ids(&mates(1), 0.3, 7));
+		assert_eq!(ids(&mates(1), 1., 0).len(), 200);
+		assert!(ids(&mates(1), 0., 0).is_empty());
+	}
+}
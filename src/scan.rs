@@ -0,0 +1,161 @@
+//! Walking the structure of FastQ input without parsing records.
+//!
+//! `scan` checks that every record has four lines, starts with `@`, has a `+` separator
+//! and as many qualities as bases, but copies nothing except checkpoint IDs: it only measures lines in the read buffer.
+//! That makes it the quickest check whether input is well-formed, and a cheap way to count records,
+//! measure read lengths or collect the checkpoints of an `index::Index` before reading anything.
+
+use std::io::{self, BufRead, Read};
+
+use super::buffer::skip_preamble;
+use super::error::Error;
+use super::position::Position;
+use super::warning::Warning;
+
+/// Length statistics of one kind of line, without line breaks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineLengths {
+	/// The number of lines.
+	pub lines: u64,
+	/// The shortest length, 0 if there were no lines.
+	pub min: usize,
+	pub max: usize,
+	/// The sum of all lengths.
+	pub total: u64,
+}
+
+impl LineLengths {
+	/// The mean length, `NaN` if there were no lines.
+	pub fn mean(&self) -> f64 { self.total as f64 / self.lines as f64 }
+	
+	fn add(&mut self, len: usize) {
+		self.min = if self.lines == 0 { len } else { self.min.min(len) };
+		self.max = self.max.max(len);
+		self.total += len as u64;
+		self.lines += 1;
+	}
+}
+
+/// The structure of FastQ input as measured by `scan`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanSummary {
+	pub records: u64,
+	/// The size of the input in bytes.
+	pub bytes: u64,
+	/// Header lines, including the `@`.
+	pub headers: LineLengths,
+	/// Sequence lines, which are as long as the quality lines.
+	pub sequences: LineLengths,
+	/// Separator lines, including the `+`.
+	pub separators: LineLengths,
+	/// The number of lines ending in `\r\n`.
+	pub crlf_lines: u64,
+	/// The interval of `checkpoints`, 0 if none were collected.
+	pub checkpoint_every: usize,
+	/// ID and byte offset of every `checkpoint_every`th record, starting with the first.
+	pub checkpoints: Vec<(String, u64)>,
+	/// What was skipped before the first record.
+	pub warnings: Vec<Warning>,
+}
+
+/// Scans FastQ input, optionally collecting checkpoints for an index.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scanner {
+	checkpoint_every: usize,
+}
+
+impl Scanner {
+	/// A scanner measuring structure only.
+	pub fn new() -> Scanner { Scanner::default() }
+	
+	/// Also collect ID and offset of every `every`th record (every record for 1), see `index::Index::from_scan`.
+	pub fn checkpoints(mut self, every: usize) -> Self {
+		self.checkpoint_every = every.max(1);
+		self
+	}
+	
+	/// Scan FastQ input to its end, failing at the first malformed record.
+	pub fn scan<R: Read>(&self, reader: R) -> Result<ScanSummary, Error> {
+		let mut reader = io::BufReader::new(reader);
+		let mut summary = ScanSummary { checkpoint_every: self.checkpoint_every, ..ScanSummary::default() };
+		let mut pos = Position::start();
+		if let Some(warning) = skip_preamble(&mut reader, &mut pos)? { summary.warnings.push(warning) }
+		let mut id = Vec::new();
+		loop {
+			let start = pos;
+			let checkpoint = self.checkpoint_every > 0 && start.record.is_multiple_of(self.checkpoint_every as u64);
+			let header = next_line(&mut reader, &mut pos, checkpoint.then_some(&mut id))?;
+			if header.bytes == 0 { break }
+			if header.first != Some(b'@') { return Err(Error::MissingAt(start, header.first)) }
+			let seq = next_line(&mut reader, &mut pos, None)?;
+			let sep_pos = pos;
+			let sep = next_line(&mut reader, &mut pos, None)?;
+			let qual = next_line(&mut reader, &mut pos, None)?;
+			if qual.bytes == 0 { return Err(Error::Incomplete(pos)) }
+			if sep.first != Some(b'+') { return Err(Error::MissingPlus(sep_pos, sep.first)) }
+			if seq.len != qual.len { return Err(Error::LengthMismatch(start, seq.len, qual.len)) }
+			if checkpoint {
+				let name = id[1..].split(u8::is_ascii_whitespace).next().unwrap_or(&[]);
+				summary.checkpoints.push((String::from_utf8_lossy(name).into_owned(), start.offset));
+				id.clear();
+			}
+			summary.headers.add(header.len);
+			summary.sequences.add(seq.len);
+			summary.separators.add(sep.len);
+			summary.crlf_lines += [header, seq, sep, qual].iter().filter(|l| l.crlf).count() as u64;
+			summary.records += 1;
+			pos.record += 1;
+		}
+		summary.bytes = pos.offset;
+		Ok(summary)
+	}
+}
+
+/// Scan FastQ input, see `Scanner`.
+pub fn scan<R: Read>(reader: R) -> Result<ScanSummary, Error> {
+	Scanner::new().scan(reader)
+}
+
+/// A line as measured by `next_line`.
+#[derive(Debug, Clone, Copy)]
+struct Line {
+	/// Bytes including the line break, 0 at the end of input.
+	bytes: usize,
+	/// Bytes without the line break.
+	len: usize,
+	first: Option<u8>,
+	crlf: bool,
+}
+
+/// Skip the next line, copying it to `keep` if given.
+fn next_line<B: BufRead>(reader: &mut B, pos: &mut Position, mut keep: Option<&mut Vec<u8>>) -> io::Result<Line> {
+	let mut line = Line { bytes: 0, len: 0, first: None, crlf: false };
+	let mut prev = None;
+	loop {
+		let buf = reader.fill_buf()?;
+		if buf.is_empty() { break }
+		let (chunk, done) = match buf.iter().position(|&b| b == b'\n') {
+			Some(end) => (&buf[..=end], true),
+			None => (buf, false),
+		};
+		if line.bytes == 0 { line.first = chunk.first().copied() }
+		if let Some(keep) = keep.as_deref_mut() { keep.extend_from_slice(chunk) }
+		let n = chunk.len();
+		line.bytes += n;
+		if done {
+			// the byte before the line break may be at the end of the previous chunk
+			line.crlf = if n >= 2 { chunk[n - 2] == b'\r' } else { prev == Some(b'\r') };
+			line.len = line.bytes - 1 - usize::from(line.crlf);
+			reader.consume(n);
+			pos.advance_line(line.bytes);
+			return Ok(line);
+		}
+		prev = chunk.last().copied();
+		reader.consume(n);
+	}
+	line.len = line.bytes;
+	pos.advance_line(line.bytes);
+	Ok(line)
+}
@@ -0,0 +1,74 @@
+//! Alignment-free comparison of read sequences: Hamming and edit distance, and shared k-mers.
+//!
+//! Bases are compared byte by byte, so case matters and `N` only matches `N`,
+//! except that k-mers containing an `N` are skipped. Pass `record.seq()` to compare records.
+
+use std::collections::HashSet;
+
+/// The number of mismatching positions, or `None` if the lengths differ.
+pub fn hamming(a: &[u8], b: &[u8]) -> Option<usize> {
+	if a.len() != b.len() { return None }
+	Some(a.iter().zip(b).filter(|(x, y)| x != y).count())
+}
+
+/// The Levenshtein distance (substitutions, insertions and deletions) if it is at most `max`.
+///
+/// Only alignments within `max` of the diagonal are considered and the search stops
+/// as soon as every one is over `max`, so this takes `O(max · len)` time at most.
+pub fn edit_distance(a: &[u8], b: &[u8], max: usize) -> Option<usize> {
+	if a.len().abs_diff(b.len()) > max { return None }
+	let over = max + 1;
+	let mut prev: Vec<usize> = (0..=b.len()).map(|j| j.min(over)).collect();
+	let mut cur = vec![over; b.len() + 1];
+	for (i, &x) in a.iter().enumerate() {
+		// row i + 1 only needs the columns within `max` of the diagonal
+		let (lo, hi) = ((i + 1).saturating_sub(max), (i + 1 + max).min(b.len()));
+		let mut row_min = over;
+		if lo == 0 {
+			cur[0] = i + 1;
+			row_min = cur[0];
+		} else {
+			cur[lo - 1] = over;
+		}
+		for j in lo.max(1)..=hi {
+			cur[j] = (prev[j - 1] + usize::from(x != b[j - 1])).min(prev[j] + 1).min(cur[j - 1] + 1).min(over);
+			row_min = row_min.min(cur[j]);
+		}
+		if row_min > max { return None }
+		std::mem::swap(&mut prev, &mut cur);
+	}
+	Some(prev[b.len()]).filter(|&d| d <= max)
+}
+
+/// The distinct k-mers of a sequence without `N`s.
+fn kmers(seq: &[u8], k: usize) -> HashSet<&[u8]> {
+	if k == 0 { return HashSet::new() }
+	seq.windows(k).filter(|kmer| !kmer.iter().any(|b| b.eq_ignore_ascii_case(&b'N'))).collect()
+}
+
+/// The number of distinct k-mers occurring in both sequences.
+pub fn shared_kmers(a: &[u8], b: &[u8], k: usize) -> usize {
+	let (a, b) = (kmers(a, k), kmers(b, k));
+	a.intersection(&b).count()
+}
+
+/// The Jaccard similarity of the k-mer sets of two sequences: shared k-mers over all distinct k-mers,
+/// from 0 for nothing in common to 1 for the same k-mers. 0 if neither sequence has a k-mer.
+pub fn kmer_similarity(a: &[u8], b: &[u8], k: usize) -> f64 {
+	let (a, b) = (kmers(a, k), kmers(b, k));
+	let shared = a.intersection(&b).count();
+	match a.len() + b.len() - shared {
+		0 => 0.,
+		all => shared as f64 / all as f64,
+	}
+}
+
+/// The fraction of the k-mers of `a` that also occur in `b`, e.g. to tell if a read is part of a longer one.
+/// 0 if `a` has no k-mer.
+pub fn kmer_containment(a: &[u8], b: &[u8], k: usize) -> f64 {
+	let (a, b) = (kmers(a, k), kmers(b, k));
+	match a.len() {
+		0 => 0.,
+		n => a.intersection(&b).count() as f64 / n as f64,
+	}
+}
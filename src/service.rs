@@ -0,0 +1,327 @@
+//! An HTTP endpoint for FastQ quality control, built on axum.
+//!
+//! `router()` answers
+//!
+//! - `POST /validate?strictness=strict&max_issues=100` with a `ValidationSummary`,
+//! - `POST /stats` with a `stats::StatsReport`,
+//! - `POST /compare?ignore_quality=true&ignore_desc=true&max_reported=100&encoding_a=phred64`,
+//!   a multipart form with the files as parts `a` and `b`, with a `compare::CompareReport`,
+//!
+//! all as JSON. A service is a few lines:
+//!
+//! ```ignore
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
+//! axum::serve(listener, fastq_comparison::service::router()).await?;
+//! ```
+//!
+//! Uploads may be gzip compressed and are parsed on tokio’s blocking threads while they stream in,
+//! so memory use does not grow with their size. The exception is `a` of `/compare`,
+//! which is received completely before `b` and is therefore held in memory.
+//!
+//! `router_with` sets limits on upload sizes, see `ServiceOptions`. Uploads over a limit are answered
+//! with `413 Payload Too Large`. axum’s own `DefaultBodyLimit` of 2 MB is lifted in favor of these limits.
+
+use std::io::{self, BufRead, Read};
+
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Multipart, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::post;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+
+use super::compare::{compare, CompareOptions, CompareReport};
+use super::input::GZIP_MAGIC;
+use super::quality::Encoding;
+use super::stats::{StatsCollector, StatsReport};
+use super::unfancy_parser::{Reader, Error};
+use super::validate::{Strictness, ValidationIssue, Validator};
+
+quick_error! {
+	/// Why a request failed.
+	#[derive(Debug)]
+	pub enum ServiceError {
+		/// The request was malformed, e.g. an unknown option or a missing form part.
+		BadRequest(msg: String) {
+			display("{}", msg)
+		}
+		/// An upload could not be parsed as FastQ.
+		Parse(err: Error) {
+			from()
+			display("{}", err)
+		}
+		/// An upload could not be received or decompressed.
+		Io(err: io::Error) {
+			from()
+			display("{}", err)
+		}
+		/// An upload was larger than the limit of this many bytes, see `ServiceOptions`.
+		TooLarge(limit: usize) {
+			display("Upload larger than {} bytes", limit)
+		}
+	}
+}
+
+impl ServiceError {
+	/// The HTTP status the error is answered with.
+	pub fn status(&self) -> StatusCode {
+		match *self {
+			ServiceError::BadRequest(_) => StatusCode::BAD_REQUEST,
+			ServiceError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+			ServiceError::Parse(Error::Io(ref e)) | ServiceError::Io(ref e) if e.kind() != io::ErrorKind::InvalidData => StatusCode::BAD_REQUEST,
+			ServiceError::Parse(_) | ServiceError::Io(_) => StatusCode::UNPROCESSABLE_ENTITY,
+		}
+	}
+}
+
+#[derive(serde::Serialize)]
+struct ErrorBody {
+	error: String,
+}
+
+impl IntoResponse for ServiceError {
+	fn into_response(self) -> Response {
+		(self.status(), Json(ErrorBody { error: self.to_string() })).into_response()
+	}
+}
+
+/// The answer of `/validate`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ValidationSummary {
+	/// Check if the upload was free of issues.
+	pub valid: bool,
+	/// The number of records checked.
+	pub records: usize,
+	/// The number of issues found.
+	pub issue_count: usize,
+	/// The first `max_issues` issues.
+	pub issues: Vec<ValidationIssue>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ValidateQuery {
+	strictness: Option<String>,
+	max_issues: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CompareQuery {
+	#[serde(default)]
+	ignore_quality: bool,
+	#[serde(default)]
+	ignore_desc: bool,
+	max_reported: Option<usize>,
+	encoding_a: Option<String>,
+	encoding_b: Option<String>,
+}
+
+/// Limits on the uploads of a router. The default holds at most 64 MiB in memory
+/// and does not limit uploads that are parsed while they stream in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceOptions {
+	/// Maximum size in bytes of the files of a request, as uploaded (i.e. compressed), `None` for no limit.
+	pub max_upload: Option<usize>,
+	/// Maximum size in bytes of the part `a` of `/compare`, which is held in memory, as uploaded.
+	pub max_held: usize,
+}
+
+impl Default for ServiceOptions {
+	fn default() -> ServiceOptions {
+		ServiceOptions { max_upload: None, max_held: 64 << 20 }
+	}
+}
+
+/// A router serving `/validate`, `/stats` and `/compare` with the default `ServiceOptions`.
+/// Nest it to serve them under a prefix.
+pub fn router() -> Router {
+	router_with(ServiceOptions::default())
+}
+
+/// A router serving `/validate`, `/stats` and `/compare` with the given limits on uploads.
+pub fn router_with(options: ServiceOptions) -> Router {
+	Router::new()
+		.route("/validate", post(validate))
+		.route("/stats", post(stats))
+		.route("/compare", post(compare_uploads))
+		// the multipart form of /compare is limited by `options` instead
+		.layer(DefaultBodyLimit::disable())
+		.with_state(options)
+}
+
+async fn validate(State(limits): State<ServiceOptions>, Query(query): Query<ValidateQuery>, body: Body) -> Result<Json<ValidationSummary>, ServiceError> {
+	let strictness = match query.strictness.as_deref() {
+		None | Some("standard") => Strictness::Standard,
+		Some("strict") => Strictness::Strict,
+		Some("lenient") => Strictness::Lenient,
+		Some(s) => return Err(ServiceError::BadRequest(format!("Unknown strictness {:?}", s))),
+	};
+	let max_issues = query.max_issues.unwrap_or(100);
+	let summary = parse_streaming(body.into_data_stream(), limits.max_upload, move |reader| {
+		let mut issues = Validator::with_strictness(strictness).issues(fastq_reader(reader)?);
+		let mut summary = ValidationSummary { valid: true, records: 0, issue_count: 0, issues: Vec::new() };
+		for issue in issues.by_ref() {
+			let issue = issue?;
+			summary.issue_count += 1;
+			if summary.issues.len() < max_issues { summary.issues.push(issue) }
+		}
+		summary.valid = summary.issue_count == 0;
+		summary.records = issues.records();
+		Ok(summary)
+	}).await?;
+	Ok(Json(summary))
+}
+
+async fn stats(State(limits): State<ServiceOptions>, body: Body) -> Result<Json<StatsReport>, ServiceError> {
+	let report = parse_streaming(body.into_data_stream(), limits.max_upload, |reader| {
+		let mut collector = StatsCollector::new();
+		for record in Reader::new(fastq_reader(reader)?).records() {
+			collector.add(&record?);
+		}
+		Ok(collector.report())
+	}).await?;
+	Ok(Json(report))
+}
+
+async fn compare_uploads(State(limits): State<ServiceOptions>, Query(query): Query<CompareQuery>, mut form: Multipart) -> Result<Json<CompareReport>, ServiceError> {
+	let mut options = CompareOptions { ignore_quality: query.ignore_quality, ignore_desc: query.ignore_desc, ..CompareOptions::default() };
+	if let Some(n) = query.max_reported { options.max_reported = n }
+	options.encoding_a = encoding(query.encoding_a.as_deref())?;
+	options.encoding_b = encoding(query.encoding_b.as_deref())?;
+	let bad_form = |e: axum::extract::multipart::MultipartError| ServiceError::BadRequest(e.body_text());
+	let mut a = form.next_field().await.map_err(bad_form)?
+		.filter(|field| field.name() == Some("a"))
+		.ok_or_else(|| ServiceError::BadRequest("Expected the form part a first".to_owned()))?;
+	let max_a = limits.max_upload.map_or(limits.max_held, |max| max.min(limits.max_held));
+	let mut a_data = Vec::new();
+	while let Some(chunk) = a.chunk().await.map_err(bad_form)? {
+		if a_data.len() + chunk.len() > max_a { return Err(ServiceError::TooLarge(max_a)) }
+		a_data.extend_from_slice(&chunk);
+	}
+	drop(a);
+	let b = form.next_field().await.map_err(bad_form)?
+		.filter(|field| field.name() == Some("b"))
+		.ok_or_else(|| ServiceError::BadRequest("Expected the form part b after a".to_owned()))?;
+	let max_b = limits.max_upload.map(|max| max - a_data.len());
+	let report = parse_streaming(b, max_b, move |reader| {
+		// compare stops at the first parse error of either file, which is reported instead
+		let (mut error_a, mut error_b) = (None, None);
+		let report = compare(
+			Reader::new(fastq_reader(io::Cursor::new(a_data))?).records().map_while(|r| r.map_err(|e| error_a = Some(e)).ok()),
+			Reader::new(fastq_reader(reader)?).records().map_while(|r| r.map_err(|e| error_b = Some(e)).ok()),
+			&options,
+		);
+		match error_a.or(error_b) {
+			Some(e) => Err(e.into()),
+			None => Ok(report),
+		}
+	}).await?;
+	Ok(Json(report))
+}
+
+fn encoding(name: Option<&str>) -> Result<Encoding, ServiceError> {
+	match name {
+		None | Some("phred33") => Ok(Encoding::Sanger),
+		Some("phred64") => Ok(Encoding::Illumina13),
+		Some("solexa") => Ok(Encoding::Solexa),
+		Some(e) => Err(ServiceError::BadRequest(format!("Unknown quality encoding {:?}", e))),
+	}
+}
+
+/// Run `parse` on a blocking thread, feeding it the chunks of `data` as they arrive.
+/// Fails with `ServiceError::TooLarge` once more than `limit` bytes arrived.
+async fn parse_streaming<S, E, T, F>(mut data: S, limit: Option<usize>, parse: F) -> Result<T, ServiceError>
+	where S: futures_util::Stream<Item=Result<Bytes, E>> + Unpin, E: std::error::Error + Send + Sync + 'static,
+		T: Send + 'static, F: FnOnce(BodyReader) -> Result<T, ServiceError> + Send + 'static
+{
+	let (tx, rx) = mpsc::channel(16);
+	let task = tokio::task::spawn_blocking(move || parse(BodyReader { chunks: rx, chunk: Bytes::new() }));
+	let mut received = 0;
+	while let Some(chunk) = data.next().await {
+		received += chunk.as_ref().map_or(0, Bytes::len);
+		if let Some(limit) = limit.filter(|&limit| received > limit) {
+			let _ = tx.send(Err(io::Error::other("upload too large"))).await;
+			drop(tx);
+			let _ = task.await;
+			return Err(ServiceError::TooLarge(limit));
+		}
+		let failed = chunk.is_err();
+		// the receiver is gone if parsing stopped early, e.g. at a parse error
+		if tx.send(chunk.map_err(io::Error::other)).await.is_err() || failed { break }
+	}
+	drop(tx);
+	task.await.map_err(|e| ServiceError::Io(io::Error::other(e)))?
+}
+
+/// A blocking reader over the chunks of a request body.
+struct BodyReader {
+	chunks: mpsc::Receiver<io::Result<Bytes>>,
+	chunk: Bytes,
+}
+
+impl Read for BodyReader {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		while self.chunk.is_empty() {
+			match self.chunks.blocking_recv() {
+				Some(chunk) => self.chunk = chunk?,
+				None => return Ok(0),
+			}
+		}
+		let n = buf.len().min(self.chunk.len());
+		buf[..n].copy_from_slice(&self.chunk.split_to(n));
+		Ok(n)
+	}
+}
+
+/// Buffer an upload, decompressing it if it starts like gzip.
+fn fastq_reader<R: Read + 'static>(reader: R) -> io::Result<Box<dyn BufRead>> {
+	let mut reader = io::BufReader::new(reader);
+	if !reader.fill_buf()?.starts_with(&GZIP_MAGIC) { return Ok(Box::new(reader)) }
+	#[cfg(feature = "gzip")]
+	return Ok(Box::new(io::BufReader::new(flate2::read::MultiGzDecoder::new(reader))));
+	#[cfg(not(feature = "gzip"))]
+	Err(io::Error::new(io::ErrorKind::Unsupported, "gzip support is not compiled in (feature \"gzip\")."))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::http::Request;
+	use tower::ServiceExt;
+	
+	const RECORD: &str = "@r\nACGT\n+\nIIII\n";
+	
+	fn status(router: Router, request: Request<Body>) -> StatusCode {
+		let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+		runtime.block_on(router.oneshot(request)).unwrap().status()
+	}
+	
+	fn form(a: &str, b: &str) -> Request<Body> {
+		let body = format!(
+			"--X\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n{}\r\n--X\r\nContent-Disposition: form-data; name=\"b\"\r\n\r\n{}\r\n--X--\r\n",
+			a, b,
+		);
+		Request::post("/compare").header("content-type", "multipart/form-data; boundary=X").body(Body::from(body)).unwrap()
+	}
+	
+	#[test]
+	fn limits_uploads() {
+		let limited = router_with(ServiceOptions { max_upload: Some(RECORD.len() * 2), max_held: 1 << 20 });
+		let stats = |records: usize| Request::post("/stats").body(Body::from(RECORD.repeat(records))).unwrap();
+		assert_eq!(status(limited.clone(), stats(2)), StatusCode::OK);
+		assert_eq!(status(limited.clone(), stats(3)), StatusCode::PAYLOAD_TOO_LARGE);
+		assert_eq!(status(limited.clone(), form(RECORD, RECORD)), StatusCode::OK);
+		assert_eq!(status(limited, form(RECORD, &RECORD.repeat(2))), StatusCode::PAYLOAD_TOO_LARGE);
+	}
+	
+	#[test]
+	fn limits_held_part() {
+		let limited = router_with(ServiceOptions { max_upload: None, max_held: RECORD.len() });
+		assert_eq!(status(limited.clone(), form(RECORD, &RECORD.repeat(2))), StatusCode::OK);
+		assert_eq!(status(limited, form(&RECORD.repeat(2), RECORD)), StatusCode::PAYLOAD_TOO_LARGE);
+		// larger than axum’s default limit of 2 MB
+		let large = RECORD.repeat((3 << 20) / RECORD.len());
+		assert_eq!(status(router(), form(&large, &large)), StatusCode::OK);
+	}
+}
@@ -0,0 +1,128 @@
+//! Deterministic shuffling of record streams larger than memory.
+//!
+//! Records are collected into runs that fit the memory budget, each run is shuffled
+//! and spilled to a temporary file, and the runs are merged by repeatedly drawing
+//! the next record from a run chosen with probability proportional to its remaining size.
+//! This yields a uniformly random permutation that only depends on the seed.
+
+use std::fs;
+use std::io::{self, Write};
+
+use super::rng::Rng;
+use super::tempstore::{TempStore, TempFile};
+use super::unfancy_parser::{Reader, Record, Records, Error};
+
+/// Shuffle records into `output` using at most about `memory_budget` bytes of record data in memory.
+/// Returns the number of records written.
+pub fn shuffle<I, W>(records: I, output: W, seed: u64, memory_budget: usize) -> Result<usize, Error>
+	where I: IntoIterator<Item=Result<Record, Error>>, W: Write
+{
+	shuffle_in(&TempStore::new()?, records, output, seed, memory_budget)
+}
+
+/// Like `shuffle`, spilling runs into the given temporary store.
+pub fn shuffle_in<I, W>(store: &TempStore, records: I, mut output: W, seed: u64, memory_budget: usize) -> Result<usize, Error>
+	where I: IntoIterator<Item=Result<Record, Error>>, W: Write
+{
+	let mut rng = Rng::new(seed);
+	let mut runs: Vec<(TempFile, usize)> = Vec::new();
+	let mut buffer = Vec::new();
+	let mut buffered = 0;
+	for record in records {
+		let record = record?;
+		buffered += record.raw_len();
+		buffer.push(record);
+		if buffered >= memory_budget {
+			runs.push(spill(store, &mut buffer, &mut rng)?);
+			buffered = 0;
+		}
+	}
+	
+	if runs.is_empty() {
+		rng.shuffle(&mut buffer);
+		for record in &buffer { record.write_raw(&mut output)?; }
+		return Ok(buffer.len());
+	}
+	if !buffer.is_empty() { runs.push(spill(store, &mut buffer, &mut rng)?) }
+	
+	let mut sources: Vec<(Records<fs::File>, usize)> = Vec::with_capacity(runs.len());
+	for (file, n) in &runs {
+		sources.push((Reader::new(fs::File::open(file.path())?).records(), *n));
+	}
+	let mut remaining: usize = sources.iter().map(|s| s.1).sum();
+	let total = remaining;
+	while remaining > 0 {
+		let mut pick = rng.below(remaining as u64) as usize;
+		let source = sources.iter_mut().find(|s| if pick < s.1 { true } else { pick -= s.1; false }).unwrap();
+		let record = source.0.next().ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Shuffle run ended early."))??;
+		source.1 -= 1;
+		remaining -= 1;
+		record.write_raw(&mut output)?;
+	}
+	Ok(total)
+}
+
+fn spill(store: &TempStore, buffer: &mut Vec<Record>, rng: &mut Rng) -> Result<(TempFile, usize), Error> {
+	rng.shuffle(buffer);
+	let mut file = io::BufWriter::new(store.file()?);
+	for record in buffer.iter() { record.write_raw(&mut file)?; }
+	let n = buffer.len();
+	buffer.clear();
+	let file = file.into_inner().map_err(|e| e.into_error())?;
+	Ok((file, n))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use crate::Record as RecordTrait;
+	
+	fn records(n: usize) -> Vec<Result<Record, Error>> {
+		(0..n).map(|i| Ok(Record::from_lines(format!("@r{}\n", i), "ACGT\n".to_owned(), "IIII\n".to_owned()))).collect()
+	}
+	
+	fn shuffled_ids(n: usize, seed: u64, memory_budget: usize) -> Vec<String> {
+		let mut out = Vec::new();
+		assert_eq!(shuffle(records(n), &mut out, seed, memory_budget).unwrap(), n);
+		Reader::new(&out[..]).records().map(|r| r.unwrap().id().unwrap().to_owned()).collect()
+	}
+	
+	fn sorted(mut ids: Vec<String>) -> Vec<String> {
+		ids.sort();
+		ids
+	}
+	
+	#[test]
+	fn same_seed_gives_same_permutation() {
+		for budget in [1, 50, 1 << 20] {
+			let ids = shuffled_ids(100, 42, budget);
+			assert_eq!(ids, shuffled_ids(100, 42, budget), "budget {}", budget);
+			assert_ne!(ids, sorted(ids.clone()), "budget {}", budget);
+		}
+	}
+	
+	#[test]
+	fn different_seeds_give_different_permutations() {
+		for budget in [1, 1 << 20] {
+			assert_ne!(shuffled_ids(100, 1, budget), shuffled_ids(100, 2, budget), "budget {}", budget);
+		}
+	}
+	
+	#[test]
+	fn merging_runs_keeps_all_records() {
+		let expected = sorted(records(100).into_iter().map(|r| r.unwrap().id().unwrap().to_owned()).collect());
+		for budget in [1, 30, 200, 1 << 20] {
+			let ids = shuffled_ids(100, 7, budget);
+			assert_eq!(sorted(ids), expected, "budget {}", budget);
+		}
+		assert!(shuffled_ids(0, 7, 1).is_empty());
+	}
+	
+	#[test]
+	fn spills_runs_beyond_the_budget() {
+		let store = TempStore::with_limit(env::temp_dir(), 0).unwrap();
+		assert!(shuffle_in(&store, records(10), io::sink(), 3, 1 << 20).is_ok());
+		assert!(shuffle_in(&store, records(10), io::sink(), 3, 1).is_err());
+	}
+}
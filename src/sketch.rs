@@ -0,0 +1,300 @@
+//! Comparing many files at once by MinHash sketches of their k-mers, e.g. to spot sample swaps in a delivery.
+//!
+//! A sketch keeps the smallest hashes of a file’s canonical k-mers, so reads from either strand count the same.
+//! The Jaccard similarity of two sketches estimates that of the files’ k-mer sets.
+//! Sequencing errors add k-mers found in one file only, so even replicates stay well below 1;
+//! what stands out is which files are much more similar to each other than to the rest.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use super::Record as RecordTrait;
+use super::input::Input;
+use super::unfancy_parser::{Reader, Error};
+
+/// How files are sketched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SketchOptions {
+	/// The k-mer length, from 1 to 32.
+	pub k: usize,
+	/// The number of hashes kept per file. Larger sketches give more exact similarities.
+	pub sketch_size: usize,
+	/// Only sketch the first reads of each file, which is plenty to tell samples apart. `None` reads them all.
+	pub max_reads: Option<u64>,
+	/// The number of files sketched at once.
+	pub threads: usize,
+}
+
+impl Default for SketchOptions {
+	fn default() -> SketchOptions {
+		let threads = thread::available_parallelism().map_or(1, |n| n.get());
+		SketchOptions { k: 21, sketch_size: 1000, max_reads: Some(1_000_000), threads }
+	}
+}
+
+/// A bottom-k MinHash sketch of the canonical k-mers of some sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sketch {
+	k: usize,
+	size: usize,
+	hashes: BTreeSet<u64>,
+}
+
+impl Sketch {
+	/// An empty sketch of k-mers of length `k` (clamped to 1–32) keeping `size` hashes (at least 1).
+	pub fn new(k: usize, size: usize) -> Sketch {
+		Sketch { k: k.clamp(1, 32), size: size.max(1), hashes: BTreeSet::new() }
+	}
+	
+	/// Sketch the first `options.max_reads` records.
+	pub fn from_records<I, R>(records: I, options: &SketchOptions) -> Result<Sketch, Error> where I: IntoIterator<Item=Result<R, Error>>, R: RecordTrait {
+		let mut sketch = Sketch::new(options.k, options.sketch_size);
+		for record in records.into_iter().take(options.max_reads.map_or(usize::MAX, |n| n as usize)) {
+			sketch.add(record?.seq());
+		}
+		Ok(sketch)
+	}
+	
+	/// Sketch a FastQ file, which may be gzip compressed.
+	pub fn from_file<P: AsRef<Path>>(path: P, options: &SketchOptions) -> Result<Sketch, Error> {
+		Sketch::from_records(Reader::new(Input::open(path)?).records(), options)
+	}
+	
+	/// Add the k-mers of a sequence. K-mers with other bases than `ACGT` are skipped.
+	pub fn add(&mut self, seq: &[u8]) {
+		let k = self.k;
+		let mask = if k == 32 { u64::MAX } else { (1 << (2 * k)) - 1 };
+		let (mut forward, mut reverse, mut len) = (0u64, 0u64, 0);
+		for &b in seq {
+			let code = match b.to_ascii_uppercase() {
+				b'A' => 0,
+				b'C' => 1,
+				b'G' => 2,
+				b'T' => 3,
+				_ => { len = 0; continue },
+			};
+			forward = (forward << 2 | code) & mask;
+			reverse = reverse >> 2 | (3 - code) << (2 * (k - 1));
+			len += 1;
+			if len >= k { self.insert(mix(forward.min(reverse))) }
+		}
+	}
+	
+	fn insert(&mut self, hash: u64) {
+		if self.hashes.len() == self.size {
+			if hash >= *self.hashes.last().unwrap() { return }
+			if self.hashes.insert(hash) { self.hashes.pop_last(); }
+		} else {
+			self.hashes.insert(hash);
+		}
+	}
+	
+	/// The number of hashes kept, which is less than the size if there were fewer k-mers.
+	pub fn len(&self) -> usize { self.hashes.len() }
+	
+	/// Check if no k-mer was added.
+	pub fn is_empty(&self) -> bool { self.hashes.is_empty() }
+	
+	/// The estimated Jaccard similarity of the two k-mer sets, from 0 to 1; 0 if either is empty.
+	/// Both sketches should have the same k.
+	pub fn jaccard(&self, other: &Sketch) -> f64 {
+		if self.is_empty() || other.is_empty() { return 0. }
+		// the smallest hashes of the union are a sketch of the union
+		let union: Vec<u64> = self.hashes.union(&other.hashes).copied().take(self.size.min(other.size)).collect();
+		let shared = union.iter().filter(|h| self.hashes.contains(h) && other.hashes.contains(h)).count();
+		shared as f64 / union.len() as f64
+	}
+}
+
+/// The SplitMix64 finalizer, spreading k-mer codes uniformly over all hashes.
+fn mix(mut z: u64) -> u64 {
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	z ^ (z >> 31)
+}
+
+/// Pairwise similarities of some files, see `compare_many`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimilarityMatrix {
+	names: Vec<String>,
+	values: Vec<f64>,
+}
+
+impl SimilarityMatrix {
+	/// The similarities of named sketches.
+	pub fn from_sketches(names: Vec<String>, sketches: &[Sketch]) -> SimilarityMatrix {
+		let n = sketches.len();
+		let mut values = vec![1.; n * n];
+		for i in 0..n {
+			for j in i + 1..n {
+				let similarity = sketches[i].jaccard(&sketches[j]);
+				values[i * n + j] = similarity;
+				values[j * n + i] = similarity;
+			}
+		}
+		SimilarityMatrix { names, values }
+	}
+	
+	/// The names of the files, in the order of rows and columns.
+	pub fn names(&self) -> &[String] { &self.names }
+	
+	/// The number of files.
+	pub fn len(&self) -> usize { self.names.len() }
+	
+	/// Check if there are no files.
+	pub fn is_empty(&self) -> bool { self.names.is_empty() }
+	
+	/// The similarity of files `i` and `j`, 1 for `i == j`.
+	pub fn get(&self, i: usize, j: usize) -> f64 { self.values[i * self.len() + j] }
+	
+	/// The other file most similar to file `i` and their similarity, `None` if there is no other.
+	pub fn most_similar(&self, i: usize) -> Option<(usize, f64)> {
+		(0..self.len()).filter(|&j| j != i).map(|j| (j, self.get(i, j))).max_by(|a, b| a.1.total_cmp(&b.1))
+	}
+	
+	/// Write the matrix as CSV, with the file names as first row and first column.
+	pub fn write_csv<W: Write>(&self, out: W) -> io::Result<()> {
+		let mut out = io::BufWriter::new(out);
+		write!(out, "file")?;
+		for name in &self.names { write!(out, ",{}", csv_field(name))? }
+		writeln!(out)?;
+		for (i, name) in self.names.iter().enumerate() {
+			write!(out, "{}", csv_field(name))?;
+			for j in 0..self.len() { write!(out, ",{:.4}", self.get(i, j))? }
+			writeln!(out)?;
+		}
+		out.flush()
+	}
+}
+
+/// Quote a CSV field if it needs it.
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_owned()
+	}
+}
+
+/// An aligned table with numbered rows and columns, each row ending in the file name.
+impl fmt::Display for SimilarityMatrix {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let width = self.len().to_string().len();
+		write!(f, "{:>width$}", "", width = width)?;
+		for j in 0..self.len() { write!(f, " {:>6}", j + 1)? }
+		writeln!(f)?;
+		for (i, name) in self.names.iter().enumerate() {
+			write!(f, "{:>width$}", i + 1, width = width)?;
+			for j in 0..self.len() { write!(f, " {:>6.3}", self.get(i, j))? }
+			writeln!(f, "  {}", name)?;
+		}
+		Ok(())
+	}
+}
+
+/// Sketch FastQ files, which may be gzip compressed, on `options.threads` threads and compare them pairwise.
+/// Fails with the first error of any file.
+pub fn compare_many<P: AsRef<Path> + Sync>(paths: &[P], options: &SketchOptions) -> Result<SimilarityMatrix, Error> {
+	let next = AtomicUsize::new(0);
+	let sketches = Mutex::new((0..paths.len()).map(|_| None).collect::<Vec<_>>());
+	thread::scope(|scope| {
+		for _ in 0..options.threads.clamp(1, paths.len().max(1)) {
+			scope.spawn(|| loop {
+				let i = next.fetch_add(1, Ordering::Relaxed);
+				let Some(path) = paths.get(i) else { break };
+				let sketch = Sketch::from_file(path, options);
+				let failed = sketch.is_err();
+				sketches.lock().unwrap()[i] = Some(sketch);
+				if failed { next.store(paths.len(), Ordering::Relaxed) }
+			});
+		}
+	});
+	// files after a failure are left unsketched, so take the first error before the gaps
+	let sketches: Vec<Sketch> = sketches.into_inner().unwrap().into_iter().map_while(|s| s).collect::<Result<_, _>>()?;
+	let names = paths.iter().map(|p| p.as_ref().display().to_string()).collect();
+	Ok(SimilarityMatrix::from_sketches(names, &sketches))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashSet;
+	
+	fn sketch(seq: &str, k: usize, size: usize) -> Sketch {
+		let mut sketch = Sketch::new(k, size);
+		sketch.add(seq.as_bytes());
+		sketch
+	}
+	
+	fn reverse_complement(seq: &str) -> String {
+		seq.bytes().rev().map(|b| match b { b'A' => 'T', b'C' => 'G', b'G' => 'C', _ => 'A' }).collect()
+	}
+	
+	/// The exact Jaccard similarity of the canonical k-mer sets.
+	fn exact_jaccard(a: &str, b: &str, k: usize) -> f64 {
+		let kmers = |seq: &str| -> HashSet<String> {
+			(0..=seq.len() - k).map(|i| {
+				let kmer = &seq[i..i + k];
+				kmer.to_owned().min(reverse_complement(kmer))
+			}).collect()
+		};
+		let (a, b) = (kmers(a), kmers(b));
+		a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+	}
+	
+	const A: &str = "ACGTTGCATGCCATAGGCTTAACCGGATCGATTGCAAGT";
+	const B: &str = "ACGTTGCATGCCATAGGCTTAGGGTCCATTAGCACCTAT";
+	
+	#[test]
+	fn jaccard_is_exact_for_sketches_holding_all_kmers() {
+		let (a, b) = (sketch(A, 5, 1000), sketch(B, 5, 1000));
+		assert_eq!(a.jaccard(&a), 1.);
+		assert_eq!(a.jaccard(&b), exact_jaccard(A, B, 5));
+		assert_eq!(a.jaccard(&b), b.jaccard(&a));
+		assert!(a.jaccard(&b) > 0. && a.jaccard(&b) < 1.);
+		assert_eq!(sketch(&reverse_complement(A), 5, 1000).jaccard(&a), 1.);
+		assert_eq!(sketch("AAAAAAAA", 5, 1000).jaccard(&sketch("CGCGCGCG", 5, 1000)), 0.);
+		assert_eq!(sketch("", 5, 1000).jaccard(&a), 0.);
+	}
+	
+	#[test]
+	fn skips_kmers_with_other_bases() {
+		assert_eq!(sketch("ACGTNACGT", 4, 1000).len(), 1);
+		assert_eq!(sketch("ACGNACG", 4, 1000).len(), 0);
+		assert_eq!(sketch("acgtacgt", 4, 1000), sketch("ACGTACGT", 4, 1000));
+	}
+	
+	#[test]
+	fn keeps_the_smallest_hashes() {
+		let full = sketch(A, 5, 1000);
+		let small = sketch(A, 5, 10);
+		assert_eq!(small.len(), 10);
+		assert!(small.hashes.iter().eq(full.hashes.iter().take(10)));
+		// a sketch of the same k-mers in another order is the same
+		let mut reordered = Sketch::new(5, 10);
+		reordered.add(&A.as_bytes()[20..]);
+		reordered.add(&A.as_bytes()[..24]);
+		assert_eq!(reordered, small);
+	}
+	
+	#[test]
+	fn matrix() {
+		let sketches = [sketch(A, 5, 1000), sketch(B, 5, 1000), sketch(&reverse_complement(A), 5, 1000)];
+		let names = vec!["a".to_owned(), "b,1".to_owned(), "a rc".to_owned()];
+		let matrix = SimilarityMatrix::from_sketches(names, &sketches);
+		assert_eq!(matrix.get(1, 1), 1.);
+		assert_eq!(matrix.get(0, 1), matrix.get(1, 0));
+		assert_eq!(matrix.most_similar(0), Some((2, 1.)));
+		assert_eq!(matrix.get(1, 0), matrix.get(1, 2));
+		let mut csv = Vec::new();
+		matrix.write_csv(&mut csv).unwrap();
+		let csv = String::from_utf8(csv).unwrap();
+		assert!(csv.starts_with("file,a,\"b,1\",a rc\na,1.0000,"), "{}", csv);
+	}
+}
@@ -0,0 +1,172 @@
+//! Sorting record streams larger than memory by ID or sequence.
+//!
+//! Records are collected into runs that fit the memory budget, each run is sorted
+//! and spilled to a temporary file, and the runs are merged. The sort is stable:
+//! records with equal keys keep their input order.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::{self, Write};
+
+use super::Record as RecordTrait;
+use super::order::IdOrder;
+use super::tempstore::{TempStore, TempFile};
+use super::unfancy_parser::{Reader, Record, Records, Error};
+
+/// What to sort records by.
+#[derive(Debug, Clone, Copy)]
+pub enum SortKey {
+	/// The read ID in the given order, e.g. `IdOrder::Natural`.
+	Id(IdOrder),
+	/// The sequence, bytewise.
+	Sequence,
+}
+
+impl SortKey {
+	/// Compare two records by this key.
+	pub fn compare<R: RecordTrait>(self, a: &R, b: &R) -> Ordering {
+		match self {
+			SortKey::Id(order) => order.compare(a.id().unwrap_or(""), b.id().unwrap_or("")),
+			SortKey::Sequence => a.seq().cmp(b.seq()),
+		}
+	}
+}
+
+/// Sort records into `output` using at most about `memory_budget` bytes of record data in memory.
+/// Returns the number of records written.
+pub fn sort<I, W>(records: I, output: W, key: SortKey, memory_budget: usize) -> Result<usize, Error>
+	where I: IntoIterator<Item=Result<Record, Error>>, W: Write
+{
+	sort_in(&TempStore::new()?, records, output, key, memory_budget)
+}
+
+/// Like `sort`, spilling runs into the given temporary store.
+pub fn sort_in<I, W>(store: &TempStore, records: I, mut output: W, key: SortKey, memory_budget: usize) -> Result<usize, Error>
+	where I: IntoIterator<Item=Result<Record, Error>>, W: Write
+{
+	let mut runs: Vec<TempFile> = Vec::new();
+	let mut buffer = Vec::new();
+	let mut buffered = 0;
+	for record in records {
+		let record = record?;
+		buffered += record.raw_len();
+		buffer.push(record);
+		if buffered >= memory_budget {
+			runs.push(spill(store, &mut buffer, key)?);
+			buffered = 0;
+		}
+	}
+	
+	if runs.is_empty() {
+		buffer.sort_by(|a, b| key.compare(a, b));
+		for record in &buffer { record.write_raw(&mut output)?; }
+		return Ok(buffer.len());
+	}
+	if !buffer.is_empty() { runs.push(spill(store, &mut buffer, key)?) }
+	
+	let mut sources: Vec<Records<fs::File>> = Vec::with_capacity(runs.len());
+	for file in &runs {
+		sources.push(Reader::new(fs::File::open(file.path())?).records());
+	}
+	let mut heap = BinaryHeap::with_capacity(sources.len());
+	for (run, source) in sources.iter_mut().enumerate() {
+		if let Some(record) = source.next() { heap.push(Reverse(Head { record: record?, run, key })) }
+	}
+	let mut n = 0;
+	while let Some(Reverse(head)) = heap.pop() {
+		head.record.write_raw(&mut output)?;
+		n += 1;
+		if let Some(record) = sources[head.run].next() { heap.push(Reverse(Head { record: record?, ..head })) }
+	}
+	Ok(n)
+}
+
+fn spill(store: &TempStore, buffer: &mut Vec<Record>, key: SortKey) -> Result<TempFile, Error> {
+	buffer.sort_by(|a, b| key.compare(a, b));
+	let mut file = io::BufWriter::new(store.file()?);
+	for record in buffer.iter() { record.write_raw(&mut file)?; }
+	buffer.clear();
+	Ok(file.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// The next record of a run, ordered by key, then run to keep the sort stable.
+struct Head {
+	record: Record,
+	run: usize,
+	key: SortKey,
+}
+
+impl PartialEq for Head {
+	fn eq(&self, other: &Head) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+	fn partial_cmp(&self, other: &Head) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Head {
+	fn cmp(&self, other: &Head) -> Ordering {
+		self.key.compare(&self.record, &other.record).then(self.run.cmp(&other.run))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	
+	/// Records `r<i>` with the sequences of `seqs`, in order.
+	fn records(ids: &[usize], seqs: &[&str]) -> Vec<Result<Record, Error>> {
+		ids.iter().zip(seqs).map(|(i, seq)| {
+			Ok(Record::from_lines(format!("@r{} run\n", i), format!("{}\n", seq), format!("{}\n", "I".repeat(seq.len()))))
+		}).collect()
+	}
+	
+	fn sorted_ids(records: Vec<Result<Record, Error>>, key: SortKey, memory_budget: usize) -> Vec<String> {
+		let mut out = Vec::new();
+		let n = sort(records, &mut out, key, memory_budget).unwrap();
+		let ids: Vec<_> = Reader::new(&out[..]).records().map(|r| r.unwrap().id().unwrap().to_owned()).collect();
+		assert_eq!(ids.len(), n);
+		ids
+	}
+	
+	#[test]
+	fn keeps_equal_keys_in_input_order_across_runs() {
+		let seqs = ["GT", "AC", "GT", "AC", "AA", "GT", "AC", "AA", "GT", "AC"];
+		let ids: Vec<_> = (0..seqs.len()).collect();
+		let expected = ["r4", "r7", "r1", "r3", "r6", "r9", "r0", "r2", "r5", "r8"];
+		for budget in [1, 20, 50, 1 << 20] {
+			assert_eq!(sorted_ids(records(&ids, &seqs), SortKey::Sequence, budget), expected, "budget {}", budget);
+		}
+	}
+	
+	#[test]
+	fn sorts_by_natural_id_or_sequence() {
+		let ids = [10, 2, 1, 100, 20];
+		let seqs = ["AAA", "TTT", "GGG", "CCC", "ACG"];
+		for budget in [1, 1 << 20] {
+			assert_eq!(sorted_ids(records(&ids, &seqs), SortKey::Id(IdOrder::Natural), budget), ["r1", "r2", "r10", "r20", "r100"]);
+			assert_eq!(sorted_ids(records(&ids, &seqs), SortKey::Id(IdOrder::Bytewise), budget), ["r1", "r10", "r100", "r2", "r20"]);
+			assert_eq!(sorted_ids(records(&ids, &seqs), SortKey::Sequence, budget), ["r10", "r20", "r100", "r1", "r2"]);
+		}
+	}
+	
+	#[test]
+	fn spills_runs_beyond_the_budget() {
+		let ids: Vec<_> = (0..4).collect();
+		let seqs = ["AC"; 4];
+		let store = TempStore::with_limit(env::temp_dir(), 0).unwrap();
+		assert!(sort_in(&store, records(&ids, &seqs), io::sink(), SortKey::Sequence, 1 << 20).is_ok());
+		assert!(sort_in(&store, records(&ids, &seqs), io::sink(), SortKey::Sequence, 1).is_err());
+	}
+	
+	#[test]
+	fn passes_on_read_errors() {
+		let mut input = records(&[0, 1], &["AC", "GT"]);
+		input.insert(1, Err(Error::Io(io::Error::other("broken"))));
+		assert!(matches!(sort(input, io::sink(), SortKey::Sequence, 1), Err(Error::Io(_))));
+	}
+}
@@ -0,0 +1,1105 @@
+//! Statistics over record streams.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Record as RecordTrait;
+use super::complexity::{dust_score, entropy};
+use super::input::Input;
+use super::paths::long_path;
+use super::unfancy_parser::{Reader, Error};
+
+/// Mean quality over relative read position, for long reads of very different lengths.
+///
+/// Each read is split into `bins` equally sized parts by percentage of its length,
+/// so a 10 kb and a 1 Mb read both contribute to every bin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelativeQualityProfile {
+	offset: u8,
+	sums: Vec<f64>,
+	counts: Vec<u64>,
+}
+
+impl RelativeQualityProfile {
+	/// Create a profile with `bins` bins for Phred+33 qualities.
+	pub fn new(bins: usize) -> RelativeQualityProfile {
+		RelativeQualityProfile::with_offset(bins, 33)
+	}
+	
+	/// Create a profile with `bins` bins for qualities with the given ASCII offset.
+	pub fn with_offset(bins: usize, offset: u8) -> RelativeQualityProfile {
+		let bins = bins.max(1);
+		RelativeQualityProfile { offset, sums: vec![0.; bins], counts: vec![0; bins] }
+	}
+	
+	/// Account for a record’s qualities.
+	pub fn add<R: RecordTrait>(&mut self, record: &R) {
+		let qual = record.qual();
+		let bins = self.sums.len();
+		for (i, &q) in qual.iter().enumerate() {
+			let bin = i * bins / qual.len();
+			self.sums[bin] += q.saturating_sub(self.offset) as f64;
+			self.counts[bin] += 1;
+		}
+	}
+	
+	/// Merge another profile with the same number of bins into this one.
+	pub fn merge(&mut self, other: &RelativeQualityProfile) {
+		for (s, o) in self.sums.iter_mut().zip(&other.sums) { *s += o }
+		for (c, o) in self.counts.iter_mut().zip(&other.counts) { *c += o }
+	}
+	
+	/// The center of each bin in percent of read length.
+	pub fn positions(&self) -> Vec<f64> {
+		let bins = self.sums.len() as f64;
+		(0..self.sums.len()).map(|i| (i as f64 + 0.5) * 100. / bins).collect()
+	}
+	
+	/// The mean quality in each bin, `NaN` for bins no base fell into.
+	pub fn mean_qualities(&self) -> Vec<f64> {
+		self.sums.iter().zip(&self.counts).map(|(&s, &c)| if c == 0 { f64::NAN } else { s / c as f64 }).collect()
+	}
+	
+	/// The number of bases in each bin.
+	pub fn base_counts(&self) -> &[u64] { &self.counts }
+	
+	/// Write the profile as TSV with the columns `percent`, `mean_quality` and `bases`.
+	pub fn write_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "percent\tmean_quality\tbases")?;
+		for ((p, q), c) in self.positions().iter().zip(self.mean_qualities()).zip(&self.counts) {
+			writeln!(out, "{:.2}\t{:.3}\t{}", p, q, c)?;
+		}
+		Ok(())
+	}
+}
+
+/// Histogram of read lengths, with assembly-style length metrics.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LengthHistogram {
+	counts: BTreeMap<usize, u64>,
+	reads: u64,
+	bases: u64,
+}
+
+impl LengthHistogram {
+	pub fn new() -> LengthHistogram { LengthHistogram::default() }
+	
+	/// Account for a record’s length.
+	pub fn add<R: RecordTrait>(&mut self, record: &R) {
+		self.add_length(record.seq().len());
+	}
+	
+	/// Account for a read of the given length.
+	pub fn add_length(&mut self, len: usize) {
+		*self.counts.entry(len).or_insert(0) += 1;
+		self.reads += 1;
+		self.bases += len as u64;
+	}
+	
+	/// Merge another histogram into this one.
+	pub fn merge(&mut self, other: &LengthHistogram) {
+		for (&len, &c) in &other.counts { *self.counts.entry(len).or_insert(0) += c }
+		self.reads += other.reads;
+		self.bases += other.bases;
+	}
+	
+	pub fn reads(&self) -> u64 { self.reads }
+	pub fn bases(&self) -> u64 { self.bases }
+	
+	/// Counts per length, shortest first.
+	pub fn counts(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+		self.counts.iter().map(|(&l, &c)| (l, c))
+	}
+	
+	pub fn min(&self) -> Option<usize> { self.counts.keys().next().copied() }
+	pub fn max(&self) -> Option<usize> { self.counts.keys().next_back().copied() }
+	
+	pub fn mean(&self) -> f64 {
+		if self.reads == 0 { f64::NAN } else { self.bases as f64 / self.reads as f64 }
+	}
+	
+	/// The length below or at which `percent` % of reads fall (nearest rank).
+	pub fn percentile(&self, percent: f64) -> Option<usize> {
+		if self.reads == 0 { return None }
+		let rank = ((percent.clamp(0., 100.) / 100. * self.reads as f64).ceil() as u64).max(1);
+		let mut seen = 0;
+		self.counts().find(|&(_, c)| { seen += c; seen >= rank }).map(|(l, _)| l)
+	}
+	
+	/// Nx and Lx for `percent` = x: The length of the shortest read, and the number of reads,
+	/// needed to cover `percent` % of all bases when going from the longest read down.
+	fn nx_lx(&self, percent: f64) -> Option<(usize, u64)> {
+		if self.bases == 0 { return None }
+		let target = percent.clamp(0., 100.) / 100. * self.bases as f64;
+		let (mut bases, mut reads) = (0u64, 0u64);
+		for (&len, &c) in self.counts.iter().rev() {
+			if len == 0 { break }
+			let needed = ((target - bases as f64) / len as f64).ceil().max(1.) as u64;
+			if needed <= c { return Some((len, reads + needed)) }
+			bases += len as u64 * c;
+			reads += c;
+		}
+		None
+	}
+	
+	/// The Nx length, e.g. `nx(50.)` for the N50.
+	pub fn nx(&self, percent: f64) -> Option<usize> { self.nx_lx(percent).map(|(n, _)| n) }
+	/// The Lx count, e.g. `lx(50.)` for the L50.
+	pub fn lx(&self, percent: f64) -> Option<u64> { self.nx_lx(percent).map(|(_, l)| l) }
+	
+	pub fn n50(&self) -> Option<usize> { self.nx(50.) }
+	pub fn n90(&self) -> Option<usize> { self.nx(90.) }
+	pub fn l50(&self) -> Option<u64> { self.lx(50.) }
+}
+
+
+/// Reads and bases per nanopore channel and time bin, from ONT header fields.
+///
+/// Uses the `ch=` and `start_time=` fields of the description, as written by MinKNOW and Guppy.
+/// Time bins are aligned to the earliest read start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelYield {
+	bin_secs: i64,
+	bins: BTreeMap<(u32, i64), (u64, u64)>,
+	skipped: u64,
+}
+
+impl ChannelYield {
+	/// Create an aggregation with time bins of the given width (at least a second).
+	pub fn new(bin: Duration) -> ChannelYield {
+		ChannelYield { bin_secs: bin.as_secs().max(1) as i64, bins: BTreeMap::new(), skipped: 0 }
+	}
+	
+	/// Account for a record, returning `false` if it lacks channel or start time.
+	pub fn add<R: RecordTrait>(&mut self, record: &R) -> bool {
+		let desc = record.desc().unwrap_or("");
+		let channel = ont_field(desc, "ch").and_then(|c| c.parse().ok());
+		let start = ont_field(desc, "start_time").and_then(parse_timestamp);
+		match (channel, start) {
+			(Some(channel), Some(start)) => {
+				let bin = self.bins.entry((channel, start.div_euclid(self.bin_secs))).or_insert((0, 0));
+				bin.0 += 1;
+				bin.1 += record.seq().len() as u64;
+				true
+			}
+			_ => { self.skipped += 1; false }
+		}
+	}
+	
+	/// Merge another aggregation with the same bin width into this one.
+	pub fn merge(&mut self, other: &ChannelYield) {
+		for (&k, &(r, b)) in &other.bins {
+			let bin = self.bins.entry(k).or_insert((0, 0));
+			bin.0 += r;
+			bin.1 += b;
+		}
+		self.skipped += other.skipped;
+	}
+	
+	/// The number of records without usable `ch=` or `start_time=` fields.
+	pub fn skipped(&self) -> u64 { self.skipped }
+	
+	/// The number of distinct channels seen.
+	pub fn channels(&self) -> usize {
+		let mut channels: Vec<u32> = self.bins.keys().map(|k| k.0).collect();
+		channels.dedup();
+		channels.len()
+	}
+	
+	/// Write TSV with the columns `channel`, `time` (start of the bin in seconds since the first bin),
+	/// `reads` and `bases`, sorted by channel and time.
+	pub fn write_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "channel\ttime\treads\tbases")?;
+		let first = self.bins.keys().map(|k| k.1).min().unwrap_or(0);
+		for (&(channel, bin), &(reads, bases)) in &self.bins {
+			writeln!(out, "{}\t{}\t{}\t{}", channel, (bin - first) * self.bin_secs, reads, bases)?;
+		}
+		Ok(())
+	}
+}
+
+/// Find the value of a `key=value` field in an ONT description.
+fn ont_field<'a>(desc: &'a str, key: &str) -> Option<&'a str> {
+	desc.split_whitespace().find_map(|f| f.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Parse an RFC 3339 timestamp like `2019-06-13T09:31:53Z` into seconds since the epoch.
+fn parse_timestamp(s: &str) -> Option<i64> {
+	let num = |r: std::ops::Range<usize>| -> Option<i64> { s.get(r)?.parse().ok() };
+	let b = s.as_bytes();
+	if b.len() < 19 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b't' | b' ') || b[13] != b':' || b[16] != b':' { return None }
+	let (y, m, d) = (num(0..4)?, num(5..7)?, num(8..10)?);
+	let secs = num(11..13)? * 3600 + num(14..16)? * 60 + num(17..19)?;
+	let rest = s[19..].trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+	let offset = match rest {
+		"" | "Z" | "z" => 0,
+		_ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+			let o = rest[1..3].parse::<i64>().ok()? * 3600 + rest[4..6].parse::<i64>().ok()? * 60;
+			if rest.starts_with('-') { -o } else if rest.starts_with('+') { o } else { return None }
+		}
+		_ => return None,
+	};
+	// days from civil, see http://howardhinnant.github.io/date_algorithms.html
+	let y = if m <= 2 { y - 1 } else { y };
+	let era = y.div_euclid(400);
+	let yoe = y - era * 400;
+	let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+	let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+	Some(days * 86_400 + secs - offset)
+}
+
+const NUCLEOTIDES: &[u8; 4] = b"ACGT";
+
+fn nucleotide_index(b: u8) -> Option<usize> {
+	match b {
+		b'A' | b'a' => Some(0),
+		b'C' | b'c' => Some(1),
+		b'G' | b'g' => Some(2),
+		b'T' | b't' | b'U' | b'u' => Some(3),
+		_ => None,
+	}
+}
+
+/// Base and dinucleotide counts of one read or many, for spotting library-prep biases.
+///
+/// Only `A`, `C`, `G` and `T`/`U` are counted; dinucleotides spanning other bases are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Composition {
+	bases: [u64; 4],
+	dinucleotides: [[u64; 4]; 4],
+}
+
+impl Composition {
+	/// Create empty counts.
+	pub fn new() -> Composition { Composition::default() }
+	
+	/// The composition of a single sequence.
+	pub fn of(seq: &[u8]) -> Composition {
+		let mut composition = Composition::new();
+		composition.add(seq);
+		composition
+	}
+	
+	/// Count the bases and dinucleotides of a sequence.
+	pub fn add(&mut self, seq: &[u8]) {
+		let mut prev: Option<usize> = None;
+		for &b in seq {
+			let i = nucleotide_index(b);
+			if let Some(i) = i {
+				self.bases[i] += 1;
+				if let Some(p) = prev { self.dinucleotides[p][i] += 1 }
+			}
+			prev = i;
+		}
+	}
+	
+	/// Add the counts of another composition.
+	pub fn merge(&mut self, other: &Composition) {
+		for (a, b) in self.bases.iter_mut().zip(&other.bases) { *a += b }
+		for (row, other_row) in self.dinucleotides.iter_mut().zip(&other.dinucleotides) {
+			for (a, b) in row.iter_mut().zip(other_row) { *a += b }
+		}
+	}
+	
+	/// How often a base occurred.
+	pub fn base_count(&self, base: u8) -> u64 {
+		nucleotide_index(base).map_or(0, |i| self.bases[i])
+	}
+	
+	/// How often a base was followed by another.
+	pub fn dinucleotide_count(&self, first: u8, second: u8) -> u64 {
+		match (nucleotide_index(first), nucleotide_index(second)) {
+			(Some(a), Some(b)) => self.dinucleotides[a][b],
+			_ => 0,
+		}
+	}
+	
+	/// The fraction of all dinucleotides that are `first` followed by `second`.
+	pub fn dinucleotide_frequency(&self, first: u8, second: u8) -> f64 {
+		let total: u64 = self.dinucleotides.iter().flatten().sum();
+		if total == 0 { return f64::NAN }
+		self.dinucleotide_count(first, second) as f64 / total as f64
+	}
+	
+	/// The frequencies of all 16 dinucleotides, in the order `AA`, `AC`, … `TT`.
+	pub fn dinucleotide_frequencies(&self) -> Vec<(String, f64)> {
+		let mut frequencies = Vec::with_capacity(16);
+		for &a in NUCLEOTIDES {
+			for &b in NUCLEOTIDES {
+				frequencies.push((String::from_utf8(vec![a, b]).unwrap(), self.dinucleotide_frequency(a, b)));
+			}
+		}
+		frequencies
+	}
+	
+	/// The observed over the expected frequency of a dinucleotide given its bases’ frequencies,
+	/// e.g. well below 1 for `CG` in vertebrate DNA.
+	pub fn odds_ratio(&self, first: u8, second: u8) -> f64 {
+		let total: u64 = self.bases.iter().sum();
+		if total == 0 { return f64::NAN }
+		let f = |b| self.base_count(b) as f64 / total as f64;
+		self.dinucleotide_frequency(first, second) / (f(first) * f(second))
+	}
+	
+	/// `(G - C) / (G + C)`, positive if G is more frequent than C.
+	pub fn gc_skew(&self) -> f64 {
+		let (g, c) = (self.base_count(b'G') as f64, self.base_count(b'C') as f64);
+		if g + c == 0. { f64::NAN } else { (g - c) / (g + c) }
+	}
+}
+
+/// Accumulates per-file QC statistics from records fed to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsCollector {
+	offset: u8,
+	lengths: LengthHistogram,
+	gc: u64,
+	n: u64,
+	q20: u64,
+	q30: u64,
+	quality_sums: Vec<u64>,
+	quality_counts: Vec<u64>,
+	composition: Composition,
+}
+
+impl Default for StatsCollector {
+	fn default() -> StatsCollector { StatsCollector::new() }
+}
+
+impl StatsCollector {
+	/// Create a collector for Phred+33 qualities.
+	pub fn new() -> StatsCollector { StatsCollector::with_offset(33) }
+	
+	/// Create a collector for qualities with the given ASCII offset.
+	pub fn with_offset(offset: u8) -> StatsCollector {
+		StatsCollector { offset, lengths: LengthHistogram::new(), gc: 0, n: 0, q20: 0, q30: 0, quality_sums: Vec::new(), quality_counts: Vec::new(), composition: Composition::new() }
+	}
+	
+	/// Account for a record.
+	pub fn add<R: RecordTrait>(&mut self, record: &R) {
+		let seq = record.seq();
+		self.lengths.add_length(seq.len());
+		for &b in seq {
+			match b {
+				b'G' | b'C' | b'g' | b'c' | b'S' | b's' => self.gc += 1,
+				b'N' | b'n' => self.n += 1,
+				_ => {},
+			}
+		}
+		self.composition.add(seq);
+		let qual = record.qual();
+		if qual.len() > self.quality_sums.len() {
+			self.quality_sums.resize(qual.len(), 0);
+			self.quality_counts.resize(qual.len(), 0);
+		}
+		for (i, &q) in qual.iter().enumerate() {
+			let q = q.saturating_sub(self.offset);
+			if q >= 20 { self.q20 += 1 }
+			if q >= 30 { self.q30 += 1 }
+			self.quality_sums[i] += q as u64;
+			self.quality_counts[i] += 1;
+		}
+	}
+	
+	/// Merge another collector with the same quality offset into this one, e.g. from another thread.
+	pub fn merge(&mut self, other: &StatsCollector) {
+		self.lengths.merge(&other.lengths);
+		self.gc += other.gc;
+		self.n += other.n;
+		self.q20 += other.q20;
+		self.q30 += other.q30;
+		self.composition.merge(&other.composition);
+		if other.quality_sums.len() > self.quality_sums.len() {
+			self.quality_sums.resize(other.quality_sums.len(), 0);
+			self.quality_counts.resize(other.quality_counts.len(), 0);
+		}
+		for (s, o) in self.quality_sums.iter_mut().zip(&other.quality_sums) { *s += o }
+		for (c, o) in self.quality_counts.iter_mut().zip(&other.quality_counts) { *c += o }
+	}
+	
+	/// The number of records seen.
+	pub fn reads(&self) -> u64 { self.lengths.reads() }
+	
+	/// The read length histogram.
+	pub fn lengths(&self) -> &LengthHistogram { &self.lengths }
+	
+	/// The base and dinucleotide composition of all reads.
+	pub fn composition(&self) -> &Composition { &self.composition }
+	
+	/// Collect the statistics of a FastQ file, which may be gzip compressed.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> Result<StatsCollector, Error> {
+		let mut collector = StatsCollector::new();
+		for record in Reader::new(Input::open(path)?).records() { collector.add(&record?) }
+		Ok(collector)
+	}
+	
+	/// Summarize the records seen so far.
+	pub fn report(&self) -> StatsReport {
+		let bases = self.lengths.bases();
+		let qual_bases: u64 = self.quality_counts.iter().sum();
+		let fraction = |n: u64, of: u64| if of == 0 { f64::NAN } else { n as f64 / of as f64 };
+		StatsReport {
+			reads: self.lengths.reads(),
+			bases,
+			min_length: self.lengths.min().unwrap_or(0),
+			max_length: self.lengths.max().unwrap_or(0),
+			mean_length: self.lengths.mean(),
+			n50: self.lengths.n50().unwrap_or(0),
+			gc_content: fraction(self.gc, bases),
+			gc_skew: self.composition.gc_skew(),
+			n_count: self.n,
+			q20_fraction: fraction(self.q20, qual_bases),
+			q30_fraction: fraction(self.q30, qual_bases),
+			mean_quality: self.quality_sums.iter().zip(&self.quality_counts).map(|(&s, &c)| fraction(s, c)).collect(),
+			length_histogram: self.lengths.counts().collect(),
+			dinucleotide_frequencies: self.composition.dinucleotide_frequencies(),
+		}
+	}
+	
+	/// Pass a report of the records seen so far to `callback` at every `interval`, see `Snapshots`.
+	pub fn with_snapshots<F: FnMut(&StatsReport)>(self, interval: SnapshotInterval, callback: F) -> Snapshots<F> {
+		Snapshots { collector: self, interval, callback, since: 0, last: Instant::now() }
+	}
+}
+
+/// How often `Snapshots` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotInterval {
+	/// After every this many records.
+	Records(u64),
+	/// After the first record once this much time passed since the last snapshot.
+	Time(Duration),
+}
+
+/// A `StatsCollector` streaming intermediate reports to a callback, e.g. for a dashboard during long jobs.
+pub struct Snapshots<F> {
+	collector: StatsCollector,
+	interval: SnapshotInterval,
+	callback: F,
+	/// Records added since the last snapshot.
+	since: u64,
+	last: Instant,
+}
+
+impl<F: FnMut(&StatsReport)> Snapshots<F> {
+	/// Account for a record, taking a snapshot if one is due.
+	pub fn add<R: RecordTrait>(&mut self, record: &R) {
+		self.collector.add(record);
+		self.since += 1;
+		let due = match self.interval {
+			SnapshotInterval::Records(n) => self.since >= n.max(1),
+			SnapshotInterval::Time(duration) => self.last.elapsed() >= duration,
+		};
+		if due { self.snapshot() }
+	}
+	
+	/// Pass a report of the records seen so far to the callback now.
+	pub fn snapshot(&mut self) {
+		(self.callback)(&self.collector.report());
+		self.since = 0;
+		self.last = Instant::now();
+	}
+	
+	/// The collector with everything seen so far.
+	pub fn collector(&self) -> &StatsCollector { &self.collector }
+	
+	/// Stop taking snapshots, returning the collector, e.g. for the final report.
+	pub fn into_inner(self) -> StatsCollector { self.collector }
+}
+
+/// Per-file summary produced by a `StatsCollector`.
+///
+/// Fractions are `NaN` if there were no bases to compute them from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsReport {
+	pub reads: u64,
+	pub bases: u64,
+	pub min_length: usize,
+	pub max_length: usize,
+	pub mean_length: f64,
+	pub n50: usize,
+	/// Fraction of G, C and S bases.
+	pub gc_content: f64,
+	/// `(G - C) / (G + C)`, see `Composition::gc_skew`.
+	pub gc_skew: f64,
+	/// Number of N bases.
+	pub n_count: u64,
+	/// Fraction of bases with a quality of at least 20.
+	pub q20_fraction: f64,
+	/// Fraction of bases with a quality of at least 30.
+	pub q30_fraction: f64,
+	/// Mean quality per read position.
+	pub mean_quality: Vec<f64>,
+	/// Number of reads per length, shortest first.
+	pub length_histogram: Vec<(usize, u64)>,
+	/// Frequency of each dinucleotide, `AA` to `TT`.
+	pub dinucleotide_frequencies: Vec<(String, f64)>,
+}
+
+impl StatsReport {
+	/// Write the summary metrics as TSV with the columns `metric` and `value`.
+	pub fn write_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "metric\tvalue")?;
+		self.write_metrics(&mut out, "")
+	}
+	
+	/// The summary metrics by name, as in `write_tsv`.
+	fn metrics(&self) -> [(&'static str, f64); 11] {
+		[
+			("reads", self.reads as f64),
+			("bases", self.bases as f64),
+			("min_length", self.min_length as f64),
+			("max_length", self.max_length as f64),
+			("mean_length", self.mean_length),
+			("n50", self.n50 as f64),
+			("gc_content", self.gc_content),
+			("gc_skew", self.gc_skew),
+			("n_count", self.n_count as f64),
+			("q20_fraction", self.q20_fraction),
+			("q30_fraction", self.q30_fraction),
+		]
+	}
+	
+	/// The summary metrics that differ in `other`, e.g. the QC of a pipeline’s output compared to its input.
+	/// Metrics that are `NaN` in both are unchanged.
+	pub fn diff(&self, other: &StatsReport) -> StatsDelta {
+		let changes = self.metrics().into_iter().zip(other.metrics())
+			.filter(|&((_, before), (_, after))| before != after && !(before.is_nan() && after.is_nan()))
+			.map(|((metric, before), (_, after))| MetricChange { metric: metric.to_owned(), before, after })
+			.collect();
+		StatsDelta { changes }
+	}
+	
+	/// Write the rows of `write_tsv`, prefixing each metric name.
+	fn write_metrics<W: Write>(&self, out: &mut W, prefix: &str) -> io::Result<()> {
+		writeln!(out, "{}reads\t{}", prefix, self.reads)?;
+		writeln!(out, "{}bases\t{}", prefix, self.bases)?;
+		writeln!(out, "{}min_length\t{}", prefix, self.min_length)?;
+		writeln!(out, "{}max_length\t{}", prefix, self.max_length)?;
+		writeln!(out, "{}mean_length\t{:.3}", prefix, self.mean_length)?;
+		writeln!(out, "{}n50\t{}", prefix, self.n50)?;
+		writeln!(out, "{}gc_content\t{:.5}", prefix, self.gc_content)?;
+		writeln!(out, "{}gc_skew\t{:.5}", prefix, self.gc_skew)?;
+		writeln!(out, "{}n_count\t{}", prefix, self.n_count)?;
+		writeln!(out, "{}q20_fraction\t{:.5}", prefix, self.q20_fraction)?;
+		writeln!(out, "{}q30_fraction\t{:.5}", prefix, self.q30_fraction)?;
+		Ok(())
+	}
+	
+	/// Write the mean quality per position as TSV with the columns `position` (from 1) and `mean_quality`.
+	pub fn write_quality_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "position\tmean_quality")?;
+		for (i, q) in self.mean_quality.iter().enumerate() {
+			writeln!(out, "{}\t{:.3}", i + 1, q)?;
+		}
+		Ok(())
+	}
+	
+	/// Write the dinucleotide frequencies as TSV with the columns `dinucleotide` and `frequency`.
+	pub fn write_dinucleotide_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "dinucleotide\tfrequency")?;
+		for (dinucleotide, f) in &self.dinucleotide_frequencies {
+			writeln!(out, "{}\t{:.5}", dinucleotide, f)?;
+		}
+		Ok(())
+	}
+	
+	/// Write the length histogram as TSV with the columns `length` and `reads`.
+	pub fn write_length_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "length\treads")?;
+		for (len, n) in &self.length_histogram {
+			writeln!(out, "{}\t{}", len, n)?;
+		}
+		Ok(())
+	}
+}
+
+/// A summary metric that differs between two `StatsReport`s.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetricChange {
+	/// The name of the metric, as in `StatsReport::write_tsv`.
+	pub metric: String,
+	pub before: f64,
+	pub after: f64,
+}
+
+impl MetricChange {
+	/// The change relative to the value before, e.g. `-0.5` if it halved.
+	/// Infinite or `NaN` if the value before was 0 or `NaN`.
+	pub fn relative_change(&self) -> f64 { (self.after - self.before) / self.before }
+}
+
+/// The differences between two `StatsReport`s, see `StatsReport::diff`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsDelta {
+	/// The changed metrics, in the order of `StatsReport::write_tsv`.
+	pub changes: Vec<MetricChange>,
+}
+
+impl StatsDelta {
+	/// Check if no metric changed.
+	pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+	
+	/// The change of a metric, if it changed.
+	pub fn get(&self, metric: &str) -> Option<&MetricChange> {
+		self.changes.iter().find(|c| c.metric == metric)
+	}
+	
+	/// Write the changes as TSV with the columns `metric`, `before`, `after` and `relative_change`.
+	pub fn write_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "metric\tbefore\tafter\trelative_change")?;
+		for c in &self.changes {
+			writeln!(out, "{}\t{}\t{}\t{:.5}", c.metric, metric_value(c.before), metric_value(c.after), c.relative_change())?;
+		}
+		Ok(())
+	}
+}
+
+/// Format counts as integers and other metrics with 5 decimals.
+pub(crate) fn metric_value(value: f64) -> String {
+	if value.fract() == 0. { format!("{}", value) } else { format!("{:.5}", value) }
+}
+
+impl fmt::Display for StatsDelta {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for c in &self.changes {
+			writeln!(f, "{:<14} {:>14} -> {:<14} ({:+.2}%)", c.metric, metric_value(c.before), metric_value(c.after), c.relative_change() * 100.)?;
+		}
+		Ok(())
+	}
+}
+
+/// Accumulates QC statistics of read pairs: those of each mate, and how the mates agree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairStatsCollector {
+	mates: [StatsCollector; 2],
+	both_passing: u64,
+	/// Sums of the mate lengths, their squares and products, for their correlation.
+	lengths: [f64; 2],
+	squares: [f64; 2],
+	products: f64,
+}
+
+impl Default for PairStatsCollector {
+	fn default() -> PairStatsCollector { PairStatsCollector::new() }
+}
+
+impl PairStatsCollector {
+	/// Create a collector for Phred+33 qualities.
+	pub fn new() -> PairStatsCollector { PairStatsCollector::with_offset(33) }
+	
+	/// Create a collector for qualities with the given ASCII offset.
+	pub fn with_offset(offset: u8) -> PairStatsCollector {
+		PairStatsCollector {
+			mates: [StatsCollector::with_offset(offset), StatsCollector::with_offset(offset)],
+			both_passing: 0, lengths: [0.; 2], squares: [0.; 2], products: 0.,
+		}
+	}
+	
+	/// Account for a pair, counting both mates as passing.
+	pub fn add<R1: RecordTrait, R2: RecordTrait>(&mut self, r1: &R1, r2: &R2) {
+		self.add_filtered(r1, r2, |_, _| true)
+	}
+	
+	/// Account for a pair, checking each mate with `passes`, which gets its sequence and qualities,
+	/// e.g. `|seq, qual| filter.keep(seq, qual)` for a `filter::ReadFilter`.
+	pub fn add_filtered<R1, R2, F>(&mut self, r1: &R1, r2: &R2, passes: F)
+		where R1: RecordTrait, R2: RecordTrait, F: Fn(&[u8], &[u8]) -> bool
+	{
+		self.mates[0].add(r1);
+		self.mates[1].add(r2);
+		if passes(r1.seq(), r1.qual()) && passes(r2.seq(), r2.qual()) { self.both_passing += 1 }
+		let (l1, l2) = (r1.seq_len() as f64, r2.seq_len() as f64);
+		self.lengths[0] += l1;
+		self.lengths[1] += l2;
+		self.squares[0] += l1 * l1;
+		self.squares[1] += l2 * l2;
+		self.products += l1 * l2;
+	}
+	
+	/// Merge another collector with the same quality offset into this one.
+	pub fn merge(&mut self, other: &PairStatsCollector) {
+		for i in 0..2 {
+			self.mates[i].merge(&other.mates[i]);
+			self.lengths[i] += other.lengths[i];
+			self.squares[i] += other.squares[i];
+		}
+		self.both_passing += other.both_passing;
+		self.products += other.products;
+	}
+	
+	/// The number of pairs seen.
+	pub fn pairs(&self) -> u64 { self.mates[0].reads() }
+	
+	/// The collector of the first mates.
+	pub fn r1(&self) -> &StatsCollector { &self.mates[0] }
+	
+	/// The collector of the second mates.
+	pub fn r2(&self) -> &StatsCollector { &self.mates[1] }
+	
+	/// Summarize the pairs seen so far.
+	pub fn report(&self) -> PairStatsReport {
+		let n = self.pairs() as f64;
+		let variance = |i: usize| self.squares[i] / n - (self.lengths[i] / n).powi(2);
+		let covariance = self.products / n - self.lengths[0] / n * self.lengths[1] / n;
+		let mean_quality = |c: &StatsCollector| c.quality_sums.iter().sum::<u64>() as f64 / c.quality_counts.iter().sum::<u64>() as f64;
+		let (r1, r2) = (self.mates[0].report(), self.mates[1].report());
+		PairStatsReport {
+			pairs: self.pairs(),
+			both_passing_fraction: self.both_passing as f64 / n,
+			length_correlation: covariance / (variance(0) * variance(1)).sqrt(),
+			mean_quality_delta: mean_quality(&self.mates[0]) - mean_quality(&self.mates[1]),
+			quality_delta: r1.mean_quality.iter().zip(&r2.mean_quality).map(|(q1, q2)| q1 - q2).collect(),
+			r1,
+			r2,
+		}
+	}
+}
+
+/// Summary of read pairs produced by a `PairStatsCollector`.
+///
+/// Metrics are `NaN` if there were no pairs, or no bases, to compute them from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PairStatsReport {
+	pub pairs: u64,
+	/// Fraction of pairs where both mates passed the filter of `PairStatsCollector::add_filtered`.
+	pub both_passing_fraction: f64,
+	/// Pearson correlation of the mate lengths, `NaN` if either mate’s length is constant.
+	pub length_correlation: f64,
+	/// Mean quality of the first mates minus that of the second mates.
+	pub mean_quality_delta: f64,
+	/// Mean quality of the first minus the second mates per read position, as long as both have bases there.
+	pub quality_delta: Vec<f64>,
+	/// The first mates’ summary.
+	pub r1: StatsReport,
+	/// The second mates’ summary.
+	pub r2: StatsReport,
+}
+
+impl PairStatsReport {
+	/// Write the pair metrics, then those of each mate prefixed with `r1_` and `r2_`,
+	/// as TSV with the columns `metric` and `value`.
+	pub fn write_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "metric\tvalue")?;
+		writeln!(out, "pairs\t{}", self.pairs)?;
+		writeln!(out, "both_passing_fraction\t{:.5}", self.both_passing_fraction)?;
+		writeln!(out, "length_correlation\t{:.5}", self.length_correlation)?;
+		writeln!(out, "mean_quality_delta\t{:.3}", self.mean_quality_delta)?;
+		self.r1.write_metrics(&mut out, "r1_")?;
+		self.r2.write_metrics(&mut out, "r2_")
+	}
+	
+	/// Write the quality difference per position as TSV with the columns `position` (from 1) and `quality_delta`.
+	pub fn write_quality_delta_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		writeln!(out, "position\tquality_delta")?;
+		for (i, d) in self.quality_delta.iter().enumerate() {
+			writeln!(out, "{}\t{:.3}", i + 1, d)?;
+		}
+		Ok(())
+	}
+}
+
+/// Metrics of a single read, one row of a `PerReadWriter` table.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReadMetrics {
+	pub id: String,
+	pub length: usize,
+	/// Fraction of G, C and S bases, `NaN` for empty reads.
+	pub gc_content: f64,
+	/// Mean quality, `NaN` for empty reads.
+	pub mean_quality: f64,
+	/// Trinucleotide entropy from 0 (repetitive) to 1, see `filter::Complexity::Entropy`.
+	pub entropy: f64,
+	/// DUST score, about 1 for random sequence and higher for repetitive, see `filter::Complexity::Dust`.
+	pub dust: f64,
+	pub n_count: usize,
+}
+
+impl ReadMetrics {
+	/// Compute the metrics of a record with qualities of the given ASCII offset.
+	pub fn of<R: RecordTrait>(record: &R, offset: u8) -> ReadMetrics {
+		let (seq, qual) = (record.seq(), record.qual());
+		let gc = seq.iter().filter(|&&b| matches!(b, b'G' | b'C' | b'g' | b'c' | b'S' | b's')).count();
+		let quality: u64 = qual.iter().map(|&q| q.saturating_sub(offset) as u64).sum();
+		ReadMetrics {
+			id: record.id().unwrap_or("").to_owned(),
+			length: seq.len(),
+			gc_content: gc as f64 / seq.len() as f64,
+			mean_quality: quality as f64 / qual.len() as f64,
+			entropy: entropy(seq),
+			dust: dust_score(seq),
+			n_count: seq.iter().filter(|&&b| b == b'N' || b == b'n').count(),
+		}
+	}
+}
+
+/// Writes one TSV row of `ReadMetrics` per record, with the columns
+/// `id`, `length`, `gc_content`, `mean_quality`, `entropy`, `dust` and `n_count`.
+///
+/// Rows are written as records come in, so tables of any size can be streamed.
+pub struct PerReadWriter<W: Write> {
+	out: io::BufWriter<W>,
+	offset: u8,
+	started: bool,
+}
+
+impl<W: Write> PerReadWriter<W> {
+	/// Write a table for Phred+33 qualities. The header is written with the first row.
+	pub fn new(out: W) -> Self { PerReadWriter::with_offset(out, 33) }
+	
+	/// Write a table for qualities with the given ASCII offset.
+	pub fn with_offset(out: W, offset: u8) -> Self {
+		PerReadWriter { out: io::BufWriter::new(out), offset, started: false }
+	}
+	
+	fn start(&mut self) -> io::Result<()> {
+		if !self.started {
+			self.started = true;
+			writeln!(self.out, "id\tlength\tgc_content\tmean_quality\tentropy\tdust\tn_count")?;
+		}
+		Ok(())
+	}
+	
+	/// Write the row of a record.
+	pub fn write_record<R: RecordTrait>(&mut self, record: &R) -> io::Result<()> {
+		self.write_metrics(&ReadMetrics::of(record, self.offset))
+	}
+	
+	/// Write a row of precomputed metrics.
+	pub fn write_metrics(&mut self, m: &ReadMetrics) -> io::Result<()> {
+		self.start()?;
+		writeln!(self.out, "{}\t{}\t{:.5}\t{:.3}\t{:.5}\t{:.3}\t{}", m.id, m.length, m.gc_content, m.mean_quality, m.entropy, m.dust, m.n_count)
+	}
+	
+	/// Flush the underlying writer, writing the header if no row was written.
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.start()?;
+		self.out.flush()
+	}
+	
+	/// Flush and return the underlying writer.
+	pub fn into_inner(mut self) -> io::Result<W> {
+		self.start()?;
+		self.out.into_inner().map_err(|e| e.into_error())
+	}
+}
+
+/// An iterator adapter feeding every record it passes on to a `StatsCollector`.
+pub struct WithStats<I> {
+	records: I,
+	stats: StatsCollector,
+}
+
+impl<I> WithStats<I> {
+	/// The statistics of the records passed so far.
+	pub fn stats(&self) -> &StatsCollector { &self.stats }
+	
+	/// Stop iterating and return the statistics.
+	pub fn into_stats(self) -> StatsCollector { self.stats }
+}
+
+impl<I, R, E> Iterator for WithStats<I> where I: Iterator<Item=Result<R, E>>, R: RecordTrait {
+	type Item = Result<R, E>;
+	
+	fn next(&mut self) -> Option<Result<R, E>> {
+		let next = self.records.next()?;
+		if let Ok(ref record) = next { self.stats.add(record) }
+		Some(next)
+	}
+}
+
+/// Collect statistics of records while passing them on.
+pub fn with_stats<I, R, E>(records: I, stats: StatsCollector) -> WithStats<I::IntoIter> where I: IntoIterator<Item=Result<R, E>>, R: RecordTrait {
+	WithStats { records: records.into_iter(), stats }
+}
+
+/// The statistics of several files and of all of them together, see `stats_many`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiStats {
+	/// Each file’s statistics, in the order the files were given.
+	pub files: Vec<(PathBuf, StatsReport)>,
+	/// The statistics of all records of all files.
+	pub total: StatsReport,
+}
+
+impl MultiStats {
+	/// Write the summary metrics as TSV with the columns `metric`, one per file named by its path, and `total`.
+	pub fn write_tsv<W: Write>(&self, mut out: W) -> io::Result<()> {
+		write!(out, "metric")?;
+		for (path, _) in &self.files { write!(out, "\t{}", path.display())? }
+		writeln!(out, "\ttotal")?;
+		let metrics: Vec<_> = self.files.iter().map(|(_, report)| report).chain([&self.total]).map(StatsReport::metrics).collect();
+		for (i, &(name, _)) in self.total.metrics().iter().enumerate() {
+			write!(out, "{}", name)?;
+			for values in &metrics { write!(out, "\t{}", metric_value(values[i].1))? }
+			writeln!(out)?;
+		}
+		Ok(())
+	}
+}
+
+/// The FastQ files in a directory, by extension (`.fastq`, `.fq`, optionally followed by `.gz`), sorted by name.
+pub fn fastq_files<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	for entry in fs::read_dir(long_path(dir))? {
+		let path = entry?.path();
+		let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+		let name = name.strip_suffix(".gz").unwrap_or(name);
+		if (name.ends_with(".fastq") || name.ends_with(".fq")) && path.is_file() { files.push(path) }
+	}
+	files.sort();
+	Ok(files)
+}
+
+/// Collect the statistics of FastQ files, which may be gzip compressed, on `threads` threads,
+/// e.g. of the `fastq_files` of a delivery folder. Fails with the first error of any file.
+pub fn stats_many<P: AsRef<Path> + Sync>(paths: &[P], threads: usize) -> Result<MultiStats, Error> {
+	let next = AtomicUsize::new(0);
+	let collectors = Mutex::new((0..paths.len()).map(|_| None).collect::<Vec<_>>());
+	thread::scope(|scope| {
+		for _ in 0..threads.clamp(1, paths.len().max(1)) {
+			scope.spawn(|| loop {
+				let i = next.fetch_add(1, Ordering::Relaxed);
+				let Some(path) = paths.get(i) else { break };
+				let collector = StatsCollector::from_file(path);
+				let failed = collector.is_err();
+				collectors.lock().unwrap()[i] = Some(collector);
+				if failed { next.store(paths.len(), Ordering::Relaxed) }
+			});
+		}
+	});
+	// files after a failure are left unread, so take the first error before the gaps
+	let collectors: Vec<StatsCollector> = collectors.into_inner().unwrap().into_iter().map_while(|c| c).collect::<Result<_, _>>()?;
+	let mut total = StatsCollector::new();
+	for collector in &collectors { total.merge(collector) }
+	let files = paths.iter().zip(&collectors).map(|(path, collector)| (path.as_ref().to_owned(), collector.report())).collect();
+	Ok(MultiStats { files, total: total.report() })
+}
+
+/// A sequencing platform, as told apart by `guess_platform`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Platform {
+	Illumina,
+	Nanopore,
+	PacBio,
+	/// Nanopore or PacBio, if the header does not tell which.
+	LongRead,
+}
+
+impl fmt::Display for Platform {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			Platform::Illumina => "Illumina",
+			Platform::Nanopore => "Nanopore",
+			Platform::PacBio => "PacBio",
+			Platform::LongRead => "long reads (Nanopore or PacBio)",
+		})
+	}
+}
+
+/// The platform a header of the form `id[ description]` looks like it is from:
+/// Casava or legacy Illumina IDs, MinKNOW’s `runid=` and `ch=` fields, or PacBio `movie/zmw/…` IDs.
+pub fn header_platform(header: &str) -> Option<Platform> {
+	let (id, desc) = match header.split_once(char::is_whitespace) {
+		Some((id, desc)) => (id, Some(desc.trim_start())),
+		None => (header, None),
+	};
+	if super::illumina::IlluminaHeader::parse(id, desc).is_some() { return Some(Platform::Illumina) }
+	if let Some(desc) = desc {
+		if ont_field(desc, "runid").is_some() || ont_field(desc, "ch").is_some() { return Some(Platform::Nanopore) }
+	}
+	let mut parts = id.split('/');
+	let movie = parts.next().unwrap_or("");
+	let zmw = parts.next().unwrap_or("");
+	if movie.starts_with('m') && movie.contains('_') && !zmw.is_empty() && zmw.bytes().all(|b| b.is_ascii_digit()) { return Some(Platform::PacBio) }
+	None
+}
+
+/// Guess the platform reads are from by their lengths, cross-checked against a header like the first record’s.
+///
+/// Short reads of mostly one length are Illumina, long reads of varying length Nanopore or PacBio,
+/// which the header tells apart. Short reads of varying length, e.g. after trimming, count as Illumina
+/// if the header says so. `None` if neither tells, or they disagree.
+pub fn guess_platform(stats: &StatsReport, header: Option<&str>) -> Option<Platform> {
+	let from_header = header.and_then(header_platform);
+	if stats.reads == 0 { return from_header }
+	let modal = stats.length_histogram.iter().map(|&(_, n)| n).max().unwrap_or(0);
+	let short = stats.max_length <= 1_000 && stats.n50 <= 600;
+	let uniform = modal * 2 > stats.reads || stats.max_length - stats.min_length <= stats.max_length / 10;
+	let long = stats.n50 >= 1_000 && !uniform;
+	match from_header {
+		Some(Platform::Illumina) if short => Some(Platform::Illumina),
+		None if short && uniform => Some(Platform::Illumina),
+		Some(p @ (Platform::Nanopore | Platform::PacBio)) if !short || !uniform => Some(p),
+		None if long => Some(Platform::LongRead),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::process;
+	
+	#[test]
+	fn stats_of_many_files() {
+		let dir = std::env::temp_dir().join(format!("fastq-stats-test-{}", process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let files = [("a.fq", "@a\nACGT\n+\nIIII\n"), ("b.fastq", "@b\nGG\n+\nII\n@c\nNA\n+\nII\n"), ("notes.txt", "")];
+		for (name, content) in files { fs::write(dir.join(name), content).unwrap() }
+		let paths = fastq_files(&dir).unwrap();
+		assert_eq!(paths, [dir.join("a.fq"), dir.join("b.fastq")]);
+		let stats = stats_many(&paths, 2).unwrap();
+		assert_eq!(stats.files.iter().map(|(_, r)| r.reads).collect::<Vec<_>>(), [1, 2]);
+		assert_eq!((stats.total.reads, stats.total.bases, stats.total.n_count, stats.total.max_length), (3, 8, 1, 4));
+		let mut tsv = Vec::new();
+		stats.write_tsv(&mut tsv).unwrap();
+		let tsv = String::from_utf8(tsv).unwrap();
+		assert!(tsv.starts_with(&format!("metric\t{}\t{}\ttotal\nreads\t1\t2\t3\nbases\t4\t4\t8\n", paths[0].display(), paths[1].display())), "{}", tsv);
+		assert!(stats_many(&[dir.join("a.fq"), dir.join("missing.fq")], 2).is_err());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+	
+	#[test]
+	fn platform_from_lengths_and_header() {
+		let stats = |reads: &[(&str, usize)]| {
+			let fastq: String = reads.iter().map(|&(id, len)| format!("@{}\n{}\n+\n{}\n", id, "A".repeat(len), "I".repeat(len))).collect();
+			let mut collector = StatsCollector::new();
+			for record in Reader::new(fastq.as_bytes()).records() { collector.add(&record.unwrap()) }
+			collector.report()
+		};
+		let illumina = stats(&[("a", 150), ("b", 150), ("c", 150), ("d", 120)]);
+		let long = stats(&[("a", 800), ("b", 12_000), ("c", 4_000), ("d", 25_000)]);
+		let trimmed = stats(&[("a", 150), ("b", 90), ("c", 40), ("d", 120)]);
+		let casava = "M00123:12:000000000-A1B2C:1:1101:15589:1332 1:N:0:ATCACG";
+		let ont = "0a1b2c3d-4e5f-6789-abcd-ef0123456789 runid=abc read=12 ch=345 start_time=2019-06-13T09:31:53Z";
+		let pacbio = "m64011_190830_220126/1/ccs";
+		assert_eq!(header_platform(casava), Some(Platform::Illumina));
+		assert_eq!(header_platform(ont), Some(Platform::Nanopore));
+		assert_eq!(header_platform(pacbio), Some(Platform::PacBio));
+		assert_eq!(header_platform("read1"), None);
+		assert_eq!(guess_platform(&illumina, None), Some(Platform::Illumina));
+		assert_eq!(guess_platform(&illumina, Some(casava)), Some(Platform::Illumina));
+		assert_eq!(guess_platform(&illumina, Some(ont)), None);
+		assert_eq!(guess_platform(&long, None), Some(Platform::LongRead));
+		assert_eq!(guess_platform(&long, Some(ont)), Some(Platform::Nanopore));
+		assert_eq!(guess_platform(&long, Some(pacbio)), Some(Platform::PacBio));
+		assert_eq!(guess_platform(&long, Some(casava)), None);
+		assert_eq!(guess_platform(&trimmed, None), None);
+		assert_eq!(guess_platform(&trimmed, Some(casava)), Some(Platform::Illumina));
+	}
+}
@@ -0,0 +1,250 @@
+//! Advice on archiving FastQ data: which codec, level and quality binning to use, and how large the result will be.
+//!
+//! Sizes are estimated from a `StatsReport` with a simple model of each part of a record:
+//! sequences at the conditional entropy of their dinucleotide frequencies,
+//! qualities at the entropy of their score classes, which binning lowers and makes run-length compressible,
+//! line breaks at the entropy of the length distribution, and headers of typical length and redundancy.
+//! Like those of `memory`, the estimates are rough upper bounds meant for comparing options, not exact sizes.
+
+use std::fmt;
+
+use super::quality::{Encoding, Quality};
+use super::stats::StatsReport;
+
+/// Assumed header length if it is not known.
+const DEFAULT_HEADER_LEN: f64 = 40.;
+/// Raw sizes below which compatibility matters more than a few percent of space.
+const SMALL_DATASET_BYTES: u64 = 64 * 1024 * 1024;
+/// Reads longer than this are treated as long reads, whose qualities are kept.
+const MAX_SHORT_READ_LENGTH: usize = 1000;
+/// The Q30 fraction from which binning costs downstream tools little.
+const MIN_BINNING_Q30_FRACTION: f64 = 0.75;
+
+/// A general purpose compressor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Codec {
+	Gzip,
+	Zstd,
+	Xz,
+}
+
+impl Codec {
+	/// The file extension of the codec, without dot.
+	pub fn extension(self) -> &'static str {
+		match self {
+			Codec::Gzip => "gz",
+			Codec::Zstd => "zst",
+			Codec::Xz => "xz",
+		}
+	}
+	
+	/// The level the advice is given for: the default of gzip, and the strongest commonly used one of the others.
+	pub fn level(self) -> u32 {
+		match self {
+			Codec::Gzip => 6,
+			Codec::Zstd => 19,
+			Codec::Xz => 9,
+		}
+	}
+	
+	/// Bits per base relative to the sequence entropy, per quality relative to the quality entropy,
+	/// and the compressed fraction of headers.
+	fn efficiency(self) -> (f64, f64, f64) {
+		match self {
+			Codec::Gzip => (1.12, 1.2, 0.15),
+			Codec::Zstd => (1.04, 1.05, 0.1),
+			Codec::Xz => (1., 1., 0.08),
+		}
+	}
+}
+
+impl fmt::Display for Codec {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			Codec::Gzip => "gzip",
+			Codec::Zstd => "zstd",
+			Codec::Xz => "xz",
+		};
+		f.write_str(name)
+	}
+}
+
+/// A lossy reduction of quality scores to a few levels, which compress much better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QualityBinning {
+	/// Keep every score.
+	Lossless,
+	/// Illumina’s eight levels: 2–9 → 6, 10–19 → 15, 20–24 → 22, 25–29 → 27, 30–34 → 33, 35–39 → 37, 40+ → 40.
+	Illumina8,
+	/// Four levels like NovaSeq: 0–2 → 2, 3–17 → 12, 18–29 → 23, 30+ → 37.
+	Illumina4,
+}
+
+impl QualityBinning {
+	/// The score `q` is stored as.
+	pub fn bin(self, q: Quality) -> Quality {
+		let q = q.score();
+		Quality::new(match self {
+			QualityBinning::Lossless => q,
+			QualityBinning::Illumina8 => match q {
+				0..=1 => q,
+				2..=9 => 6,
+				10..=19 => 15,
+				20..=24 => 22,
+				25..=29 => 27,
+				30..=34 => 33,
+				35..=39 => 37,
+				_ => 40,
+			},
+			QualityBinning::Illumina4 => match q {
+				0..=2 => 2,
+				3..=17 => 12,
+				18..=29 => 23,
+				_ => 37,
+			},
+		})
+	}
+	
+	/// Bin a quality string in place. Characters below score 0 are kept.
+	pub fn apply(self, qual: &mut [u8], encoding: Encoding) {
+		if self == QualityBinning::Lossless { return }
+		for c in qual {
+			if let Some(q) = Quality::from_ascii(*c, encoding) { *c = self.bin(q).to_ascii(encoding) }
+		}
+	}
+	
+	/// Bits per quality given the fractions of scores below 20, from 20 to 29 and from 30.
+	fn bits(self, classes: [f64; 3]) -> f64 {
+		let class_entropy = entropy(classes);
+		let [low, mid, high] = classes;
+		// the bits to tell apart the levels within each class, and how well runs of them compress
+		let (within, runs) = match self {
+			QualityBinning::Lossless => (2.5, 1.),
+			QualityBinning::Illumina8 => (low + mid + high * 3f64.log2(), 0.8),
+			QualityBinning::Illumina4 => (low, 0.65),
+		};
+		(class_entropy + within) * runs
+	}
+}
+
+impl fmt::Display for QualityBinning {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			QualityBinning::Lossless => "lossless",
+			QualityBinning::Illumina8 => "8 levels",
+			QualityBinning::Illumina4 => "4 levels",
+		};
+		f.write_str(name)
+	}
+}
+
+/// A way to store a dataset and its estimated size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageOption {
+	pub codec: Codec,
+	/// The compression level, see `Codec::level`.
+	pub level: u32,
+	pub binning: QualityBinning,
+	/// The estimated compressed size in bytes.
+	pub estimated_bytes: u64,
+}
+
+/// The outcome of `advise_storage`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageAdvice {
+	/// The estimated uncompressed size in bytes.
+	pub raw_bytes: u64,
+	/// The option to use.
+	pub recommended: StorageOption,
+	/// Why it was chosen.
+	pub reasons: Vec<String>,
+	/// Every combination of codec and binning, smallest first.
+	pub options: Vec<StorageOption>,
+}
+
+impl StorageAdvice {
+	/// The estimated size of the recommended option relative to the uncompressed data, `NaN` if there is none.
+	pub fn ratio(&self) -> f64 {
+		self.recommended.estimated_bytes as f64 / self.raw_bytes as f64
+	}
+}
+
+/// Recommend how to archive a dataset described by its QC report.
+///
+/// Small datasets are kept as gzip for compatibility, others use zstd, which comes close to xz
+/// but decompresses many times faster. Qualities of short reads that are mostly Q30 or better
+/// are binned to eight levels, which costs variant callers little; other qualities are kept.
+pub fn advise_storage(stats: &StatsReport) -> StorageAdvice {
+	let reads = stats.reads as f64;
+	let bases = stats.bases as f64;
+	let raw_bytes = (reads * (DEFAULT_HEADER_LEN + 6.) + 2. * bases) as u64;
+	let seq_bits = sequence_bits(stats);
+	let length_bits = length_bits(stats);
+	let classes = [1. - stats.q20_fraction, stats.q20_fraction - stats.q30_fraction, stats.q30_fraction];
+	let has_qualities = classes.iter().all(|f| f.is_finite());
+	
+	let estimate = |codec: Codec, binning: QualityBinning| {
+		let (seq_factor, qual_factor, header_factor) = codec.efficiency();
+		let qual_bits = if has_qualities { binning.bits(classes) * qual_factor } else { 0. };
+		let bits = bases * (seq_bits * seq_factor + qual_bits) + reads * length_bits;
+		let bytes = bits / 8. + reads * DEFAULT_HEADER_LEN * header_factor;
+		StorageOption { codec, level: codec.level(), binning, estimated_bytes: bytes.ceil() as u64 }
+	};
+	let mut options = Vec::with_capacity(9);
+	for codec in [Codec::Gzip, Codec::Zstd, Codec::Xz] {
+		for binning in [QualityBinning::Lossless, QualityBinning::Illumina8, QualityBinning::Illumina4] {
+			options.push(estimate(codec, binning));
+		}
+	}
+	options.sort_by_key(|o| o.estimated_bytes);
+	
+	let mut reasons = Vec::new();
+	let codec = if raw_bytes < SMALL_DATASET_BYTES {
+		reasons.push("the dataset is small, so gzip’s compatibility outweighs the space other codecs save".to_owned());
+		Codec::Gzip
+	} else {
+		reasons.push("zstd compresses almost as well as xz and decompresses many times faster".to_owned());
+		Codec::Zstd
+	};
+	let binning = if !has_qualities {
+		QualityBinning::Lossless
+	} else if stats.max_length > MAX_SHORT_READ_LENGTH {
+		reasons.push(format!("reads of up to {} bases are long reads, whose tools rely on exact qualities", stats.max_length));
+		QualityBinning::Lossless
+	} else if stats.q30_fraction >= MIN_BINNING_Q30_FRACTION {
+		reasons.push(format!("{:.0}% of the bases are Q30 or better, so binning to 8 levels loses little", stats.q30_fraction * 100.));
+		QualityBinning::Illumina8
+	} else {
+		reasons.push(format!("only {:.0}% of the bases are Q30 or better, so the exact qualities are worth keeping", stats.q30_fraction * 100.));
+		QualityBinning::Lossless
+	};
+	if stats.min_length != stats.max_length {
+		reasons.push(format!("read lengths vary from {} to {}, which costs about {:.1} bits per read", stats.min_length, stats.max_length, length_bits));
+	}
+	StorageAdvice { raw_bytes, recommended: estimate(codec, binning), reasons, options }
+}
+
+/// Bits per base given the preceding base, from the dinucleotide frequencies, 2 if they are unknown.
+fn sequence_bits(stats: &StatsReport) -> f64 {
+	let pairs: Vec<f64> = stats.dinucleotide_frequencies.iter().map(|&(_, f)| f).filter(|f| f.is_finite()).collect();
+	let total: f64 = pairs.iter().sum();
+	if pairs.len() != 16 || total <= 0. { return 2. }
+	let pairs: Vec<f64> = pairs.iter().map(|f| f / total).collect();
+	let firsts: Vec<f64> = pairs.chunks(4).map(|row| row.iter().sum()).collect();
+	(entropy(pairs) - entropy(firsts)).clamp(0., 2.)
+}
+
+/// Bits per read to encode its length, the entropy of the length distribution.
+fn length_bits(stats: &StatsReport) -> f64 {
+	if stats.reads == 0 { return 0. }
+	entropy(stats.length_histogram.iter().map(|&(_, n)| n as f64 / stats.reads as f64))
+}
+
+/// The Shannon entropy in bits of a probability distribution.
+fn entropy<I: IntoIterator<Item=f64>>(probabilities: I) -> f64 {
+	probabilities.into_iter().filter(|&p| p > 0.).map(|p| -p * p.log2()).sum()
+}
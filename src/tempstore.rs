@@ -0,0 +1,138 @@
+//! Managed temporary files for features that spill records to disk.
+//!
+//! A `TempStore` owns a fresh directory, hands out `TempFile`s inside it,
+//! keeps track of how many bytes they hold and removes everything when dropped.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, AtomicU64, Ordering};
+
+use super::paths::long_dir;
+
+static STORE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+struct Inner {
+	dir: PathBuf,
+	next: AtomicUsize,
+	used: AtomicU64,
+	limit: Option<u64>,
+}
+
+impl Drop for Inner {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.dir);
+	}
+}
+
+/// A directory of temporary files with size accounting and cleanup on drop.
+#[derive(Clone)]
+pub struct TempStore(Arc<Inner>);
+
+impl TempStore {
+	/// Create a store below the system temporary directory.
+	pub fn new() -> io::Result<TempStore> {
+		TempStore::in_dir(env::temp_dir())
+	}
+	
+	/// Create a store below the given directory, which has to exist.
+	pub fn in_dir<P: AsRef<Path>>(base: P) -> io::Result<TempStore> {
+		TempStore::create(base.as_ref(), None)
+	}
+	
+	/// Create a store below the given directory that refuses to hold more than `limit` bytes.
+	pub fn with_limit<P: AsRef<Path>>(base: P, limit: u64) -> io::Result<TempStore> {
+		TempStore::create(base.as_ref(), Some(limit))
+	}
+	
+	fn create(base: &Path, limit: Option<u64>) -> io::Result<TempStore> {
+		loop {
+			let n = STORE_COUNTER.fetch_add(1, Ordering::Relaxed);
+			let dir = long_dir(base).join(format!(".fastq-tmp-{}-{}", process::id(), n));
+			match fs::create_dir(&dir) {
+				Ok(()) => return Ok(TempStore(Arc::new(Inner { dir, next: AtomicUsize::new(0), used: AtomicU64::new(0), limit }))),
+				Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+				Err(e) => return Err(e),
+			}
+		}
+	}
+	
+	/// Create a new, empty temporary file in the store.
+	pub fn file(&self) -> io::Result<TempFile> {
+		let n = self.0.next.fetch_add(1, Ordering::Relaxed);
+		let path = self.0.dir.join(format!("{:06}.tmp", n));
+		let file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+		Ok(TempFile { store: self.0.clone(), path, file, size: 0 })
+	}
+	
+	/// The directory holding the temporary files.
+	pub fn dir(&self) -> &Path { &self.0.dir }
+	
+	/// Bytes currently held by all files of the store.
+	pub fn used(&self) -> u64 { self.0.used.load(Ordering::SeqCst) }
+	
+	/// The maximum number of bytes the store may hold, if any.
+	pub fn limit(&self) -> Option<u64> { self.0.limit }
+}
+
+/// A temporary file that is deleted when dropped.
+///
+/// Writes are accounted against the owning store and fail once its limit would be exceeded.
+pub struct TempFile {
+	store: Arc<Inner>,
+	path: PathBuf,
+	file: fs::File,
+	size: u64,
+}
+
+impl TempFile {
+	/// The path of the file.
+	pub fn path(&self) -> &Path { &self.path }
+	
+	/// Bytes written to the file.
+	pub fn size(&self) -> u64 { self.size }
+	
+	/// Flush pending writes and seek back to the start to read the file again.
+	pub fn rewind(&mut self) -> io::Result<()> {
+		self.file.flush()?;
+		self.file.seek(SeekFrom::Start(0)).map(|_| ())
+	}
+}
+
+impl Write for TempFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = buf.len() as u64;
+		let used = self.store.used.fetch_add(n, Ordering::SeqCst) + n;
+		if let Some(limit) = self.store.limit {
+			if used > limit {
+				self.store.used.fetch_sub(n, Ordering::SeqCst);
+				return Err(io::Error::other(format!("Temporary storage limit of {} bytes exceeded.", limit)));
+			}
+		}
+		let written = self.file.write(buf);
+		let kept = *written.as_ref().unwrap_or(&0) as u64;
+		self.store.used.fetch_sub(n - kept, Ordering::SeqCst);
+		self.size += kept;
+		written
+	}
+	
+	fn flush(&mut self) -> io::Result<()> { self.file.flush() }
+}
+
+impl Read for TempFile {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.file.read(buf) }
+}
+
+impl Seek for TempFile {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> { self.file.seek(pos) }
+}
+
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		self.store.used.fetch_sub(self.size, Ordering::SeqCst);
+		let _ = fs::remove_file(&self.path);
+	}
+}
@@ -0,0 +1,140 @@
+//! Translation of nucleotide sequences into protein records.
+
+use std::fmt;
+use std::io;
+
+use super::Record as RecordTrait;
+use super::complement;
+use super::unfancy_parser::{Record, Error};
+
+const BASES: &[u8; 4] = b"TCAG";
+
+/// A genetic code mapping each of the 64 codons to an amino acid (`*` for stop).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodonTable {
+	amino_acids: [u8; 64],
+}
+
+impl CodonTable {
+	/// The standard code (NCBI table 1).
+	pub fn standard() -> CodonTable {
+		CodonTable::from_ncbi_string(b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG")
+	}
+	
+	/// The vertebrate mitochondrial code (NCBI table 2).
+	pub fn vertebrate_mitochondrial() -> CodonTable {
+		CodonTable::from_ncbi_string(b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG")
+	}
+	
+	/// Create a table from the 64 amino acids in NCBI order (codons sorted by `TCAG`).
+	pub fn from_ncbi_string(amino_acids: &[u8; 64]) -> CodonTable {
+		CodonTable { amino_acids: *amino_acids }
+	}
+	
+	/// Reassign a single codon, e.g. to model a stop codon readthrough.
+	pub fn with_codon(mut self, codon: &[u8; 3], amino_acid: u8) -> CodonTable {
+		if let Some(i) = codon_index(codon) { self.amino_acids[i] = amino_acid }
+		self
+	}
+	
+	/// Translate a codon. Codons containing ambiguous bases translate to `X`.
+	pub fn translate_codon(&self, codon: &[u8]) -> u8 {
+		codon_index(codon).map_or(b'X', |i| self.amino_acids[i])
+	}
+	
+	/// Translate a sequence in the given frame.
+	pub fn translate(&self, seq: &[u8], frame: Frame) -> Vec<u8> {
+		let strand = match frame {
+			Frame::Forward(_) => seq.to_vec(),
+			Frame::Reverse(_) => seq.iter().rev().map(|&b| complement(b)).collect(),
+		};
+		strand.get(frame.offset()..).unwrap_or(&[])
+			.chunks(3).filter(|c| c.len() == 3)
+			.map(|c| self.translate_codon(c))
+			.collect()
+	}
+}
+
+impl Default for CodonTable {
+	fn default() -> CodonTable { CodonTable::standard() }
+}
+
+/// A reading frame: strand and offset (0, 1 or 2) of the first codon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+	Forward(u8),
+	Reverse(u8),
+}
+
+impl Frame {
+	/// All six reading frames.
+	pub const ALL: [Frame; 6] = [
+		Frame::Forward(0), Frame::Forward(1), Frame::Forward(2),
+		Frame::Reverse(0), Frame::Reverse(1), Frame::Reverse(2),
+	];
+	
+	fn offset(self) -> usize {
+		match self { Frame::Forward(o) | Frame::Reverse(o) => (o % 3) as usize }
+	}
+}
+
+impl fmt::Display for Frame {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Frame::Forward(o) => write!(f, "+{}", o % 3 + 1),
+			Frame::Reverse(o) => write!(f, "-{}", o % 3 + 1),
+		}
+	}
+}
+
+/// A translated sequence, displayed as a FASTA record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProteinRecord {
+	pub id: String,
+	pub desc: Option<String>,
+	pub seq: Vec<u8>,
+}
+
+impl fmt::Display for ProteinRecord {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, ">{}", self.id)?;
+		if let Some(ref desc) = self.desc { write!(f, " {}", desc)? }
+		writeln!(f)?;
+		writeln!(f, "{}", String::from_utf8_lossy(&self.seq))
+	}
+}
+
+/// Translate a record in each of the given frames,
+/// naming the results `<id>_<frame>` and noting the frame in the description.
+pub fn translate_record(record: &Record, frames: &[Frame], table: &CodonTable) -> Vec<ProteinRecord> {
+	let id = record.id().unwrap_or("");
+	frames.iter().map(|&frame| {
+		let suffix = match frame { Frame::Forward(o) => format!("f{}", o % 3 + 1), Frame::Reverse(o) => format!("r{}", o % 3 + 1) };
+		ProteinRecord {
+			id: format!("{}_{}", id, suffix),
+			desc: Some(format!("frame={}", frame)),
+			seq: table.translate(record.seq(), frame),
+		}
+	}).collect()
+}
+
+/// Translate every record of a stream and write the proteins as FASTA.
+pub fn write_translations<I, W>(records: I, frames: &[Frame], table: &CodonTable, mut out: W) -> Result<usize, Error>
+	where I: IntoIterator<Item=Result<Record, Error>>, W: io::Write
+{
+	let mut n = 0;
+	for record in records {
+		for protein in translate_record(&record?, frames, table) {
+			write!(out, "{}", protein)?;
+			n += 1;
+		}
+	}
+	Ok(n)
+}
+
+fn codon_index(codon: &[u8]) -> Option<usize> {
+	codon.iter().try_fold(0, |i, &b| {
+		let b = match b.to_ascii_uppercase() { b'U' => b'T', b => b };
+		BASES.iter().position(|&x| x == b).map(|p| i * 4 + p)
+	})
+}
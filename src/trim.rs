@@ -0,0 +1,543 @@
+//! Streaming trimming of records: by quality, to fixed lengths, or to externally computed ranges.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead};
+use std::ops::Range;
+use std::path::Path;
+
+use super::Record as RecordTrait;
+use super::quality::Encoding;
+use super::unfancy_parser::{Record, Error};
+use super::paths::long_path;
+
+/// Per-read ranges to keep, e.g. computed by an external tool.
+///
+/// The text form has one read per line: ID, 0-based start and exclusive end, separated by tabs.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrimTable {
+	ranges: HashMap<String, Range<usize>>,
+}
+
+impl TrimTable {
+	/// Create an empty table.
+	pub fn new() -> TrimTable { TrimTable::default() }
+	
+	/// Read a table from its text form.
+	pub fn from_reader<R: BufRead>(reader: R) -> io::Result<TrimTable> {
+		let mut table = TrimTable::new();
+		for (i, line) in reader.lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() || line.starts_with('#') { continue }
+			let fields: Vec<&str> = line.trim_end().split('\t').collect();
+			let bad_line = || io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: expected <id>\\t<start>\\t<end>", i + 1));
+			if fields.len() != 3 { return Err(bad_line()) }
+			let start = fields[1].parse().map_err(|_| bad_line())?;
+			let end = fields[2].parse().map_err(|_| bad_line())?;
+			if start > end { return Err(bad_line()) }
+			table.insert(fields[0], start..end);
+		}
+		Ok(table)
+	}
+	
+	/// Read a table from a file in its text form.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<TrimTable> {
+		TrimTable::from_reader(io::BufReader::new(fs::File::open(long_path(path))?))
+	}
+	
+	/// Set the range to keep for a read.
+	pub fn insert(&mut self, id: &str, keep: Range<usize>) {
+		self.ranges.insert(id.to_owned(), keep);
+	}
+	
+	/// The range to keep for a read.
+	pub fn get(&self, id: &str) -> Option<Range<usize>> {
+		self.ranges.get(id).cloned()
+	}
+	
+	/// Number of reads in the table.
+	pub fn len(&self) -> usize { self.ranges.len() }
+	
+	/// Check if the table has no reads.
+	pub fn is_empty(&self) -> bool { self.ranges.is_empty() }
+}
+
+/// An iterator adapter applying a `TrimTable` to records.
+pub struct TrimByTable<'t, I> {
+	records: I,
+	table: &'t TrimTable,
+	drop_missing: bool,
+	missing: usize,
+}
+
+impl<'t, I> TrimByTable<'t, I> {
+	/// Drop reads that are not in the table instead of passing them through untrimmed.
+	pub fn drop_missing(mut self, yes: bool) -> Self {
+		self.drop_missing = yes;
+		self
+	}
+	
+	/// Number of reads seen so far that were not in the table.
+	pub fn missing(&self) -> usize { self.missing }
+}
+
+impl<'t, I> Iterator for TrimByTable<'t, I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		loop {
+			let mut record = match self.records.next()? {
+				Ok(record) => record,
+				Err(e) => return Some(Err(e)),
+			};
+			match record.id().and_then(|id| self.table.get(id)) {
+				Some(keep) => record.keep_range(keep),
+				None => {
+					self.missing += 1;
+					if self.drop_missing { continue }
+				},
+			}
+			return Some(Ok(record));
+		}
+	}
+}
+
+/// Trim each record to the range the table lists for its ID.
+pub fn trim_by_table<I>(records: I, table: &TrimTable) -> TrimByTable<'_, I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	TrimByTable { records: records.into_iter(), table, drop_missing: false, missing: 0 }
+}
+
+/// Decides which part of a read to keep.
+pub trait Trimmer {
+	/// The range of the read to keep, given its sequence and qualities.
+	fn keep(&self, seq: &[u8], qual: &[u8]) -> Range<usize>;
+}
+
+/// How low-quality 3′ ends are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityMethod {
+	/// Cut where the sum of `min_q - q` from the 3′ end is largest, like `bwa aln -q`.
+	Bwa,
+	/// Cut at the first window of this many bases with a mean quality below `min_q`, like Trimmomatic.
+	SlidingWindow(usize),
+}
+
+/// Trims low-quality 3′ ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityTrimmer {
+	pub min_q: u8,
+	pub encoding: Encoding,
+	pub method: QualityMethod,
+}
+
+impl Trimmer for QualityTrimmer {
+	fn keep(&self, _seq: &[u8], qual: &[u8]) -> Range<usize> {
+		let offset = self.encoding.offset() as i32;
+		let min_q = self.min_q as i32;
+		let end = match self.method {
+			QualityMethod::Bwa => {
+				let (mut sum, mut max, mut end) = (0, 0, qual.len());
+				for (i, &q) in qual.iter().enumerate().rev() {
+					sum += min_q - (q as i32 - offset);
+					if sum < 0 { break }
+					if sum > max { max = sum; end = i }
+				}
+				end
+			},
+			QualityMethod::SlidingWindow(window) => {
+				let window = window.max(1).min(qual.len().max(1));
+				let threshold = min_q * window as i32;
+				qual.windows(window)
+					.position(|w| w.iter().map(|&q| q as i32 - offset).sum::<i32>() < threshold)
+					.unwrap_or(qual.len())
+			},
+		};
+		0..end
+	}
+}
+
+/// Keeps at most the first `n` bases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop(pub usize);
+
+impl Trimmer for Crop {
+	fn keep(&self, seq: &[u8], _qual: &[u8]) -> Range<usize> { 0..self.0.min(seq.len()) }
+}
+
+/// Removes the first `n` bases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadCrop(pub usize);
+
+impl Trimmer for HeadCrop {
+	fn keep(&self, seq: &[u8], _qual: &[u8]) -> Range<usize> { self.0.min(seq.len())..seq.len() }
+}
+
+/// Settings for finding adapters at 3′ ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdapterOptions {
+	/// Maximum errors (mismatches, insertions and deletions) per aligned adapter base.
+	pub max_error_rate: f64,
+	/// Minimum number of adapter bases a partial match at the read end needs.
+	pub min_overlap: usize,
+	/// Encoding of the qualities to weight mismatches by, or `None` to count each one fully.
+	///
+	/// With qualities, a mismatch counts as the probability that the read’s base call is right,
+	/// so sequencing errors in an adapter cost little. Partial matches at the read end shorter than
+	/// `min_overlap` are then removed too if their `remnant_score` reaches `min_remnant_score`.
+	pub quality: Option<Encoding>,
+	/// Minimum log10 likelihood ratio of adapter over random sequence for a partial match
+	/// shorter than `min_overlap`. The default of 0.5 removes a single matching base of quality 7 or more.
+	pub min_remnant_score: f64,
+}
+
+impl Default for AdapterOptions {
+	fn default() -> AdapterOptions {
+		AdapterOptions { max_error_rate: 0.1, min_overlap: 3, quality: None, min_remnant_score: 0.5 }
+	}
+}
+
+/// The log10 likelihood ratio of the read’s end being `adapter` rather than random sequence,
+/// given the error probabilities of its base calls. `N`s on either side count as no evidence.
+pub fn remnant_score(seq: &[u8], error_probabilities: &[f64], adapter: &[u8]) -> f64 {
+	seq.iter().zip(error_probabilities).zip(adapter)
+		.filter(|&((&b, _), &a)| a != b'N' && !b.eq_ignore_ascii_case(&b'N'))
+		.map(|((&b, &e), &a)| {
+			let p = if a.eq_ignore_ascii_case(&b) { 1. - e } else { e / 3. };
+			(p / 0.25).log10()
+		})
+		.sum()
+}
+
+/// Removes adapters and everything after them.
+///
+/// An adapter is found anywhere in the read, or partially, with only a prefix of it at the read’s end.
+/// `N` matches any base. If several adapters match, the one starting first is removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterTrimmer {
+	adapters: Vec<Vec<u8>>,
+	options: AdapterOptions,
+}
+
+impl AdapterTrimmer {
+	/// Look for any of `adapters`.
+	pub fn new(adapters: &[&[u8]], options: AdapterOptions) -> AdapterTrimmer {
+		AdapterTrimmer { adapters: adapters.iter().map(|a| a.to_ascii_uppercase()).collect(), options }
+	}
+	
+	/// The position at which `adapter` starts in `seq`, by semi-global alignment.
+	/// `error_probabilities` has one entry per base if mismatches are weighted by quality, or none.
+	fn find(&self, seq: &[u8], error_probabilities: &[f64], adapter: &[u8]) -> Option<usize> {
+		let (m, n) = (adapter.len(), seq.len());
+		if m == 0 { return None }
+		let matches = |a: u8, b: u8| a == b'N' || b.eq_ignore_ascii_case(&b'N') || a.eq_ignore_ascii_case(&b);
+		let mismatch_cost = |j: usize| error_probabilities.get(j).map_or(1., |e| 1. - e);
+		let allowed = |len: usize| self.options.max_error_rate * len as f64;
+		let cheaper = |a: (f64, usize), b: (f64, usize)| if b < a { b } else { a };
+		// costs and read start positions of the previous and current adapter prefix
+		let mut prev: Vec<(f64, usize)> = (0..=n).map(|j| (0., j)).collect();
+		let mut cur = vec![(0., 0); n + 1];
+		// start of the best partial match so far
+		let mut best: Option<usize> = None;
+		for i in 1..=m {
+			cur[0] = (i as f64, 0);
+			for j in 1..=n {
+				let substitution = if matches(adapter[i - 1], seq[j - 1]) { 0. } else { mismatch_cost(j - 1) };
+				let diagonal = (prev[j - 1].0 + substitution, prev[j - 1].1);
+				let deletion = (prev[j].0 + 1., prev[j].1);
+				let insertion = (cur[j - 1].0 + 1., cur[j - 1].1);
+				cur[j] = cheaper(cheaper(diagonal, deletion), insertion);
+			}
+			let (errors, start) = cur[n];
+			if i >= self.options.min_overlap.min(m) && errors <= allowed(i) && start < n {
+				best = Some(start);
+			}
+			std::mem::swap(&mut prev, &mut cur);
+		}
+		// a complete match ending anywhere beats a partial one at the end
+		let complete = prev.iter().enumerate()
+			.filter(|&(j, &(errors, start))| errors <= allowed(m) && start < j)
+			.map(|(_, &cost)| cost)
+			.reduce(cheaper)
+			.map(|(_, start)| start);
+		complete.or(best).or_else(|| self.find_remnant(seq, error_probabilities, adapter))
+	}
+	
+	/// The start of the best-scoring partial match at the end of `seq` shorter than `min_overlap`,
+	/// if mismatches are weighted by quality.
+	fn find_remnant(&self, seq: &[u8], error_probabilities: &[f64], adapter: &[u8]) -> Option<usize> {
+		if error_probabilities.is_empty() { return None }
+		let n = seq.len();
+		let max = self.options.min_overlap.min(adapter.len() + 1).min(n + 1);
+		let mut best: Option<(f64, usize)> = None;
+		for k in 1..max {
+			let score = remnant_score(&seq[n - k..], &error_probabilities[n - k..], &adapter[..k]);
+			if score >= self.options.min_remnant_score && best.is_none_or(|(s, _)| score >= s) {
+				best = Some((score, n - k));
+			}
+		}
+		best.map(|(_, start)| start)
+	}
+}
+
+impl Trimmer for AdapterTrimmer {
+	fn keep(&self, seq: &[u8], qual: &[u8]) -> Range<usize> {
+		let error_probabilities: Vec<f64> = match self.options.quality {
+			Some(encoding) => (0..seq.len()).map(|j| match qual.get(j) {
+				Some(&q) => encoding.error_probability(q).min(0.75),
+				None => 0.75,
+			}).collect(),
+			None => Vec::new(),
+		};
+		let end = self.adapters.iter().filter_map(|a| self.find(seq, &error_probabilities, a)).min().unwrap_or(seq.len());
+		0..end
+	}
+}
+
+/// Counts of what a `Trimmed` adapter did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrimStats {
+	/// Reads seen.
+	pub reads: usize,
+	/// Reads that lost at least one base.
+	pub trimmed: usize,
+	/// Bases removed in total.
+	pub bases_removed: usize,
+}
+
+/// An iterator adapter trimming records with a `Trimmer`, keeping sequence and qualities in sync.
+pub struct Trimmed<I, T> {
+	records: I,
+	trimmer: T,
+	stats: TrimStats,
+}
+
+impl<I, T> Trimmed<I, T> {
+	/// What was trimmed so far.
+	pub fn stats(&self) -> TrimStats { self.stats }
+}
+
+impl<I> Trimmed<I, QualityTrimmer> {
+	/// Use a sliding window of `window` bases instead of the BWA algorithm.
+	pub fn sliding_window(mut self, window: usize) -> Self {
+		self.trimmer.method = QualityMethod::SlidingWindow(window);
+		self
+	}
+}
+
+impl<I, T> Iterator for Trimmed<I, T> where I: Iterator<Item=Result<Record, Error>>, T: Trimmer {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		let mut record = self.records.next()?;
+		if let Ok(ref mut record) = record {
+			let len = record.seq_len();
+			let keep = self.trimmer.keep(record.seq(), record.qual());
+			let kept = keep.end.min(len).saturating_sub(keep.start);
+			self.stats.reads += 1;
+			if kept < len {
+				self.stats.trimmed += 1;
+				self.stats.bases_removed += len - kept;
+				record.keep_range(keep);
+			}
+		}
+		Some(record)
+	}
+}
+
+/// Trim records with any `Trimmer`.
+pub fn trim_with<I, T>(records: I, trimmer: T) -> Trimmed<I::IntoIter, T> where I: IntoIterator<Item=Result<Record, Error>>, T: Trimmer {
+	Trimmed { records: records.into_iter(), trimmer, stats: TrimStats::default() }
+}
+
+/// Trim 3′ ends with qualities below `min_q` (a score, not a character) the BWA way.
+pub fn trim_quality<I>(records: I, min_q: u8, encoding: Encoding) -> Trimmed<I::IntoIter, QualityTrimmer> where I: IntoIterator<Item=Result<Record, Error>> {
+	trim_with(records, QualityTrimmer { min_q, encoding, method: QualityMethod::Bwa })
+}
+
+/// Cut reads down to at most `len` bases.
+pub fn crop<I>(records: I, len: usize) -> Trimmed<I::IntoIter, Crop> where I: IntoIterator<Item=Result<Record, Error>> {
+	trim_with(records, Crop(len))
+}
+
+/// Remove the first `n` bases of each read.
+pub fn head_crop<I>(records: I, n: usize) -> Trimmed<I::IntoIter, HeadCrop> where I: IntoIterator<Item=Result<Record, Error>> {
+	trim_with(records, HeadCrop(n))
+}
+
+/// Remove adapters found at the 3′ ends of reads, and everything after them.
+/// The adapter’s `stats()` tell how many reads were trimmed.
+pub fn trim_adapter<I>(records: I, adapters: &[&[u8]], options: AdapterOptions) -> Trimmed<I::IntoIter, AdapterTrimmer> where I: IntoIterator<Item=Result<Record, Error>> {
+	trim_with(records, AdapterTrimmer::new(adapters, options))
+}
+
+/// Adapter and quality trimming settings for common library types, for use cases like the defaults of cutadapt or fastp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimPreset {
+	/// Illumina TruSeq DNA libraries: the TruSeq adapter, then 3′ ends below Q20. Keeps reads of 20 bases or more.
+	IlluminaDna,
+	/// Illumina Nextera and other Tn5 libraries: the Nextera transposase adapter, then 3′ ends below Q20.
+	/// Keeps reads of 20 bases or more.
+	Nextera,
+	/// Illumina small RNA libraries: the small RNA 3′ adapter, then 3′ ends below Q20. Keeps reads of 18 bases or more.
+	SmallRna,
+}
+
+impl TrimPreset {
+	/// All presets.
+	pub const ALL: [TrimPreset; 3] = [TrimPreset::IlluminaDna, TrimPreset::Nextera, TrimPreset::SmallRna];
+	
+	/// The preset’s name, e.g. `illumina-dna`.
+	pub fn name(self) -> &'static str {
+		match self {
+			TrimPreset::IlluminaDna => "illumina-dna",
+			TrimPreset::Nextera => "nextera",
+			TrimPreset::SmallRna => "small-rna",
+		}
+	}
+	
+	/// The preset with this name.
+	pub fn from_name(name: &str) -> Option<TrimPreset> {
+		TrimPreset::ALL.iter().copied().find(|p| p.name() == name)
+	}
+	
+	/// The adapters removed from 3′ ends.
+	pub fn adapters(self) -> &'static [&'static [u8]] {
+		match self {
+			TrimPreset::IlluminaDna => &[b"AGATCGGAAGAGC"],
+			TrimPreset::Nextera => &[b"CTGTCTCTTATACACATCT"],
+			TrimPreset::SmallRna => &[b"TGGAATTCTCGG"],
+		}
+	}
+	
+	/// The quality score 3′ ends are trimmed to after removing adapters.
+	pub fn min_quality(self) -> u8 { 20 }
+	
+	/// The minimum length of reads to keep after trimming.
+	pub fn min_length(self) -> usize {
+		match self {
+			TrimPreset::IlluminaDna | TrimPreset::Nextera => 20,
+			TrimPreset::SmallRna => 18,
+		}
+	}
+	
+	/// The trimmer for reads with qualities in `encoding`.
+	pub fn trimmer(self, encoding: Encoding) -> PresetTrimmer {
+		PresetTrimmer {
+			adapter: AdapterTrimmer::new(self.adapters(), AdapterOptions { quality: Some(encoding), ..AdapterOptions::default() }),
+			quality: QualityTrimmer { min_q: self.min_quality(), encoding, method: QualityMethod::Bwa },
+		}
+	}
+}
+
+/// Removes adapters, then low-quality 3′ ends of what is left, as set up by a `TrimPreset`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresetTrimmer {
+	adapter: AdapterTrimmer,
+	quality: QualityTrimmer,
+}
+
+impl Trimmer for PresetTrimmer {
+	fn keep(&self, seq: &[u8], qual: &[u8]) -> Range<usize> {
+		let adapter = self.adapter.keep(seq, qual);
+		let end = adapter.end.min(qual.len());
+		let quality = self.quality.keep(&seq[adapter.clone()], &qual[adapter.start.min(end)..end]);
+		adapter.start + quality.start..adapter.start + quality.end
+	}
+}
+
+/// Trim records the way `preset` says. Reads shorter than `preset.min_length()` afterwards are not dropped here,
+/// use `filter::min_length` for that.
+pub fn trim_preset<I>(records: I, preset: TrimPreset, encoding: Encoding) -> Trimmed<I::IntoIter, PresetTrimmer> where I: IntoIterator<Item=Result<Record, Error>> {
+	trim_with(records, preset.trimmer(encoding))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::unfancy_parser::Reader;
+	
+	#[test]
+	fn preset_removes_adapter_then_low_quality_end() {
+		// 10 good bases, 4 bad ones, then the TruSeq adapter
+		let seq = "ACGTACGTACTTTTAGATCGGAAGAGCACAC";
+		let qual = format!("{}{}{}", "I".repeat(10), "####", "I".repeat(17));
+		let text = format!("@a\n{}\n+\n{}\n@b\nACGTACGT\n+\nIIIIIIII\n", seq, qual);
+		let mut trimmed = trim_preset(Reader::new(text.as_bytes()).records(), TrimPreset::IlluminaDna, Encoding::Sanger);
+		let a = trimmed.next().unwrap().unwrap();
+		assert_eq!((a.seq(), a.qual()), (&b"ACGTACGTAC"[..], &b"IIIIIIIIII"[..]));
+		assert_eq!(trimmed.next().unwrap().unwrap().seq(), b"ACGTACGT");
+		assert_eq!(trimmed.stats(), TrimStats { reads: 2, trimmed: 1, bases_removed: 21 });
+	}
+	
+	#[test]
+	fn presets_by_name() {
+		for preset in TrimPreset::ALL { assert_eq!(TrimPreset::from_name(preset.name()), Some(preset)) }
+		assert_eq!(TrimPreset::from_name("bogus"), None);
+	}
+	
+	const ADAPTER: &[u8] = b"AGATCGGAAGAGC";
+	
+	fn adapter_end(seq: &str, qual: Option<&str>, options: AdapterOptions) -> usize {
+		let qual = qual.map_or_else(|| "I".repeat(seq.len()), str::to_owned);
+		AdapterTrimmer::new(&[ADAPTER], options).keep(seq.as_bytes(), qual.as_bytes()).end
+	}
+	
+	#[test]
+	fn finds_exact_and_partial_adapters() {
+		let options = AdapterOptions::default();
+		assert_eq!(adapter_end("ACGTACGTACAGATCGGAAGAGCTTTT", None, options), 10);
+		assert_eq!(adapter_end("ACGTACGTACagatcggaagagc", None, options), 10);
+		assert_eq!(adapter_end("ACGTACGTACAGATC", None, options), 10);
+		assert_eq!(adapter_end("ACGTACGTACAGA", None, options), 10);
+		// shorter than min_overlap
+		assert_eq!(adapter_end("ACGTACGTACAG", None, options), 12);
+		assert_eq!(adapter_end("ACGTACGTACAG", None, AdapterOptions { min_overlap: 2, ..options }), 10);
+		assert_eq!(adapter_end("ACGTACGTACGT", None, options), 12);
+	}
+	
+	#[test]
+	fn tolerates_errors_in_adapters() {
+		let options = AdapterOptions::default();
+		// a mismatch, an N, a deletion and an insertion
+		assert_eq!(adapter_end("ACGTACGTACAGATCTGAAGAGCTT", None, options), 10);
+		assert_eq!(adapter_end("ACGTACGTACAGATCGNAAGAGCTT", None, options), 10);
+		assert_eq!(adapter_end("ACGTACGTACAGATCGAAGAGCTT", None, options), 10);
+		assert_eq!(adapter_end("ACGTACGTACAGATCGGTAAGAGCTT", None, options), 10);
+		// two errors are too many for 13 bases at the default rate, but not at a higher one
+		assert_eq!(adapter_end("ACGTACGTACAGTTCGGAAGTGCTT", None, options), 25);
+		assert_eq!(adapter_end("ACGTACGTACAGTTCGGAAGTGCTT", None, AdapterOptions { max_error_rate: 0.2, ..options }), 10);
+	}
+	
+	#[test]
+	fn removes_first_of_several_adapters() {
+		let trimmer = AdapterTrimmer::new(&[ADAPTER, b"CTGTCTCTTATA"], AdapterOptions::default());
+		let seq = b"ACGTACGTCTGTCTCTTATAAGATCGGAAGAGC";
+		assert_eq!(trimmer.keep(seq, &[b'I'; 33]), 0..8);
+		assert_eq!(trimmer.keep(b"ACGT", b"IIII"), 0..4);
+	}
+	
+	#[test]
+	fn weights_mismatches_by_quality() {
+		let options = AdapterOptions { quality: Some(Encoding::Sanger), ..AdapterOptions::default() };
+		// two mismatches in the adapter, at positions 12 and 20
+		let seq = "ACGTACGTACAGTTCGGAAGTGCTT";
+		let low = format!("{}#{}#{}", "I".repeat(12), "I".repeat(7), "I".repeat(4));
+		assert_eq!(adapter_end(seq, Some(&low), options), 10);
+		assert_eq!(adapter_end(seq, None, options), 25);
+		assert_eq!(adapter_end(seq, Some(&low), AdapterOptions { quality: None, ..options }), 25);
+	}
+	
+	#[test]
+	fn removes_short_remnants_by_score() {
+		let options = AdapterOptions { quality: Some(Encoding::Sanger), ..AdapterOptions::default() };
+		assert_eq!(adapter_end("ACGTACGTACGA", None, options), 11);
+		assert_eq!(adapter_end("ACGTACGTACAG", None, options), 10);
+		assert_eq!(adapter_end("ACGTACGTACGA", Some("IIIIIIIIIII#"), options), 12);
+		assert_eq!(adapter_end("ACGTACGTACGA", None, AdapterOptions { min_remnant_score: 1., ..options }), 12);
+		assert_eq!(adapter_end("ACGTACGTACGA", None, AdapterOptions { quality: None, ..options }), 12);
+		assert!((remnant_score(b"AN", &[0., 0.], b"AG") - 4f64.log10()).abs() < 1e-9);
+		assert!(remnant_score(b"C", &[0.], b"A") < 0.);
+	}
+}
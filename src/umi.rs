@@ -0,0 +1,255 @@
+//! Extraction of unique molecular identifiers (UMIs) and grouping reads into UMI families.
+//!
+//! Reads sharing a UMI and the start of their sequence stem from the same molecule,
+//! so each family can be collapsed into one consensus read with PCR and sequencing errors corrected.
+
+use std::collections::HashMap;
+
+use super::Record as RecordTrait;
+use super::cluster::{self, Cluster, Clusterer, Distance};
+use super::extensions::Umi;
+use super::unfancy_parser::{Record, Error};
+
+/// Where the UMI of a read is found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UmiSource {
+	/// The last `:`-separated field of the ID, as written by bcl2fastq and UMI-tools.
+	IdSuffix,
+	/// A description field starting with the given prefix, e.g. `RX:Z:` or `umi=`.
+	Tag(String),
+	/// The first bases of the read, which are cut off.
+	ReadStart(usize),
+}
+
+impl UmiSource {
+	/// Find the UMI of a record and attach it as a `Umi` extension, returning it.
+	pub fn extract(&self, record: &mut Record) -> Option<String> {
+		let umi = match *self {
+			UmiSource::IdSuffix => record.id()
+				.and_then(|id| id.rsplit_once(':'))
+				.map(|(_, umi)| umi)
+				.filter(|umi| !umi.is_empty() && umi.bytes().all(|b| b"ACGTN+".contains(&b)))
+				.map(str::to_owned),
+			UmiSource::Tag(ref prefix) => record.desc()
+				.and_then(|desc| desc.split_whitespace().find_map(|field| field.strip_prefix(prefix.as_str())))
+				.map(str::to_owned),
+			UmiSource::ReadStart(len) => {
+				if record.seq_len() < len { return None }
+				let umi = String::from_utf8_lossy(&record.seq()[..len]).into_owned();
+				record.keep_range(len..record.seq_len());
+				Some(umi)
+			},
+		}?;
+		record.extensions_mut().insert(Umi(umi.clone()));
+		Some(umi)
+	}
+}
+
+/// An iterator attaching UMIs to records, see `extract_umis`.
+pub struct ExtractUmis<'s, I> {
+	records: I,
+	source: &'s UmiSource,
+	missing: usize,
+}
+
+impl<'s, I> ExtractUmis<'s, I> {
+	/// Number of records so far in which no UMI was found.
+	pub fn missing(&self) -> usize { self.missing }
+}
+
+impl<'s, I> Iterator for ExtractUmis<'s, I> where I: Iterator<Item=Result<Record, Error>> {
+	type Item = Result<Record, Error>;
+	
+	fn next(&mut self) -> Option<Result<Record, Error>> {
+		Some(self.records.next()?.map(|mut record| {
+			if self.source.extract(&mut record).is_none() { self.missing += 1 }
+			record
+		}))
+	}
+}
+
+/// Attach the UMI of each record as a `Umi` extension. Records without UMI are passed on unchanged.
+pub fn extract_umis<I>(records: I, source: &UmiSource) -> ExtractUmis<'_, I::IntoIter> where I: IntoIterator<Item=Result<Record, Error>> {
+	ExtractUmis { records: records.into_iter(), source, missing: 0 }
+}
+
+/// Reads of the same molecule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Family {
+	/// The corrected UMI, i.e. the most frequent one of the family.
+	pub umi: String,
+	/// Indices of the family’s reads; `centroid` is a read carrying the corrected UMI.
+	pub reads: Cluster,
+}
+
+impl Family {
+	/// The number of reads in the family.
+	pub fn size(&self) -> usize { self.reads.size() }
+}
+
+/// Groups reads into families by UMI and sequence prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UmiGrouper {
+	prefix_len: usize,
+	max_mismatches: usize,
+	min_family_size: usize,
+}
+
+impl Default for UmiGrouper {
+	fn default() -> UmiGrouper { UmiGrouper::new() }
+}
+
+impl UmiGrouper {
+	/// Group by the first 8 bases and UMIs with at most 1 mismatch.
+	pub fn new() -> UmiGrouper {
+		UmiGrouper { prefix_len: 8, max_mismatches: 1, min_family_size: 1 }
+	}
+	
+	/// Only group reads starting with the same `len` bases (0 to group by UMI alone).
+	pub fn prefix_len(mut self, len: usize) -> Self {
+		self.prefix_len = len;
+		self
+	}
+	
+	/// Merge UMIs differing in up to `n` positions into the more frequent one.
+	pub fn max_mismatches(mut self, n: usize) -> Self {
+		self.max_mismatches = n;
+		self
+	}
+	
+	/// Skip families with fewer reads in `consensus_records`.
+	pub fn min_family_size(mut self, n: usize) -> Self {
+		self.min_family_size = n.max(1);
+		self
+	}
+	
+	/// Group records carrying a `Umi` extension into families. Records without one are left out.
+	pub fn group(&self, records: &[Record]) -> Vec<Family> {
+		let mut by_prefix: HashMap<&[u8], Vec<usize>> = HashMap::new();
+		let mut order = Vec::new();
+		for (i, record) in records.iter().enumerate() {
+			if record.extensions().get::<Umi>().is_none() { continue }
+			let seq = record.seq();
+			let prefix = &seq[..self.prefix_len.min(seq.len())];
+			by_prefix.entry(prefix).or_insert_with(|| { order.push(prefix); Vec::new() }).push(i);
+		}
+		let clusterer = Clusterer::new(Distance::Hamming, self.max_mismatches);
+		let mut families = Vec::new();
+		for prefix in order {
+			let indices = &by_prefix[prefix];
+			let umis: Vec<&str> = indices.iter().map(|&i| records[i].extensions().get::<Umi>().unwrap().0.as_str()).collect();
+			for cluster in clusterer.cluster_keys(&umis).clusters {
+				families.push(Family {
+					umi: umis[cluster.centroid].to_owned(),
+					reads: Cluster {
+						centroid: indices[cluster.centroid],
+						members: cluster.members.iter().map(|&m| indices[m]).collect(),
+					},
+				});
+			}
+		}
+		families
+	}
+	
+	/// Group records and build one consensus read per family of at least `min_family_size` reads.
+	///
+	/// Consensus reads are named after a read with the family’s UMI and carry it as extension,
+	/// with the description `umi=… family_size=…`.
+	pub fn consensus_records(&self, records: &[Record]) -> Vec<Record> {
+		self.group(records).into_iter()
+			.filter(|family| family.size() >= self.min_family_size)
+			.map(|family| {
+				let mut consensus = cluster::consensus(records, &family.reads);
+				let header = format!("{} umi={} family_size={}", consensus.id().unwrap_or(""), family.umi, family.size());
+				consensus.set_header(&header);
+				consensus.extensions_mut().insert(Umi(family.umi));
+				consensus
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn read(head: &str, seq: &str, qual: &str) -> Record {
+		Record::from_lines(format!("@{}\n", head), format!("{}\n", seq), format!("{}\n", qual))
+	}
+	
+	/// Reads with UMIs in their ID suffix, extracted.
+	fn umi_reads(reads: &[(&str, &str, &str)]) -> Vec<Record> {
+		reads.iter().map(|&(head, seq, qual)| {
+			let mut record = read(head, seq, qual);
+			UmiSource::IdSuffix.extract(&mut record);
+			record
+		}).collect()
+	}
+	
+	#[test]
+	fn extracts_umis() {
+		let mut record = read("r1:AACG", "ACGT", "IIII");
+		assert_eq!(UmiSource::IdSuffix.extract(&mut record), Some("AACG".to_owned()));
+		assert_eq!(record.extensions().get::<Umi>(), Some(&Umi("AACG".to_owned())));
+		
+		let mut record = read("r1:lane2 RX:Z:GGTT other", "ACGT", "IIII");
+		assert_eq!(UmiSource::IdSuffix.extract(&mut record), None);
+		assert!(record.extensions().get::<Umi>().is_none());
+		assert_eq!(UmiSource::Tag("RX:Z:".to_owned()).extract(&mut record), Some("GGTT".to_owned()));
+		
+		let mut record = read("r1", "AACGTTTT", "#$%&IIII");
+		assert_eq!(UmiSource::ReadStart(4).extract(&mut record), Some("AACG".to_owned()));
+		assert_eq!((record.seq(), record.qual()), (&b"TTTT"[..], &b"IIII"[..]));
+		assert_eq!(UmiSource::ReadStart(5).extract(&mut record), None);
+		assert_eq!(record.seq(), b"TTTT");
+		
+		let records = vec![Ok(read("a:ACGT", "A", "I")), Ok(read("b", "A", "I")), Ok(read("c:ACGT+TTTT", "A", "I"))];
+		let mut extracted = extract_umis(records, &UmiSource::IdSuffix);
+		let umis: Vec<_> = extracted.by_ref().map(|r| r.unwrap().extensions().get::<Umi>().map(|u| u.0.clone())).collect();
+		assert_eq!(umis, [Some("ACGT".to_owned()), None, Some("ACGT+TTTT".to_owned())]);
+		assert_eq!(extracted.missing(), 1);
+	}
+	
+	#[test]
+	fn groups_families_by_umi_and_prefix() {
+		let records = umi_reads(&[
+			("a:AAAA", "ACGTACGTAA", "IIIIIIIIII"),
+			("b:AAAT", "ACGTACGTAA", "IIIIIIIIII"),
+			("c:AAAA", "ACGTACGTAA", "IIIIIIIIII"),
+			("d:CCCC", "ACGTACGTAA", "IIIIIIIIII"),
+			("e:AAAA", "TTTTTTTTAA", "IIIIIIIIII"),
+			("f", "ACGTACGTAA", "IIIIIIIIII"),
+		]);
+		let family = |umi: &str, centroid, members: &[usize]| Family { umi: umi.to_owned(), reads: Cluster { centroid, members: members.to_vec() } };
+		assert_eq!(UmiGrouper::new().group(&records), [family("AAAA", 0, &[0, 1, 2]), family("CCCC", 3, &[3]), family("AAAA", 4, &[4])]);
+		assert_eq!(UmiGrouper::new().max_mismatches(0).group(&records), [family("AAAA", 0, &[0, 2]), family("AAAT", 1, &[1]), family("CCCC", 3, &[3]), family("AAAA", 4, &[4])]);
+		assert_eq!(UmiGrouper::new().prefix_len(0).group(&records), [family("AAAA", 0, &[0, 1, 2, 4]), family("CCCC", 3, &[3])]);
+	}
+	
+	#[test]
+	fn builds_consensus_per_family() {
+		let records = umi_reads(&[
+			("a:AAAA", "ACGTACGTAA", "##########"),
+			("b:AAAA", "ACGTACGTCA", "IIIIIIIIII"),
+			("c:AAAT", "ACGTACGTCA", "5555555555"),
+			("d:CCCC", "ACGTACGTAA", "IIIIIIIIII"),
+		]);
+		let consensus = UmiGrouper::new().min_family_size(2).consensus_records(&records);
+		assert_eq!(consensus.len(), 1);
+		let c = &consensus[0];
+		assert_eq!((c.id(), c.desc()), (Some("a:AAAA"), Some("umi=AAAA family_size=3")));
+		assert_eq!((c.seq(), c.qual()), (&b"ACGTACGTCA"[..], &b"IIIIIIIIII"[..]));
+		assert_eq!(c.extensions().get::<Umi>(), Some(&Umi("AAAA".to_owned())));
+		assert_eq!(UmiGrouper::new().consensus_records(&records).len(), 2);
+	}
+	
+	#[test]
+	fn consensus_ties_keep_the_centroid_base() {
+		let records = umi_reads(&[
+			("a:AAAA", "ACGTACGTAA", "##########"),
+			("b:AAAA", "ACGTACGTCA", "IIIIIIII5I"),
+		]);
+		let consensus = UmiGrouper::new().consensus_records(&records);
+		assert_eq!((consensus[0].seq(), consensus[0].qual()), (&b"ACGTACGTAA"[..], &b"IIIIIIII#I"[..]));
+	}
+}
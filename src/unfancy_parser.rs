@@ -1,25 +1,190 @@
 use std::io;
+use std::borrow::Cow;
 use std::io::prelude::*;
-use std::ascii::AsciiExt;
 use std::fs;
 use std::fmt;
 use std::path::Path;
 use std::convert::AsRef;
+use std::marker::PhantomData;
+#[cfg(feature = "pipeline")]
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use super::Record as RecordTrait;
+use super::builder::{self, RecordError};
+use super::buffer::{self, AdaptiveReader, BufferSizer};
+use super::pool::{RecordPool, PooledRecords};
+use super::quality::numeric_to_phred33;
+use super::extensions::Extensions;
+use super::input::Input;
+use super::fancy_parser;
+use super::progress::ProgressHook;
+use super::error_hook::{ErrorContext, ErrorHook};
+use super::valid::{Raw, Valid, Validated, ValidationState};
+use super::{Position, Progress, ReaderOptions, TextPolicy, Warning};
+use super::paths::long_path;
 
 
-/// A FastQ reader.
-pub struct Reader<R: io::Read> {
-    reader: io::BufReader<R>,
+quick_error! {
+    /// An error encountered while reading FastQ records.
+    #[derive(Debug)]
+    pub enum Error {
+        /// A record did not start with `@`.
+        MissingAt(pos: Position) {
+            description("No @ at record start")
+            display("{}: Expected @ at record start.", pos)
+        }
+        /// The separator line did not start with `+`.
+        MissingPlus(pos: Position) {
+            description("No + after sequence")
+            display("{}: Expected + after sequence.", pos)
+        }
+        /// Input ended before all four lines of a record were read.
+        IncompleteRecord(pos: Position) {
+            description("Incomplete record")
+            display("{}: Incomplete record. Each FastQ record has to consist \
+                     of 4 lines: header, sequence, separator and qualities.", pos)
+        }
+        /// A numeric quality was no integer from 0 to 93, see `ReaderOptions::numeric_quality`,
+        /// or a quality converted to Phred+33 was no printable character, see `quality::normalize_phred33`.
+        InvalidQuality(pos: Position, value: String) {
+            description("Invalid quality")
+            display("{}: Invalid quality {:?}.", pos, value)
+        }
+        /// A record failed `Record::check` in a reader made by `Reader::validated`.
+        InvalidRecord(pos: Position, reason: String) {
+            description("Invalid record")
+            display("{}: Invalid record: {}", pos, reason)
+        }
+        /// A warning occurred with `ReaderOptions::warnings_fatal`.
+        Warning(pos: Position, warning: Warning) {
+            description("Warning treated as error")
+            display("{}: {}", pos, warning)
+        }
+        /// Reading from the underlying source failed.
+        Io(err: io::Error) {
+            from()
+            cause(err)
+            display("{}", err)
+        }
+    }
+}
+
+
+impl Error {
+    /// Where in the input the error was found, unless it is an I/O error.
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            Error::MissingAt(pos) | Error::MissingPlus(pos) | Error::IncompleteRecord(pos) | Error::InvalidQuality(pos, _) | Error::InvalidRecord(pos, _) | Error::Warning(pos, _) => Some(pos),
+            Error::Io(_) => None,
+        }
+    }
+
+    /// The position of the error, to be moved if the reader did not count from the start of input.
+    #[cfg(feature = "pipeline")]
+    pub(crate) fn position_mut(&mut self) -> Option<&mut Position> {
+        match *self {
+            Error::MissingAt(ref mut pos) | Error::MissingPlus(ref mut pos) | Error::IncompleteRecord(ref mut pos) | Error::InvalidQuality(ref mut pos, _) | Error::InvalidRecord(ref mut pos, _) | Error::Warning(ref mut pos, _) => Some(pos),
+            Error::Io(_) => None,
+        }
+    }
+}
+
+
+/// How an `Error` is serialized: like itself, with I/O errors as their message.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+enum ErrorRepr {
+    MissingAt(Position),
+    MissingPlus(Position),
+    IncompleteRecord(Position),
+    InvalidQuality(Position, String),
+    InvalidRecord(Position, String),
+    Warning(Position, Warning),
+    Io(String),
+}
+
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Error::MissingAt(pos) => ErrorRepr::MissingAt(pos),
+            Error::MissingPlus(pos) => ErrorRepr::MissingPlus(pos),
+            Error::IncompleteRecord(pos) => ErrorRepr::IncompleteRecord(pos),
+            Error::InvalidQuality(pos, ref value) => ErrorRepr::InvalidQuality(pos, value.clone()),
+            Error::InvalidRecord(pos, ref reason) => ErrorRepr::InvalidRecord(pos, reason.clone()),
+            Error::Warning(pos, ref warning) => ErrorRepr::Warning(pos, warning.clone()),
+            Error::Io(ref e) => ErrorRepr::Io(e.to_string()),
+        }.serialize(serializer)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Error {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match ErrorRepr::deserialize(deserializer)? {
+            ErrorRepr::MissingAt(pos) => Error::MissingAt(pos),
+            ErrorRepr::MissingPlus(pos) => Error::MissingPlus(pos),
+            ErrorRepr::IncompleteRecord(pos) => Error::IncompleteRecord(pos),
+            ErrorRepr::InvalidQuality(pos, value) => Error::InvalidQuality(pos, value),
+            ErrorRepr::InvalidRecord(pos, reason) => Error::InvalidRecord(pos, reason),
+            ErrorRepr::Warning(pos, warning) => Error::Warning(pos, warning),
+            ErrorRepr::Io(e) => Error::Io(io::Error::other(e)),
+        })
+    }
+}
+
+
+/// A FastQ reader, checking its records if it is a `Reader<R, Validated>`.
+pub struct Reader<R: io::Read, S: ValidationState = Raw> {
+    reader: AdaptiveReader<R>,
+    sizer: BufferSizer,
     sep_line: String,
+    options: ReaderOptions,
+    warnings: Vec<Warning>,
+    pos: Position,
+    progress: ProgressHook<R>,
+    error_hook: ErrorHook,
+    state: PhantomData<S>,
 }
 
 
-impl Reader<fs::File> {
-    /// Read from a given file.
+impl Reader<Input> {
+    /// Read from a given file, decompressing it if it is gzip or bgzip compressed.
     pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        fs::File::open(path).map(Reader::new)
+        Input::open(path).map(Reader::from_input)
+    }
+
+    /// Read from a given gzip or bgzip compressed file.
+    pub fn from_gz<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Input::open_gz(path).map(Reader::from_input)
+    }
+
+    fn from_input(input: Input) -> Self {
+        let size = input.file_size();
+        let mut reader = Reader::new(input);
+        reader.progress = reader.progress.sized(size, Input::compressed_offset);
+        reader
+    }
+}
+
+
+#[cfg(feature = "example-data")]
+impl Reader<&'static [u8]> {
+    /// Read the eight records of `example::FASTQ`, to try things out without a file.
+    ///
+    /// ```
+    /// use fastq_comparison::Record;
+    /// use fastq_comparison::unfancy_parser::Reader;
+    ///
+    /// let lengths: Vec<usize> = Reader::example().records().map(|r| r.map(|r| r.seq().len())).collect::<Result<_, _>>()?;
+    /// assert_eq!(lengths.len(), fastq_comparison::example::RECORDS);
+    /// # Ok::<(), fastq_comparison::unfancy_parser::Error>(())
+    /// ```
+    pub fn example() -> Self {
+        Reader::new(super::example::FASTQ.as_bytes())
     }
 }
 
@@ -27,49 +192,400 @@ impl Reader<fs::File> {
 impl<R: io::Read> Reader<R> {
     /// Read from a given `io::Read`.
     pub fn new(reader: R) -> Self {
+        Reader::with_options(reader, ReaderOptions::default())
+    }
+
+    /// Read from a given `io::Read`, parsing as configured by `options`.
+    pub fn with_options(reader: R, options: ReaderOptions) -> Self {
+        let sizer = BufferSizer::new(options.buffer);
         Reader {
-            reader: io::BufReader::new(reader),
+            reader: AdaptiveReader::with_capacity(sizer.initial(), reader),
+            sizer,
             sep_line: String::new(),
+            options,
+            warnings: Vec::new(),
+            pos: Position::start(),
+            progress: ProgressHook::new(),
+            error_hook: ErrorHook::new(),
+            state: PhantomData,
         }
     }
 
+    /// Check every record read, failing with `Error::InvalidRecord` on the first invalid one.
+    /// The records are then yielded as `Valid` records.
+    pub fn validated(self) -> Reader<R, Validated> {
+        let Reader { reader, sizer, sep_line, options, warnings, pos, progress, error_hook, state: _ } = self;
+        Reader { reader, sizer, sep_line, options, warnings, pos, progress, error_hook, state: PhantomData }
+    }
+
+    /// Count positions from `pos` instead of the start of input, for readers starting mid-file.
+    #[cfg(feature = "pipeline")]
+    pub(crate) fn starting_at(mut self, pos: Position) -> Self {
+        self.pos = pos;
+        self
+    }
+
+    /// Return an iterator over the records of this FastQ file,
+    /// checking each record out of the given pool instead of allocating it.
+    pub fn records_pooled(self, pool: &RecordPool) -> PooledRecords<R> {
+        PooledRecords::new(self, pool)
+    }
+}
+
+
+impl<R: io::Read, S: ValidationState> Reader<R, S> {
+    /// Call `callback` every `interval` records and at the end of input with how far reading got.
+    /// Percent complete is only known for readers created with `from_file` or `from_gz`.
+    pub fn with_progress<F>(mut self, interval: u64, callback: F) -> Self where F: FnMut(&Progress) + Send + 'static {
+        self.progress.set(interval, callback);
+        self
+    }
+
+    /// Call `hook` with every error before `read` returns it, along with the failing record's lines
+    /// as far as they were read. Replaces a hook set before.
+    pub fn on_error<F>(mut self, hook: F) -> Self where F: FnMut(&ErrorContext) + Send + 'static {
+        self.error_hook.set(hook);
+        self
+    }
+
+    /// The position of the next line to be read.
+    pub fn position(&self) -> Position {
+        self.pos
+    }
+
+    /// The current size of the read buffer in bytes.
+    pub fn buffer_size(&self) -> usize {
+        self.reader.capacity()
+    }
+
+    /// Warnings collected since they were last taken.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Take the warnings collected so far, leaving none behind.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
     /// Read into a given record.
     /// Returns an error if the record in incomplete or syntax is violated.
     /// The content of the record can be checked via the record object.
-    pub fn read(&mut self, record: &mut Record) -> io::Result<()> {
+    pub fn read(&mut self, record: &mut Record) -> Result<(), Error> {
+        let (index, start) = (self.pos.record, self.pos.offset);
+        let pos = self.pos;
+        let mut result = self.read_record(record);
+        if S::CHECKS && result.is_ok() && !record.header.is_empty() {
+            result = record.check().map_err(|reason| Error::InvalidRecord(pos, reason.to_owned()));
+        }
+        if !record.header.is_empty() {
+            self.pos.record = index + 1;
+            self.sizer.observe(self.pos.offset - start);
+            self.reader.adapt(self.sizer.target());
+            self.progress.record(self.pos, self.reader.get_ref());
+        } else if result.is_ok() {
+            self.progress.finish(self.pos, self.reader.get_ref());
+        }
+        if let Err(ref e) = result {
+            let lines = [&record.header, &record.seq, &self.sep_line, &record.qual];
+            self.error_hook.fire(e, pos, lines.into_iter().map(String::as_bytes));
+        }
+        result
+    }
+
+    fn read_record(&mut self, record: &mut Record) -> Result<(), Error> {
         record.clear();
-        try!(self.reader.read_line(&mut record.header));
+        self.sep_line.clear();
+        if self.pos.line == 1 && self.pos.record == 0 {
+            let pos = self.pos;
+            if let Some(warning) = buffer::skip_preamble(&mut self.reader, &mut self.pos)? {
+                self.warn(pos, warning)?;
+            }
+        }
+        let start = self.pos;
+        read_line_at(&mut self.reader, &mut self.pos, &mut record.header, self.options.text)?;
 
         if !record.header.is_empty() {
             if !record.header.starts_with('@') {
-                return Err(io::Error::new(io::ErrorKind::Other, "Expected @ at record start."));
+                return Err(Error::MissingAt(start));
             }
-            try!(self.reader.read_line(&mut record.seq));
-            try!(self.reader.read_line(&mut self.sep_line));
-            try!(self.reader.read_line(&mut record.qual));
+            let (mut stripped, sep_pos);
+            if self.options.allow_multiline {
+                (stripped, sep_pos) = self.read_wrapped(record)?;
+            } else {
+                stripped = false;
+                read_line_at(&mut self.reader, &mut self.pos, &mut record.seq, self.options.text)?;
+                sep_pos = self.pos;
+                read_line_at(&mut self.reader, &mut self.pos, &mut self.sep_line, self.options.text)?;
+                let qual_pos = self.pos;
+                read_line_at(&mut self.reader, &mut self.pos, &mut record.qual, self.options.text)?;
+                if self.options.numeric_quality && !record.qual.is_empty() {
+                    record.qual = numeric_to_phred33(&record.qual).map_err(|v| Error::InvalidQuality(qual_pos, v))? + "\n";
+                }
+            }
+            record.offset = Some(start.offset);
             if record.qual.is_empty() {
-                return Err(io::Error::new(io::ErrorKind::Other,
-                                          "Incomplete record. Each FastQ record has to consist \
-                                           of 4 lines: header, sequence, separator and qualities."));
+                return Err(Error::IncompleteRecord(self.pos));
+            }
+            if !self.sep_line.starts_with('+') {
+                return Err(Error::MissingPlus(sep_pos));
+            }
+            if self.options.strip_whitespace {
+                stripped |= strip_inner_whitespace(&mut record.seq);
+                stripped |= strip_inner_whitespace(&mut record.qual);
+                if stripped {
+                    self.warn(start, Warning::StrippedWhitespace { record: self.pos.record as usize })?;
+                }
             }
         }
 
         Ok(())
     }
 
+    /// Collect a warning, or fail with it if warnings are fatal.
+    fn warn(&mut self, pos: Position, warning: Warning) -> Result<(), Error> {
+        if self.options.warnings_fatal {
+            return Err(Error::Warning(pos, warning));
+        }
+        self.warnings.push(warning);
+        Ok(())
+    }
+
+    /// Read sequence lines up to the `+` separator, then quality lines until they are as long.
+    /// Whitespace is stripped while reading if configured, returning whether there was any
+    /// and where the separator line is.
+    fn read_wrapped(&mut self, record: &mut Record) -> Result<(bool, Position), Error> {
+        let mut line = String::new();
+        let mut stripped = false;
+        let mut sep_pos;
+        loop {
+            line.clear();
+            sep_pos = self.pos;
+            let n = read_line_at(&mut self.reader, &mut self.pos, &mut line, self.options.text)?;
+            if n == 0 || line.starts_with('+') {
+                self.sep_line = std::mem::take(&mut line);
+                break;
+            }
+            if self.options.strip_whitespace {
+                stripped |= strip_inner_whitespace(&mut line);
+            }
+            record.seq.push_str(line.trim_end_matches(['\r', '\n']));
+        }
+        if self.sep_line.is_empty() {
+            return Ok((stripped, sep_pos));
+        }
+        let len = record.seq.len();
+        record.seq.push('\n');
+        let mut lines = 0;
+        while lines == 0 || record.qual.len() < len {
+            line.clear();
+            let qual_pos = self.pos;
+            let n = read_line_at(&mut self.reader, &mut self.pos, &mut line, self.options.text)?;
+            if n == 0 {
+                break;
+            }
+            if self.options.numeric_quality {
+                line = numeric_to_phred33(&line).map_err(|v| Error::InvalidQuality(qual_pos, v))?;
+            } else if self.options.strip_whitespace {
+                stripped |= strip_inner_whitespace(&mut line);
+            }
+            record.qual.push_str(line.trim_end_matches(['\r', '\n']));
+            lines += 1;
+        }
+        if lines > 0 {
+            record.qual.push('\n');
+        }
+        Ok((stripped, sep_pos))
+    }
+
     /// Return an iterator over the records of this FastQ file.
-    pub fn records(self) -> Records<R> {
+    pub fn records(self) -> Records<R, S> {
         Records { reader: self }
     }
 }
 
 
+/// Read a line including its line break, advancing `pos` past it.
+fn read_line_at<B: BufRead>(reader: &mut B, pos: &mut Position, line: &mut String, text: TextPolicy) -> io::Result<usize> {
+    let n = text.read_line(reader, line)?;
+    pos.advance_line(n);
+    Ok(n)
+}
+
+
+/// Remove spaces and tabs from a line, keeping its line break.
+fn strip_inner_whitespace(line: &mut String) -> bool {
+    let len = line.len();
+    line.retain(|c| c != ' ' && c != '\t');
+    line.len() != len
+}
+
+
+/// Cut a line down to a range of its content, keeping its line break.
+#[cfg(feature = "pipeline")]
+fn keep_line_range(line: &mut String, keep: Range<usize>) {
+    let len = line.trim_end().len();
+    let end = keep.end.min(len);
+    let start = keep.start.min(end);
+    line.replace_range(end..len, "");
+    line.replace_range(..start, "");
+}
+
+
 /// A FastQ record.
-#[derive(Debug, Clone, PartialEq)]
+/// Equality compares header, sequence and qualities only.
+#[derive(Debug, Clone)]
 pub struct Record {
     header: String,
     seq: String,
     qual: String,
+    offset: Option<u64>,
+    extensions: Extensions,
+    validated: ValidFlag,
+}
+
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        self.header == other.header && self.seq == other.seq && self.qual == other.qual
+    }
+}
+
+
+/// Whether a record passed `check()` since it was last changed.
+#[derive(Debug, Default)]
+struct ValidFlag(AtomicBool);
+
+impl ValidFlag {
+    fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, valid: bool) {
+        self.0.store(valid, Ordering::Relaxed)
+    }
+}
+
+impl Clone for ValidFlag {
+    fn clone(&self) -> Self {
+        ValidFlag(AtomicBool::new(self.get()))
+    }
+}
+
+
+impl Record {
+    /// Create a record from its raw header, sequence and quality lines.
+    pub(crate) fn from_lines(header: String, seq: String, qual: String) -> Self {
+        Record { header, seq, qual, offset: None, extensions: Extensions::new(), validated: ValidFlag::default() }
+    }
+
+    /// Check the record unless it is known to be valid already.
+    #[cfg(feature = "pipeline")]
+    pub(crate) fn ensure_valid(&self) -> Result<(), &str> {
+        if self.is_validated() {
+            return Ok(());
+        }
+        self.check()
+    }
+
+    /// Side data attached to this record.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Side data attached to this record, for adding or changing values.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Cut sequence and qualities down to the given range, clamped to the sequence length.
+    #[cfg(feature = "pipeline")]
+    pub(crate) fn keep_range(&mut self, keep: Range<usize>) {
+        keep_line_range(&mut self.seq, keep.clone());
+        keep_line_range(&mut self.qual, keep);
+        self.validated.set(false);
+    }
+
+    /// Rewrite the quality line in place. `f` must keep the qualities ASCII.
+    pub(crate) fn map_qual<F: FnOnce(&mut [u8])>(&mut self, f: F) {
+        self.map_qual_with_seq(|_, qual| f(qual))
+    }
+
+    /// Rewrite the quality line in place, given the sequence. `f` must keep the qualities ASCII.
+    pub(crate) fn map_qual_with_seq<F: FnOnce(&[u8], &mut [u8])>(&mut self, f: F) {
+        let len = self.qual.trim_end().len();
+        let mut bytes = std::mem::take(&mut self.qual).into_bytes();
+        f(self.seq.trim_end().as_bytes(), &mut bytes[..len]);
+        // a rewrite breaking the contract must not panic, and the replacement characters fail validation later
+        self.qual = String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+        self.validated.set(false);
+    }
+
+    /// Rewrite sequence and quality lines in place. `f` must keep both ASCII.
+    #[cfg(feature = "pipeline")]
+    pub(crate) fn map_seq_qual<F: FnOnce(&mut [u8], &mut [u8])>(&mut self, f: F) {
+        let (seq_len, qual_len) = (self.seq.trim_end().len(), self.qual.trim_end().len());
+        let mut seq = std::mem::take(&mut self.seq).into_bytes();
+        let mut qual = std::mem::take(&mut self.qual).into_bytes();
+        f(&mut seq[..seq_len], &mut qual[..qual_len]);
+        // as in `map_qual_with_seq`, e.g. reversing a non-ASCII sequence leaves replacement characters to fail validation
+        let text = |bytes: Vec<u8>| String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+        self.seq = text(seq);
+        self.qual = text(qual);
+        self.validated.set(false);
+    }
+
+    /// Replace the header line by `@`, `header` and the previous line ending.
+    pub(crate) fn set_header(&mut self, header: &str) {
+        let eol = match self.header.trim_end_matches(['\r', '\n']).len() {
+            0 => "\n".to_owned(),
+            len => self.header.split_off(len),
+        };
+        self.header.clear();
+        self.header.push('@');
+        self.header.push_str(header);
+        self.header.push_str(&eol);
+        self.validated.set(false);
+    }
+
+    /// Set the byte offset reported by `offset()`.
+    pub(crate) fn set_offset(&mut self, offset: Option<u64>) {
+        self.offset = offset;
+    }
+
+    /// Approximate size of the record’s text in bytes.
+    #[cfg(feature = "pipeline")]
+    pub(crate) fn raw_len(&self) -> usize {
+        self.header.len() + self.seq.len() + self.qual.len() + 2
+    }
+
+    /// Write the record as four FastQ lines, returning the number of bytes written.
+    #[cfg(feature = "pipeline")]
+    pub(crate) fn write_raw<W: Write>(&self, out: &mut W) -> io::Result<usize> {
+        let mut n = 0;
+        for line in &[self.header.as_str(), self.seq.as_str(), "+", self.qual.as_str()] {
+            let line = line.trim_end_matches(['\r', '\n']);
+            out.write_all(line.as_bytes())?;
+            out.write_all(b"\n")?;
+            n += line.len() + 1;
+        }
+        Ok(n)
+    }
+
+    /// Append the tags of all extensions to the description.
+    pub fn serialize_extensions(&mut self) {
+        let tags = self.extensions.tags();
+        if tags.is_empty() {
+            return;
+        }
+        let len = self.header.trim_end().len();
+        let eol = self.header.split_off(len);
+        for tag in tags {
+            self.header.push(' ');
+            self.header.push_str(&tag);
+        }
+        self.header.push_str(&eol);
+    }
 }
 
 
@@ -80,6 +596,9 @@ impl super::Record for Record {
             header: String::new(),
             seq: String::new(),
             qual: String::new(),
+            offset: None,
+            extensions: Extensions::new(),
+            validated: ValidFlag::default(),
         }
     }
 
@@ -103,27 +622,43 @@ impl super::Record for Record {
             return Err("Unequal length of sequence an qualities.");
         }
 
+        self.validated.set(true);
         Ok(())
     }
 
+    /// Check if the record passed `check()` and was not changed since.
+    fn is_validated(&self) -> bool {
+        self.validated.get()
+    }
+
     /// Return the id of the record.
     fn id(&self) -> Option<&str> {
-        self.header[1..].trim_right().splitn(2, ' ').next()
+        self.header.get(1..).unwrap_or("").trim_end().split(' ').next()
     }
 
     /// Return descriptions if present.
     fn desc(&self) -> Option<&str> {
-        self.header[1..].trim_right().splitn(2, ' ').skip(1).next()
+        self.header.get(1..).unwrap_or("").trim_end().split_once(' ').map(|(_, desc)| desc)
     }
 
     /// Return the sequence of the record.
     fn seq(&self) -> &[u8] {
-        self.seq.trim_right().as_bytes()
+        self.seq.trim_end().as_bytes()
     }
 
     /// Return the base qualities of the record.
     fn qual(&self) -> &[u8] {
-        self.qual.trim_right().as_bytes()
+        self.qual.trim_end().as_bytes()
+    }
+
+    /// Return the header line without `@` and line break.
+    fn header(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self.header.get(1..).unwrap_or("").trim_end())
+    }
+
+    /// Return the byte offset of the record, if read by a `Reader`.
+    fn offset(&self) -> Option<u64> {
+        self.offset
     }
 
     /// Clear the record.
@@ -131,6 +666,81 @@ impl super::Record for Record {
         self.header.clear();
         self.seq.clear();
         self.qual.clear();
+        self.offset = None;
+        self.extensions.clear();
+        self.validated.set(false);
+    }
+}
+
+
+impl From<fancy_parser::Record> for Record {
+    fn from(r: fancy_parser::Record) -> Self {
+        let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+        let mut record = Record::from_lines(format!("@{}\n", r.header()), text(r.seq()) + "\n", text(r.qual()) + "\n");
+        record.offset = r.offset();
+        record
+    }
+}
+
+
+impl super::RecordMut for Record {
+    fn set_id(&mut self, id: &str) -> Result<(), RecordError> {
+        builder::check_id(id)?;
+        let header = match RecordTrait::desc(self) {
+            Some(desc) => format!("{} {}", id, desc),
+            None => id.to_owned(),
+        };
+        self.set_header(&header);
+        Ok(())
+    }
+
+    fn set_desc(&mut self, desc: Option<&str>) -> Result<(), RecordError> {
+        if let Some(desc) = desc {
+            builder::check_desc(desc)?;
+        }
+        let header = match (RecordTrait::id(self).unwrap_or(""), desc) {
+            (id, Some(desc)) if !desc.is_empty() => format!("{} {}", id, desc),
+            (id, _) => id.to_owned(),
+        };
+        self.set_header(&header);
+        Ok(())
+    }
+
+    fn set_seq_qual(&mut self, seq: &[u8], qual: &[u8]) -> Result<(), RecordError> {
+        builder::check_seq_qual(seq, qual)?;
+        replace_line(&mut self.seq, seq);
+        replace_line(&mut self.qual, qual);
+        self.validated.set(false);
+        Ok(())
+    }
+}
+
+
+/// Replace the content of a line by ASCII `content`, keeping its line break.
+fn replace_line(line: &mut String, content: &[u8]) {
+    let eol = match line.trim_end_matches(['\r', '\n']).len() {
+        0 if line.is_empty() => "\n".to_owned(),
+        len => line.split_off(len),
+    };
+    line.clear();
+    line.extend(content.iter().map(|&b| b as char));
+    line.push_str(&eol);
+}
+
+
+/// Records are serialized like `fancy_parser::Record`s, with ID and description split.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Record {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fancy_parser::Record::from(self.clone()).serialize(serializer)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Record {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        fancy_parser::Record::deserialize(deserializer).map(Record::from)
     }
 }
 
@@ -142,16 +752,199 @@ impl fmt::Display for Record {
 }
 
 
-/// An iterator over the records of a FastQ file.
-pub struct Records<R: io::Read> {
-    reader: Reader<R>,
+/// Line ending written after each line of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
 }
 
 
-impl<R: io::Read> Iterator for Records<R> {
-    type Item = io::Result<Record>;
+impl LineEnding {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+
+/// How a writer reproduces record headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderStyle {
+    /// Write id and description exactly as given.
+    #[default]
+    Original,
+    /// Collapse whitespace runs in the description into single spaces.
+    Normalized,
+    /// Write Illumina headers as Casava 1.8 `id read:filtered:control:index`,
+    /// converting old-style `id#index/read` and dropping other description fields.
+    /// Other headers are written as given.
+    Casava,
+    /// Write the id only.
+    IdOnly,
+}
+
+
+/// Formatting options of a FastQ writer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriterOptions {
+    /// Line ending to use.
+    pub line_ending: LineEnding,
+    /// Repeat the header after the `+` separator.
+    pub repeat_header: bool,
+    /// How to write headers.
+    pub header_style: HeaderStyle,
+}
+
 
-    fn next(&mut self) -> Option<io::Result<Record>> {
+/// Build a header in the given style, without `@` and line break.
+fn styled_header(style: HeaderStyle, id: &str, desc: Option<&str>) -> String {
+    let joined = |id: &str, desc: Option<&str>| match desc {
+        Some(desc) => format!("{} {}", id, desc),
+        None => id.to_owned(),
+    };
+    match style {
+        HeaderStyle::Original => joined(id, desc),
+        HeaderStyle::Normalized => {
+            let desc = desc.map(|d| d.split_whitespace().collect::<Vec<_>>().join(" "));
+            joined(id, desc.as_deref().filter(|d| !d.is_empty()))
+        }
+        HeaderStyle::Casava => match casava_fields(id, desc) {
+            Some((id, fields)) => format!("{} {}", id, fields),
+            None => joined(id, desc),
+        },
+        HeaderStyle::IdOnly => id.to_owned(),
+    }
+}
+
+
+/// Split an Illumina header into the bare id and Casava 1.8 `read:filtered:control:index` fields.
+fn casava_fields<'a>(id: &'a str, desc: Option<&str>) -> Option<(&'a str, String)> {
+    let first = desc.and_then(|d| d.split_whitespace().next()).unwrap_or("");
+    let parts: Vec<&str> = first.split(':').collect();
+    if parts.len() == 4 && matches!(parts[0], "1" | "2" | "3") && matches!(parts[1], "Y" | "N") {
+        return Some((id, parts.join(":")));
+    }
+    // pre-1.8: instrument:lane:tile:x:y#index/read
+    let (rest, read) = id.rsplit_once('/').filter(|(_, r)| matches!(*r, "1" | "2" | "3"))?;
+    let (bare, index) = rest.split_once('#').unwrap_or((rest, ""));
+    if bare.split(':').count() < 5 {
+        return None;
+    }
+    let index = if index.chars().all(|c| c == '0') { "" } else { index };
+    Some((bare, format!("{}:N:0:{}", read, index)))
+}
+
+
+/// Write a record given as its parts as `Writer::write` does, without buffering.
+pub(crate) fn write_parts<W: Write>(out: &mut W, options: &WriterOptions, id: &str, desc: Option<&str>, seq: &[u8], qual: &[u8]) -> io::Result<()> {
+    let eol = options.line_ending.as_bytes();
+    let header = styled_header(options.header_style, id, desc).into_bytes();
+
+    out.write_all(b"@")?;
+    out.write_all(&header)?;
+    out.write_all(eol)?;
+    out.write_all(seq)?;
+    out.write_all(eol)?;
+    out.write_all(b"+")?;
+    if options.repeat_header {
+        out.write_all(&header)?;
+    }
+    out.write_all(eol)?;
+    out.write_all(qual)?;
+    out.write_all(eol)
+}
+
+
+/// A FastQ writer.
+pub struct Writer<W: io::Write> {
+    writer: io::BufWriter<W>,
+    options: WriterOptions,
+}
+
+
+impl Writer<fs::File> {
+    /// Write to a given file, creating or truncating it.
+    pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::File::create(long_path(path)).map(Writer::new)
+    }
+
+    /// Write to a given file with the given formatting options.
+    pub fn to_file_with_options<P: AsRef<Path>>(path: P, options: WriterOptions) -> io::Result<Self> {
+        fs::File::create(long_path(path)).map(|f| Writer::with_options(f, options))
+    }
+}
+
+
+impl<W: io::Write> Writer<W> {
+    /// Write to a given `io::Write` with default formatting.
+    pub fn new(writer: W) -> Self {
+        Writer::with_options(writer, WriterOptions::default())
+    }
+
+    /// Write to a given `io::Write` with the given formatting options.
+    pub fn with_options(writer: W, options: WriterOptions) -> Self {
+        Writer {
+            writer: io::BufWriter::new(writer),
+            options,
+        }
+    }
+
+    /// Like `with_options`, buffering `capacity` bytes. With 0, every field goes straight to `writer`.
+    pub fn with_capacity(writer: W, options: WriterOptions, capacity: usize) -> Self {
+        Writer {
+            writer: io::BufWriter::with_capacity(capacity, writer),
+            options,
+        }
+    }
+
+    /// Write a record of any parser.
+    /// Records not known to be valid are checked first, failing with `InvalidData`.
+    pub fn write_record<R: RecordTrait>(&mut self, record: &R) -> io::Result<()> {
+        if !record.is_validated() {
+            record.check().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        self.write(record.id().unwrap_or(""), record.desc(), record.seq(), record.qual())
+    }
+
+    /// Write a record known to be valid, without checking it again.
+    pub fn write_valid<R: RecordTrait>(&mut self, record: &Valid<R>) -> io::Result<()> {
+        self.write(record.id().unwrap_or(""), record.desc(), record.seq(), record.qual())
+    }
+
+    /// Write a record given as its parts, without validating them.
+    pub fn write(&mut self, id: &str, desc: Option<&str>, seq: &[u8], qual: &[u8]) -> io::Result<()> {
+        write_parts(&mut self.writer, &self.options, id, desc, seq, qual)
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flush and return the underlying writer.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+
+/// An iterator over the records of a FastQ file, yielding `Valid` records if it is `Records<R, Validated>`.
+pub struct Records<R: io::Read, S: ValidationState = Raw> {
+    reader: Reader<R, S>,
+}
+
+
+impl<R: io::Read, S: ValidationState> Records<R, S> {
+    /// Take the warnings collected by the underlying reader so far.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.reader.take_warnings()
+    }
+
+    fn next_record(&mut self) -> Option<Result<Record, Error>> {
         let mut record = Record::new();
         match self.reader.read(&mut record) {
             Ok(()) if record.is_empty() => None,
@@ -160,3 +953,21 @@ impl<R: io::Read> Iterator for Records<R> {
         }
     }
 }
+
+
+impl<R: io::Read> Iterator for Records<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Result<Record, Error>> {
+        self.next_record()
+    }
+}
+
+
+impl<R: io::Read> Iterator for Records<R, Validated> {
+    type Item = Result<Valid<Record>, Error>;
+
+    fn next(&mut self) -> Option<Result<Valid<Record>, Error>> {
+        Some(self.next_record()?.map(Valid::checked))
+    }
+}
@@ -0,0 +1,216 @@
+//! Splitting reads into compressed parts of a target size for upload, with a resumable manifest.
+//!
+//! Parts are named `<prefix>.part0001.fastq.gz`, `<prefix>.part0002.fastq.gz`, …
+//! and listed in `<prefix>.manifest.tsv` with their record ranges, sizes and MD5 digests.
+//! The manifest is updated after each finished part, so an interrupted split can be resumed
+//! by running it again on the same input: parts that are still intact are kept, the rest is rewritten.
+
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use super::md5::{Md5, Md5Writer};
+use super::paths::{long_dir, long_path};
+use super::unfancy_parser::{Record, Error};
+
+/// A finished part as listed in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Part {
+	/// The part number, starting at 1.
+	pub number: usize,
+	/// The file name, relative to the output directory.
+	pub file: String,
+	/// The number of the first record in the part, counting from 0.
+	pub first_record: u64,
+	/// The number of records in the part.
+	pub records: u64,
+	/// The compressed size in bytes.
+	pub bytes: u64,
+	/// The MD5 digest of the compressed file, as lowercase hex.
+	pub md5: String,
+}
+
+impl Part {
+	/// Check that the part’s file in `dir` has the listed size and digest.
+	pub fn verify<P: AsRef<Path>>(&self, dir: P) -> io::Result<bool> {
+		let mut file = match fs::File::open(long_path(dir.as_ref().join(&self.file))) {
+			Ok(file) => file,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+			Err(e) => return Err(e),
+		};
+		if file.metadata()?.len() != self.bytes { return Ok(false) }
+		let mut md5 = Md5::new();
+		let mut buf = vec![0; 64 * 1024];
+		loop {
+			match file.read(&mut buf)? {
+				0 => break,
+				n => md5.update(&buf[..n]),
+			}
+		}
+		Ok(md5.hex() == self.md5)
+	}
+}
+
+/// The list of finished parts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+	pub parts: Vec<Part>,
+}
+
+impl Manifest {
+	/// The number of records in all parts.
+	pub fn records(&self) -> u64 {
+		self.parts.iter().map(|p| p.records).sum()
+	}
+	
+	/// The numbers of the parts whose files in `dir` are missing or differ from the manifest.
+	pub fn verify<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<usize>> {
+		let mut bad = Vec::new();
+		for part in &self.parts {
+			if !part.verify(dir.as_ref())? { bad.push(part.number) }
+		}
+		Ok(bad)
+	}
+	
+	/// Write the manifest as TSV with a header line.
+	pub fn write_to<W: Write>(&self, out: W) -> io::Result<()> {
+		let mut out = io::BufWriter::new(out);
+		writeln!(out, "#part\tfile\tfirst_record\trecords\tbytes\tmd5")?;
+		for p in &self.parts {
+			writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", p.number, p.file, p.first_record, p.records, p.bytes, p.md5)?;
+		}
+		out.flush()
+	}
+	
+	/// Read a manifest written by `write_to`.
+	pub fn read_from<R: Read>(reader: R) -> io::Result<Manifest> {
+		let mut manifest = Manifest::default();
+		for (i, line) in io::BufReader::new(reader).lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() || line.starts_with('#') { continue }
+			let bad_line = || io::Error::new(io::ErrorKind::InvalidData, format!("Manifest line {}: expected 6 tab-separated fields", i + 1));
+			let fields: Vec<&str> = line.trim_end().split('\t').collect();
+			if fields.len() != 6 { return Err(bad_line()) }
+			manifest.parts.push(Part {
+				number: fields[0].parse().map_err(|_| bad_line())?,
+				file: fields[1].to_owned(),
+				first_record: fields[2].parse().map_err(|_| bad_line())?,
+				records: fields[3].parse().map_err(|_| bad_line())?,
+				bytes: fields[4].parse().map_err(|_| bad_line())?,
+				md5: fields[5].to_owned(),
+			});
+		}
+		Ok(manifest)
+	}
+	
+	/// Save the manifest, replacing the file only once it is completely written.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		let path = long_path(path);
+		let mut tmp = path.clone().into_os_string();
+		tmp.push(".tmp");
+		self.write_to(fs::File::create(&tmp)?)?;
+		fs::rename(tmp, path)
+	}
+	
+	/// Load a manifest from a file.
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Manifest> {
+		Manifest::read_from(fs::File::open(long_path(path))?)
+	}
+}
+
+/// Splits a record stream into gzip compressed parts of about a target size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadSplitter {
+	dir: PathBuf,
+	prefix: String,
+	target_size: u64,
+	level: u32,
+}
+
+impl UploadSplitter {
+	/// Write parts of about `target_size` compressed bytes to `dir`, which is created if needed.
+	pub fn new<P: AsRef<Path>>(dir: P, target_size: u64) -> UploadSplitter {
+		UploadSplitter { dir: dir.as_ref().to_owned(), prefix: "reads".to_owned(), target_size: target_size.max(1), level: 6 }
+	}
+	
+	/// Name the parts and manifest `<prefix>.…` instead of `reads.…`.
+	pub fn prefix(mut self, prefix: &str) -> Self {
+		self.prefix = prefix.to_owned();
+		self
+	}
+	
+	/// Set the gzip compression level (0–9, default 6).
+	pub fn compression(mut self, level: u32) -> Self {
+		self.level = level.min(9);
+		self
+	}
+	
+	/// The path of the manifest.
+	pub fn manifest_path(&self) -> PathBuf {
+		self.dir.join(format!("{}.manifest.tsv", self.prefix))
+	}
+	
+	/// The file name of a part.
+	pub fn part_name(&self, number: usize) -> String {
+		format!("{}.part{:04}.fastq.gz", self.prefix, number)
+	}
+	
+	/// Split the records, resuming after the intact parts of a previous run.
+	///
+	/// The input must be the same as in the previous run; its first records are skipped
+	/// as far as they are in intact parts. Parts after the first damaged one are written again.
+	pub fn split<I>(&self, records: I) -> Result<Manifest, Error> where I: IntoIterator<Item=Result<Record, Error>> {
+		let dir = long_dir(&self.dir);
+		fs::create_dir_all(&dir)?;
+		let manifest_path = self.manifest_path();
+		let mut manifest = match Manifest::load(&manifest_path) {
+			Ok(manifest) => manifest,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => Manifest::default(),
+			Err(e) => return Err(e.into()),
+		};
+		let mut intact = 0;
+		for part in &manifest.parts {
+			if !part.verify(&dir)? { break }
+			intact += 1;
+		}
+		if intact < manifest.parts.len() {
+			manifest.parts.truncate(intact);
+			manifest.save(&manifest_path)?;
+		}
+		
+		let mut records = records.into_iter().peekable();
+		let mut done = manifest.records();
+		for _ in 0..done {
+			match records.next() {
+				Some(record) => { record?; },
+				None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Input has fewer records than the manifest lists").into()),
+			}
+		}
+		while records.peek().is_some() {
+			let number = manifest.parts.len() + 1;
+			let file = self.part_name(number);
+			let out = Md5Writer::new(io::BufWriter::new(fs::File::create(dir.join(&file))?));
+			let mut gz = GzEncoder::new(out, Compression::new(self.level));
+			let mut count = 0;
+			while gz.get_ref().bytes < self.target_size {
+				let record = match records.next() {
+					Some(record) => record?,
+					None => break,
+				};
+				record.write_raw(&mut gz)?;
+				count += 1;
+			}
+			let mut out = gz.finish()?;
+			out.flush()?;
+			manifest.parts.push(Part { number, file, first_record: done, records: count, bytes: out.bytes, md5: out.md5.hex() });
+			manifest.save(&manifest_path)?;
+			done += count;
+		}
+		Ok(manifest)
+	}
+}
@@ -0,0 +1,66 @@
+//! Records proven valid at the type level.
+//!
+//! `Reader<R, Raw>`, the default, yields records as parsed. `Reader<R, Validated>`, made by
+//! `Reader::validated`, checks every record and yields `Valid` ones, which writers accept without
+//! checking them again. APIs that must not serialize unchecked data can ask for `Valid` records.
+
+use std::ops::Deref;
+
+use super::Record;
+
+mod sealed {
+	pub trait Sealed {}
+}
+
+/// Whether a reader checks its records, `Raw` or `Validated`.
+pub trait ValidationState: sealed::Sealed {
+	#[doc(hidden)]
+	const CHECKS: bool;
+}
+
+/// The state of a reader yielding records as parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Raw;
+
+/// The state of a reader checking every record, yielding `Valid` ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Validated;
+
+impl sealed::Sealed for Raw {}
+impl sealed::Sealed for Validated {}
+
+impl ValidationState for Raw {
+	const CHECKS: bool = false;
+}
+
+impl ValidationState for Validated {
+	const CHECKS: bool = true;
+}
+
+/// A record that passed `Record::check`. It can be read but not changed, so it stays valid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Valid<T>(T);
+
+impl<T: Record> Valid<T> {
+	/// Check a record, returning why it is invalid if it is.
+	pub fn new(record: T) -> Result<Valid<T>, String> {
+		record.check().map_err(str::to_owned)?;
+		Ok(Valid(record))
+	}
+	
+	/// Wrap a record that was just checked.
+	pub(crate) fn checked(record: T) -> Valid<T> { Valid(record) }
+	
+	/// Return the record, e.g. to change it.
+	pub fn into_inner(self) -> T { self.0 }
+}
+
+impl<T> Deref for Valid<T> {
+	type Target = T;
+	
+	fn deref(&self) -> &T { &self.0 }
+}
+
+impl<T> AsRef<T> for Valid<T> {
+	fn as_ref(&self) -> &T { &self.0 }
+}
@@ -0,0 +1,231 @@
+//! Streaming validation of FastQ text with configurable strictness.
+//!
+//! Unlike `Record::check`, the validator looks at the raw lines,
+//! so it can report line numbers and formatting deviations parsers silently accept.
+
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Presets for `ValidationOptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+	/// Only canonical four-line FastQ with uppercase bases and a bare `+`.
+	Strict,
+	/// Additionally accept a repeated header after `+` and an empty description.
+	Standard,
+	/// Accept all tolerated deviations.
+	Lenient,
+}
+
+/// Which deviations from canonical FastQ are accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationOptions {
+	/// Accept a header ending in whitespace, i.e. with an empty description.
+	pub allow_empty_description: bool,
+	/// Accept lowercase bases.
+	pub allow_lowercase: bool,
+	/// Accept the header repeated after `+` (it still has to match).
+	pub allow_plus_header: bool,
+	/// Accept `\r\n` line endings.
+	pub allow_crlf: bool,
+}
+
+impl ValidationOptions {
+	/// Options for a strictness level.
+	pub fn new(strictness: Strictness) -> ValidationOptions {
+		let (standard, lenient) = match strictness {
+			Strictness::Strict => (false, false),
+			Strictness::Standard => (true, false),
+			Strictness::Lenient => (true, true),
+		};
+		ValidationOptions {
+			allow_empty_description: standard,
+			allow_lowercase: lenient,
+			allow_plus_header: standard,
+			allow_crlf: lenient,
+		}
+	}
+}
+
+impl Default for ValidationOptions {
+	fn default() -> ValidationOptions { ValidationOptions::new(Strictness::Standard) }
+}
+
+/// Kinds of problems the validator finds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IssueKind {
+	MissingAt,
+	EmptyId,
+	EmptyDescription,
+	MissingPlus,
+	PlusHeader,
+	PlusHeaderMismatch,
+	InvalidBase(u8),
+	LowercaseBase,
+	InvalidQuality(u8),
+	LengthMismatch { seq: usize, qual: usize },
+	/// Lines end in `\r\n`. Reported once, at the first such line.
+	Crlf,
+	Truncated,
+}
+
+impl fmt::Display for IssueKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			IssueKind::MissingAt => write!(f, "header does not start with @"),
+			IssueKind::EmptyId => write!(f, "empty read ID"),
+			IssueKind::EmptyDescription => write!(f, "header ends in whitespace"),
+			IssueKind::MissingPlus => write!(f, "separator does not start with +"),
+			IssueKind::PlusHeader => write!(f, "header repeated after +"),
+			IssueKind::PlusHeaderMismatch => write!(f, "text after + differs from header"),
+			IssueKind::InvalidBase(b) => write!(f, "invalid base {:?}", b as char),
+			IssueKind::LowercaseBase => write!(f, "lowercase base"),
+			IssueKind::InvalidQuality(q) => write!(f, "invalid quality character {:?}", q as char),
+			IssueKind::LengthMismatch { seq, qual } => write!(f, "sequence length {} but quality length {}", seq, qual),
+			IssueKind::Crlf => write!(f, "CRLF line ending"),
+			IssueKind::Truncated => write!(f, "record truncated by end of input"),
+		}
+	}
+}
+
+/// A problem found at a specific place in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValidationIssue {
+	/// 1-based line number.
+	pub line: usize,
+	/// 0-based index of the record.
+	pub record: usize,
+	pub kind: IssueKind,
+}
+
+impl fmt::Display for ValidationIssue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {} (record {}): {}", self.line, self.record, self.kind)
+	}
+}
+
+/// Validates FastQ text according to a set of options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validator {
+	options: ValidationOptions,
+}
+
+impl Validator {
+	/// Create a validator with the given options.
+	pub fn new(options: ValidationOptions) -> Validator { Validator { options } }
+	
+	/// Create a validator for a strictness level.
+	pub fn with_strictness(strictness: Strictness) -> Validator { Validator::new(ValidationOptions::new(strictness)) }
+	
+	/// Validate input lazily, yielding issues as they are found.
+	pub fn issues<R: BufRead>(&self, reader: R) -> Issues<R> {
+		Issues { options: self.options.clone(), reader, line: 0, records: 0, pending: Vec::new(), crlf: false, done: false }
+	}
+	
+	/// Check if the input is free of issues.
+	pub fn is_valid<R: BufRead>(&self, reader: R) -> io::Result<bool> {
+		self.issues(reader).next().transpose().map(|issue| issue.is_none())
+	}
+}
+
+/// An iterator over the issues of some input.
+pub struct Issues<R> {
+	options: ValidationOptions,
+	reader: R,
+	line: usize,
+	records: usize,
+	pending: Vec<ValidationIssue>,
+	/// Whether a CRLF line ending was reported already.
+	crlf: bool,
+	done: bool,
+}
+
+impl<R: BufRead> Issues<R> {
+	/// Number of records checked so far.
+	pub fn records(&self) -> usize { self.records }
+	
+	fn read_line(&mut self, buf: &mut Vec<u8>) -> io::Result<bool> {
+		buf.clear();
+		if self.reader.read_until(b'\n', buf)? == 0 { return Ok(false) }
+		self.line += 1;
+		if buf.last() == Some(&b'\n') { buf.pop(); }
+		if buf.last() == Some(&b'\r') {
+			buf.pop();
+			if !self.options.allow_crlf && !self.crlf {
+				self.crlf = true;
+				self.issue(IssueKind::Crlf);
+			}
+		}
+		Ok(true)
+	}
+	
+	fn issue(&mut self, kind: IssueKind) {
+		let (line, record) = (self.line, self.records);
+		self.pending.push(ValidationIssue { line, record, kind });
+	}
+	
+	/// Validate the next record, queueing its issues. Returns false at end of input.
+	fn check_record(&mut self) -> io::Result<bool> {
+		let (mut head, mut seq, mut sep, mut qual) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+		if !self.read_line(&mut head)? { return Ok(false) }
+		if head.first() != Some(&b'@') { self.issue(IssueKind::MissingAt) }
+		let header = head.get(1..).unwrap_or(&[]);
+		if header.split(|b| b.is_ascii_whitespace()).next().is_none_or(|id| id.is_empty()) { self.issue(IssueKind::EmptyId) }
+		if header.last().is_some_and(u8::is_ascii_whitespace) && !self.options.allow_empty_description {
+			self.issue(IssueKind::EmptyDescription)
+		}
+		
+		if !self.read_line(&mut seq)? { self.issue(IssueKind::Truncated); return Ok(false) }
+		let mut lowercase = false;
+		for &b in &seq {
+			match b {
+				b'A' | b'C' | b'G' | b'T' | b'U' | b'N' | b'R' | b'Y' | b'S' | b'W' | b'K' | b'M' | b'B' | b'D' | b'H' | b'V' | b'.' | b'-' => {},
+				b'a' | b'c' | b'g' | b't' | b'u' | b'n' | b'r' | b'y' | b's' | b'w' | b'k' | b'm' | b'b' | b'd' | b'h' | b'v' => lowercase = true,
+				b => { self.issue(IssueKind::InvalidBase(b)); break },
+			}
+		}
+		if lowercase && !self.options.allow_lowercase { self.issue(IssueKind::LowercaseBase) }
+		
+		if !self.read_line(&mut sep)? { self.issue(IssueKind::Truncated); return Ok(false) }
+		if sep.first() != Some(&b'+') {
+			self.issue(IssueKind::MissingPlus)
+		} else if sep.len() > 1 {
+			if !self.options.allow_plus_header { self.issue(IssueKind::PlusHeader) }
+			if sep[1..] != *header { self.issue(IssueKind::PlusHeaderMismatch) }
+		}
+		
+		if !self.read_line(&mut qual)? { self.issue(IssueKind::Truncated); return Ok(false) }
+		if let Some(&q) = qual.iter().find(|&&q| !(b'!'..=b'~').contains(&q)) { self.issue(IssueKind::InvalidQuality(q)) }
+		if seq.len() != qual.len() { self.issue(IssueKind::LengthMismatch { seq: seq.len(), qual: qual.len() }) }
+		self.records += 1;
+		Ok(true)
+	}
+}
+
+impl<R: BufRead> Iterator for Issues<R> {
+	type Item = io::Result<ValidationIssue>;
+	
+	fn next(&mut self) -> Option<io::Result<ValidationIssue>> {
+		while self.pending.is_empty() && !self.done {
+			match self.check_record() {
+				Ok(more) => self.done = !more,
+				Err(e) => { self.done = true; return Some(Err(e)) },
+			}
+		}
+		if self.pending.is_empty() { None } else { Some(Ok(self.pending.remove(0))) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	#[test]
+	fn reports_crlf_once() {
+		let data = b"@r1\r\nACGT\r\n+\r\nIIII\r\n@r2\r\nACGT\r\n+\r\nIIII\r\n";
+		let issues: Vec<_> = Validator::new(ValidationOptions::default()).issues(&data[..]).collect::<Result<_, _>>().unwrap();
+		assert_eq!(issues, [ValidationIssue { line: 1, record: 0, kind: IssueKind::Crlf }]);
+	}
+}
@@ -0,0 +1,34 @@
+//! Recoverable oddities noticed while reading.
+
+use std::fmt;
+
+/// Something a reader tolerated and normalized instead of failing.
+///
+/// `record` is the 0-based index of the affected record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Warning {
+	/// Whitespace was removed from the sequence or quality line.
+	StrippedWhitespace { record: usize },
+	/// Input was skipped to resynchronize after a malformed record: `bytes` bytes on `lines` lines
+	/// starting at byte `offset`, where the malformed record started. It keeps its index,
+	/// so the next record read has index `record + 1`.
+	SkippedInput { record: usize, offset: u64, bytes: u64, lines: u64 },
+	/// A UTF-8 byte order mark or blank lines before the first record were skipped.
+	SkippedPreamble { bom: bool, blank_lines: u64 },
+}
+
+impl fmt::Display for Warning {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Warning::StrippedWhitespace { record } => write!(f, "Record {}: removed whitespace from sequence or qualities", record),
+			Warning::SkippedInput { record, offset, bytes, lines } =>
+				write!(f, "Record {}: skipped {} bytes on {} lines from byte {} to the next record", record, bytes, lines, offset),
+			Warning::SkippedPreamble { bom, blank_lines } => match (bom, blank_lines) {
+				(true, 0) => write!(f, "Skipped byte order mark before the first record"),
+				(true, n) => write!(f, "Skipped byte order mark and {} blank lines before the first record", n),
+				(false, n) => write!(f, "Skipped {} blank lines before the first record", n),
+			},
+		}
+	}
+}
@@ -0,0 +1,328 @@
+//! Exit codes and output of the `fastq` command line tool.
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Run `fastq` with some arguments and standard input.
+fn fastq(args: &[&str], stdin: &str) -> Output {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_fastq"))
+		.args(args)
+		.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+		.spawn().unwrap();
+	// commands like head may exit before reading all of their input
+	match child.stdin.take().unwrap().write_all(stdin.as_bytes()) {
+		Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => panic!("{}", e),
+		_ => {},
+	}
+	child.wait_with_output().unwrap()
+}
+
+fn code(output: &Output) -> i32 {
+	output.status.code().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+	String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+/// A file in a temporary directory of its own for each test.
+fn temp_file(test: &str, name: &str, content: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("fastq-cli-test-{}-{}", std::process::id(), test));
+	fs::create_dir_all(&dir).unwrap();
+	let path = dir.join(name);
+	fs::write(&path, content).unwrap();
+	path
+}
+
+const TWO: &str = "@r1\nACGT\n+\nIIII\n@r2\nGG\n+\nII\n";
+const TWO_CHANGED: &str = "@r1\nACGT\n+\nIIII\n@r2\nGA\n+\nII\n";
+const NO_PLUS: &str = "@r1\nACGT\n-\nIIII\n";
+const SHORT_QUALITY: &str = "@r1\nACGT\n+\nIII\n";
+
+#[test]
+fn usage_errors() {
+	for args in [&[][..], &["bogus"], &["validate"], &["validate", "--bogus", "-"], &["validate", "--max-issues", "x", "-"], &["compare", "-"]] {
+		let output = fastq(args, "");
+		assert_eq!(code(&output), 64, "{:?}", args);
+		assert!(String::from_utf8_lossy(&output.stderr).contains("Usage: fastq"), "{:?}", args);
+	}
+	let help = fastq(&["help"], "");
+	assert_eq!(code(&help), 0);
+	assert!(stdout(&help).starts_with("Usage: fastq"));
+}
+
+#[test]
+fn validate() {
+	let valid = fastq(&["validate", "-"], TWO);
+	assert_eq!(code(&valid), 0);
+	assert_eq!(stdout(&valid), "-: 2 records, 0 issues\n");
+	let invalid = fastq(&["validate", "-"], SHORT_QUALITY);
+	assert_eq!(code(&invalid), 1);
+	assert!(stdout(&invalid).ends_with("-: 1 records, 1 issues\n"), "{}", stdout(&invalid));
+}
+
+#[test]
+fn compare() {
+	let a = temp_file("compare", "a.fq", TWO);
+	let b = temp_file("compare", "b.fq", TWO_CHANGED);
+	let bad = temp_file("compare", "bad.fq", NO_PLUS);
+	let (a, b, bad) = (a.to_str().unwrap(), b.to_str().unwrap(), bad.to_str().unwrap());
+	let same = fastq(&["compare", a, a], "");
+	assert_eq!(code(&same), 0);
+	assert!(stdout(&same).contains("identical:               2\n"), "{}", stdout(&same));
+	let differ = fastq(&["compare", a, b], "");
+	assert_eq!(code(&differ), 1);
+	assert!(stdout(&differ).contains("sequence mismatches:     1\n"), "{}", stdout(&differ));
+	assert!(stdout(&differ).contains("  r2: "), "{}", stdout(&differ));
+	let json = fastq(&["compare", "--json", a, b], "");
+	assert_eq!(code(&json), 1);
+	assert!(stdout(&json).starts_with("{\"records_a\":2,\"records_b\":2,\"identical\":1,"), "{}", stdout(&json));
+	assert!(stdout(&json).ends_with("\"differences\":[{\"Sequence\":{\"id\":\"r2\",\"index_a\":1,\"index_b\":1,\"distance\":1}}]}\n"), "{}", stdout(&json));
+	let reordered = temp_file("compare", "reordered.fq", "@r2\nGG\n+\nII\n@r1\nACGT\n+\nIIII\n");
+	let reordered = reordered.to_str().unwrap();
+	assert_eq!(code(&fastq(&["compare", a, reordered], "")), 1);
+	assert_eq!(code(&fastq(&["compare", "--unordered", a, reordered], "")), 0);
+	assert_eq!(code(&fastq(&["compare", "--ignore-qual", a, a], "")), 0);
+	let a2 = temp_file("compare", "a2.fq", "@r1\nTT\n+\nII\n@r2\nCC\n+\nII\n");
+	let b2 = temp_file("compare", "b2.fq", "@r1\nTT\n+\nII\n");
+	let (a2, b2) = (a2.to_str().unwrap(), b2.to_str().unwrap());
+	assert_eq!(code(&fastq(&["compare", "--paired", a, a2, a, a2], "")), 0);
+	let dropped = fastq(&["compare", "--paired", a, a2, a, b2], "");
+	assert_eq!(code(&dropped), 1);
+	assert!(stdout(&dropped).contains("mate dropped in B:       1\n"), "{}", stdout(&dropped));
+	assert!(stdout(&dropped).contains("\n  r2: R2 dropped in B\n"), "{}", stdout(&dropped));
+	assert!(stdout(&fastq(&["compare", "--paired", "--json", a, a2, a, b2], "")).contains("\"dropped_mates\":[{\"id\":\"r2\",\"mate\":\"R2\",\"dropped_in\":\"B\"}]"));
+	assert_eq!(code(&fastq(&["compare", "--paired", a, a2, a], "")), 64);
+	let parse_error = fastq(&["compare", a, bad], "");
+	assert_eq!(code(&parse_error), 2);
+	assert!(String::from_utf8_lossy(&parse_error.stderr).contains("line 3"));
+	fs::remove_dir_all(std::path::Path::new(a).parent().unwrap()).unwrap();
+}
+
+#[test]
+fn io_errors() {
+	let missing = std::env::temp_dir().join("fastq-cli-test-missing.fq");
+	assert_eq!(code(&fastq(&["stats", missing.to_str().unwrap()], "")), 3);
+}
+
+#[test]
+fn error_json() {
+	let report = temp_file("error-json", "error.json", "");
+	let missing = std::env::temp_dir().join("fastq-cli-test-missing.fq");
+	let output = fastq(&["stats", "--error-json", report.to_str().unwrap(), missing.to_str().unwrap()], "");
+	assert_eq!(code(&output), 3);
+	let json = fs::read_to_string(&report).unwrap();
+	assert!(json.starts_with("{\"exit_code\":3,\"kind\":\"io\",\"io_kind\":\"NotFound\",\"message\":\""), "{}", json);
+	assert_eq!(code(&fastq(&["--error-json", report.to_str().unwrap(), "convert", "--to", "fasta", "-"], NO_PLUS)), 2);
+	assert!(fs::read_to_string(&report).unwrap().starts_with("{\"exit_code\":2,\"kind\":\"parse\",\"io_kind\":null,"));
+	assert_eq!(code(&fastq(&["bogus", "--error-json", report.to_str().unwrap()], "")), 64);
+	assert!(fs::read_to_string(&report).unwrap().starts_with("{\"exit_code\":64,\"kind\":\"usage\","));
+	assert_eq!(code(&fastq(&["validate", "--error-json"], "")), 64);
+	fs::remove_dir_all(report.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn stats_and_convert() {
+	let stats = fastq(&["stats", "-"], TWO);
+	assert_eq!(code(&stats), 0);
+	assert!(stdout(&stats).starts_with("metric\tvalue\nreads\t2\nbases\t6\n"), "{}", stdout(&stats));
+	let fasta = fastq(&["convert", "--to", "fasta", "-"], TWO);
+	assert_eq!(code(&fasta), 0);
+	assert_eq!(stdout(&fasta), ">r1\nACGT\n>r2\nGG\n");
+	assert_eq!(code(&fastq(&["convert", "--to", "fasta", "-"], NO_PLUS)), 2);
+	let a = temp_file("stats-per-file", "a.fq", TWO);
+	temp_file("stats-per-file", "b.fastq", TWO_CHANGED);
+	let dir = a.parent().unwrap();
+	let per_file = fastq(&["stats", "--per-file", "--threads", "2", dir.to_str().unwrap()], "");
+	assert_eq!(code(&per_file), 0, "{}", String::from_utf8_lossy(&per_file.stderr));
+	assert!(stdout(&per_file).contains("\ttotal\nreads\t2\t2\t4\nbases\t6\t6\t12\n"), "{}", stdout(&per_file));
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn selftest() {
+	let output = fastq(&["selftest"], "");
+	assert_eq!(code(&output), 0, "{}", stdout(&output));
+	assert!(stdout(&output).starts_with("parsers  ok"));
+}
+
+#[test]
+fn demux() {
+	let pairs = |mate: usize| -> String {
+		["ACGT", "TTTT", "GGGG", "ACGT"].iter().enumerate()
+			.map(|(i, barcode)| format!("@p{} {}:N:0:{}\nACGT\n+\nIIII\n", i, mate, barcode)).collect()
+	};
+	let sheet = temp_file("demux", "sheet.csv", "ACGT,s1\nTTTT\ts2\n");
+	let r1 = temp_file("demux", "r1.fq", &pairs(1));
+	let r2 = temp_file("demux", "r2.fq", &pairs(2));
+	let dir = sheet.parent().unwrap();
+	let out = dir.join("out");
+	let (sheet, r1, r2, out_str) = (sheet.to_str().unwrap(), r1.to_str().unwrap(), r2.to_str().unwrap(), out.to_str().unwrap());
+	let output = fastq(&["demux", "--samplesheet", sheet, "--r1", r1, "--r2", r2, "--out", out_str, "--template", "{sample}_{mate}.fq"], "");
+	assert_eq!(code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(stdout(&output), "sample\tpairs\ns1\t2\ns2\t1\nundetermined\t1\n");
+	assert_eq!(fs::read_to_string(out.join("manifest.tsv")).unwrap(), "\
+		sample\tpairs\tr1\tr2\n\
+		s1\t2\ts1_R1.fq\ts1_R2.fq\n\
+		s2\t1\ts2_R1.fq\ts2_R2.fq\n\
+		undetermined\t1\tundetermined_R1.fq\tundetermined_R2.fq\n");
+	assert_eq!(fs::read_to_string(out.join("s1_R2.fq")).unwrap(), "@p0 2:N:0:ACGT\nACGT\n+\nIIII\n@p3 2:N:0:ACGT\nACGT\n+\nIIII\n");
+	assert_eq!(code(&fastq(&["demux", "--samplesheet", sheet, "--r1", r1, "--out", out_str], "")), 64);
+	assert_eq!(code(&fastq(&["demux", "--samplesheet", sheet, "--r1", r1, "--r2", r2, "--out", out_str, "--template", "{sample}.fq"], "")), 64);
+	let zstd = fastq(&["demux", "--samplesheet", sheet, "--r1", r1, "--r2", r2, "--out", out_str, "--template", "{sample}_{mate}.fq.zst", "--dictionary-pairs", "0"], "");
+	if cfg!(feature = "zstd") {
+		assert_eq!(code(&zstd), 0, "{}", String::from_utf8_lossy(&zstd.stderr));
+		assert!(fs::read(out.join("s1_R2.fq.zst")).unwrap().starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+	} else {
+		assert_eq!(code(&zstd), 64);
+	}
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn amplicons() {
+	let scheme = temp_file("amplicons", "scheme.tsv", "amp1\tACGTTGCA\tGGATCCAA\namp2\tTTGGCCAA\tCAGRTACT\n");
+	let dir = scheme.parent().unwrap();
+	let reads = |seqs: &[&str]| -> String { seqs.iter().map(|s| format!("@r\n{}\n+\n{}\n", s, "I".repeat(s.len()))).collect() };
+	let a = temp_file("amplicons", "a.fastq", &reads(&["ACGTTGCAcccccTTGGATCC", "GGATCCAAgggTGCAACGT", "ACGT"]));
+	let b = temp_file("amplicons", "b.fq", &reads(&["TTGGCCAAggggggAGTATCTG"]));
+	let out = dir.join("out");
+	let (scheme, a, b, out_str) = (scheme.to_str().unwrap(), a.to_str().unwrap(), b.to_str().unwrap(), out.to_str().unwrap());
+	let output = fastq(&["amplicons", "--primers", scheme, "--out", out_str, "--template", "{sample}.{amplicon}.fq", "--trim-primers", a, b], "");
+	assert_eq!(code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+	let matrix = "amplicon\ta\tb\namp1\t2\t0\namp2\t0\t1\noff_length\t0\t0\nunassigned\t1\t0\n";
+	assert_eq!(stdout(&output), matrix);
+	assert_eq!(fs::read_to_string(out.join("counts.tsv")).unwrap(), matrix);
+	assert_eq!(fs::read_to_string(out.join("a.amp1.fq")).unwrap(), reads(&["ccccc", "ggg"]));
+	assert_eq!(fs::read_to_string(out.join("a.unassigned.fq")).unwrap(), reads(&["ACGT"]));
+	assert_eq!(code(&fastq(&["amplicons", "--primers", scheme, "--out", out_str, a, a], "")), 64);
+	assert_eq!(code(&fastq(&["amplicons", "--primers", scheme, a], "")), 64);
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn trim() {
+	let reads = "@a\nACGTACGTACGTACGTACGTACGTAGATCGGAAGAGCACAC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n@b\nACGTACGTAGATCGGAAGAGC\n+\nIIIIIIIIIIIIIIIIIIIII\n";
+	let output = fastq(&["trim", "--preset", "illumina-dna", "-i", "-"], reads);
+	assert_eq!(code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(stdout(&output), "@a\nACGTACGTACGTACGTACGTACGT\n+\nIIIIIIIIIIIIIIIIIIIIIIII\n");
+	let summary = String::from_utf8_lossy(&output.stderr);
+	assert!(summary.contains("reads trimmed:           2\n") && summary.contains("bases trimmed:           30\n"), "{}", summary);
+	assert!(summary.contains("reads too short:         1\n") && summary.contains("reads written:           1\n"), "{}", summary);
+	let gz = temp_file("trim", "out.fq.gz", "");
+	assert_eq!(code(&fastq(&["trim", "--preset", "illumina-dna", "--min-length", "5", "-o", gz.to_str().unwrap()], reads)), 0);
+	let stats = fastq(&["stats", gz.to_str().unwrap()], "");
+	assert!(stdout(&stats).starts_with("metric\tvalue\nreads\t2\nbases\t32\n"), "{}", stdout(&stats));
+	assert_eq!(code(&fastq(&["trim", "--preset", "bogus"], reads)), 64);
+	// without --preset, the platform is guessed from read lengths and headers
+	assert_eq!(code(&fastq(&["trim"], reads)), 64);
+	let casava = "@M00123:12:000000000-A1B2C:1:1101:15589:1332 1:N:0:ATCACG\nACGTACGTACGTACGTACGTACGTAGATCGGAAGAGCACAC\n+\nIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII\n";
+	let guessed = fastq(&["trim"], casava);
+	assert_eq!(code(&guessed), 0, "{}", String::from_utf8_lossy(&guessed.stderr));
+	assert!(String::from_utf8_lossy(&guessed.stderr).starts_with("preset:                  illumina-dna (guessed from the reads)\n"));
+	assert!(stdout(&guessed).ends_with("\nACGTACGTACGTACGTACGTACGT\n+\nIIIIIIIIIIIIIIIIIIIIIIII\n"), "{}", stdout(&guessed));
+	let ont: String = [900, 15_000, 4_000].iter().enumerate().map(|(i, &n)| format!("@r{} runid=x ch={}\n{}\n+\n{}\n", i, i, "A".repeat(n), "I".repeat(n))).collect();
+	let long = fastq(&["trim"], &ont);
+	assert_eq!(code(&long), 64);
+	assert!(String::from_utf8_lossy(&long.stderr).contains("Nanopore"), "{}", String::from_utf8_lossy(&long.stderr));
+	fs::remove_dir_all(gz.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn ingest() {
+	let dir = temp_file("ingest", "notes.txt", "").parent().unwrap().to_owned();
+	let (input, out) = (dir.join("in"), dir.join("out"));
+	fs::create_dir_all(&input).unwrap();
+	fs::write(input.join("a.fq"), TWO).unwrap();
+	let (input_str, out_str) = (input.to_str().unwrap(), out.to_str().unwrap());
+	let output = fastq(&["ingest", "--once", "--min-length", "3", "--out", out_str, input_str], "");
+	if !cfg!(feature = "notify") {
+		assert_eq!(code(&output), 64);
+		return fs::remove_dir_all(dir).unwrap();
+	}
+	assert_eq!(code(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+	assert!(stdout(&output).starts_with("a.fq\t28\t") && stdout(&output).ends_with("\t2\t1\tdone\n"), "{}", stdout(&output));
+	assert_eq!(fs::read_to_string(out.join("a.fq")).unwrap(), "@r1\nACGT\n+\nIIII\n");
+	// processed files are skipped, failing ones make the exit code 1
+	fs::write(input.join("b.fq"), NO_PLUS).unwrap();
+	let output = fastq(&["ingest", "--once", "--out", out_str, input_str], "");
+	assert_eq!(code(&output), 1);
+	assert!(stdout(&output).starts_with("b.fq\t") && stdout(&output).contains("\tfailed: "), "{}", stdout(&output));
+	assert_eq!(fs::read_to_string(out.join("ledger.tsv")).unwrap().lines().count(), 3);
+	assert_eq!(code(&fastq(&["ingest", "--once", "--out", input_str, input_str], "")), 64);
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sample_and_head() {
+	let head = fastq(&["head", "-n", "1"], TWO);
+	assert_eq!(code(&head), 0);
+	assert_eq!(stdout(&head), "@r1\nACGT\n+\nIIII\n");
+	assert_eq!(stdout(&fastq(&["sample", "-f", "1", "-"], TWO)), TWO);
+	assert_eq!(stdout(&fastq(&["sample", "-f", "0"], TWO)), "");
+	let many: String = (0..100).map(|i| format!("@r{}\nACGT\n+\nIIII\n", i)).collect();
+	let sampled = stdout(&fastq(&["sample", "-f", "0.5", "-s", "42"], &many));
+	assert_eq!(sampled, stdout(&fastq(&["sample", "-f", "0.5", "-s", "42"], &many)));
+	assert_ne!(sampled, stdout(&fastq(&["sample", "-f", "0.5", "-s", "7"], &many)));
+	assert_eq!(code(&fastq(&["sample", "-f", "2"], TWO)), 64);
+	let mates = |mate: usize| -> String { (0..100).map(|i| format!("@r{}/{}\nACGT\n+\nIIII\n", i, mate)).collect() };
+	let (r1, r2) = (stdout(&fastq(&["sample", "--paired", "-f", "0.5"], &mates(1))), stdout(&fastq(&["sample", "--paired", "-f", "0.5"], &mates(2))));
+	assert!(!r1.is_empty() && r1.len() < mates(1).len());
+	assert_eq!(r1.replace("/1\n", "/2\n"), r2);
+	// compressed input gives compressed standard output
+	let gz = temp_file("sample-and-head", "in.fq.gz", "");
+	assert_eq!(code(&fastq(&["head", "-n", "50", "-o", gz.to_str().unwrap()], &many)), 0);
+	let output = fastq(&["head", "-n", "20", gz.to_str().unwrap()], "");
+	assert_eq!(code(&output), 0);
+	assert!(output.stdout.starts_with(&[0x1f, 0x8b]));
+	let plain = temp_file("sample-and-head", "out.fq", "");
+	fs::write(&plain, &output.stdout).unwrap();
+	assert!(stdout(&fastq(&["stats", plain.to_str().unwrap()], "")).starts_with("metric\tvalue\nreads\t20\n"));
+	fs::remove_dir_all(gz.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn interleave_and_deinterleave() {
+	let r1 = temp_file("interleave", "r1.fq", "@p1/1\nAC\n+\nII\n@p2/1\nGG\n+\nII\n");
+	let r2 = temp_file("interleave", "r2.fq", "@p1/2\nTT\n+\nII\n@p2/2\nCC\n+\nII\n");
+	let (r1_str, r2_str) = (r1.to_str().unwrap(), r2.to_str().unwrap());
+	let interleaved = fastq(&["interleave", r1_str, r2_str], "");
+	assert_eq!(code(&interleaved), 0);
+	assert_eq!(stdout(&interleaved), "@p1/1\nAC\n+\nII\n@p1/2\nTT\n+\nII\n@p2/1\nGG\n+\nII\n@p2/2\nCC\n+\nII\n");
+	let dir = r1.parent().unwrap();
+	let (out1, out2) = (dir.join("out1.fq"), dir.join("out2.fq.gz"));
+	let split = fastq(&["deinterleave", "-1", out1.to_str().unwrap(), "-2", out2.to_str().unwrap()], &stdout(&interleaved));
+	assert_eq!(code(&split), 0, "{}", String::from_utf8_lossy(&split.stderr));
+	assert_eq!(fs::read_to_string(&out1).unwrap(), fs::read_to_string(&r1).unwrap());
+	assert!(fs::read(&out2).unwrap().starts_with(&[0x1f, 0x8b]));
+	assert!(stdout(&fastq(&["stats", out2.to_str().unwrap()], "")).starts_with("metric\tvalue\nreads\t2\nbases\t4\n"));
+	let other = temp_file("interleave", "other.fq", "@p1/2\nTT\n+\nII\n@p3/2\nCC\n+\nII\n");
+	assert_eq!(code(&fastq(&["interleave", r1_str, other.to_str().unwrap()], "")), 2);
+	assert_eq!(code(&fastq(&["deinterleave", "-1", out1.to_str().unwrap(), "-2", out2.to_str().unwrap()], "@p1/1\nAC\n+\nII\n")), 2);
+	assert_eq!(code(&fastq(&["deinterleave", "-1", out1.to_str().unwrap()], "")), 64);
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn help_json_and_completions() {
+	let usage = stdout(&fastq(&["help"], ""));
+	let help = fastq(&["--help-json"], "");
+	assert_eq!(code(&help), 0);
+	let json = stdout(&help);
+	assert!(json.starts_with("{\"name\":\"fastq\",\"global_options\":[{\"name\":\"--error-json\","), "{}", json);
+	assert!(json.contains("{\"name\":\"--preset\",\"value\":\"preset\",\"choices\":[\"illumina-dna\",\"nextera\",\"small-rna\"],\"path\":false}"), "{}", json);
+	// every command in the usage is described
+	let commands = usage.lines().filter(|l| l.starts_with("  ") && l.as_bytes().get(2).is_some_and(u8::is_ascii_lowercase));
+	for command in commands.map(|l| l.split_whitespace().next().unwrap()) {
+		assert!(json.contains(&format!("{{\"name\":\"{}\",\"summary\":", command)), "{}", command);
+	}
+	let bash = fastq(&["completions", "bash"], "");
+	assert_eq!(code(&bash), 0);
+	assert!(stdout(&bash).ends_with("complete -o filenames -F _fastq fastq\n"));
+	assert!(stdout(&fastq(&["completions", "zsh"], "")).starts_with("#compdef fastq\n"));
+	assert!(stdout(&fastq(&["completions", "fish"], "")).contains("complete -c fastq -n '__fish_seen_subcommand_from demux' -l out -r -F\n"));
+	assert_eq!(code(&fastq(&["completions", "tcsh"], "")), 64);
+}